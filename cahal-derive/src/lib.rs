@@ -0,0 +1,306 @@
+//! Derive macros for `cahal`, re-exported from there
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, quote};
+use syn::{
+    Data, DeriveInput, Expr, Fields, GenericArgument, PathArguments, Type, parse_macro_input,
+    spanned::Spanned,
+};
+
+/// Derive `HasProperties` by looking up the fields of a struct by the selector they report through `RawProperty::selector`.
+///
+/// Fields are checked in declaration order. Field attributes:
+/// - `#[property(skip)]`: the field is not a property
+/// - `#[property(flatten)]`: the field implements `HasProperties` itself and is searched after all plain property fields
+///
+/// Selectors that appear as a constant generic argument of a field type (e.g. `Prop<u32, kAudioObjectPropertyClass>`)
+/// are known statically, and using the same one on two fields is a compile error
+#[proc_macro_derive(HasProperties, attributes(property))]
+pub fn derive_has_properties(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum FieldKind {
+    Property,
+    Flatten,
+    Skip,
+}
+
+fn field_kind(field: &syn::Field) -> syn::Result<FieldKind> {
+    let mut kind = FieldKind::Property;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("property")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                kind = FieldKind::Skip;
+                Ok(())
+            } else if meta.path.is_ident("flatten") {
+                kind = FieldKind::Flatten;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip` or `flatten`"))
+            }
+        })?;
+    }
+    Ok(kind)
+}
+
+/// Find a selector constant among the generic arguments of the field type, i.e. a path argument named like `kAudio...`
+fn static_selector(ty: &Type) -> Option<String> {
+    let path = match ty {
+        Type::Path(path) => path,
+        // types passed through a `macro_rules` fragment (like the fields of `audio_object!`) arrive wrapped in a group
+        Type::Group(group) => return static_selector(&group.elem),
+        _ => return None,
+    };
+    let PathArguments::AngleBracketed(args) = &path.path.segments.last()?.arguments else {
+        return None;
+    };
+    args.args.iter().find_map(|arg| {
+        let path = match arg {
+            GenericArgument::Type(Type::Path(p)) => &p.path,
+            GenericArgument::Const(Expr::Path(p)) => &p.path,
+            _ => return None,
+        };
+        let ident = path.segments.last()?.ident.to_string();
+        let mut chars = ident.chars();
+        (chars.next() == Some('k') && chars.next().is_some_and(|c| c.is_ascii_uppercase()))
+            .then(|| path.to_token_stream().to_string())
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "HasProperties can only be derived for structs",
+        ));
+    };
+    let fields = match &data.fields {
+        Fields::Named(fields) => &fields.named,
+        Fields::Unnamed(fields) => &fields.unnamed,
+        Fields::Unit => {
+            return Err(syn::Error::new(
+                input.span(),
+                "HasProperties can't be derived for unit structs",
+            ));
+        }
+    };
+
+    let mut props = Vec::new();
+    let mut flattened = Vec::new();
+    let mut seen: Vec<(String, &syn::Field)> = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => ident.to_token_stream(),
+            None => syn::Index::from(i).to_token_stream(),
+        };
+        match field_kind(field)? {
+            FieldKind::Skip => {}
+            FieldKind::Flatten => flattened.push(member),
+            FieldKind::Property => {
+                if let Some(sel) = static_selector(&field.ty) {
+                    if let Some((_, first)) = seen.iter().find(|(s, _)| *s == sel) {
+                        let mut err = syn::Error::new(
+                            field.ty.span(),
+                            format!("duplicate property selector `{sel}`"),
+                        );
+                        err.combine(syn::Error::new(first.ty.span(), "selector first used here"));
+                        return Err(err);
+                    }
+                    seen.push((sel, field));
+                }
+                props.push(member);
+            }
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::cahal::audio_object::HasProperties for #name #ty_generics #where_clause {
+            fn get_object_property(
+                &self,
+                address: ::cahal::property::PropertyAddress,
+            ) -> ::core::option::Option<&dyn ::cahal::property::RawProperty> {
+                #(
+                    if ::cahal::property::RawProperty::selector(&self.#props) == address.selector {
+                        return ::core::option::Option::Some(&self.#props);
+                    }
+                )*
+                #(
+                    if let ::core::option::Option::Some(prop) =
+                        ::cahal::audio_object::HasProperties::get_object_property(&self.#flattened, address)
+                    {
+                        return ::core::option::Option::Some(prop);
+                    }
+                )*
+                ::core::option::Option::None
+            }
+
+            fn get_object_property_mut(
+                &mut self,
+                address: ::cahal::property::PropertyAddress,
+            ) -> ::core::option::Option<&mut dyn ::cahal::property::RawProperty> {
+                #(
+                    if ::cahal::property::RawProperty::selector(&self.#props) == address.selector {
+                        return ::core::option::Option::Some(&mut self.#props);
+                    }
+                )*
+                #(
+                    if let ::core::option::Option::Some(prop) =
+                        ::cahal::audio_object::HasProperties::get_object_property_mut(&mut self.#flattened, address)
+                    {
+                        return ::core::option::Option::Some(prop);
+                    }
+                )*
+                ::core::option::Option::None
+            }
+
+            fn visit_properties(
+                &self,
+                visitor: &mut dyn FnMut(
+                    ::cahal::property::PropertyAddress,
+                    &dyn ::cahal::property::RawProperty,
+                ),
+            ) {
+                #(
+                    ::cahal::audio_object::visit_property(&self.#props, visitor);
+                )*
+                #(
+                    ::cahal::audio_object::HasProperties::visit_properties(&self.#flattened, visitor);
+                )*
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn expanded(input: DeriveInput) -> String {
+        expand(input).unwrap().to_string()
+    }
+
+    fn error(input: DeriveInput) -> String {
+        expand(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn properties_are_checked_in_order_before_flattened_fields() {
+        let out = expanded(parse_quote! {
+            struct Device {
+                #[property(flatten)]
+                base: AudioObjectBase,
+                latency: Prop<u32, kAudioDevicePropertyLatency>,
+                safety_offset: Prop<u32, kAudioDevicePropertySafetyOffset>,
+            }
+        });
+        let latency = out.find("& self . latency").unwrap();
+        let safety_offset = out.find("& self . safety_offset").unwrap();
+        let base = out.find("get_object_property (& self . base").unwrap();
+        assert!(latency < safety_offset && safety_offset < base);
+        assert!(out.contains("impl :: cahal :: audio_object :: HasProperties for Device"));
+    }
+
+    #[test]
+    fn skipped_fields_are_left_out() {
+        let out = expanded(parse_quote! {
+            struct Device {
+                #[property(skip)]
+                id: u32,
+                latency: Prop<u32, kAudioDevicePropertyLatency>,
+            }
+        });
+        assert!(!out.contains("self . id"));
+        assert!(out.contains("self . latency"));
+    }
+
+    #[test]
+    fn tuple_structs_use_field_indices() {
+        let out = expanded(parse_quote! {
+            struct Device(#[property(skip)] u32, Prop<u32, kAudioDevicePropertyLatency>);
+        });
+        assert!(!out.contains("self . 0"));
+        assert!(out.contains("self . 1"));
+    }
+
+    #[test]
+    fn generics_are_carried_over() {
+        let out = expanded(parse_quote! {
+            struct Device<T: RawProperty> where T: Send {
+                inner: T,
+            }
+        });
+        assert!(out.contains("impl < T : RawProperty > :: cahal :: audio_object :: HasProperties for Device < T > where T : Send"));
+    }
+
+    #[test]
+    fn static_selectors_are_found_in_any_argument_position() {
+        assert_eq!(
+            static_selector(&parse_quote!(Prop<u32, kAudioDevicePropertyLatency, true>)).as_deref(),
+            Some("kAudioDevicePropertyLatency")
+        );
+        assert_eq!(
+            static_selector(&parse_quote!(Prop<u32, base::kAudioDevicePropertyLatency>)).as_deref(),
+            Some("base :: kAudioDevicePropertyLatency")
+        );
+        assert_eq!(static_selector(&parse_quote!(Prop<u32, 0x6c746e63>)), None);
+        assert_eq!(static_selector(&parse_quote!(Prop<u32, SELECTOR>)), None);
+        assert_eq!(static_selector(&parse_quote!(CFStringProp)), None);
+    }
+
+    #[test]
+    fn duplicate_selectors_are_rejected() {
+        let err = error(parse_quote! {
+            struct Device {
+                latency: Prop<u32, kAudioDevicePropertyLatency>,
+                also_latency: Prop<f64, kAudioDevicePropertyLatency, true>,
+            }
+        });
+        assert_eq!(
+            err,
+            "duplicate property selector `kAudioDevicePropertyLatency`"
+        );
+    }
+
+    #[test]
+    fn skipped_and_flattened_fields_are_not_duplicates() {
+        expanded(parse_quote! {
+            struct Device {
+                latency: Prop<u32, kAudioDevicePropertyLatency>,
+                #[property(skip)]
+                cached: Prop<u32, kAudioDevicePropertyLatency>,
+                #[property(flatten)]
+                inner: Wrapper<kAudioDevicePropertyLatency>,
+            }
+        });
+    }
+
+    #[test]
+    fn unsupported_inputs_are_rejected() {
+        assert_eq!(
+            error(parse_quote! { enum Device { A } }),
+            "HasProperties can only be derived for structs"
+        );
+        assert_eq!(
+            error(parse_quote! { struct Device; }),
+            "HasProperties can't be derived for unit structs"
+        );
+        assert_eq!(
+            error(parse_quote! {
+                struct Device {
+                    #[property(hidden)]
+                    latency: Prop<u32, kAudioDevicePropertyLatency>,
+                }
+            }),
+            "expected `skip` or `flatten`"
+        );
+    }
+}
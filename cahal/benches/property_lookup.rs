@@ -0,0 +1,112 @@
+//! Compares looking up the properties of a device with 40 of them through the `HasProperties` derive, which checks the
+//! fields one after another, and through a [`PropertyTable`]. Run with `cargo bench --bench property_lookup`
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use cahal::{
+    audio_object::HasProperties,
+    property::{Prop, PropertyAddress, PropertyTable},
+};
+
+const ROUNDS: u32 = 100_000;
+
+macro_rules! device {
+    ($($field:ident = $selector:literal,)*) => {
+        #[derive(HasProperties)]
+        struct DerivedDevice {
+            $($field: Prop<u32, $selector>,)*
+        }
+
+        fn derived_device() -> DerivedDevice {
+            DerivedDevice {
+                $($field: Prop($selector),)*
+            }
+        }
+
+        fn table_device() -> PropertyTable {
+            PropertyTable::new()$(.with(Prop::<u32, $selector>($selector)))*
+        }
+
+        const SELECTORS: &[u32] = &[$($selector),*];
+    };
+}
+
+device! {
+    p00 = 0x70000000,
+    p01 = 0x70000001,
+    p02 = 0x70000002,
+    p03 = 0x70000003,
+    p04 = 0x70000004,
+    p05 = 0x70000005,
+    p06 = 0x70000006,
+    p07 = 0x70000007,
+    p08 = 0x70000008,
+    p09 = 0x70000009,
+    p10 = 0x7000000a,
+    p11 = 0x7000000b,
+    p12 = 0x7000000c,
+    p13 = 0x7000000d,
+    p14 = 0x7000000e,
+    p15 = 0x7000000f,
+    p16 = 0x70000010,
+    p17 = 0x70000011,
+    p18 = 0x70000012,
+    p19 = 0x70000013,
+    p20 = 0x70000014,
+    p21 = 0x70000015,
+    p22 = 0x70000016,
+    p23 = 0x70000017,
+    p24 = 0x70000018,
+    p25 = 0x70000019,
+    p26 = 0x7000001a,
+    p27 = 0x7000001b,
+    p28 = 0x7000001c,
+    p29 = 0x7000001d,
+    p30 = 0x7000001e,
+    p31 = 0x7000001f,
+    p32 = 0x70000020,
+    p33 = 0x70000021,
+    p34 = 0x70000022,
+    p35 = 0x70000023,
+    p36 = 0x70000024,
+    p37 = 0x70000025,
+    p38 = 0x70000026,
+    p39 = 0x70000027,
+}
+
+/// The time one lookup of each of `selectors` takes on average
+fn measure(object: &dyn HasProperties, selectors: &[u32]) -> Duration {
+    let addresses: Vec<_> = selectors
+        .iter()
+        .map(|&selector| PropertyAddress::global(selector))
+        .collect();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for &address in &addresses {
+            black_box(object.get_object_property(black_box(address)));
+        }
+    }
+    start.elapsed() / (ROUNDS * addresses.len() as u32)
+}
+
+fn main() {
+    let derived = derived_device();
+    let table = table_device();
+    let cases: [(&str, &[u32]); 4] = [
+        ("first", &SELECTORS[..1]),
+        ("last", &SELECTORS[SELECTORS.len() - 1..]),
+        ("all", SELECTORS),
+        ("missing", &[0x6d697373]),
+    ];
+    println!("{:<10} {:>12} {:>12}", "lookup", "derive", "table");
+    for (name, selectors) in cases {
+        println!(
+            "{name:<10} {:>12?} {:>12?}",
+            measure(&derived, selectors),
+            measure(&table, selectors)
+        );
+    }
+}
@@ -1,101 +1,1334 @@
-use crate::property::ArrayProp;
-use crate::property::{Prop, PropertySelector, RawProperty};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
+use std::ops::ControlFlow;
+use std::sync::PoisonError;
+
 use core_foundation::string::CFString;
+
+use crate::notification::ChangeQueue;
+use crate::os_err::{OSResult, OSStatus, OSStatusError};
+use crate::property::{
+    CFStringProp, Prop, PropertyAddress, PropertySelector, PropertyTable, Qualifier, RawProperty,
+    RawPropertyExt,
+};
 use coreaudio_sys::kAudioObjectPropertyBaseClass;
 use coreaudio_sys::kAudioObjectPropertyClass;
 use coreaudio_sys::kAudioObjectPropertyName;
 use coreaudio_sys::kAudioObjectPropertyOwnedObjects;
 use coreaudio_sys::kAudioObjectPropertyOwner;
+use coreaudio_sys::pid_t;
 use coreaudio_sys::AudioClassID;
 use coreaudio_sys::AudioObjectID;
+use coreaudio_sys::{kAudioObjectClassID, kAudioObjectPlugInObject, kAudioObjectUnknown};
 use polonius_the_crab::{exit_polonius, polonius, polonius_return};
 
+mod audio_box;
+mod builder;
+mod clock;
+mod control;
+mod device;
+mod dump;
+mod owned;
+mod plugin;
+mod sample_rate;
+mod stream;
+
+pub use crate::persist::{apply_plist, to_plist};
+pub use audio_box::AudioBoxObject;
+pub use builder::{DeviceBuilder, DeviceHandle};
+pub use clock::ClockDeviceObject;
+pub use control::{
+    BooleanControlObject, ChannelVolumes, DataDestinationControlObject, DataSourceControlObject,
+    MasterVolumePolicy, MuteControlObject, SelectorControlObject, StereoPanControlObject,
+    VolumeControlObject, VolumeHandle,
+};
+pub use device::{
+    clock_domain, device_uid, model_uid, AudioDeviceObject, ClockAlgorithm, TransportType,
+};
+pub use dump::{dump, log_tree};
+pub use owned::OwnedObjectsProp;
+pub use plugin::PluginObject;
+use plugin::{PublishedKind, SharedPublished};
+pub use sample_rate::{RateRequestHook, SampleRateHandle, SampleRateManager};
+pub use stream::{AudioStreamObject, StreamDirection};
+
+/// An object in the tree of objects a driver publishes to the HAL.
+///
+/// Subobjects are exposed through [`AudioObject::visit_children`] and [`AudioObject::visit_children_mut`], which call
+/// the visitor with each direct child until it returns [`ControlFlow::Break`]. Objects without subobjects don't need to
+/// implement them. Children stored as `Vec<Box<dyn AudioObject>>` can be visited with [`Children`].
+///
+/// # Migrating from `subobjects`
+/// Earlier versions required `subobjects()`/`subobjects_mut()` returning slices of references. Replace them with:
+/// ```ignore
+/// fn visit_children<'a>(
+///     &'a self,
+///     visitor: &mut dyn FnMut(&'a dyn AudioObject) -> ControlFlow<()>,
+/// ) -> ControlFlow<()> {
+///     visitor(&self.stream)?;
+///     self.controls.visit(visitor)
+/// }
+/// ```
+/// and the same with `&mut` for `visit_children_mut`
 pub trait AudioObject: HasProperties {
-    fn subobjects(&self) -> &[&dyn AudioObject];
-    fn subobjects_mut(&mut self) -> &mut [&mut dyn AudioObject];
     fn id(&self) -> AudioObjectID;
-    fn get_property(&self, sel: PropertySelector) -> Option<&dyn RawProperty> {
-        if let Some(prop) = self.get_object_property(sel) {
+    /// Call `visitor` with every direct subobject of this object, stopping early if it returns [`ControlFlow::Break`].
+    /// Returns `Break` if the visitor did
+    fn visit_children<'a>(
+        &'a self,
+        visitor: &mut dyn FnMut(&'a dyn AudioObject) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let _ = visitor;
+        ControlFlow::Continue(())
+    }
+    /// Mutable variant of [`AudioObject::visit_children`]
+    fn visit_children_mut<'a>(
+        &'a mut self,
+        visitor: &mut dyn FnMut(&'a mut dyn AudioObject) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let _ = visitor;
+        ControlFlow::Continue(())
+    }
+    /// Look up the property at `address` on this object or any of its subobjects. Properties that are currently absent
+    /// (see [`RawProperty::is_present`]) are skipped. Use [`RawPropertyExt::value`] on the result to get at the typed
+    /// value, e.g. `object.get_property(address)?.value::<u32>()`.
+    ///
+    /// Properties whose value depends on the scope or element (like the stream list of a device) answer for the full
+    /// address through [`RawProperty::get_at`] and friends, so pass the same address there when reading the value
+    fn get_property(&self, address: PropertyAddress) -> Option<&dyn RawProperty> {
+        if let Some(prop) = self
+            .get_object_property(address)
+            .filter(|prop| prop.is_present())
+        {
             return Some(prop);
         }
-        for obj in self.subobjects() {
-            if let Some(prop) = obj.get_property(sel) {
-                return Some(prop);
+        let mut found = None;
+        let _ = self.visit_children(&mut |child| {
+            found = child.get_property(address);
+            if found.is_some() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
             }
+        });
+        found
+    }
+    /// Call `visitor` with every present property on this object or any of its subobjects that matches `address`, which
+    /// may contain wildcards in any of its parts (see [`PropertyAddress::matches`]). The visitor also receives the id of
+    /// the object the property belongs to
+    fn visit_matching(
+        &self,
+        address: PropertyAddress,
+        visitor: &mut dyn FnMut(AudioObjectID, PropertyAddress, &dyn RawProperty),
+    ) {
+        let id = self.id();
+        self.visit_properties(&mut |stored, prop| {
+            if stored.matches(&address) && prop.is_present() {
+                visitor(id, stored, prop);
+            }
+        });
+        let _ = self.visit_children(&mut |child| {
+            child.visit_matching(address, visitor);
+            ControlFlow::Continue(())
+        });
+    }
+    /// Collect the object ids and addresses of all properties matching `address`, see [`AudioObject::visit_matching`]
+    fn matching_properties(
+        &self,
+        address: PropertyAddress,
+    ) -> Vec<(AudioObjectID, PropertyAddress)> {
+        let mut matches = Vec::new();
+        self.visit_matching(address, &mut |id, stored, _| matches.push((id, stored)));
+        matches
+    }
+    /// The ids and addresses of all present properties of this object and, if `recursive`, of all of its subobjects
+    fn all_property_selectors(&self, recursive: bool) -> Vec<(AudioObjectID, PropertyAddress)> {
+        let everything = PropertyAddress::anywhere(PropertySelector::WILDCARD);
+        if recursive {
+            return self.matching_properties(everything);
+        }
+        let id = self.id();
+        let mut addresses = Vec::new();
+        self.visit_properties(&mut |address, prop| {
+            if prop.is_present() {
+                addresses.push((id, address));
+            }
+        });
+        addresses
+    }
+    /// Whether any property on this object or its subobjects matches `address`, which may contain wildcards
+    fn has_property(&self, address: PropertyAddress) -> bool {
+        if !address.selector.is_wildcard() && self.get_property(address).is_some() {
+            return true;
         }
-        None
+        let mut found = false;
+        self.visit_matching(address, &mut |_, _, _| found = true);
+        found
     }
-    fn get_property_mut(&mut self, sel: PropertySelector) -> Option<&mut dyn RawProperty> {
+    fn get_property_mut(&mut self, address: PropertyAddress) -> Option<&mut dyn RawProperty> {
         let mut borrow = self;
         if let Some(prop) = polonius!(|borrow| -> Option<&'polonius mut dyn RawProperty> {
-            let opt = borrow.get_object_property_mut(sel);
-            if opt.is_some() {
+            let opt = borrow.get_object_property_mut(address);
+            if opt.as_ref().is_some_and(|prop| prop.is_present()) {
                 polonius_return!(opt);
             }
             exit_polonius!(None)
         }) {
             return prop;
         }
-        for obj in borrow.subobjects_mut() {
-            let prop = obj.get_property_mut(sel);
-            if prop.is_some() {
-                return prop;
+        let mut found = None;
+        let _ = borrow.visit_children_mut(&mut |child| {
+            found = child.get_property_mut(address);
+            if found.is_some() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
             }
+        });
+        found
+    }
+}
+
+impl<'o> dyn AudioObject + 'o {
+    /// Find the object with the given id in the tree rooted at this object, including this object itself
+    pub fn find_by_id(&self, id: AudioObjectID) -> Option<&dyn AudioObject> {
+        if self.id() == id {
+            return Some(self);
+        }
+        let mut found = None;
+        let _ = self.visit_children(&mut |child| {
+            found = child.find_by_id(id);
+            if found.is_some() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        found
+    }
+    /// Mutable variant of [`find_by_id`](#method.find_by_id)
+    pub fn find_by_id_mut(&mut self, id: AudioObjectID) -> Option<&mut dyn AudioObject> {
+        if self.id() == id {
+            return Some(self);
         }
-        None
+        let mut found = None;
+        let _ = self.visit_children_mut(&mut |child| {
+            found = child.find_by_id_mut(id);
+            if found.is_some() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        found
     }
 }
 
+/// Storage for boxed subobjects, for implementing [`AudioObject::visit_children`] and
+/// [`AudioObject::visit_children_mut`] of objects with a dynamic set of children
+#[derive(Default)]
+pub struct Children {
+    objects: Vec<Box<dyn AudioObject + Send>>,
+}
+
+impl Children {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(&mut self, child: Box<dyn AudioObject + Send>) {
+        self.objects.push(child);
+    }
+    /// Remove the child with the given id, returning it if there was one
+    pub fn remove(&mut self, id: AudioObjectID) -> Option<Box<dyn AudioObject + Send>> {
+        let index = self.objects.iter().position(|child| child.id() == id)?;
+        Some(self.objects.remove(index))
+    }
+    pub fn get(&self, id: AudioObjectID) -> Option<&(dyn AudioObject + Send)> {
+        self.objects
+            .iter()
+            .find(|child| child.id() == id)
+            .map(|child| child.as_ref())
+    }
+    pub fn ids(&self) -> impl Iterator<Item = AudioObjectID> + '_ {
+        self.objects.iter().map(|child| child.id())
+    }
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+    /// Call `visitor` with each child, see [`AudioObject::visit_children`]
+    pub fn visit<'a>(
+        &'a self,
+        visitor: &mut dyn FnMut(&'a dyn AudioObject) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for child in &self.objects {
+            visitor(child.as_ref())?;
+        }
+        ControlFlow::Continue(())
+    }
+    /// Call `visitor` with each child, see [`AudioObject::visit_children_mut`]
+    pub fn visit_mut<'a>(
+        &'a mut self,
+        visitor: &mut dyn FnMut(&'a mut dyn AudioObject) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for child in &mut self.objects {
+            visitor(child.as_mut())?;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl std::fmt::Debug for Children {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.ids()).finish()
+    }
+}
+
+/// Declare an audio object struct along with its [`HasProperties`] and [`AudioObject`] implementations and a constructor.
+///
+/// The struct must start with a `base` field (usually an [`AudioObjectBase`]), followed by fields marked either `#[prop]`
+/// for properties of the object or `#[child]` for subobjects, which must implement [`AudioObject`] themselves:
+/// ```no_run
+/// # use cahal::{audio_object::{AudioObjectBase, AudioStreamObject}, property::Prop};
+/// # use cahal::base::{kAudioDevicePropertyNominalSampleRate, AudioObjectID};
+/// cahal::audio_object! {
+///     pub struct MyDevice {
+///         pub base: AudioObjectBase,
+///         #[prop] pub rate: Prop<f64, kAudioDevicePropertyNominalSampleRate, true>,
+///         #[child] pub stream: AudioStreamObject,
+///     }
+/// }
+/// # fn build(id: AudioObjectID, base: AudioObjectBase, stream: AudioStreamObject) -> MyDevice {
+/// let device = MyDevice::new(id, base, Prop(48000.0), stream);
+/// # device }
+/// ```
+/// This expands to a struct with an additional private `id` field, deriving [`HasProperties`] (so the same
+/// attributes and duplicate selector checks apply, with the `#[prop]` fields looked up before `base`), an [`AudioObject`]
+/// implementation visiting the `#[child]` fields in order, and a `new` function taking the id, the base and every other
+/// field in declaration order.
+///
+/// Declaring a selector twice among the `#[prop]` fields doesn't compile:
+/// ```compile_fail
+/// # use cahal::{audio_object::AudioObjectBase, property::Prop};
+/// # use cahal::base::kAudioDevicePropertyLatency;
+/// cahal::audio_object! {
+///     pub struct Twice {
+///         pub base: AudioObjectBase,
+///         #[prop] pub latency: Prop<u32, kAudioDevicePropertyLatency>,
+///         #[prop] pub safety_offset: Prop<u32, kAudioDevicePropertyLatency>,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! audio_object {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $base_vis:vis base: $base_ty:ty
+            $(, $($fields:tt)*)?
+        }
+    ) => {
+        $crate::audio_object!(@munch
+            {$(#[$meta])* $vis $name $base_vis $base_ty}
+            [] [] []
+            $($($fields)*)?
+        );
+    };
+    (@munch $header:tt [$($decl:tt)*] [$($child:ident)*] [$($arg:ident : $aty:ty,)*]
+        #[prop] $fvis:vis $field:ident : $fty:ty $(, $($rest:tt)*)?
+    ) => {
+        $crate::audio_object!(@munch $header
+            [$($decl)* $fvis $field: $fty,]
+            [$($child)*]
+            [$($arg: $aty,)* $field: $fty,]
+            $($($rest)*)?
+        );
+    };
+    (@munch $header:tt [$($decl:tt)*] [$($child:ident)*] [$($arg:ident : $aty:ty,)*]
+        #[child] $fvis:vis $field:ident : $fty:ty $(, $($rest:tt)*)?
+    ) => {
+        $crate::audio_object!(@munch $header
+            [$($decl)* #[property(skip)] $fvis $field: $fty,]
+            [$($child)* $field]
+            [$($arg: $aty,)* $field: $fty,]
+            $($($rest)*)?
+        );
+    };
+    (@munch {$(#[$meta:meta])* $vis:vis $name:ident $base_vis:vis $base_ty:ty}
+        [$($decl:tt)*] [$($child:ident)*] [$($arg:ident : $aty:ty,)*]
+    ) => {
+        $(#[$meta])*
+        #[derive($crate::audio_object::HasProperties)]
+        $vis struct $name {
+            #[property(skip)]
+            id: $crate::base::AudioObjectID,
+            #[property(flatten)]
+            $base_vis base: $base_ty,
+            $($decl)*
+        }
+
+        impl $name {
+            #[allow(clippy::too_many_arguments)]
+            pub fn new(id: $crate::base::AudioObjectID, base: $base_ty, $($arg: $aty),*) -> Self {
+                Self { id, base, $($arg),* }
+            }
+        }
+
+        impl $crate::audio_object::AudioObject for $name {
+            fn id(&self) -> $crate::base::AudioObjectID {
+                self.id
+            }
+            fn visit_children<'a>(
+                &'a self,
+                visitor: &mut dyn FnMut(&'a dyn $crate::audio_object::AudioObject) -> ::core::ops::ControlFlow<()>,
+            ) -> ::core::ops::ControlFlow<()> {
+                let _ = &visitor;
+                $(visitor(&self.$child)?;)*
+                ::core::ops::ControlFlow::Continue(())
+            }
+            fn visit_children_mut<'a>(
+                &'a mut self,
+                visitor: &mut dyn FnMut(&'a mut dyn $crate::audio_object::AudioObject) -> ::core::ops::ControlFlow<()>,
+            ) -> ::core::ops::ControlFlow<()> {
+                let _ = &visitor;
+                $(visitor(&mut self.$child)?;)*
+                ::core::ops::ControlFlow::Continue(())
+            }
+        }
+    };
+}
+
+/// Derive [`HasProperties`] from the property fields of a struct, see the macro documentation for the supported attributes
+///
+/// ```no_run
+/// # use cahal::{audio_object::HasProperties, property::Prop};
+/// # use cahal::base::{kAudioDevicePropertyLatency, kAudioDevicePropertySafetyOffset};
+/// #[derive(HasProperties)]
+/// struct Timing {
+///     latency: Prop<u32, kAudioDevicePropertyLatency>,
+///     safety_offset: Prop<u32, kAudioDevicePropertySafetyOffset>,
+/// }
+/// ```
+/// Using a selector twice doesn't compile:
+/// ```compile_fail
+/// # use cahal::{audio_object::HasProperties, property::Prop};
+/// # use cahal::base::kAudioDevicePropertyLatency;
+/// #[derive(HasProperties)]
+/// struct Timing {
+///     latency: Prop<u32, kAudioDevicePropertyLatency>,
+///     safety_offset: Prop<u32, kAudioDevicePropertyLatency>,
+/// }
+/// ```
+pub use cahal_derive::HasProperties;
+
 pub trait HasProperties {
-    fn get_object_property(&self, sel: PropertySelector) -> Option<&dyn RawProperty>;
-    fn get_object_property_mut(&mut self, sel: PropertySelector) -> Option<&mut dyn RawProperty>;
+    /// Look up the property at `address` on this object only. Implementations that store scope or element specific
+    /// properties should treat wildcard scopes and elements as matching any stored entry (see [`PropertyAddress::matches`]).
+    ///
+    /// This may return properties that are currently absent (see [`RawProperty::is_present`]), so that they can be
+    /// filled in from Rust. [`AudioObject::get_property`] filters those out
+    fn get_object_property(&self, address: PropertyAddress) -> Option<&dyn RawProperty>;
+    fn get_object_property_mut(&mut self, address: PropertyAddress)
+        -> Option<&mut dyn RawProperty>;
+    /// Call `visitor` with every property of this object (and only this object), along with the address it lives at.
+    /// Properties that don't depend on the scope or element should be reported with [`PropertyAddress::anywhere`], which
+    /// [`visit_property`] takes care of (along with properties that keep an instance per scope).
+    ///
+    /// This is what wildcard selectors are resolved with, so implementations that don't override it only answer queries
+    /// for concrete selectors
+    fn visit_properties(&self, visitor: &mut dyn FnMut(PropertyAddress, &dyn RawProperty)) {
+        let _ = visitor;
+    }
+    /// The addresses of all properties of this object, as reported by [`HasProperties::visit_properties`]. Each of them
+    /// can be looked up with [`HasProperties::get_object_property`]
+    fn property_selectors(&self) -> Vec<PropertyAddress> {
+        let mut addresses = Vec::new();
+        self.visit_properties(&mut |address, _| addresses.push(address));
+        addresses
+    }
+}
+
+/// Report `prop` to `visitor` the way [`HasProperties::visit_properties`] expects: each of its instances at its own
+/// address if it keeps several (see [`RawProperty::visit_instances`]), the property itself at
+/// [`PropertyAddress::anywhere`] otherwise
+pub fn visit_property(
+    prop: &dyn RawProperty,
+    visitor: &mut dyn FnMut(PropertyAddress, &dyn RawProperty),
+) {
+    if !prop.visit_instances(visitor) {
+        visitor(PropertyAddress::anywhere(prop.selector()), prop);
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, HasProperties)]
 pub struct AudioObjectBase {
     pub base_class: Prop<AudioClassID, kAudioObjectPropertyBaseClass>,
     pub class: Prop<AudioClassID, kAudioObjectPropertyClass>,
     pub owner: Prop<AudioObjectID, kAudioObjectPropertyOwner>,
-    pub owned_objects: ArrayProp<AudioObjectID, kAudioObjectPropertyOwnedObjects>,
-    pub name: Prop<CFString, kAudioObjectPropertyName>,
-}
-#[allow(non_upper_case_globals)]
-impl HasProperties for AudioObjectBase {
-    fn get_object_property(&self, sel: PropertySelector) -> Option<&dyn RawProperty> {
-        Some(match sel.into() {
-            kAudioObjectPropertyBaseClass => &self.base_class,
-            kAudioObjectPropertyClass => &self.class,
-            kAudioObjectPropertyName => &self.name,
-            kAudioObjectPropertyOwnedObjects => &self.owned_objects,
-            kAudioObjectPropertyOwner => &self.owner,
-            _ => return None,
-        })
-    }
-
-    fn get_object_property_mut(&mut self, sel: PropertySelector) -> Option<&mut dyn RawProperty> {
-        Some(match sel.into() {
-            kAudioObjectPropertyBaseClass => &mut self.base_class,
-            kAudioObjectPropertyClass => &mut self.class,
-            kAudioObjectPropertyName => &mut self.name,
-            kAudioObjectPropertyOwnedObjects => &mut self.owned_objects,
-            kAudioObjectPropertyOwner => &mut self.owner,
-            _ => return None,
-        })
-    }
+    pub owned_objects: OwnedObjectsProp,
+    pub name: CFStringProp<kAudioObjectPropertyName>,
 }
 impl AudioObjectBase {
+    /// The base properties of an object without subobjects. `name` can be a `&'static str`, which is wrapped without
+    /// copying, or an owned `String` built at runtime
     pub fn new(
         base_class: AudioClassID,
         class: AudioClassID,
         owner: AudioObjectID,
-        name: &'static str,
+        name: impl Into<Cow<'static, str>>,
     ) -> Self {
+        let name = match name.into() {
+            Cow::Borrowed(name) => CFStringProp::from_static(name),
+            Cow::Owned(name) => CFStringProp::new(CFString::new(&name)),
+        };
         Self {
             base_class: Prop(base_class),
             class: Prop(class),
             owner: Prop(owner),
-            owned_objects: ArrayProp::new(),
-            name: Prop(CFString::from_static_string(name)),
+            owned_objects: OwnedObjectsProp::new(),
+            name,
+        }
+    }
+}
+
+impl From<AudioObjectBase> for PropertyTable {
+    fn from(base: AudioObjectBase) -> Self {
+        PropertyTable::new()
+            .with(base.base_class)
+            .with(base.class)
+            .with(base.owner)
+            .with(base.owned_objects)
+            .with(base.name)
+    }
+}
+
+/// The objects published by a plugin, indexed by their [`AudioObjectID`]. This is the entry point for answering property
+/// calls from the HAL: the object is resolved from the id the HAL passes in first, then the property is looked up on that
+/// object only, without searching its subobjects. Subobjects that should be reachable by id have to be registered
+/// separately.
+///
+/// Devices, boxes and clock devices owned by the plug-in object are published through a [`PluginObject`] attached to
+/// the registry (see [`PluginObject::attach`]) as they are registered and unregistered
+#[derive(Default)]
+pub struct ObjectRegistry {
+    objects: HashMap<AudioObjectID, Box<dyn AudioObject + Send>>,
+    ids: ObjectIdAllocator,
+    changes: ChangeQueue,
+    published: SharedPublished,
+}
+
+impl ObjectRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A registry that assigns ids from `ids` in [`ObjectRegistry::register_new`]
+    pub fn with_allocator(ids: ObjectIdAllocator) -> Self {
+        Self {
+            ids,
+            ..Self::default()
+        }
+    }
+    /// Record the changes of owned object lists made by [`ObjectRegistry::add_child`] and
+    /// [`ObjectRegistry::remove_child`], of the object lists of the plug-in object and of properties written through
+    /// [`ObjectRegistry::set_property`] in `changes`, instead of the queue the registry starts out with
+    pub fn notify_via(&mut self, changes: ChangeQueue) {
+        self.changes = changes;
+    }
+    /// The queue the changes to the objects are recorded in. The driver flushes it after the calls of the HAL that
+    /// change properties, changes made elsewhere have to be flushed by the driver itself
+    pub fn changes(&self) -> &ChangeQueue {
+        &self.changes
+    }
+    pub fn allocator(&self) -> &ObjectIdAllocator {
+        &self.ids
+    }
+    /// Use this to reserve the ids of objects restored from persisted state before registering new ones
+    pub fn allocator_mut(&mut self) -> &mut ObjectIdAllocator {
+        &mut self.ids
+    }
+    /// Allocate a fresh id, build the object for it with `make` and register it, returning the id
+    pub fn register_new(
+        &mut self,
+        make: impl FnOnce(AudioObjectID) -> Box<dyn AudioObject + Send>,
+    ) -> OSResult<AudioObjectID> {
+        let id = self.ids.allocate()?;
+        let object = make(id);
+        debug_assert_eq!(object.id(), id, "object registered under a different id");
+        self.register(object)?;
+        Ok(id)
+    }
+    /// Add `object` under its [`AudioObject::id`]. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if another
+    /// object is already registered with that id
+    pub fn register(&mut self, object: Box<dyn AudioObject + Send>) -> OSStatus {
+        let id = object.id();
+        if self.objects.contains_key(&id) {
+            log::error!("an object with id {id} is already registered");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        self.ids.mark_used(id);
+        self.objects.insert(id, object);
+        self.publish(id);
+        Ok(())
+    }
+    /// Remove the object with the given id, returning it if it was registered. The id stays used, so it is never handed
+    /// out again by [`ObjectRegistry::register_new`]
+    pub fn unregister(&mut self, id: AudioObjectID) -> Option<Box<dyn AudioObject + Send>> {
+        let object = self.objects.remove(&id)?;
+        self.unpublish(id);
+        Some(object)
+    }
+    /// Register `child` as a subobject of the registered object `parent`, in one step setting the `owner` of the child,
+    /// adding it to the `owned_objects` of the parent and registering it. Returns the id of the child.
+    ///
+    /// Fails with [`OSStatusError::HW_BAD_OBJECT_ERR`] if `parent` isn't registered, with
+    /// [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if the id of the child is taken and with
+    /// [`OSStatusError::HW_UNSUPPORTED_OP`] if either object lacks the properties involved. Nothing changes on failure
+    pub fn add_child(
+        &mut self,
+        parent: AudioObjectID,
+        mut child: Box<dyn AudioObject + Send>,
+    ) -> OSResult<AudioObjectID> {
+        let id = child.id();
+        if self.objects.contains_key(&id) {
+            log::error!("an object with id {id} is already registered");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        let (class, base_class) = classes_of(child.as_ref());
+        let owned = self
+            .objects
+            .get_mut(&parent)
+            .ok_or(OSStatusError::HW_BAD_OBJECT_ERR)?
+            .get_object_property_mut(PropertyAddress::global(kAudioObjectPropertyOwnedObjects))
+            .and_then(|prop| prop.value_mut::<OwnedObjectsProp>())
+            .ok_or(OSStatusError::HW_UNSUPPORTED_OP)?;
+        let owner = child
+            .get_object_property_mut(PropertyAddress::global(kAudioObjectPropertyOwner))
+            .and_then(|prop| prop.value_mut::<AudioObjectID>())
+            .ok_or(OSStatusError::HW_UNSUPPORTED_OP)?;
+        *owner = parent;
+        owned.insert(id, class, base_class);
+        self.ids.mark_used(id);
+        self.objects.insert(id, child);
+        self.mark_owned_objects_changed(parent);
+        self.publish(id);
+        Ok(id)
+    }
+    /// Unregister the object with the given id and remove it from the `owned_objects` of its owner, if the owner is
+    /// registered. The change of the owner's list is recorded in the [`ChangeQueue`] of the registry, if there is one.
+    /// Fails with [`OSStatusError::HW_BAD_OBJECT_ERR`] if there is no such object
+    pub fn remove_child(&mut self, id: AudioObjectID) -> OSResult<Box<dyn AudioObject + Send>> {
+        let child = self
+            .objects
+            .remove(&id)
+            .ok_or(OSStatusError::HW_BAD_OBJECT_ERR)?;
+        let owner = child
+            .get_object_property(PropertyAddress::global(kAudioObjectPropertyOwner))
+            .and_then(|prop| prop.value::<AudioObjectID>())
+            .copied();
+        let owned = owner
+            .and_then(|owner| self.objects.get_mut(&owner))
+            .and_then(|parent| {
+                parent.get_object_property_mut(PropertyAddress::global(
+                    kAudioObjectPropertyOwnedObjects,
+                ))
+            })
+            .and_then(|prop| prop.value_mut::<OwnedObjectsProp>());
+        if let (Some(owner), Some(owned)) = (owner, owned) {
+            owned.remove(id);
+            self.mark_owned_objects_changed(owner);
+        }
+        self.unpublish(id);
+        Ok(child)
+    }
+    pub(crate) fn published(&self) -> &SharedPublished {
+        &self.published
+    }
+    /// Publish the registered object `id` through the plug-in object if it is a device, box or clock device owned by it
+    fn publish(&mut self, id: AudioObjectID) {
+        let Some(object) = self.objects.get(&id) else {
+            return;
+        };
+        let global_u32 = |selector| {
+            object
+                .get_object_property(PropertyAddress::global(selector))
+                .and_then(|prop| prop.value::<u32>())
+                .copied()
+        };
+        if global_u32(kAudioObjectPropertyOwner) != Some(kAudioObjectPlugInObject) {
+            return;
+        }
+        let Some(kind) = global_u32(kAudioObjectPropertyClass).and_then(PublishedKind::of_class)
+        else {
+            return;
+        };
+        let Some(uid) = object
+            .get_object_property(PropertyAddress::global(kind.uid_selector()))
+            .and_then(|prop| prop.value::<CFString>())
+            .map(|uid| uid.to_string())
+        else {
+            log::warn!("not publishing object {id}, it has no UID");
+            return;
+        };
+        let (class, base_class) = classes_of(object.as_ref());
+        self.published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(kind, id, &uid);
+        if let Some(owned) = self.owned_objects_mut(kAudioObjectPlugInObject) {
+            owned.insert(id, class, base_class);
+        }
+        self.mark_published_changed(kind);
+    }
+    /// Remove `id` from the objects published through the plug-in object, if it is one of them
+    fn unpublish(&mut self, id: AudioObjectID) {
+        let Some(kind) = self
+            .published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(id)
+        else {
+            return;
+        };
+        if let Some(owned) = self.owned_objects_mut(kAudioObjectPlugInObject) {
+            owned.remove(id);
+        }
+        self.mark_published_changed(kind);
+    }
+    fn owned_objects_mut(&mut self, id: AudioObjectID) -> Option<&mut OwnedObjectsProp> {
+        self.objects
+            .get_mut(&id)?
+            .get_object_property_mut(PropertyAddress::global(kAudioObjectPropertyOwnedObjects))?
+            .value_mut::<OwnedObjectsProp>()
+    }
+    fn mark_published_changed(&self, kind: PublishedKind) {
+        self.changes.mark(
+            kAudioObjectPlugInObject,
+            PropertyAddress::global(kind.list_selector()),
+        );
+        self.mark_owned_objects_changed(kAudioObjectPlugInObject);
+    }
+    fn mark_owned_objects_changed(&self, parent: AudioObjectID) {
+        self.changes.mark(
+            parent,
+            PropertyAddress::global(kAudioObjectPropertyOwnedObjects),
+        );
+    }
+    pub fn contains(&self, id: AudioObjectID) -> bool {
+        self.objects.contains_key(&id)
+    }
+    /// The object with the given id, or [`OSStatusError::HW_BAD_OBJECT_ERR`] if there is none
+    pub fn get(&self, id: AudioObjectID) -> OSResult<&(dyn AudioObject + Send)> {
+        self.objects
+            .get(&id)
+            .map(|obj| obj.as_ref())
+            .ok_or(OSStatusError::HW_BAD_OBJECT_ERR)
+    }
+    /// Mutable variant of [`ObjectRegistry::get`]
+    pub fn get_mut(&mut self, id: AudioObjectID) -> OSResult<&mut (dyn AudioObject + Send)> {
+        match self.objects.get_mut(&id) {
+            Some(obj) => Ok(obj.as_mut()),
+            None => Err(OSStatusError::HW_BAD_OBJECT_ERR),
+        }
+    }
+    /// The present property at `address` on the object with the given id. Fails with
+    /// [`OSStatusError::HW_BAD_OBJECT_ERR`] for unknown objects and [`OSStatusError::HW_UNKNOWN_PROP_ERR`] if the object
+    /// doesn't have the property
+    pub fn property(
+        &self,
+        id: AudioObjectID,
+        address: PropertyAddress,
+    ) -> OSResult<&dyn RawProperty> {
+        self.get(id)?
+            .get_object_property(address)
+            .filter(|prop| prop.is_present())
+            .ok_or(OSStatusError::HW_UNKNOWN_PROP_ERR)
+    }
+    /// Mutable variant of [`ObjectRegistry::property`]
+    pub fn property_mut(
+        &mut self,
+        id: AudioObjectID,
+        address: PropertyAddress,
+    ) -> OSResult<&mut dyn RawProperty> {
+        self.get_mut(id)?
+            .get_object_property_mut(address)
+            .filter(|prop| prop.is_present())
+            .ok_or(OSStatusError::HW_UNKNOWN_PROP_ERR)
+    }
+    /// Record that the property at `address` on the object `id` changed in the [`ChangeQueue`] of the registry
+    pub fn mark_changed(&self, id: AudioObjectID, address: PropertyAddress) {
+        self.changes.mark(id, address);
+    }
+    /// Write the property at `address` on the object `id` on behalf of the client process `client_pid`, the way the HAL
+    /// does through `SetPropertyData`. Fails like [`ObjectRegistry::property`], and with
+    /// [`OSStatusError::HW_UNSUPPORTED_OP`] if the property isn't settable. A successful write records the property and
+    /// its [`RawProperty::dependents`] in the [`ChangeQueue`] of the registry, for the driver to flush once the call
+    /// returned. Writes of [deferred](RawProperty::is_deferred) properties aren't recorded
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    pub unsafe fn set_property(
+        &mut self,
+        id: AudioObjectID,
+        client_pid: pid_t,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let prop = self.property_mut(id, address)?;
+        if !prop.is_mut() {
+            log::error!(
+                "property {} of object {id} is not settable",
+                address.selector
+            );
+            return Err(OSStatusError::HW_UNSUPPORTED_OP);
+        }
+        unsafe { prop.set_by_client(client_pid, address, qualifier, data, data_size)? };
+        if prop.is_deferred() {
+            return Ok(());
+        }
+        let dependents = prop.dependents();
+        self.changes.mark(id, address);
+        for &selector in dependents {
+            self.changes.mark(
+                id,
+                PropertyAddress {
+                    selector: selector.into(),
+                    ..address
+                },
+            );
+        }
+        Ok(())
+    }
+    pub fn ids(&self) -> impl Iterator<Item = AudioObjectID> + '_ {
+        self.objects.keys().copied()
+    }
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+/// The class and base class `object` reports, `kAudioObjectClassID` for the ones it lacks
+fn classes_of(object: &dyn AudioObject) -> (AudioClassID, AudioClassID) {
+    let class_id = |selector| {
+        object
+            .get_object_property(PropertyAddress::global(selector))
+            .and_then(|prop| prop.value::<AudioClassID>())
+            .copied()
+            .unwrap_or(kAudioObjectClassID)
+    };
+    (
+        class_id(kAudioObjectPropertyClass),
+        class_id(kAudioObjectPropertyBaseClass),
+    )
+}
+
+impl std::fmt::Debug for ObjectRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.objects.keys()).finish()
+    }
+}
+
+/// Hands out unique [`AudioObjectID`]s for the objects of a plugin. Ids increase monotonically from a configurable base
+/// and are never handed out twice, even after the object they belonged to went away. Ids of objects restored from
+/// persisted state can be reserved up front so that they aren't allocated to anything else
+#[derive(Debug, Clone)]
+pub struct ObjectIdAllocator {
+    /// `None` once every id up to `AudioObjectID::MAX` has been handed out
+    next: Option<AudioObjectID>,
+    used: HashSet<AudioObjectID>,
+}
+
+impl ObjectIdAllocator {
+    /// The first id handed out by default, right after the plugin object
+    pub const DEFAULT_BASE: AudioObjectID = kAudioObjectPlugInObject + 1;
+
+    /// An allocator starting at `base`. `kAudioObjectUnknown` is never handed out
+    pub fn new(base: AudioObjectID) -> Self {
+        Self {
+            next: Some(base.max(kAudioObjectUnknown + 1)),
+            used: HashSet::new(),
+        }
+    }
+    /// A fresh id that hasn't been allocated or reserved before. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`]
+    /// once the id space is exhausted
+    pub fn allocate(&mut self) -> OSResult<AudioObjectID> {
+        while let Some(id) = self.next {
+            self.next = id.checked_add(1);
+            if self.used.insert(id) {
+                return Ok(id);
+            }
+        }
+        log::error!("ran out of object ids");
+        Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR)
+    }
+    /// Reserve `id` for an object whose id is fixed, e.g. because it was restored from persisted state. Fails with
+    /// [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if the id was already allocated or reserved, or is
+    /// `kAudioObjectUnknown`
+    pub fn reserve(&mut self, id: AudioObjectID) -> OSStatus {
+        if id == kAudioObjectUnknown || !self.used.insert(id) {
+            log::error!("object id {id} is already in use");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        Ok(())
+    }
+    /// Whether `id` was allocated or reserved
+    pub fn is_used(&self, id: AudioObjectID) -> bool {
+        self.used.contains(&id)
+    }
+    fn mark_used(&mut self, id: AudioObjectID) {
+        self.used.insert(id);
+    }
+}
+
+impl Default for ObjectIdAllocator {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BASE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioBooleanControlClassID, kAudioControlClassID, kAudioDeviceClassID,
+        kAudioDevicePropertyLatency, kAudioHardwareBadObjectError,
+        kAudioHardwareIllegalOperationError, kAudioStreamClassID, kAudioVolumeControlClassID,
+    };
+
+    use super::*;
+    use crate::{
+        os_err::result_to_err_code,
+        property::{PropertyElement, PropertyScope, RawPropertyExt},
+    };
+
+    #[derive(HasProperties)]
+    struct Derived {
+        latency: Prop<u32, kAudioDevicePropertyLatency>,
+        // shadows the class of `base`
+        class: Prop<AudioClassID, kAudioObjectPropertyClass>,
+        #[property(skip)]
+        _skipped: Prop<u32, kAudioObjectPropertyOwner>,
+        #[property(flatten)]
+        base: AudioObjectBase,
+    }
+
+    fn derived() -> Derived {
+        Derived {
+            latency: Prop(64),
+            class: Prop(0x73686477),
+            _skipped: Prop(99),
+            base: AudioObjectBase::new(kAudioObjectClassID, kAudioDeviceClassID, 1, "Device"),
+        }
+    }
+
+    fn value<T: Copy + 'static>(object: &dyn HasProperties, selector: u32) -> Option<T> {
+        let prop = object.get_object_property(PropertyAddress::anywhere(selector.into()))?;
+        Some(*prop.value::<T>().unwrap())
+    }
+
+    #[test]
+    fn derived_lookup_prefers_fields_over_flattened_ones() {
+        let object = derived();
+        assert_eq!(value::<u32>(&object, kAudioDevicePropertyLatency), Some(64));
+        assert_eq!(
+            value::<AudioClassID>(&object, kAudioObjectPropertyClass),
+            Some(0x73686477)
+        );
+        assert_eq!(
+            value::<AudioClassID>(&object, kAudioObjectPropertyBaseClass),
+            Some(kAudioObjectClassID)
+        );
+        // the skipped field is not visible, the base answers instead
+        assert_eq!(
+            value::<AudioObjectID>(&object, kAudioObjectPropertyOwner),
+            Some(1)
+        );
+        assert!(object
+            .get_object_property(PropertyAddress::anywhere(PropertySelector::from_fourcc(
+                b"miss"
+            )))
+            .is_none());
+    }
+
+    #[test]
+    fn derived_lookup_mut_reaches_the_same_fields() {
+        let mut object = derived();
+        let address = PropertyAddress::anywhere(kAudioObjectPropertyOwner.into());
+        *object
+            .get_object_property_mut(address)
+            .unwrap()
+            .value_mut::<AudioObjectID>()
+            .unwrap() = 7;
+        assert_eq!(object.base.owner.0, 7);
+        assert_eq!(object._skipped.0, 99);
+    }
+
+    crate::audio_object! {
+        #[derive(Debug)]
+        struct Leaf {
+            base: AudioObjectBase,
+        }
+    }
+
+    crate::audio_object! {
+        #[derive(Debug)]
+        struct Declared {
+            pub base: AudioObjectBase,
+            #[prop] latency: Prop<u32, kAudioDevicePropertyLatency>,
+            #[child] first: Leaf,
+            #[prop] pub class: Prop<AudioClassID, kAudioObjectPropertyClass>,
+            #[child] second: Leaf,
         }
     }
+
+    fn declared() -> Declared {
+        let base = |name| AudioObjectBase::new(kAudioObjectClassID, kAudioDeviceClassID, 1, name);
+        Declared::new(
+            2,
+            base("Device"),
+            Prop(64),
+            Leaf::new(3, base("First")),
+            Prop(0x73686477),
+            Leaf::new(4, base("Second")),
+        )
+    }
+
+    #[test]
+    fn declared_objects_take_their_fields_in_order() {
+        let object = declared();
+        assert_eq!(object.id(), 2);
+        assert_eq!(object.latency.0, 64);
+        assert_eq!(object.class.0, 0x73686477);
+        assert_eq!((object.first.id(), object.second.id()), (3, 4));
+    }
+
+    #[test]
+    fn declared_properties_come_before_the_base() {
+        let object = declared();
+        assert_eq!(value::<u32>(&object, kAudioDevicePropertyLatency), Some(64));
+        assert_eq!(
+            value::<AudioClassID>(&object, kAudioObjectPropertyClass),
+            Some(0x73686477)
+        );
+        assert_eq!(
+            value::<AudioClassID>(&object, kAudioObjectPropertyBaseClass),
+            Some(kAudioObjectClassID)
+        );
+        // children aren't properties of the object
+        let selectors: Vec<_> = object
+            .property_selectors()
+            .into_iter()
+            .map(|a| u32::from(a.selector))
+            .collect();
+        assert_eq!(
+            selectors,
+            [
+                kAudioDevicePropertyLatency,
+                kAudioObjectPropertyClass,
+                kAudioObjectPropertyBaseClass,
+                kAudioObjectPropertyClass,
+                kAudioObjectPropertyOwner,
+                kAudioObjectPropertyOwnedObjects,
+                kAudioObjectPropertyName,
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_children_are_visited_in_order() {
+        let mut object = declared();
+        let mut ids = Vec::new();
+        let _ = object.visit_children(&mut |child| {
+            ids.push(child.id());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(ids, [3, 4]);
+
+        let mut ids = Vec::new();
+        let _ = object.visit_children_mut(&mut |child| {
+            ids.push(child.id());
+            ControlFlow::Break(())
+        });
+        assert_eq!(ids, [3]);
+
+        let mut leaves = 0;
+        let _ = object.first.visit_children(&mut |_| {
+            leaves += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(leaves, 0);
+    }
+
+    fn leaf(id: AudioObjectID, class: AudioClassID, owner: AudioObjectID) -> Box<Leaf> {
+        let base = AudioObjectBase::new(kAudioObjectClassID, class, owner, "");
+        Box::new(Leaf::new(id, base))
+    }
+
+    fn owner_of(objects: &ObjectRegistry, id: AudioObjectID) -> Option<AudioObjectID> {
+        value::<AudioObjectID>(objects.get(id).ok()?, kAudioObjectPropertyOwner)
+    }
+
+    fn owned_by(objects: &ObjectRegistry, id: AudioObjectID) -> Vec<AudioObjectID> {
+        objects
+            .property(
+                id,
+                PropertyAddress::global(kAudioObjectPropertyOwnedObjects),
+            )
+            .unwrap()
+            .value::<OwnedObjectsProp>()
+            .unwrap()
+            .ids()
+            .to_vec()
+    }
+
+    fn code<T>(result: OSResult<T>) -> i32 {
+        result_to_err_code(result.map(drop))
+    }
+
+    fn registry_with_parent() -> (ObjectRegistry, ChangeQueue) {
+        let mut objects = ObjectRegistry::new();
+        let changes = ChangeQueue::new();
+        objects.notify_via(changes.clone());
+        objects
+            .register(leaf(2, kAudioDeviceClassID, kAudioObjectPlugInObject))
+            .unwrap();
+        (objects, changes)
+    }
+
+    #[test]
+    fn added_children_are_owned_listed_and_registered() {
+        let (mut objects, changes) = registry_with_parent();
+        // the owner the child was built with is replaced by the parent
+        assert_eq!(
+            objects.add_child(2, leaf(3, kAudioStreamClassID, 99)).ok(),
+            Some(3)
+        );
+        assert_eq!(
+            objects
+                .add_child(2, leaf(4, kAudioVolumeControlClassID, 2))
+                .ok(),
+            Some(4)
+        );
+        assert_eq!(owner_of(&objects, 3), Some(2));
+        assert_eq!(owner_of(&objects, 4), Some(2));
+        assert_eq!(owned_by(&objects, 2), [3, 4]);
+        assert!(objects.contains(3) && objects.contains(4));
+        assert!(objects.allocator().is_used(3));
+
+        let owned = objects
+            .property(2, PropertyAddress::global(kAudioObjectPropertyOwnedObjects))
+            .unwrap()
+            .value::<OwnedObjectsProp>()
+            .unwrap();
+        assert_eq!(
+            owned.classes_of(3),
+            Some((kAudioStreamClassID, kAudioObjectClassID))
+        );
+        assert_eq!(owned.matching(&[kAudioStreamClassID]), [3]);
+        assert_eq!(owned.matching(&[kAudioControlClassID]), [4]);
+
+        let changes: Vec<_> = changes.take().iter().copied().collect();
+        assert_eq!(
+            changes,
+            [(2, PropertyAddress::global(kAudioObjectPropertyOwnedObjects))]
+        );
+    }
+
+    #[test]
+    fn failed_adds_change_nothing() {
+        let (mut objects, changes) = registry_with_parent();
+        objects
+            .add_child(2, leaf(3, kAudioStreamClassID, 2))
+            .unwrap();
+        let _ = changes.take();
+
+        // the id is taken, by the child or by the parent itself
+        assert_eq!(
+            code(objects.add_child(2, leaf(3, kAudioStreamClassID, 2))),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(
+            code(objects.add_child(3, leaf(2, kAudioStreamClassID, 3))),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        // there is no such parent
+        assert_eq!(
+            code(objects.add_child(7, leaf(5, kAudioStreamClassID, 7))),
+            kAudioHardwareBadObjectError as i32
+        );
+        assert!(!objects.contains(5));
+        assert_eq!(owned_by(&objects, 2), [3]);
+        assert!(owned_by(&objects, 3).is_empty());
+        assert_eq!(owner_of(&objects, 3), Some(2));
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn removed_children_are_unlisted_and_unregistered() {
+        let (mut objects, changes) = registry_with_parent();
+        for id in [3, 4] {
+            objects
+                .add_child(2, leaf(id, kAudioStreamClassID, 2))
+                .unwrap();
+        }
+        let _ = changes.take();
+
+        let child = objects.remove_child(3).unwrap();
+        assert_eq!(child.id(), 3);
+        assert!(!objects.contains(3));
+        assert_eq!(owned_by(&objects, 2), [4]);
+        // the id isn't handed out again
+        assert!(objects.allocator().is_used(3));
+        let changes_after: Vec<_> = changes.take().iter().copied().collect();
+        assert_eq!(
+            changes_after,
+            [(2, PropertyAddress::global(kAudioObjectPropertyOwnedObjects))]
+        );
+
+        assert_eq!(
+            code(objects.remove_child(3)),
+            kAudioHardwareBadObjectError as i32
+        );
+        assert_eq!(owned_by(&objects, 2), [4]);
+        assert!(changes.is_empty());
+
+        // a removed child can be added again, under a different parent
+        objects.add_child(4, child).unwrap();
+        assert_eq!(owner_of(&objects, 3), Some(4));
+        assert_eq!(owned_by(&objects, 4), [3]);
+        assert_eq!(owned_by(&objects, 2), [4]);
+    }
+
+    #[test]
+    fn removing_a_child_of_an_unregistered_owner_still_unregisters_it() {
+        let (mut objects, changes) = registry_with_parent();
+        objects
+            .add_child(2, leaf(3, kAudioStreamClassID, 2))
+            .unwrap();
+        objects.unregister(2).unwrap();
+        let _ = changes.take();
+
+        assert_eq!(
+            objects.remove_child(3).map(|child| child.id()).ok(),
+            Some(3)
+        );
+        assert!(objects.is_empty());
+        assert!(changes.is_empty());
+    }
+
+    /// Check that every address `object` lists is answered by the property it was listed with, through both lookups.
+    /// Where a field shadows a flattened one, the lookup answers with whichever was listed first
+    fn assert_lookups_agree_with_enumeration(object: &mut dyn HasProperties) {
+        let mut listed: Vec<(PropertyAddress, *const ())> = Vec::new();
+        object.visit_properties(&mut |address, prop| {
+            if !listed.iter().any(|&(seen, _)| seen == address) {
+                listed.push((address, (prop as *const dyn RawProperty).cast()));
+            }
+        });
+        assert!(!listed.is_empty());
+        for (address, prop) in listed {
+            let found = object
+                .get_object_property(address)
+                .unwrap_or_else(|| panic!("{address:?} is listed but not found"));
+            assert_eq!(found.selector(), address.selector, "{address:?}");
+            // properties keeping an instance per scope are found as a whole, and answer with the listed instance
+            let mut answers = std::ptr::addr_eq(found, prop);
+            found.visit_instances(&mut |at, instance| {
+                answers |= at == address && std::ptr::addr_eq(instance, prop);
+            });
+            assert!(answers, "{address:?}");
+            let found_mut = object
+                .get_object_property_mut(address)
+                .unwrap_or_else(|| panic!("{address:?} is listed but not found mutably"));
+            let found_mut: *const dyn RawProperty = if std::ptr::addr_eq(found_mut, prop) {
+                found_mut
+            } else {
+                found_mut.instance_at_mut(address).unwrap()
+            };
+            assert!(std::ptr::addr_eq(found_mut, prop), "{address:?}");
+        }
+    }
+
+    #[test]
+    fn lookups_agree_with_enumeration_on_prebuilt_objects() {
+        assert_lookups_agree_with_enumeration(&mut PluginObject::new(
+            "Manufacturer",
+            "com.example.plugin",
+        ));
+        assert_lookups_agree_with_enumeration(&mut AudioBoxObject::new(
+            2,
+            "Box",
+            "com.example.box",
+        ));
+        assert_lookups_agree_with_enumeration(&mut ClockDeviceObject::new(
+            3,
+            "com.example.clock",
+            &[44_100.0, 48_000.0],
+        ));
+        assert_lookups_agree_with_enumeration(&mut BooleanControlObject::new(
+            4,
+            1,
+            kAudioBooleanControlClassID,
+            PropertyScope::INPUT,
+            PropertyElement::MAIN,
+        ));
+        assert_lookups_agree_with_enumeration(&mut DataSourceControlObject::new(
+            5,
+            1,
+            PropertyScope::INPUT,
+            &[(10, "Line"), (11, "Mic")],
+        ));
+
+        let mut objects = ObjectRegistry::new();
+        let device = DeviceBuilder::new("Device", "com.example.device")
+            .input_stream(1)
+            .output_stream(2)
+            .volume_control(PropertyScope::OUTPUT)
+            .mute_control(PropertyScope::OUTPUT)
+            .stereo_pan_control(PropertyScope::OUTPUT, PropertyElement::MAIN, [1, 2])
+            .build(&mut objects)
+            .unwrap();
+        let ids: Vec<_> = [device.id()]
+            .into_iter()
+            .chain(device.streams(StreamDirection::Input))
+            .chain(device.streams(StreamDirection::Output))
+            .chain(device.controls())
+            .collect();
+        assert_eq!(ids.len(), 6);
+        for id in ids {
+            assert_lookups_agree_with_enumeration(objects.get_mut(id).unwrap());
+        }
+    }
+
+    #[test]
+    fn lookups_agree_with_enumeration_on_shadowed_fields() {
+        assert_lookups_agree_with_enumeration(&mut derived());
+        assert_lookups_agree_with_enumeration(&mut declared());
+    }
+
+    #[test]
+    fn derived_visit_lists_fields_then_flattened_ones() {
+        let selectors: Vec<_> = derived()
+            .property_selectors()
+            .into_iter()
+            .map(|a| u32::from(a.selector))
+            .collect();
+        assert_eq!(
+            selectors,
+            [
+                kAudioDevicePropertyLatency,
+                kAudioObjectPropertyClass,
+                kAudioObjectPropertyBaseClass,
+                kAudioObjectPropertyClass,
+                kAudioObjectPropertyOwner,
+                kAudioObjectPropertyOwnedObjects,
+                kAudioObjectPropertyName,
+            ]
+        );
+    }
 }
@@ -0,0 +1,216 @@
+use core_foundation::string::CFString;
+use coreaudio_sys::{
+    kAudioBoxClassID, kAudioBoxPropertyAcquired, kAudioBoxPropertyBoxUID,
+    kAudioBoxPropertyDeviceList, kAudioBoxPropertyHasAudio, kAudioBoxPropertyHasMIDI,
+    kAudioBoxPropertyHasVideo, kAudioBoxPropertyIsProtected, kAudioObjectClassID,
+    kAudioObjectPlugInObject, AudioObjectID,
+};
+
+use crate::{
+    os_err::OSStatus,
+    property::{ArrayProp, BoolProp, CFStringProp, HookedProp},
+};
+
+use super::{AudioObject, AudioObjectBase, HasProperties};
+
+/// Runs `on_change` with the new state when the HAL acquires (`true`) or releases (`false`) the box
+fn acquire_hook(
+    mut on_change: impl FnMut(bool) -> OSStatus + Send + 'static,
+) -> impl FnMut(&u32, &u32) -> OSStatus + Send + 'static {
+    move |&old, &new| {
+        let acquired = new != 0;
+        if (old != 0) == acquired {
+            return Ok(());
+        }
+        on_change(acquired)
+    }
+}
+
+/// An audio box (`kAudioBoxClassID`), representing a piece of hardware that can be acquired and released by the user.
+///
+/// A released box is expected to unpublish its devices and an acquired one to publish them again. The HAL toggles
+/// `kAudioBoxPropertyAcquired`, which runs the hook set with [`AudioBoxObject::on_acquire`] so the driver can do so (and
+/// notify the host). The hook can veto the change by returning an error.
+///
+/// Like devices, the box is owned by the plug-in object. Publish it with
+/// [`PluginObject::add_box`](super::PluginObject::add_box)
+#[derive(Debug, HasProperties)]
+pub struct AudioBoxObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    pub box_uid: CFStringProp<kAudioBoxPropertyBoxUID>,
+    pub has_audio: BoolProp<kAudioBoxPropertyHasAudio>,
+    pub has_video: BoolProp<kAudioBoxPropertyHasVideo>,
+    pub has_midi: BoolProp<kAudioBoxPropertyHasMIDI>,
+    pub is_protected: BoolProp<kAudioBoxPropertyIsProtected>,
+    acquired: HookedProp<u32, kAudioBoxPropertyAcquired>,
+    device_list: ArrayProp<AudioObjectID, kAudioBoxPropertyDeviceList>,
+}
+
+impl AudioBoxObject {
+    /// An acquired box with audio and no devices yet
+    pub fn new(id: AudioObjectID, name: &str, uid: &str) -> Self {
+        Self {
+            id,
+            base: AudioObjectBase::new(
+                kAudioObjectClassID,
+                kAudioBoxClassID,
+                kAudioObjectPlugInObject,
+                name.to_owned(),
+            ),
+            box_uid: CFStringProp::new(CFString::new(uid)),
+            has_audio: BoolProp(true),
+            has_video: BoolProp(false),
+            has_midi: BoolProp(false),
+            is_protected: BoolProp(false),
+            acquired: HookedProp::new(1, acquire_hook(|_| Ok(()))),
+            device_list: ArrayProp::new(),
+        }
+    }
+    /// Run `on_change` whenever the HAL acquires or releases the box. Writes that don't change the state don't run it
+    pub fn on_acquire(mut self, on_change: impl FnMut(bool) -> OSStatus + Send + 'static) -> Self {
+        let acquired = *self.acquired.get();
+        self.acquired = HookedProp::new(acquired, acquire_hook(on_change));
+        self
+    }
+    pub fn is_acquired(&self) -> bool {
+        *self.acquired.get() != 0
+    }
+    /// Change the state from Rust, without running the hook
+    pub fn set_acquired(&mut self, acquired: bool) {
+        self.acquired.set(acquired as u32);
+    }
+    pub fn devices(&self) -> &[AudioObjectID] {
+        &self.device_list
+    }
+    /// List the device with the given id as part of this box
+    pub fn add_device(&mut self, id: AudioObjectID) {
+        if !self.device_list.contains(&id) {
+            self.device_list.push(id);
+        }
+    }
+    /// Remove a device from this box, returning whether it was listed
+    pub fn remove_device(&mut self, id: AudioObjectID) -> bool {
+        let len = self.device_list.len();
+        self.device_list.retain(|&device| device != id);
+        self.device_list.len() != len
+    }
+}
+
+impl AudioObject for AudioBoxObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem,
+        sync::{Arc, Mutex, PoisonError},
+    };
+
+    use coreaudio_sys::kAudioHardwareIllegalOperationError;
+
+    use super::*;
+    use crate::{
+        audio_object::PluginObject,
+        os_err::{result_to_err_code, OSStatusError},
+        property::{PropertyAddress, RawProperty},
+    };
+
+    /// A box recording the states its hook was run with, vetoing releases while `locked` holds
+    fn audio_box(locked: bool) -> (AudioBoxObject, Arc<Mutex<Vec<bool>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let audio_box =
+            AudioBoxObject::new(2, "Box", "com.example.box").on_acquire(move |acquired| {
+                recorded
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(acquired);
+                if locked && !acquired {
+                    return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+                }
+                Ok(())
+            });
+        (audio_box, calls)
+    }
+
+    /// Write `acquired` the way the HAL does, returning the status code
+    fn write(audio_box: &mut AudioBoxObject, acquired: u32) -> i32 {
+        let prop: &mut dyn RawProperty = audio_box
+            .get_object_property_mut(PropertyAddress::global(kAudioBoxPropertyAcquired))
+            .unwrap();
+        let status =
+            unsafe { prop.set((&raw const acquired).cast(), mem::size_of::<u32>() as u32) };
+        result_to_err_code(status)
+    }
+
+    fn calls(calls: &Mutex<Vec<bool>>) -> Vec<bool> {
+        calls.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    #[test]
+    fn boxes_are_released_and_acquired_again() {
+        let (mut audio_box, recorded) = audio_box(false);
+        assert!(audio_box.is_acquired());
+        assert_eq!(write(&mut audio_box, 0), 0);
+        assert!(!audio_box.is_acquired());
+        assert_eq!(write(&mut audio_box, 1), 0);
+        assert!(audio_box.is_acquired());
+        assert_eq!(calls(&recorded), [false, true]);
+    }
+
+    #[test]
+    fn writes_of_the_same_state_dont_run_the_hook() {
+        let (mut audio_box, recorded) = audio_box(false);
+        assert_eq!(write(&mut audio_box, 1), 0);
+        // any nonzero value means acquired
+        assert_eq!(write(&mut audio_box, 7), 0);
+        assert_eq!(write(&mut audio_box, 0), 0);
+        assert_eq!(write(&mut audio_box, 0), 0);
+        assert_eq!(calls(&recorded), [false]);
+    }
+
+    #[test]
+    fn vetoed_releases_keep_the_box_acquired() {
+        let (mut audio_box, recorded) = audio_box(true);
+        assert_eq!(
+            write(&mut audio_box, 0),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert!(audio_box.is_acquired());
+        assert_eq!(calls(&recorded), [false]);
+    }
+
+    #[test]
+    fn changes_from_rust_dont_run_the_hook() {
+        let (mut audio_box, recorded) = audio_box(true);
+        audio_box.set_acquired(false);
+        assert!(!audio_box.is_acquired());
+        assert_eq!(write(&mut audio_box, 1), 0);
+        assert!(audio_box.is_acquired());
+        assert_eq!(calls(&recorded), [true]);
+    }
+
+    #[test]
+    fn boxes_list_their_devices_and_are_listed_by_the_plugin() {
+        let (mut audio_box, _) = audio_box(false);
+        audio_box.add_device(3);
+        audio_box.add_device(4);
+        audio_box.add_device(3);
+        assert_eq!(audio_box.devices(), [3, 4]);
+        assert!(audio_box.remove_device(3));
+        assert!(!audio_box.remove_device(3));
+        assert_eq!(audio_box.devices(), [4]);
+
+        let mut plugin = PluginObject::new("Manufacturer", "com.example.plugin");
+        plugin.add_box(audio_box.id(), "com.example.box");
+        assert_eq!(plugin.boxes(), [2]);
+        assert!(plugin.remove_box(2));
+        assert!(plugin.boxes().is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+use coreaudio_sys::{
+    kAudioMuteControlClassID, kAudioStereoPanControlClassID, kAudioVolumeControlClassID,
+    AudioObjectID,
+};
+
+use crate::{
+    class_hierarchy::validate_classes,
+    notification::ChangeQueue,
+    os_err::{OSResult, OSStatusError},
+    property::{
+        float32_format, AtomicHandle, CFStringProp, EnumProp, Prop, PropertyElement, PropertyScope,
+        RangeListProp,
+    },
+};
+
+use super::{
+    clock_domain, device_uid, model_uid, AudioDeviceObject, AudioObject, AudioStreamObject,
+    MuteControlObject, ObjectRegistry, SampleRateHandle, StereoPanControlObject, StreamDirection,
+    TransportType, VolumeControlObject, VolumeHandle,
+};
+
+/// Decibel range of the volume controls added by [`DeviceBuilder::volume_control`]
+const VOLUME_RANGE_DB: (f32, f32) = (-96.0, 0.0);
+
+/// Assembles a device together with its streams and controls, allocating their ids from an [`ObjectRegistry`] and
+/// wiring up the ownership between them (`owner`, `owned_objects` and the stream lists of the device).
+///
+/// ```ignore
+/// let device = DeviceBuilder::new("My Device", "com.example.device")
+///     .sample_rates(&[44100.0, 48000.0])
+///     .output_stream(2)
+///     .volume_control(PropertyScope::OUTPUT)
+///     .mute_control(PropertyScope::OUTPUT)
+///     .build(&mut registry)?;
+/// ```
+/// The device is published as soon as it is registered if the [`PluginObject`](super::PluginObject) is attached to
+/// `registry` (see [`PluginObject::attach`](super::PluginObject::attach)), otherwise add it to the plug-in object
+/// afterwards
+#[derive(Debug, Clone)]
+pub struct DeviceBuilder {
+    name: String,
+    uid: String,
+    model: Option<(String, String)>,
+    manufacturer: Option<String>,
+    serial_number: Option<String>,
+    firmware_version: Option<String>,
+    clock_domain: u32,
+    transport_type: TransportType,
+    latency: Vec<(PropertyScope, u32, u32)>,
+    buffer_frame_size_range: Option<(u32, u32)>,
+    sample_rates: Vec<f64>,
+    streams: Vec<(StreamDirection, u32)>,
+    volume_controls: Vec<PropertyScope>,
+    mute_controls: Vec<PropertyScope>,
+    pan_controls: Vec<(PropertyScope, PropertyElement, [u32; 2])>,
+    changes: Option<ChangeQueue>,
+}
+
+/// The ids of the objects created by [`DeviceBuilder::build`], along with handles to the control values for the IO path
+/// and to the sample rates of the device
+#[derive(Debug, Clone)]
+pub struct DeviceHandle {
+    device: AudioObjectID,
+    sample_rate: SampleRateHandle,
+    streams: Vec<(AudioObjectID, StreamDirection)>,
+    volume_controls: Vec<(AudioObjectID, PropertyScope, VolumeHandle)>,
+    mute_controls: Vec<(AudioObjectID, PropertyScope, AtomicHandle<bool>)>,
+    pan_controls: Vec<(
+        AudioObjectID,
+        PropertyScope,
+        PropertyElement,
+        AtomicHandle<f32>,
+    )>,
+}
+
+impl DeviceBuilder {
+    /// A device running at 48kHz, without streams or controls
+    pub fn new(name: &str, uid: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            uid: uid.to_owned(),
+            model: None,
+            manufacturer: None,
+            serial_number: None,
+            firmware_version: None,
+            clock_domain: 0,
+            transport_type: TransportType::Virtual,
+            latency: Vec::new(),
+            buffer_frame_size_range: None,
+            sample_rates: vec![48000.0],
+            streams: Vec::new(),
+            volume_controls: Vec::new(),
+            mute_controls: Vec::new(),
+            pan_controls: Vec::new(),
+            changes: None,
+        }
+    }
+    /// A device without a UID of its own, identified by `parts` (e.g. a serial number) within the plug-in with the
+    /// bundle id `namespace`, see [`device_uid`]
+    pub fn with_derived_uid(name: &str, namespace: &str, parts: &[&str]) -> Self {
+        Self::new(name, &device_uid(namespace, parts).to_string())
+    }
+    /// Report a model UID built from `vendor`, `model` and the channel counts of the streams, see [`model_uid`].
+    /// Otherwise the model UID is the device UID
+    pub fn model(mut self, vendor: &str, model: &str) -> Self {
+        self.model = Some((vendor.to_owned(), model.to_owned()));
+        self
+    }
+    /// Report `manufacturer` through `kAudioObjectPropertyManufacturer`
+    pub fn manufacturer(mut self, manufacturer: &str) -> Self {
+        self.manufacturer = Some(manufacturer.to_owned());
+        self
+    }
+    /// Report `serial_number` through `kAudioObjectPropertySerialNumber`
+    pub fn serial_number(mut self, serial_number: &str) -> Self {
+        self.serial_number = Some(serial_number.to_owned());
+        self
+    }
+    /// Report `firmware_version` through `kAudioObjectPropertyFirmwareVersion`
+    pub fn firmware_version(mut self, firmware_version: &str) -> Self {
+        self.firmware_version = Some(firmware_version.to_owned());
+        self
+    }
+    /// Report `transport_type` instead of [`TransportType::Virtual`]
+    pub fn transport_type(mut self, transport_type: TransportType) -> Self {
+        self.transport_type = transport_type;
+        self
+    }
+    /// Report the clock domain shared by all devices of the plug-in with the bundle id `bundle_id` that are built with
+    /// this option, see [`clock_domain`]. Only use it for devices that are actually driven by the same clock
+    pub fn same_clock_domain(mut self, bundle_id: &str) -> Self {
+        self.clock_domain = clock_domain(bundle_id);
+        self
+    }
+    /// Report a latency and a safety offset of the given number of frames in `scope`
+    pub fn latency(mut self, scope: PropertyScope, latency: u32, safety_offset: u32) -> Self {
+        self.latency.push((scope, latency, safety_offset));
+        self
+    }
+    /// Accept IO buffers of `min` to `max` frames, see [`AudioDeviceObject::set_buffer_frame_size_range`]
+    pub fn buffer_frame_size_range(mut self, min: u32, max: u32) -> Self {
+        self.buffer_frame_size_range = Some((min, max));
+        self
+    }
+    /// The supported sample rates. The device starts out at the first one
+    pub fn sample_rates(mut self, rates: &[f64]) -> Self {
+        if !rates.is_empty() {
+            self.sample_rates = rates.to_vec();
+        }
+        self
+    }
+    /// Add an input stream with `channels` channels of 32 bit float at each of the sample rates
+    pub fn input_stream(mut self, channels: u32) -> Self {
+        self.streams.push((StreamDirection::Input, channels));
+        self
+    }
+    /// Add an output stream with `channels` channels of 32 bit float at each of the sample rates
+    pub fn output_stream(mut self, channels: u32) -> Self {
+        self.streams.push((StreamDirection::Output, channels));
+        self
+    }
+    /// Add a volume control on the main element of `scope`
+    pub fn volume_control(mut self, scope: PropertyScope) -> Self {
+        self.volume_controls.push(scope);
+        self
+    }
+    /// Add a mute control on the main element of `scope`
+    pub fn mute_control(mut self, scope: PropertyScope) -> Self {
+        self.mute_controls.push(scope);
+        self
+    }
+    /// Add a stereo pan control on `element` of `scope`, panning between the device channels in `channels`
+    pub fn stereo_pan_control(
+        mut self,
+        scope: PropertyScope,
+        element: PropertyElement,
+        channels: [u32; 2],
+    ) -> Self {
+        self.pan_controls.push((scope, element, channels));
+        self
+    }
+    /// Record changes of control values made by the HAL, and of whether the device is alive or running, in `changes`
+    /// instead of the [queue](ObjectRegistry::changes) of the registry the device is built in
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+    /// Create the device and all of its subobjects and register them with `registry`. Nothing stays registered if this
+    /// fails. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if one of the objects reports a base class that
+    /// doesn't match its class, see [`validate_classes`]
+    pub fn build(self, registry: &mut ObjectRegistry) -> OSResult<DeviceHandle> {
+        let mut created: Vec<Box<dyn AudioObject + Send>> = Vec::new();
+        let device_id = registry.allocator_mut().allocate()?;
+        let mut device = AudioDeviceObject::new(
+            device_id,
+            self.name.clone(),
+            &self.uid,
+            self.sample_rates[0],
+        );
+        device
+            .sample_rate_mut()
+            .set_available(RangeListProp::from_discrete(&self.sample_rates));
+        let changes = self
+            .changes
+            .clone()
+            .unwrap_or_else(|| registry.changes().clone());
+        device = device.notify_via(changes.clone());
+        device.clock_domain = Prop(self.clock_domain);
+        device.transport_type = EnumProp(self.transport_type);
+        if let Some((min, max)) = self.buffer_frame_size_range {
+            device.set_buffer_frame_size_range(min, max);
+        }
+        for &(scope, latency, safety_offset) in &self.latency {
+            device = device
+                .with_latency(scope, latency)
+                .with_safety_offset(scope, safety_offset);
+        }
+        if let Some(manufacturer) = &self.manufacturer {
+            device.set_manufacturer(manufacturer);
+        }
+        if let Some(serial_number) = &self.serial_number {
+            device.set_serial_number(serial_number);
+        }
+        if let Some(firmware_version) = &self.firmware_version {
+            device.set_firmware_version(firmware_version);
+        }
+        if let Some((vendor, model)) = &self.model {
+            let channels: Vec<String> = self
+                .streams
+                .iter()
+                .map(|(direction, channels)| match direction {
+                    StreamDirection::Input => format!("in{channels}"),
+                    StreamDirection::Output => format!("out{channels}"),
+                })
+                .collect();
+            device.model_uid = CFStringProp::new(model_uid(vendor, model, &channels.join("-")));
+        }
+        let mut handle = DeviceHandle {
+            device: device_id,
+            sample_rate: device.sample_rate().handle(),
+            streams: Vec::new(),
+            volume_controls: Vec::new(),
+            mute_controls: Vec::new(),
+            pan_controls: Vec::new(),
+        };
+
+        let mut next_channel = [1, 1];
+        for (direction, channels) in self.streams {
+            let id = registry.allocator_mut().allocate()?;
+            let formats = self
+                .sample_rates
+                .iter()
+                .map(|&rate| float32_format(rate, channels));
+            let mut stream = AudioStreamObject::new(
+                id,
+                device_id,
+                direction,
+                float32_format(self.sample_rates[0], channels),
+            );
+            for format in formats {
+                stream.add_format(format);
+            }
+            let starting_channel = &mut next_channel[direction as usize];
+            stream.starting_channel.0 = *starting_channel;
+            *starting_channel += channels;
+            device.add_stream(id, direction.scope(), channels);
+            handle.streams.push((id, direction));
+            created.push(Box::new(stream));
+        }
+        for scope in self.volume_controls {
+            let id = registry.allocator_mut().allocate()?;
+            let (min_db, max_db) = VOLUME_RANGE_DB;
+            let control = VolumeControlObject::new(
+                id,
+                device_id,
+                scope,
+                PropertyElement::MAIN,
+                min_db,
+                max_db,
+            );
+            device.add_control(id, kAudioVolumeControlClassID);
+            handle.volume_controls.push((id, scope, control.handle()));
+            created.push(Box::new(control));
+        }
+        for scope in self.mute_controls {
+            let id = registry.allocator_mut().allocate()?;
+            let control = MuteControlObject::new(id, device_id, scope).notify_via(changes.clone());
+            device.add_control(id, kAudioMuteControlClassID);
+            handle.mute_controls.push((id, scope, control.handle()));
+            created.push(Box::new(control));
+        }
+        for (scope, element, channels) in self.pan_controls {
+            let id = registry.allocator_mut().allocate()?;
+            let control = StereoPanControlObject::new(id, device_id, scope, element, channels)
+                .notify_via(changes.clone());
+            device.add_control(id, kAudioStereoPanControlClassID);
+            handle
+                .pan_controls
+                .push((id, scope, element, control.handle()));
+            created.push(Box::new(control));
+        }
+        created.push(Box::new(device));
+        for object in &created {
+            if let Some(violation) = validate_classes(object.as_ref()).first() {
+                log::error!("can't build device {:?}: {violation}", self.name);
+                return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+            }
+        }
+
+        let mut registered = Vec::new();
+        for object in created {
+            let id = object.id();
+            if let Err(err) = registry.register(object) {
+                for id in registered {
+                    registry.unregister(id);
+                }
+                return Err(err);
+            }
+            registered.push(id);
+        }
+        Ok(handle)
+    }
+}
+
+impl DeviceHandle {
+    /// The id of the device
+    pub fn id(&self) -> AudioObjectID {
+        self.device
+    }
+    /// The sample rates of the device, for handling rate changes requested by the HAL
+    pub fn sample_rate(&self) -> &SampleRateHandle {
+        &self.sample_rate
+    }
+    /// The ids of the streams in `direction`, in the order they were added
+    pub fn streams(&self, direction: StreamDirection) -> impl Iterator<Item = AudioObjectID> + '_ {
+        self.streams
+            .iter()
+            .filter(move |(_, d)| *d == direction)
+            .map(|(id, _)| *id)
+    }
+    /// The value of the volume control in `scope`, if one was added
+    pub fn volume(&self, scope: PropertyScope) -> Option<&VolumeHandle> {
+        self.volume_controls
+            .iter()
+            .find(|(_, s, _)| *s == scope)
+            .map(|(_, _, handle)| handle)
+    }
+    /// The value of the mute control in `scope`, if one was added
+    pub fn mute(&self, scope: PropertyScope) -> Option<&AtomicHandle<bool>> {
+        self.mute_controls
+            .iter()
+            .find(|(_, s, _)| *s == scope)
+            .map(|(_, _, handle)| handle)
+    }
+    /// The value of the stereo pan control on `element` of `scope`, if one was added
+    pub fn pan(
+        &self,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Option<&AtomicHandle<f32>> {
+        self.pan_controls
+            .iter()
+            .find(|(_, s, e, _)| *s == scope && *e == element)
+            .map(|(_, _, _, handle)| handle)
+    }
+    /// The ids of all controls of the device
+    pub fn controls(&self) -> impl Iterator<Item = AudioObjectID> + '_ {
+        self.volume_controls
+            .iter()
+            .map(|(id, _, _)| *id)
+            .chain(self.mute_controls.iter().map(|(id, _, _)| *id))
+            .chain(self.pan_controls.iter().map(|(id, _, _, _)| *id))
+    }
+}
@@ -0,0 +1,64 @@
+use core_foundation::string::CFString;
+use coreaudio_sys::{
+    kAudioClockDeviceClassID, kAudioClockDevicePropertyAvailableNominalSampleRates,
+    kAudioClockDevicePropertyClockDomain, kAudioClockDevicePropertyDeviceIsAlive,
+    kAudioClockDevicePropertyDeviceIsRunning, kAudioClockDevicePropertyDeviceUID,
+    kAudioClockDevicePropertyLatency, kAudioClockDevicePropertyNominalSampleRate,
+    kAudioClockDevicePropertyTransportType, kAudioObjectClassID, kAudioObjectPlugInObject,
+    AudioObjectID,
+};
+
+use crate::property::{BoolProp, CFStringProp, EnumProp, Prop, RangeListProp};
+
+use super::{AudioObject, AudioObjectBase, HasProperties, TransportType};
+
+/// A clock device (`kAudioClockDeviceClassID`): a time source without any streams, published separately from the audio
+/// devices. Mirrors [`AudioDeviceObject`](super::AudioDeviceObject) for the properties both have.
+///
+/// Like devices, clock devices are owned by the plug-in object. Publish them with
+/// [`PluginObject::add_clock_device`](super::PluginObject::add_clock_device)
+#[derive(Debug, HasProperties)]
+pub struct ClockDeviceObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    pub device_uid: CFStringProp<kAudioClockDevicePropertyDeviceUID>,
+    pub transport_type: EnumProp<TransportType, kAudioClockDevicePropertyTransportType>,
+    pub clock_domain: Prop<u32, kAudioClockDevicePropertyClockDomain>,
+    pub nominal_sample_rate: Prop<f64, kAudioClockDevicePropertyNominalSampleRate, true>,
+    pub available_sample_rates: RangeListProp<kAudioClockDevicePropertyAvailableNominalSampleRates>,
+    pub latency: Prop<u32, kAudioClockDevicePropertyLatency>,
+    pub is_alive: BoolProp<kAudioClockDevicePropertyDeviceIsAlive>,
+    pub is_running: BoolProp<kAudioClockDevicePropertyDeviceIsRunning>,
+}
+
+impl ClockDeviceObject {
+    /// A clock device named after its `uid`, supporting the discrete sample rates in `rates` and running at the first
+    /// of them
+    pub fn new(id: AudioObjectID, uid: &str, rates: &[f64]) -> Self {
+        Self {
+            id,
+            base: AudioObjectBase::new(
+                kAudioObjectClassID,
+                kAudioClockDeviceClassID,
+                kAudioObjectPlugInObject,
+                uid.to_owned(),
+            ),
+            device_uid: CFStringProp::new(CFString::new(uid)),
+            transport_type: EnumProp(TransportType::Virtual),
+            clock_domain: Prop(0),
+            nominal_sample_rate: Prop(rates.first().copied().unwrap_or_default()),
+            available_sample_rates: RangeListProp::from_discrete(rates),
+            latency: Prop(0),
+            is_alive: BoolProp(true),
+            is_running: BoolProp(false),
+        }
+    }
+}
+
+impl AudioObject for ClockDeviceObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
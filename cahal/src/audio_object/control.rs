@@ -0,0 +1,1076 @@
+use std::{any::Any, ffi::c_void, mem};
+
+use core_foundation::{
+    base::TCFType,
+    string::{CFString, CFStringRef},
+};
+
+use coreaudio_sys::{
+    kAudioBooleanControlClassID, kAudioBooleanControlPropertyValue, kAudioControlClassID,
+    kAudioControlPropertyElement, kAudioControlPropertyScope, kAudioDataDestinationControlClassID,
+    kAudioDataSourceControlClassID, kAudioJackControlClassID, kAudioLevelControlClassID,
+    kAudioLevelControlPropertyConvertDecibelsToScalar,
+    kAudioLevelControlPropertyConvertScalarToDecibels, kAudioLevelControlPropertyDecibelRange,
+    kAudioLevelControlPropertyDecibelValue, kAudioLevelControlPropertyScalarValue,
+    kAudioListenbackControlClassID, kAudioMuteControlClassID, kAudioPhantomPowerControlClassID,
+    kAudioSelectorControlClassID, kAudioSelectorControlPropertyAvailableItems,
+    kAudioSelectorControlPropertyCurrentItem, kAudioSelectorControlPropertyItemName,
+    kAudioSoloControlClassID, kAudioStereoPanControlClassID,
+    kAudioStereoPanControlPropertyPanningChannels, kAudioStereoPanControlPropertyValue,
+    kAudioVolumeControlClassID, AudioObjectID, AudioValueRange,
+};
+
+use crate::{
+    notification::ChangeQueue,
+    os_err::{OSStatus, OSStatusError},
+    property::{
+        read_value, write_value, ArrayProp, AtomicHandle, ChannelPairProp, ConversionProp,
+        HookedProp, Prop, PropertyAddress, PropertyElement, PropertyScope, PropertySelector,
+        Qualifier, RawProperty, TranslationProp,
+    },
+};
+
+use super::{AudioObject, AudioObjectBase, HasProperties};
+
+fn control_base(class: u32, base_class: u32, owner: AudioObjectID) -> AudioObjectBase {
+    AudioObjectBase::new(base_class, class, owner, "")
+}
+
+/// Maps the scalar value of a level control (`0.0..=1.0`) linearly onto its decibel range
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DecibelCurve {
+    min: f32,
+    max: f32,
+}
+
+impl DecibelCurve {
+    fn to_decibels(self, scalar: f32) -> f32 {
+        self.min + scalar.clamp(0.0, 1.0) * (self.max - self.min)
+    }
+    fn to_scalar(self, decibels: f32) -> f32 {
+        if self.max <= self.min {
+            return 1.0;
+        }
+        (decibels.clamp(self.min, self.max) - self.min) / (self.max - self.min)
+    }
+}
+
+/// The scalar value of a volume control, shared with the IO path. Obtained from [`VolumeControlObject::handle`]
+#[derive(Debug, Clone)]
+pub struct VolumeHandle {
+    scalar: AtomicHandle<f32>,
+    curve: DecibelCurve,
+}
+
+impl VolumeHandle {
+    pub fn scalar(&self) -> f32 {
+        self.scalar.load()
+    }
+    pub fn set_scalar(&self, scalar: f32) {
+        self.scalar.store(scalar.clamp(0.0, 1.0));
+    }
+    pub fn decibels(&self) -> f32 {
+        self.curve.to_decibels(self.scalar())
+    }
+    pub fn set_decibels(&self, decibels: f32) {
+        self.scalar.store(self.curve.to_scalar(decibels));
+    }
+    /// The linear amplitude factor to apply to samples
+    pub fn gain(&self) -> f32 {
+        10f32.powf(self.decibels() / 20.0)
+    }
+    /// Set the volume from a linear amplitude factor, clamped to the decibel range
+    pub fn set_gain(&self, gain: f32) {
+        self.set_decibels(20.0 * gain.log10());
+    }
+}
+
+/// How the master (element 0) of a [`VolumeControlObject`] with per-channel values relates to the channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MasterVolumePolicy {
+    /// The master and the channels are set independently, and the master is applied on top of each channel
+    #[default]
+    Multiply,
+    /// The master follows the channels as the average of their scalar values. Setting the master sets every channel
+    FollowChannels,
+}
+
+/// The master and per-channel values of a volume control, shared with the IO path. Obtained from
+/// [`VolumeControlObject::channel_volumes`]
+#[derive(Debug, Clone)]
+pub struct ChannelVolumes {
+    master: VolumeHandle,
+    channels: Vec<VolumeHandle>,
+    policy: MasterVolumePolicy,
+}
+
+impl ChannelVolumes {
+    /// The value of `element`: the master for element 0, channel `n` for element `n`
+    pub fn element(&self, element: PropertyElement) -> Option<&VolumeHandle> {
+        match u32::from(element) {
+            0 => Some(&self.master),
+            channel => self.channels.get(channel as usize - 1),
+        }
+    }
+    pub fn master(&self) -> &VolumeHandle {
+        &self.master
+    }
+    /// The number of channels with a value of their own
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+    /// The linear amplitude factor to apply to `channel` (starting at 1), taking the master into account. Real-time
+    /// safe
+    pub fn gain(&self, channel: u32) -> f32 {
+        let Some(value) = channel
+            .checked_sub(1)
+            .and_then(|index| self.channels.get(index as usize))
+        else {
+            return self.master.gain();
+        };
+        match self.policy {
+            MasterVolumePolicy::Multiply => self.master.gain() * value.gain(),
+            MasterVolumePolicy::FollowChannels => value.gain(),
+        }
+    }
+    /// Write the gain of each channel to `gains`, see [`ChannelVolumes::gain`]. Real-time safe
+    pub fn gains(&self, gains: &mut [f32]) {
+        for (channel, gain) in (1..).zip(gains.iter_mut()) {
+            *gain = self.gain(channel);
+        }
+    }
+    fn set(&self, element: PropertyElement, set: impl Fn(&VolumeHandle)) -> OSStatus {
+        let Some(value) = self.element(element) else {
+            return Err(OSStatusError::HW_BAD_OBJECT_ERR);
+        };
+        set(value);
+        if self.policy == MasterVolumePolicy::FollowChannels && !self.channels.is_empty() {
+            if element == PropertyElement::MAIN {
+                let scalar = self.master.scalar();
+                self.channels
+                    .iter()
+                    .for_each(|channel| channel.set_scalar(scalar));
+            } else {
+                let sum: f32 = self.channels.iter().map(VolumeHandle::scalar).sum();
+                self.master.set_scalar(sum / self.channels.len() as f32);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `kAudioLevelControlPropertyScalarValue` or `kAudioLevelControlPropertyDecibelValue`, as a view of the shared values
+struct LevelProp<const SEL: u32>(ChannelVolumes);
+
+impl<const SEL: u32> LevelProp<SEL> {
+    const IS_DECIBELS: bool = SEL == kAudioLevelControlPropertyDecibelValue;
+    /// Both views share one value, so writing either changes the other
+    const DEPENDENTS: &'static [u32] = if Self::IS_DECIBELS {
+        &[kAudioLevelControlPropertyScalarValue]
+    } else {
+        &[kAudioLevelControlPropertyDecibelValue]
+    };
+}
+
+impl<const SEL: u32> std::fmt::Debug for LevelProp<SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LevelProp")
+            .field(&PropertySelector::new(SEL))
+            .field(&self.0.master.scalar())
+            .finish()
+    }
+}
+
+impl<const SEL: u32> RawProperty for LevelProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<f32>() as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    fn dependents(&self) -> &'static [u32] {
+        Self::DEPENDENTS
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0.master
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0.master
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let address = PropertyAddress::global(SEL);
+        unsafe { self.set_at(address, Qualifier::NONE, data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let address = PropertyAddress::global(SEL);
+        unsafe {
+            self.get_at(
+                address,
+                Qualifier::NONE,
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let value: f32 = unsafe { read_value(data, data_size)? };
+        if value.is_nan() {
+            log::error!("rejected NaN write to {}", self.selector());
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        self.0.set(address.element, |handle| {
+            if Self::IS_DECIBELS {
+                handle.set_decibels(value);
+            } else {
+                handle.set_scalar(value);
+            }
+        })
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(handle) = self.0.element(address.element) else {
+            return Err(OSStatusError::HW_BAD_OBJECT_ERR);
+        };
+        let value = if Self::IS_DECIBELS {
+            handle.decibels()
+        } else {
+            handle.scalar()
+        };
+        unsafe { write_value(value, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// A volume control (`kAudioVolumeControlClassID`). The scalar and the decibel value are two views of a single value,
+/// with the scalar mapped linearly onto the decibel range.
+///
+/// The value is stored atomically, so the IO path can read it through a [`VolumeHandle`] without locking.
+///
+/// With [`VolumeControlObject::with_channels`], the control also keeps a value per channel, which the HAL addresses
+/// through the element of the scalar and decibel properties (element 0 being the master). Requests for elements beyond
+/// the last channel fail with [`OSStatusError::HW_BAD_OBJECT_ERR`]
+#[derive(Debug, HasProperties)]
+pub struct VolumeControlObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    scope: Prop<u32, kAudioControlPropertyScope>,
+    element: Prop<u32, kAudioControlPropertyElement>,
+    scalar: LevelProp<kAudioLevelControlPropertyScalarValue>,
+    decibels: LevelProp<kAudioLevelControlPropertyDecibelValue>,
+    decibel_range: Prop<AudioValueRange, kAudioLevelControlPropertyDecibelRange>,
+    scalar_to_decibels: ConversionProp<f32, kAudioLevelControlPropertyConvertScalarToDecibels>,
+    decibels_to_scalar: ConversionProp<f32, kAudioLevelControlPropertyConvertDecibelsToScalar>,
+}
+
+impl VolumeControlObject {
+    /// A volume control of the device `owner` for `scope` and `element`, covering `min_db..=max_db` and starting out
+    /// at full volume
+    pub fn new(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+        min_db: f32,
+        max_db: f32,
+    ) -> Self {
+        let curve = DecibelCurve {
+            min: min_db.min(max_db),
+            max: max_db.max(min_db),
+        };
+        let handle = VolumeHandle {
+            scalar: AtomicHandle::new(1.0),
+            curve,
+        };
+        let volumes = ChannelVolumes {
+            master: handle,
+            channels: Vec::new(),
+            policy: MasterVolumePolicy::default(),
+        };
+        Self {
+            id,
+            base: control_base(kAudioVolumeControlClassID, kAudioLevelControlClassID, owner),
+            scope: Prop(scope.into()),
+            element: Prop(element.into()),
+            scalar: LevelProp(volumes.clone()),
+            decibels: LevelProp(volumes),
+            decibel_range: Prop(AudioValueRange {
+                mMinimum: curve.min as f64,
+                mMaximum: curve.max as f64,
+            }),
+            scalar_to_decibels: ConversionProp::new(move |scalar| Ok(curve.to_decibels(scalar))),
+            decibels_to_scalar: ConversionProp::new(move |decibels| Ok(curve.to_scalar(decibels))),
+        }
+    }
+    pub fn scope(&self) -> PropertyScope {
+        self.scope.0.into()
+    }
+    pub fn element(&self) -> PropertyElement {
+        self.element.0.into()
+    }
+    /// Keep a value for each of `channels` channels next to the master, starting out at the value of the master. The
+    /// master relates to the channels according to `policy`
+    pub fn with_channels(mut self, channels: u32, policy: MasterVolumePolicy) -> Self {
+        let master = self.scalar.0.master.clone();
+        let volumes = ChannelVolumes {
+            channels: (0..channels)
+                .map(|_| VolumeHandle {
+                    scalar: AtomicHandle::new(master.scalar()),
+                    curve: master.curve,
+                })
+                .collect(),
+            master,
+            policy,
+        };
+        self.scalar = LevelProp(volumes.clone());
+        self.decibels = LevelProp(volumes);
+        self
+    }
+    /// A handle to the value of the master for the IO path
+    pub fn handle(&self) -> VolumeHandle {
+        self.scalar.0.master.clone()
+    }
+    /// Handles to the values of the master and all channels for the IO path
+    pub fn channel_volumes(&self) -> ChannelVolumes {
+        self.scalar.0.clone()
+    }
+    /// The value of `element`, if the control has it
+    pub fn channel(&self, element: PropertyElement) -> Option<&VolumeHandle> {
+        self.scalar.0.element(element)
+    }
+    pub fn scalar(&self) -> f32 {
+        self.scalar.0.master.scalar()
+    }
+    pub fn set_scalar(&self, scalar: f32) {
+        let _ = self
+            .scalar
+            .0
+            .set(PropertyElement::MAIN, |master| master.set_scalar(scalar));
+    }
+    pub fn decibels(&self) -> f32 {
+        self.scalar.0.master.decibels()
+    }
+    pub fn set_decibels(&self, decibels: f32) {
+        let _ = self.scalar.0.set(PropertyElement::MAIN, |master| {
+            master.set_decibels(decibels)
+        });
+    }
+    /// See [`VolumeHandle::gain`]
+    pub fn gain(&self) -> f32 {
+        self.scalar.0.master.gain()
+    }
+    /// See [`VolumeHandle::set_gain`]
+    pub fn set_gain(&self, gain: f32) {
+        let _ = self
+            .scalar
+            .0
+            .set(PropertyElement::MAIN, |master| master.set_gain(gain));
+    }
+}
+
+impl AudioObject for VolumeControlObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+/// The value of a boolean control, stored atomically and shared with the IO path. Changes are recorded in the
+/// [`ChangeQueue`] of the control, if it has one
+struct BoolValueProp<const SEL: u32> {
+    object: AudioObjectID,
+    value: AtomicHandle<bool>,
+    changes: Option<ChangeQueue>,
+}
+
+impl<const SEL: u32> BoolValueProp<SEL> {
+    fn new(object: AudioObjectID, value: bool) -> Self {
+        Self {
+            object,
+            value: AtomicHandle::new(value),
+            changes: None,
+        }
+    }
+    fn get(&self) -> bool {
+        self.value.load_acquire()
+    }
+    fn set(&self, value: bool) {
+        let old = self.get();
+        self.value.store(value);
+        if let Some(changes) = self.changes.as_ref().filter(|_| old != value) {
+            changes.mark(self.object, PropertyAddress::global(SEL));
+        }
+    }
+}
+
+impl<const SEL: u32> std::fmt::Debug for BoolValueProp<SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BoolValueProp")
+            .field(&PropertySelector::new(SEL))
+            .field(&self.get())
+            .finish()
+    }
+}
+
+impl<const SEL: u32> RawProperty for BoolValueProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<u32>() as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let raw: u32 = unsafe { read_value(data, data_size)? };
+        BoolValueProp::set(self, raw != 0);
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let raw = self.get() as u32;
+        unsafe { write_value(raw, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// A mute control (`kAudioMuteControlClassID`). The value is stored atomically, so the IO path can read it through
+/// [`MuteControlObject::handle`] without locking.
+///
+/// Changes of the value, whether made by the HAL or from Rust, are recorded in the [`ChangeQueue`] set with
+/// [`MuteControlObject::notify_via`] so the host can be told about them
+#[derive(Debug, HasProperties)]
+pub struct MuteControlObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    scope: Prop<u32, kAudioControlPropertyScope>,
+    element: Prop<u32, kAudioControlPropertyElement>,
+    value: BoolValueProp<kAudioBooleanControlPropertyValue>,
+}
+
+impl MuteControlObject {
+    /// An unmuted control of the device `owner` for the main element of `scope`
+    pub fn new(id: AudioObjectID, owner: AudioObjectID, scope: PropertyScope) -> Self {
+        Self {
+            id,
+            base: control_base(kAudioMuteControlClassID, kAudioBooleanControlClassID, owner),
+            scope: Prop(scope.into()),
+            element: Prop(PropertyElement::MAIN.into()),
+            value: BoolValueProp::new(id, false),
+        }
+    }
+    /// Record changes of the value in `changes`
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        self.value.changes = Some(changes);
+        self
+    }
+    pub fn scope(&self) -> PropertyScope {
+        self.scope.0.into()
+    }
+    pub fn element(&self) -> PropertyElement {
+        self.element.0.into()
+    }
+    /// A handle to the value for the IO path
+    pub fn handle(&self) -> AtomicHandle<bool> {
+        self.value.value.clone()
+    }
+    pub fn is_muted(&self) -> bool {
+        self.value.get()
+    }
+    pub fn set_muted(&self, muted: bool) {
+        self.value.set(muted);
+    }
+}
+
+impl AudioObject for MuteControlObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+/// A boolean control of any of the classes derived from `kAudioBooleanControlClassID` that don't need more than the
+/// value (solo, jack, listenback, phantom power, ...). Reports the class it was created with and
+/// `kAudioBooleanControlClassID` as its base class.
+///
+/// The value behaves exactly like that of a [`MuteControlObject`]: it is stored atomically for the IO path (see
+/// [`BooleanControlObject::handle`]) and changes are recorded in the [`ChangeQueue`] set with
+/// [`BooleanControlObject::notify_via`]
+#[derive(Debug, HasProperties)]
+pub struct BooleanControlObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    scope: Prop<u32, kAudioControlPropertyScope>,
+    element: Prop<u32, kAudioControlPropertyElement>,
+    value: BoolValueProp<kAudioBooleanControlPropertyValue>,
+}
+
+impl BooleanControlObject {
+    /// A control of class `class` of the device `owner` for `scope` and `element`, starting out off
+    pub fn new(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        class: u32,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self {
+            id,
+            base: control_base(class, kAudioBooleanControlClassID, owner),
+            scope: Prop(scope.into()),
+            element: Prop(element.into()),
+            value: BoolValueProp::new(id, false),
+        }
+    }
+    /// A solo control (`kAudioSoloControlClassID`)
+    pub fn solo(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self::new(id, owner, kAudioSoloControlClassID, scope, element)
+    }
+    /// A jack control (`kAudioJackControlClassID`), telling whether something is plugged into the jack
+    pub fn jack(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self::new(id, owner, kAudioJackControlClassID, scope, element)
+    }
+    /// A listenback control (`kAudioListenbackControlClassID`)
+    pub fn listenback(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self::new(id, owner, kAudioListenbackControlClassID, scope, element)
+    }
+    /// A phantom power control (`kAudioPhantomPowerControlClassID`)
+    pub fn phantom_power(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self::new(id, owner, kAudioPhantomPowerControlClassID, scope, element)
+    }
+    /// Record changes of the value in `changes`
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        self.value.changes = Some(changes);
+        self
+    }
+    pub fn class(&self) -> u32 {
+        self.base.class.0
+    }
+    pub fn scope(&self) -> PropertyScope {
+        self.scope.0.into()
+    }
+    pub fn element(&self) -> PropertyElement {
+        self.element.0.into()
+    }
+    /// A handle to the value for the IO path
+    pub fn handle(&self) -> AtomicHandle<bool> {
+        self.value.value.clone()
+    }
+    pub fn value(&self) -> bool {
+        self.value.get()
+    }
+    pub fn set_value(&self, value: bool) {
+        self.value.set(value);
+    }
+}
+
+impl AudioObject for BooleanControlObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+/// The pan value of a stereo pan control, stored atomically and shared with the IO path. Writes are clamped to
+/// `0.0..=1.0` and changes are recorded in the [`ChangeQueue`] of the control, if it has one
+struct PanValueProp {
+    object: AudioObjectID,
+    value: AtomicHandle<f32>,
+    changes: Option<ChangeQueue>,
+}
+
+impl PanValueProp {
+    fn get(&self) -> f32 {
+        self.value.load_acquire()
+    }
+    fn set(&self, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        let old = self.get();
+        self.value.store(value);
+        if let Some(changes) = self.changes.as_ref().filter(|_| old != value) {
+            changes.mark(
+                self.object,
+                PropertyAddress::global(kAudioStereoPanControlPropertyValue),
+            );
+        }
+    }
+}
+
+impl std::fmt::Debug for PanValueProp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PanValueProp").field(&self.get()).finish()
+    }
+}
+
+impl RawProperty for PanValueProp {
+    fn selector(&self) -> PropertySelector {
+        kAudioStereoPanControlPropertyValue.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<f32>() as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let value: f32 = unsafe { read_value(data, data_size)? };
+        if value.is_nan() {
+            log::error!("rejected NaN write to {}", self.selector());
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        PanValueProp::set(self, value);
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.get(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// A stereo pan control (`kAudioStereoPanControlClassID`), panning between the two channels reported through
+/// `kAudioStereoPanControlPropertyPanningChannels`. A value of 0.0 is fully left, 0.5 centered and 1.0 fully right.
+///
+/// Like the mute control, the value is stored atomically for the IO path (see [`StereoPanControlObject::handle`]) and
+/// changes are recorded in the [`ChangeQueue`] set with [`StereoPanControlObject::notify_via`]
+#[derive(Debug, HasProperties)]
+pub struct StereoPanControlObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    scope: Prop<u32, kAudioControlPropertyScope>,
+    element: Prop<u32, kAudioControlPropertyElement>,
+    value: PanValueProp,
+    panning_channels: ChannelPairProp<kAudioStereoPanControlPropertyPanningChannels>,
+}
+
+impl StereoPanControlObject {
+    /// A centered pan control of the device `owner` for `scope` and `element`, panning between the device channels
+    /// `left` and `right`
+    pub fn new(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        element: PropertyElement,
+        [left, right]: [u32; 2],
+    ) -> Self {
+        Self {
+            id,
+            base: control_base(kAudioStereoPanControlClassID, kAudioControlClassID, owner),
+            scope: Prop(scope.into()),
+            element: Prop(element.into()),
+            value: PanValueProp {
+                object: id,
+                value: AtomicHandle::new(0.5),
+                changes: None,
+            },
+            panning_channels: ChannelPairProp::new(left, right),
+        }
+    }
+    /// Record changes of the value in `changes`
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        self.value.changes = Some(changes);
+        self
+    }
+    pub fn scope(&self) -> PropertyScope {
+        self.scope.0.into()
+    }
+    pub fn element(&self) -> PropertyElement {
+        self.element.0.into()
+    }
+    /// The device channels panned between, left first
+    pub fn panning_channels(&self) -> [u32; 2] {
+        self.panning_channels.channels()
+    }
+    /// A handle to the value for the IO path
+    pub fn handle(&self) -> AtomicHandle<f32> {
+        self.value.value.clone()
+    }
+    pub fn pan(&self) -> f32 {
+        self.value.get()
+    }
+    /// Set the pan value, clamped to `0.0..=1.0`
+    pub fn set_pan(&self, pan: f32) {
+        self.value.set(pan);
+    }
+}
+
+impl AudioObject for StereoPanControlObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+/// Validates writes of the current item of a selector control and records changes in `changes`
+fn selection_hook(
+    object: AudioObjectID,
+    items: Vec<u32>,
+    changes: Option<ChangeQueue>,
+) -> impl FnMut(&u32, &u32) -> OSStatus + Send + 'static {
+    move |&old, &new| {
+        if !items.contains(&new) {
+            log::error!("rejected selection of unknown item {new}");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        if let Some(changes) = changes.as_ref().filter(|_| old != new) {
+            changes.mark(
+                object,
+                PropertyAddress::global(kAudioSelectorControlPropertyCurrentItem),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A selector control of class `CLASS`, selecting one of a fixed list of items, each with an id and a name. The name
+/// of an item is looked up through `kAudioSelectorControlPropertyItemName` with the item id as the qualifier. Use it
+/// through [`DataSourceControlObject`] or [`DataDestinationControlObject`].
+///
+/// Selecting an item that isn't in the list is rejected with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`]. Changes of
+/// the selection by the HAL are recorded in the [`ChangeQueue`] set with [`SelectorControlObject::notify_via`]
+#[derive(Debug, HasProperties)]
+pub struct SelectorControlObject<const CLASS: u32> {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    scope: Prop<u32, kAudioControlPropertyScope>,
+    element: Prop<u32, kAudioControlPropertyElement>,
+    available_items: ArrayProp<u32, kAudioSelectorControlPropertyAvailableItems>,
+    current_item: HookedProp<u32, kAudioSelectorControlPropertyCurrentItem>,
+    item_name: TranslationProp<u32, CFStringRef, kAudioSelectorControlPropertyItemName>,
+}
+
+/// A data source control (`kAudioDataSourceControlClassID`), selecting where a device gets its input from
+pub type DataSourceControlObject = SelectorControlObject<kAudioDataSourceControlClassID>;
+
+/// A data destination control (`kAudioDataDestinationControlClassID`), selecting where a device sends its output
+pub type DataDestinationControlObject = SelectorControlObject<kAudioDataDestinationControlClassID>;
+
+impl<const CLASS: u32> SelectorControlObject<CLASS> {
+    /// A control of the device `owner` for the main element of `scope`, offering `items` as `(id, name)` pairs. The first
+    /// item is selected initially
+    pub fn new(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        scope: PropertyScope,
+        items: &[(u32, &str)],
+    ) -> Self {
+        let ids: Vec<u32> = items.iter().map(|&(item, _)| item).collect();
+        let names: Vec<(u32, String)> = items
+            .iter()
+            .map(|&(item, name)| (item, name.to_owned()))
+            .collect();
+        Self {
+            id,
+            base: control_base(CLASS, kAudioSelectorControlClassID, owner),
+            scope: Prop(scope.into()),
+            element: Prop(PropertyElement::MAIN.into()),
+            available_items: ArrayProp::new_with(ids.clone()),
+            current_item: HookedProp::new(
+                ids.first().copied().unwrap_or_default(),
+                selection_hook(id, ids, None),
+            ),
+            item_name: TranslationProp::new(move |item: u32| {
+                let Some((_, name)) = names.iter().find(|(id, _)| *id == item) else {
+                    log::error!("no selector item {item}");
+                    return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+                };
+                // the HAL takes ownership of the returned string
+                let name = CFString::new(name);
+                let name_ref = name.as_concrete_TypeRef();
+                mem::forget(name);
+                Ok(name_ref)
+            }),
+        }
+    }
+    /// Record changes of the selection by the HAL in `changes`
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        let current = *self.current_item.get();
+        self.current_item = HookedProp::new(
+            current,
+            selection_hook(self.id, self.available_items.to_vec(), Some(changes)),
+        );
+        self
+    }
+    pub fn scope(&self) -> PropertyScope {
+        self.scope.0.into()
+    }
+    pub fn element(&self) -> PropertyElement {
+        self.element.0.into()
+    }
+    pub fn items(&self) -> &[u32] {
+        &self.available_items
+    }
+    /// The id of the selected item
+    pub fn current_item(&self) -> u32 {
+        *self.current_item.get()
+    }
+    /// Select another item from Rust. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if there is no item with
+    /// that id
+    pub fn select(&mut self, item: u32) -> OSStatus {
+        if !self.available_items.contains(&item) {
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        self.current_item.set(item);
+        Ok(())
+    }
+}
+
+impl<const CLASS: u32> AudioObject for SelectorControlObject<CLASS> {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use coreaudio_sys::kAudioHardwareIllegalOperationError;
+
+    use super::*;
+    use crate::os_err::result_to_err_code;
+
+    #[test]
+    fn item_names_are_handed_out_owned() {
+        let control =
+            DataSourceControlObject::new(2, 1, PropertyScope::INPUT, &[(10, "Line"), (11, "Mic")]);
+        let address = PropertyAddress::global(kAudioSelectorControlPropertyItemName);
+        let prop = control.get_object_property(address).unwrap();
+        let item = 11u32;
+        let qualifier = unsafe { Qualifier::new(4, (&raw const item).cast()) };
+        let mut out: CFStringRef = ptr::null();
+        let mut len = 0;
+        let size = mem::size_of::<CFStringRef>() as u32;
+        unsafe { prop.get_at(address, qualifier, size, (&raw mut out).cast(), &mut len) }.unwrap();
+        assert_eq!(len, size);
+        // created for the HAL, which releases its only reference
+        let name = unsafe { CFString::wrap_under_create_rule(out) };
+        assert_eq!(name.retain_count(), 1);
+        assert_eq!(name.to_string(), "Mic");
+    }
+
+    fn volume() -> VolumeControlObject {
+        VolumeControlObject::new(
+            2,
+            1,
+            PropertyScope::OUTPUT,
+            PropertyElement::MAIN,
+            -96.0,
+            0.0,
+        )
+    }
+
+    /// Write `value` to the property `selector` of `control` the way the HAL does
+    fn write(control: &mut VolumeControlObject, selector: u32, value: f32) -> OSStatus {
+        let prop = control
+            .get_object_property_mut(PropertyAddress::global(selector))
+            .unwrap();
+        unsafe { prop.set((&raw const value).cast(), mem::size_of::<f32>() as u32) }
+    }
+
+    /// Read the property `selector` of `control` the way the HAL does, passing `value` in the buffer for conversions
+    fn read(control: &VolumeControlObject, selector: u32, value: f32) -> f32 {
+        let prop = control
+            .get_object_property(PropertyAddress::global(selector))
+            .unwrap();
+        let mut out = value;
+        let mut len = 0;
+        let size = mem::size_of::<f32>() as u32;
+        unsafe { prop.get(size, (&raw mut out).cast(), &mut len) }.unwrap();
+        assert_eq!(len, size);
+        out
+    }
+
+    #[test]
+    fn scalar_writes_move_the_decibels() {
+        let mut control = volume();
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+            0.0
+        );
+        write(&mut control, kAudioLevelControlPropertyScalarValue, 0.5).unwrap();
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyScalarValue, 0.0),
+            0.5
+        );
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+            -48.0
+        );
+        assert_eq!(control.handle().scalar(), 0.5);
+
+        // out of range scalars are clamped
+        write(&mut control, kAudioLevelControlPropertyScalarValue, 2.0).unwrap();
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn decibel_writes_move_the_scalar() {
+        let mut control = volume();
+        write(&mut control, kAudioLevelControlPropertyDecibelValue, -24.0).unwrap();
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyScalarValue, 0.0),
+            0.75
+        );
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+            -24.0
+        );
+
+        write(&mut control, kAudioLevelControlPropertyDecibelValue, -120.0).unwrap();
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyScalarValue, 1.0),
+            0.0
+        );
+        assert_eq!(
+            read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+            -96.0
+        );
+    }
+
+    #[test]
+    fn scalars_and_decibels_round_trip() {
+        let mut control = volume();
+        for scalar in [0.0, 0.1, 0.25, 0.5, 0.9, 1.0] {
+            let decibels = read(
+                &control,
+                kAudioLevelControlPropertyConvertScalarToDecibels,
+                scalar,
+            );
+            let back = read(
+                &control,
+                kAudioLevelControlPropertyConvertDecibelsToScalar,
+                decibels,
+            );
+            assert!(
+                (back - scalar).abs() < 1e-6,
+                "{scalar} -> {decibels} dB -> {back}"
+            );
+
+            write(&mut control, kAudioLevelControlPropertyScalarValue, scalar).unwrap();
+            assert_eq!(
+                read(&control, kAudioLevelControlPropertyDecibelValue, 0.0),
+                decibels
+            );
+            write(
+                &mut control,
+                kAudioLevelControlPropertyDecibelValue,
+                decibels,
+            )
+            .unwrap();
+            let scalar_read = read(&control, kAudioLevelControlPropertyScalarValue, 0.0);
+            assert!((scalar_read - scalar).abs() < 1e-6);
+        }
+        // converting doesn't change the value
+        write(&mut control, kAudioLevelControlPropertyScalarValue, 0.5).unwrap();
+        read(
+            &control,
+            kAudioLevelControlPropertyConvertScalarToDecibels,
+            0.1,
+        );
+        assert_eq!(control.scalar(), 0.5);
+    }
+
+    #[test]
+    fn nan_writes_are_rejected() {
+        let mut control = volume();
+        let status = write(
+            &mut control,
+            kAudioLevelControlPropertyScalarValue,
+            f32::NAN,
+        );
+        assert_eq!(
+            result_to_err_code(status),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(control.scalar(), 1.0);
+    }
+}
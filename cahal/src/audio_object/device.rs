@@ -0,0 +1,531 @@
+use std::borrow::Cow;
+
+use core_foundation::string::CFString;
+use coreaudio_sys::{
+    kAudioControlClassID, kAudioDeviceClassID, kAudioDeviceClockAlgorithmRaw,
+    kAudioDeviceClockAlgorithmSimpleIIR, kAudioDeviceClockAlgorithmTwelvePtMovingWindowAverage,
+    kAudioDevicePropertyBufferFrameSizeRange, kAudioDevicePropertyClockAlgorithm,
+    kAudioDevicePropertyClockDomain, kAudioDevicePropertyClockIsStable,
+    kAudioDevicePropertyDeviceCanBeDefaultDevice,
+    kAudioDevicePropertyDeviceCanBeDefaultSystemDevice, kAudioDevicePropertyDeviceIsAlive,
+    kAudioDevicePropertyDeviceIsRunning, kAudioDevicePropertyDeviceUID,
+    kAudioDevicePropertyHogMode, kAudioDevicePropertyLatency, kAudioDevicePropertyModelUID,
+    kAudioDevicePropertySafetyOffset, kAudioDevicePropertyStreamConfiguration,
+    kAudioDevicePropertyStreams, kAudioDevicePropertyTransportType,
+    kAudioDevicePropertyZeroTimeStampPeriod, kAudioDeviceTransportTypeAVB,
+    kAudioDeviceTransportTypeAggregate, kAudioDeviceTransportTypeAirPlay,
+    kAudioDeviceTransportTypeBluetooth, kAudioDeviceTransportTypeBluetoothLE,
+    kAudioDeviceTransportTypeBuiltIn, kAudioDeviceTransportTypeContinuityCaptureWired,
+    kAudioDeviceTransportTypeContinuityCaptureWireless, kAudioDeviceTransportTypeDisplayPort,
+    kAudioDeviceTransportTypeFireWire, kAudioDeviceTransportTypeHDMI, kAudioDeviceTransportTypePCI,
+    kAudioDeviceTransportTypeThunderbolt, kAudioDeviceTransportTypeUSB,
+    kAudioDeviceTransportTypeUnknown, kAudioDeviceTransportTypeVirtual, kAudioObjectClassID,
+    kAudioObjectPlugInObject, kAudioObjectPropertyControlList, kAudioObjectPropertyFirmwareVersion,
+    kAudioObjectPropertyManufacturer, kAudioObjectPropertySerialNumber, kAudioStreamClassID,
+    kAudioStreamPropertyLatency, pid_t, AudioClassID, AudioObjectID, AudioValueRange,
+};
+use uuid::Uuid;
+
+use crate::{
+    class_hierarchy::parent_class,
+    notification::ChangeQueue,
+    os_err::{OSStatus, OSStatusError},
+    property::{
+        ArrayProp, AtomicHandle, AtomicProp, BoolProp, CFStringProp, EnumProp, HogModeProp,
+        OptionProp, Prop, PropertyAddress, PropertyScope, RawPropertyExt, ScopedArrayProp,
+        ScopedProps, StreamConfigurationProp,
+    },
+    property_enum,
+};
+
+use super::{AudioObject, AudioObjectBase, HasProperties, ObjectRegistry, SampleRateManager};
+
+/// A device UID that stays the same for the same inputs: a version 5 UUID over `parts` (e.g. a serial number) in a
+/// namespace derived from `namespace`, which should be the bundle id of the plug-in. The parts are separated by NUL
+/// bytes before hashing, so `["ab", "c"]` and `["a", "bc"]` give different UIDs.
+///
+/// The derivation is fixed: changing it would make the system forget the settings of every device it was used for
+pub fn device_uid(namespace: &str, parts: &[&str]) -> CFString {
+    let namespace = Uuid::new_v5(&Uuid::NAMESPACE_DNS, namespace.as_bytes());
+    let name = parts.join("\0");
+    CFString::new(
+        &Uuid::new_v5(&namespace, name.as_bytes())
+            .hyphenated()
+            .to_string(),
+    )
+}
+
+/// A model UID shared by all devices of the same kind: `vendor:model:format`, where `format` describes what sets the
+/// devices apart from other models, e.g. their channel layout
+pub fn model_uid(vendor: &str, model: &str, format: &str) -> CFString {
+    CFString::new(&format!("{vendor}:{model}:{format}"))
+}
+
+/// A clock domain for the devices of the plug-in with the bundle id `bundle_id`, for devices that are driven by the same
+/// clock. Derived from a version 5 UUID like [`device_uid`], so it is stable across launches, and never 0, which
+/// means the domain is unknown
+pub fn clock_domain(bundle_id: &str) -> u32 {
+    let uuid = Uuid::new_v5(&Uuid::NAMESPACE_DNS, bundle_id.as_bytes());
+    let bytes = uuid.as_bytes();
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).max(1)
+}
+
+property_enum! {
+    /// How the device smooths its zero time stamps, as reported by `kAudioDevicePropertyClockAlgorithm`
+    #[derive(Debug)]
+    pub enum ClockAlgorithm {
+        Raw = kAudioDeviceClockAlgorithmRaw,
+        SimpleIir = kAudioDeviceClockAlgorithmSimpleIIR,
+        TwelvePtMovingWindowAverage = kAudioDeviceClockAlgorithmTwelvePtMovingWindowAverage,
+    }
+}
+
+property_enum! {
+    /// How a device is connected to the computer, as reported by `kAudioDevicePropertyTransportType`. Virtual devices
+    /// should report [`TransportType::Virtual`] so they get the right icon
+    #[derive(Debug)]
+    pub enum TransportType {
+        Unknown = kAudioDeviceTransportTypeUnknown,
+        BuiltIn = kAudioDeviceTransportTypeBuiltIn,
+        Aggregate = kAudioDeviceTransportTypeAggregate,
+        Virtual = kAudioDeviceTransportTypeVirtual,
+        Pci = kAudioDeviceTransportTypePCI,
+        Usb = kAudioDeviceTransportTypeUSB,
+        FireWire = kAudioDeviceTransportTypeFireWire,
+        Bluetooth = kAudioDeviceTransportTypeBluetooth,
+        BluetoothLe = kAudioDeviceTransportTypeBluetoothLE,
+        Hdmi = kAudioDeviceTransportTypeHDMI,
+        DisplayPort = kAudioDeviceTransportTypeDisplayPort,
+        AirPlay = kAudioDeviceTransportTypeAirPlay,
+        Avb = kAudioDeviceTransportTypeAVB,
+        Thunderbolt = kAudioDeviceTransportTypeThunderbolt,
+        ContinuityCaptureWired = kAudioDeviceTransportTypeContinuityCaptureWired,
+        ContinuityCaptureWireless = kAudioDeviceTransportTypeContinuityCaptureWireless,
+    }
+}
+
+/// An audio device with the properties the HAL requires of every device. Everything but the name, UID and sample rate
+/// starts out with a default that suits a simple virtual device, adjust the public fields as needed.
+///
+/// `kAudioDevicePropertyStreams` answers with the input or output streams in the input and output scopes, and with all
+/// streams, inputs first, in the global and wildcard scopes. `kAudioDevicePropertyStreamConfiguration` lists the channel
+/// counts of the same streams the same way.
+///
+/// Streams and controls are separate objects, registered with the [`ObjectRegistry`](super::ObjectRegistry) under their
+/// own ids. [`AudioDeviceObject::add_stream`] and [`AudioDeviceObject::add_control`] make them part of the device.
+///
+/// Whether the device is alive and running is stored atomically, so the IO path can update it. Use
+/// [`AudioDeviceObject::set_alive`] and [`AudioDeviceObject::set_running`], which record actual changes in the
+/// [`ChangeQueue`] set with [`AudioDeviceObject::notify_via`] so the host can be told about them
+#[derive(Debug, HasProperties)]
+pub struct AudioDeviceObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    pub device_uid: CFStringProp<kAudioDevicePropertyDeviceUID>,
+    pub model_uid: CFStringProp<kAudioDevicePropertyModelUID>,
+    /// Shown by Audio MIDI Setup, absent until set with [`AudioDeviceObject::set_manufacturer`]
+    pub manufacturer: OptionProp<CFStringProp<kAudioObjectPropertyManufacturer>>,
+    pub serial_number: OptionProp<CFStringProp<kAudioObjectPropertySerialNumber>>,
+    pub firmware_version: OptionProp<CFStringProp<kAudioObjectPropertyFirmwareVersion>>,
+    pub transport_type: EnumProp<TransportType, kAudioDevicePropertyTransportType>,
+    /// Devices with the same non-zero clock domain are driven by the same clock, see [`clock_domain`]
+    pub clock_domain: Prop<u32, kAudioDevicePropertyClockDomain>,
+    pub clock_algorithm: EnumProp<ClockAlgorithm, kAudioDevicePropertyClockAlgorithm>,
+    /// Whether the clock of the device runs at a steady rate, so the HAL doesn't need to filter its time stamps much
+    pub clock_is_stable: BoolProp<kAudioDevicePropertyClockIsStable>,
+    latency: ScopedProps<Prop<u32, kAudioDevicePropertyLatency>>,
+    safety_offset: ScopedProps<Prop<u32, kAudioDevicePropertySafetyOffset>>,
+    #[property(flatten)]
+    sample_rate: SampleRateManager,
+    /// Absent until set with [`AudioDeviceObject::set_buffer_frame_size_range`]
+    buffer_frame_size_range:
+        OptionProp<Prop<AudioValueRange, kAudioDevicePropertyBufferFrameSizeRange>>,
+    #[property(skip)]
+    buffer_frame_size: AtomicHandle<u32>,
+    streams: ScopedArrayProp<AudioObjectID, kAudioDevicePropertyStreams>,
+    stream_configuration: StreamConfigurationProp<kAudioDevicePropertyStreamConfiguration>,
+    control_list: ArrayProp<AudioObjectID, kAudioObjectPropertyControlList>,
+    is_alive: AtomicProp<bool, kAudioDevicePropertyDeviceIsAlive>,
+    is_running: AtomicProp<bool, kAudioDevicePropertyDeviceIsRunning>,
+    #[property(skip)]
+    changes: Option<ChangeQueue>,
+    hog_mode: HogModeProp<kAudioDevicePropertyHogMode>,
+    /// Number of sample frames between two zero time stamps
+    pub zero_timestamp_period: Prop<u32, kAudioDevicePropertyZeroTimeStampPeriod>,
+    pub can_be_default: BoolProp<kAudioDevicePropertyDeviceCanBeDefaultDevice>,
+    pub can_be_default_system: BoolProp<kAudioDevicePropertyDeviceCanBeDefaultSystemDevice>,
+}
+
+impl AudioDeviceObject {
+    /// A device owned by the plug-in object, running at a single `sample_rate` with no streams or controls yet
+    pub fn new(
+        id: AudioObjectID,
+        name: impl Into<Cow<'static, str>>,
+        uid: &str,
+        sample_rate: f64,
+    ) -> Self {
+        Self {
+            id,
+            base: AudioObjectBase::new(
+                kAudioObjectClassID,
+                kAudioDeviceClassID,
+                kAudioObjectPlugInObject,
+                name,
+            ),
+            device_uid: CFStringProp::new(CFString::new(uid)),
+            model_uid: CFStringProp::new(CFString::new(uid)),
+            manufacturer: OptionProp::none(kAudioObjectPropertyManufacturer),
+            serial_number: OptionProp::none(kAudioObjectPropertySerialNumber),
+            firmware_version: OptionProp::none(kAudioObjectPropertyFirmwareVersion),
+            transport_type: EnumProp(TransportType::Virtual),
+            clock_domain: Prop(0),
+            clock_algorithm: EnumProp(ClockAlgorithm::Raw),
+            clock_is_stable: BoolProp(true),
+            latency: ScopedProps::uniform(Prop(0)),
+            safety_offset: ScopedProps::uniform(Prop(0)),
+            sample_rate: SampleRateManager::new(id, sample_rate),
+            buffer_frame_size_range: OptionProp::none(kAudioDevicePropertyBufferFrameSizeRange),
+            buffer_frame_size: AtomicHandle::new(0),
+            streams: ScopedArrayProp::new(),
+            stream_configuration: StreamConfigurationProp::new(),
+            control_list: ArrayProp::new(),
+            is_alive: AtomicProp::new(true),
+            is_running: AtomicProp::new(false),
+            changes: None,
+            hog_mode: HogModeProp::new(),
+            zero_timestamp_period: Prop(sample_rate as u32),
+            can_be_default: BoolProp(true),
+            can_be_default_system: BoolProp(true),
+        }
+    }
+    /// Record changes of whether the device is alive or running in `changes`
+    pub fn notify_via(mut self, changes: ChangeQueue) -> Self {
+        self.sample_rate.set_changes(changes.clone());
+        self.changes = Some(changes);
+        self
+    }
+    pub fn is_alive(&self) -> bool {
+        self.is_alive.load_acquire()
+    }
+    /// Mark the device as alive or dead. A device that died should be unpublished soon after
+    pub fn set_alive(&self, alive: bool) {
+        let old = self.is_alive();
+        self.is_alive.store(alive);
+        if old != alive {
+            self.mark_changed(kAudioDevicePropertyDeviceIsAlive);
+        }
+    }
+    pub fn is_running(&self) -> bool {
+        self.is_running.load_acquire()
+    }
+    /// Mark IO on the device as started or stopped
+    pub fn set_running(&self, running: bool) {
+        let old = self.is_running();
+        self.is_running.store(running);
+        if old != running {
+            self.mark_changed(kAudioDevicePropertyDeviceIsRunning);
+        }
+    }
+    /// A handle to whether the device is running, for the IO path. Changes made through it aren't recorded
+    pub fn running_handle(&self) -> AtomicHandle<bool> {
+        self.is_running.handle()
+    }
+    /// The nominal and available sample rates
+    pub fn sample_rate(&self) -> &SampleRateManager {
+        &self.sample_rate
+    }
+    /// The nominal and available sample rates, for handling rate changes requested by the HAL
+    pub fn sample_rate_mut(&mut self) -> &mut SampleRateManager {
+        &mut self.sample_rate
+    }
+    /// Accept IO buffers of `min` to `max` frames, as published through `kAudioDevicePropertyBufferFrameSizeRange`
+    pub fn set_buffer_frame_size_range(&mut self, min: u32, max: u32) {
+        self.buffer_frame_size_range.replace(Prop(AudioValueRange {
+            mMinimum: min.min(max) as f64,
+            mMaximum: max.max(min) as f64,
+        }));
+    }
+    /// The accepted IO buffer sizes in frames, if a range was set
+    pub fn buffer_frame_size_range(&self) -> Option<(u32, u32)> {
+        let range = self.buffer_frame_size_range.get()?.0;
+        Some((range.mMinimum as u32, range.mMaximum as u32))
+    }
+    /// The IO buffer size closest to `frames` that is within the accepted range. Any size is accepted if no range was
+    /// set
+    pub fn clamp_buffer_frame_size(&self, frames: u32) -> u32 {
+        self.buffer_frame_size_range()
+            .map_or(frames, |(min, max)| frames.clamp(min, max))
+    }
+    /// Record the IO buffer size the HAL settled on, e.g. the `io_buffer_frame_size` of the first IO operation, so other
+    /// code can size its buffers. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if it is out of range
+    pub fn record_buffer_frame_size(&self, frames: u32) -> OSStatus {
+        if self.clamp_buffer_frame_size(frames) != frames {
+            log::error!("IO buffer size of {frames} frames is out of range");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        self.buffer_frame_size.store(frames);
+        Ok(())
+    }
+    /// The IO buffer size recorded with [`AudioDeviceObject::record_buffer_frame_size`], 0 until there is one. The
+    /// handle can be moved to the IO path
+    pub fn buffer_frame_size(&self) -> AtomicHandle<u32> {
+        self.buffer_frame_size.clone()
+    }
+    /// Start out with a latency of `frames` in `scope`
+    pub fn with_latency(mut self, scope: PropertyScope, frames: u32) -> Self {
+        self.latency.scope_mut(scope).0 = frames;
+        self
+    }
+    /// Start out with a safety offset of `frames` in `scope`
+    pub fn with_safety_offset(mut self, scope: PropertyScope, frames: u32) -> Self {
+        self.safety_offset.scope_mut(scope).0 = frames;
+        self
+    }
+    /// The latency of the device in `scope`, in frames
+    pub fn latency(&self, scope: PropertyScope) -> u32 {
+        self.latency.scope(scope).0
+    }
+    /// The safety offset of the device in `scope`, in frames
+    pub fn safety_offset(&self, scope: PropertyScope) -> u32 {
+        self.safety_offset.scope(scope).0
+    }
+    /// Switch to a new latency and safety offset in `scope`. Clients cache these, so once the device is published, only
+    /// call this from `perform_change` after requesting the change through the host
+    pub fn commit_latency(&mut self, scope: PropertyScope, latency: u32, safety_offset: u32) {
+        let latency_changed =
+            std::mem::replace(&mut self.latency.scope_mut(scope).0, latency) != latency;
+        let offset_changed =
+            std::mem::replace(&mut self.safety_offset.scope_mut(scope).0, safety_offset)
+                != safety_offset;
+        for (changed, selector) in [
+            (latency_changed, kAudioDevicePropertyLatency),
+            (offset_changed, kAudioDevicePropertySafetyOffset),
+        ] {
+            if let Some(changes) = self.changes.as_ref().filter(|_| changed) {
+                changes.mark(self.id, PropertyAddress::scoped(selector, scope));
+            }
+        }
+    }
+    /// The latency clients will compute for presenting audio in `scope`: the latency and safety offset of the device plus
+    /// the largest latency of its streams in `scope`, which are looked up in `registry`
+    pub fn total_presentation_latency(
+        &self,
+        scope: PropertyScope,
+        registry: &ObjectRegistry,
+    ) -> u32 {
+        let stream_latency = self
+            .streams(scope)
+            .into_iter()
+            .filter_map(|stream| {
+                registry
+                    .property(stream, PropertyAddress::global(kAudioStreamPropertyLatency))
+                    .ok()?
+                    .value::<u32>()
+                    .copied()
+            })
+            .max()
+            .unwrap_or(0);
+        self.latency(scope) + self.safety_offset(scope) + stream_latency
+    }
+    /// The process with exclusive access to the device, if any. IO of other clients should be refused while it has it
+    pub fn hogging_pid(&self) -> Option<pid_t> {
+        self.hog_mode.hogging_pid()
+    }
+    /// A handle to the hogging pid for the IO path, see [`HogModeProp::handle`]
+    pub fn hog_mode_handle(&self) -> AtomicHandle<i32> {
+        self.hog_mode.handle()
+    }
+    /// Take hog mode away from whichever process has it, returning that process
+    pub fn release_hog_mode(&self) -> Option<pid_t> {
+        let released = self.hog_mode.release();
+        if released.is_some() {
+            self.mark_changed(kAudioDevicePropertyHogMode);
+        }
+        released
+    }
+    fn mark_changed(&self, selector: u32) {
+        if let Some(changes) = &self.changes {
+            changes.mark(self.id, PropertyAddress::global(selector));
+        }
+    }
+    pub fn set_manufacturer(&mut self, manufacturer: &str) {
+        self.manufacturer
+            .replace(CFStringProp::new(CFString::new(manufacturer)));
+    }
+    pub fn set_serial_number(&mut self, serial_number: &str) {
+        self.serial_number
+            .replace(CFStringProp::new(CFString::new(serial_number)));
+    }
+    pub fn set_firmware_version(&mut self, firmware_version: &str) {
+        self.firmware_version
+            .replace(CFStringProp::new(CFString::new(firmware_version)));
+    }
+    /// The streams of the device as seen in `scope`: the input or output streams, or all of them for any other scope
+    pub fn streams(&self, scope: PropertyScope) -> Vec<AudioObjectID> {
+        self.streams.scoped(scope)
+    }
+    /// Make the stream with the given id and number of channels part of this device, in the input or output `scope`.
+    /// Adding a stream again updates its number of channels
+    pub fn add_stream(&mut self, id: AudioObjectID, scope: PropertyScope, channels: u32) {
+        let (Some(streams), Some(configuration)) = (
+            self.streams.scope_mut(scope),
+            self.stream_configuration.scope_mut(scope),
+        ) else {
+            log::error!("streams are either input or output, not in {scope:?}");
+            return;
+        };
+        match streams.iter().position(|&stream| stream == id) {
+            Some(index) => configuration[index] = channels,
+            None => {
+                streams.push(id);
+                configuration.push(channels);
+            }
+        }
+        self.base
+            .owned_objects
+            .insert(id, kAudioStreamClassID, kAudioObjectClassID);
+    }
+    /// Remove a stream from this device, returning whether it was part of it
+    pub fn remove_stream(&mut self, id: AudioObjectID) -> bool {
+        let mut removed = false;
+        for (streams, configuration) in [
+            (
+                &mut self.streams.input,
+                &mut self.stream_configuration.input,
+            ),
+            (
+                &mut self.streams.output,
+                &mut self.stream_configuration.output,
+            ),
+        ] {
+            if let Some(index) = streams.iter().position(|&stream| stream == id) {
+                streams.remove(index);
+                configuration.remove(index);
+                removed = true;
+            }
+        }
+        if removed {
+            self.base.owned_objects.remove(id);
+        }
+        removed
+    }
+    /// The controls of the device, in the order they were added
+    pub fn controls(&self) -> &[AudioObjectID] {
+        &self.control_list
+    }
+    /// Make the control with the given id and class part of this device, listing it in `kAudioObjectPropertyControlList`
+    /// as well as the owned objects
+    pub fn add_control(&mut self, id: AudioObjectID, class: AudioClassID) {
+        if !self.control_list.contains(&id) {
+            self.control_list.push(id);
+        }
+        let base_class = parent_class(class).unwrap_or(kAudioControlClassID);
+        self.base.owned_objects.insert(id, class, base_class);
+    }
+    /// Remove a control from this device, returning whether it was part of it
+    pub fn remove_control(&mut self, id: AudioObjectID) -> bool {
+        let len = self.control_list.len();
+        self.control_list.retain(|&control| control != id);
+        if self.control_list.len() == len {
+            return false;
+        }
+        self.base.owned_objects.remove(id);
+        true
+    }
+}
+
+impl AudioObject for AudioDeviceObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{kAudioMuteControlClassID, kAudioVolumeControlClassID};
+
+    use super::*;
+    use crate::conformance::{validate, validate_classes, validate_required};
+
+    fn device() -> AudioDeviceObject {
+        AudioDeviceObject::new(2, "Device", "com.example.device", 48_000.0)
+    }
+
+    #[test]
+    fn default_devices_conform() {
+        let device = device();
+        assert_eq!(validate(&device), []);
+        assert_eq!(validate_classes(&device), []);
+        assert_eq!(validate_required(&device), []);
+    }
+
+    #[test]
+    fn devices_with_streams_and_controls_conform() {
+        let mut device = device()
+            .with_latency(PropertyScope::OUTPUT, 64)
+            .with_safety_offset(PropertyScope::OUTPUT, 16);
+        device.add_stream(3, PropertyScope::INPUT, 1);
+        device.add_stream(4, PropertyScope::OUTPUT, 2);
+        device.add_control(5, kAudioVolumeControlClassID);
+        device.add_control(6, kAudioMuteControlClassID);
+        device.set_buffer_frame_size_range(64, 4096);
+        device.set_manufacturer("Manufacturer");
+        assert_eq!(validate(&device), []);
+        assert_eq!(validate_classes(&device), []);
+        assert_eq!(device.streams(PropertyScope::INPUT), [3]);
+        assert_eq!(device.streams(PropertyScope::GLOBAL), [3, 4]);
+        assert_eq!(device.controls(), [5, 6]);
+    }
+
+    // these are pinned: a different value would make the system forget the settings of existing devices
+    #[test]
+    fn device_uids_are_stable() {
+        let uid = |namespace, parts| device_uid(namespace, parts).to_string();
+        assert_eq!(
+            uid("com.example.plugin", &["SN-0001"]),
+            "629d0974-70cd-57f4-a62a-37374e9508a3"
+        );
+        assert_eq!(
+            uid("com.example.plugin", &["SN-0002"]),
+            "24e36fa0-c374-5072-b56f-324e26e1b286"
+        );
+        assert_eq!(
+            uid("com.example.other", &["SN-0001"]),
+            "084e6f72-5401-5df6-b676-b98c291298ac"
+        );
+        assert_eq!(
+            uid("com.example.plugin", &[]),
+            "510c4dcf-4e5a-5258-b3dd-4f77adf02488"
+        );
+        // the parts are separated, not just concatenated
+        assert_eq!(
+            uid("com.example.plugin", &["ab", "c"]),
+            "b914c5a3-e644-582d-bf17-2114853741d8"
+        );
+        assert_eq!(
+            uid("com.example.plugin", &["a", "bc"]),
+            "a93d4d6e-fece-59c5-b83e-a35a8a908258"
+        );
+    }
+
+    #[test]
+    fn model_uids_join_their_parts() {
+        assert_eq!(
+            model_uid("Example", "Interface", "2in2out").to_string(),
+            "Example:Interface:2in2out"
+        );
+        assert_eq!(model_uid("", "", "").to_string(), "::");
+    }
+
+    #[test]
+    fn clock_domains_are_stable() {
+        assert_eq!(clock_domain("com.example.plugin"), 0x8ffc9108);
+        assert_eq!(clock_domain("com.example.other"), 0x2af49652);
+        assert_eq!(
+            clock_domain("com.example.plugin"),
+            clock_domain("com.example.plugin")
+        );
+    }
+}
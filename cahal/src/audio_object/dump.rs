@@ -0,0 +1,204 @@
+use std::fmt::{self, Write};
+use std::ops::ControlFlow;
+
+use core_foundation::string::CFString;
+use coreaudio_sys::{
+    kAudioObjectPropertyClass, kAudioObjectPropertyName, kAudioObjectPropertyOwner,
+};
+
+use crate::property::{fmt_fourcc, PropertyAddress, Qualifier, RawProperty, RawPropertyExt};
+
+use super::AudioObject;
+
+/// Values longer than this many bytes are cut off in the dump
+const MAX_VALUE_BYTES: usize = 16;
+const INDENT: &str = "  ";
+
+struct FourCc(u32);
+
+impl fmt::Display for FourCc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_fourcc(self.0, f)
+    }
+}
+
+fn global_u32(object: &dyn AudioObject, selector: u32) -> Option<u32> {
+    object
+        .get_object_property(PropertyAddress::global(selector))?
+        .value::<u32>()
+        .copied()
+}
+
+fn write_value(out: &mut String, prop: &dyn RawProperty) -> fmt::Result {
+    let Some(bytes) = prop.value_bytes() else {
+        return write!(out, " <unreadable>");
+    };
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    out.push(' ');
+    for byte in bytes.iter().take(MAX_VALUE_BYTES) {
+        write!(out, "{byte:02x}")?;
+    }
+    if bytes.len() > MAX_VALUE_BYTES {
+        write!(out, "... ({} bytes)", bytes.len())?;
+    }
+    Ok(())
+}
+
+fn dump_object(out: &mut String, object: &dyn AudioObject, depth: usize) -> fmt::Result {
+    let indent = INDENT.repeat(depth);
+    write!(out, "{indent}#{}", object.id())?;
+    if let Some(class) = global_u32(object, kAudioObjectPropertyClass) {
+        write!(out, " {}", FourCc(class))?;
+    }
+    if let Some(name) = object
+        .get_object_property(PropertyAddress::global(kAudioObjectPropertyName))
+        .and_then(|prop| prop.value::<CFString>())
+    {
+        write!(out, " {:?}", name.to_string())?;
+    }
+    if let Some(owner) = global_u32(object, kAudioObjectPropertyOwner) {
+        write!(out, " owner #{owner}")?;
+    }
+    out.push('\n');
+
+    let mut result = Ok(());
+    object.visit_properties(&mut |address, prop| {
+        if result.is_err() || !prop.is_present() {
+            return;
+        }
+        result = (|| {
+            write!(out, "{indent}{INDENT}{}", address.selector)?;
+            if !address.scope.is_wildcard() {
+                write!(out, " scope {}", FourCc(address.scope.into()))?;
+            }
+            if !address.element.is_wildcard() {
+                write!(out, " element {}", u32::from(address.element))?;
+            }
+            let access = if prop.is_mut() { "rw" } else { "ro" };
+            write!(
+                out,
+                " {access} size {}",
+                prop.byte_size_at(address, Qualifier::NONE)
+            )?;
+            write_value(out, prop)?;
+            out.push('\n');
+            Ok(())
+        })();
+    });
+    result?;
+
+    let mut result = Ok(());
+    let _ = object.visit_children(&mut |child| {
+        result = dump_object(out, child, depth + 1);
+        match result {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        }
+    });
+    result
+}
+
+/// A human readable listing of `root` and its subobjects: the id, class, name and owner of each object, followed by its
+/// properties with their address, mutability, size and (abbreviated) current value in hex. Subobjects are indented below
+/// their parent
+pub fn dump(root: &dyn AudioObject) -> String {
+    let mut out = String::new();
+    // writing to a String can't fail
+    let _ = dump_object(&mut out, root, 0);
+    out
+}
+
+/// Write [`dump`] of `root` to the log at debug level
+pub fn log_tree(root: &dyn AudioObject) {
+    if log::log_enabled!(log::Level::Debug) {
+        log::debug!("object tree:\n{}", dump(root));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioDeviceClassID, kAudioDevicePropertyAvailableNominalSampleRates,
+        kAudioDevicePropertyLatency, kAudioObjectClassID, kAudioPlugInClassID,
+        kAudioPlugInPropertyDeviceList, kAudioStreamClassID, kAudioStreamPropertyIsActive,
+    };
+
+    use super::*;
+    use crate::{
+        audio_object::AudioObjectBase,
+        property::{ArrayProp, BoolProp, Prop},
+    };
+
+    crate::audio_object! {
+        struct Stream {
+            base: AudioObjectBase,
+            #[prop] is_active: BoolProp<kAudioStreamPropertyIsActive, true>,
+        }
+    }
+
+    crate::audio_object! {
+        struct Device {
+            base: AudioObjectBase,
+            #[prop] latency: Prop<u32, kAudioDevicePropertyLatency>,
+            #[prop] rates: ArrayProp<f64, kAudioDevicePropertyAvailableNominalSampleRates>,
+            #[child] stream: Stream,
+        }
+    }
+
+    crate::audio_object! {
+        struct Plugin {
+            base: AudioObjectBase,
+            #[prop] devices: ArrayProp<u32, kAudioPlugInPropertyDeviceList>,
+            #[child] device: Device,
+        }
+    }
+
+    fn plugin() -> Plugin {
+        let base =
+            |class, owner, name| AudioObjectBase::new(kAudioObjectClassID, class, owner, name);
+        let stream = Stream::new(3, base(kAudioStreamClassID, 2, "Output"), BoolProp(true));
+        let device = Device::new(
+            2,
+            base(kAudioDeviceClassID, 1, "Device"),
+            Prop(512),
+            ArrayProp::new_with(vec![44_100.0, 48_000.0, 96_000.0]),
+            stream,
+        );
+        Plugin::new(
+            1,
+            base(kAudioPlugInClassID, 0, "Plugin"),
+            ArrayProp::new_with(vec![2]),
+            device,
+        )
+    }
+
+    #[test]
+    fn trees_dump_as_expected() {
+        let expected = r##"#1 'aplg' (0x61706c67) "Plugin" owner #0
+  'dev#' (0x64657623) ro size 4 02000000
+  'bcls' (0x62636c73) ro size 4 6a626f61
+  'clas' (0x636c6173) ro size 4 676c7061
+  'stdv' (0x73746476) ro size 4 00000000
+  'ownd' (0x6f776e64) ro size 0
+  'lnam' (0x6c6e616d) ro size 8 506c7567696e
+  #2 'adev' (0x61646576) "Device" owner #1
+    'ltnc' (0x6c746e63) ro size 4 00020000
+    'nsr#' (0x6e737223) ro size 24 000000008088e540000000000070e740... (24 bytes)
+    'bcls' (0x62636c73) ro size 4 6a626f61
+    'clas' (0x636c6173) ro size 4 76656461
+    'stdv' (0x73746476) ro size 4 01000000
+    'ownd' (0x6f776e64) ro size 0
+    'lnam' (0x6c6e616d) ro size 8 446576696365
+    #3 'astr' (0x61737472) "Output" owner #2
+      'sact' (0x73616374) rw size 4 01000000
+      'bcls' (0x62636c73) ro size 4 6a626f61
+      'clas' (0x636c6173) ro size 4 72747361
+      'stdv' (0x73746476) ro size 4 02000000
+      'ownd' (0x6f776e64) ro size 0
+      'lnam' (0x6c6e616d) ro size 8 4f7574707574
+"##;
+        assert_eq!(dump(&plugin()), expected);
+    }
+}
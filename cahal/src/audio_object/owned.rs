@@ -0,0 +1,148 @@
+use std::{any::Any, ffi::c_void};
+
+use coreaudio_sys::{kAudioObjectPropertyOwnedObjects, AudioClassID, AudioObjectID};
+
+use crate::{
+    class_hierarchy::descends_from,
+    os_err::{OSStatus, OSStatusError},
+    property::{ArrayProp, PropertySelector, Qualifier, RawProperty},
+};
+
+/// `kAudioObjectPropertyOwnedObjects`, listing the subobjects of an object along with their class and base class.
+///
+/// The HAL may pass an array of `AudioClassID`s as the qualifier to only ask for some of the children, e.g. just the
+/// streams of a device. A child matches if its class or its base class is (or derives from) one of the listed classes.
+/// An empty qualifier lists all children.
+///
+/// The value of this property (see [`RawPropertyExt::value`](crate::property::RawPropertyExt::value)) is the
+/// `OwnedObjectsProp` itself
+#[derive(Debug, Clone, Default)]
+pub struct OwnedObjectsProp {
+    ids: ArrayProp<AudioObjectID, kAudioObjectPropertyOwnedObjects>,
+    classes: Vec<(AudioClassID, AudioClassID)>,
+}
+
+impl OwnedObjectsProp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The ids of all children, in the order they were added
+    pub fn ids(&self) -> &[AudioObjectID] {
+        &self.ids
+    }
+    pub fn contains(&self, id: AudioObjectID) -> bool {
+        self.ids.contains(&id)
+    }
+    /// The class and base class `id` was added with
+    pub fn classes_of(&self, id: AudioObjectID) -> Option<(AudioClassID, AudioClassID)> {
+        let index = self.ids.iter().position(|&owned| owned == id)?;
+        Some(self.classes[index])
+    }
+    /// Add the child `id`, or update the classes of it if it is already listed
+    pub fn insert(&mut self, id: AudioObjectID, class: AudioClassID, base_class: AudioClassID) {
+        match self.ids.iter().position(|&owned| owned == id) {
+            Some(index) => self.classes[index] = (class, base_class),
+            None => {
+                self.ids.push(id);
+                self.classes.push((class, base_class));
+            }
+        }
+    }
+    /// Remove the child `id`, returning whether it was listed
+    pub fn remove(&mut self, id: AudioObjectID) -> bool {
+        let Some(index) = self.ids.iter().position(|&owned| owned == id) else {
+            return false;
+        };
+        self.ids.remove(index);
+        self.classes.remove(index);
+        true
+    }
+    /// The children matching any of the classes in `filter`, all of them if `filter` is empty
+    pub fn matching(&self, filter: &[AudioClassID]) -> Vec<AudioObjectID> {
+        if filter.is_empty() {
+            return self.ids.to_vec();
+        }
+        self.ids
+            .iter()
+            .zip(&self.classes)
+            .filter(|&(_, &(class, base_class))| {
+                filter.iter().any(|&wanted| {
+                    descends_from(class, wanted) || descends_from(base_class, wanted)
+                })
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+    /// The children asked for by `qualifier`. Qualifiers that aren't an array of class ids are ignored
+    fn qualified(&self, qualifier: Qualifier<'_>) -> Vec<AudioObjectID> {
+        let filter = qualifier.read_array::<AudioClassID>().unwrap_or_else(|| {
+            log::warn!(
+                "ignoring owned objects qualifier of {} bytes, not an array of class ids",
+                qualifier.size()
+            );
+            Vec::new()
+        });
+        self.matching(&filter)
+    }
+}
+
+impl RawProperty for OwnedObjectsProp {
+    fn selector(&self) -> PropertySelector {
+        kAudioObjectPropertyOwnedObjects.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.ids.byte_size()
+    }
+
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        Err(OSStatusError::HW_UNSUPPORTED_OP)
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.ids.get(out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        if qualifier.is_empty() {
+            return self.byte_size();
+        }
+        ArrayProp::<AudioObjectID, kAudioObjectPropertyOwnedObjects>::new_with(
+            self.qualified(qualifier),
+        )
+        .byte_size()
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if qualifier.is_empty() {
+            return unsafe { self.get(out_alloc_size, data_out, data_len_out) };
+        }
+        let matching = ArrayProp::<AudioObjectID, kAudioObjectPropertyOwnedObjects>::new_with(
+            self.qualified(qualifier),
+        );
+        unsafe { matching.get(out_alloc_size, data_out, data_len_out) }
+    }
+}
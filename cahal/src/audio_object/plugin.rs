@@ -0,0 +1,461 @@
+use std::{
+    any::Any,
+    ffi::c_void,
+    fmt,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use core_foundation::string::CFString;
+use coreaudio_sys::{
+    kAudioBoxClassID, kAudioBoxPropertyBoxUID, kAudioClockDeviceClassID,
+    kAudioClockDevicePropertyDeviceUID, kAudioDeviceClassID, kAudioDevicePropertyDeviceUID,
+    kAudioObjectClassID, kAudioObjectPlugInObject, kAudioObjectPropertyManufacturer,
+    kAudioObjectUnknown, kAudioPlugInClassID, kAudioPlugInPropertyBoxList,
+    kAudioPlugInPropertyBundleID, kAudioPlugInPropertyClockDeviceList,
+    kAudioPlugInPropertyDeviceList, kAudioPlugInPropertyResourceBundle,
+    kAudioPlugInPropertyTranslateUIDToBox, kAudioPlugInPropertyTranslateUIDToClockDevice,
+    kAudioPlugInPropertyTranslateUIDToDevice, AudioClassID, AudioObjectID,
+};
+
+use crate::{
+    os_err::{OSStatus, OSStatusError},
+    property::{
+        ArrayProp, CFStringProp, PropertySelector, RawProperty, UidTranslationProp, UrlProp,
+    },
+};
+
+use super::{AudioObject, AudioObjectBase, HasProperties, ObjectRegistry};
+
+/// The kinds of objects the plug-in object lists and translates UIDs for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PublishedKind {
+    Device,
+    Box,
+    ClockDevice,
+}
+
+impl PublishedKind {
+    /// The kind of objects of `class`, if the plug-in object lists them
+    pub(crate) fn of_class(class: AudioClassID) -> Option<Self> {
+        [
+            (kAudioDeviceClassID, Self::Device),
+            (kAudioBoxClassID, Self::Box),
+            (kAudioClockDeviceClassID, Self::ClockDevice),
+        ]
+        .into_iter()
+        .find_map(|(kind_class, kind)| (kind_class == class).then_some(kind))
+    }
+    /// The class of objects of this kind
+    pub(crate) fn class(self) -> AudioClassID {
+        match self {
+            Self::Device => kAudioDeviceClassID,
+            Self::Box => kAudioBoxClassID,
+            Self::ClockDevice => kAudioClockDeviceClassID,
+        }
+    }
+    /// The selector of the UID property of objects of this kind
+    pub(crate) fn uid_selector(self) -> u32 {
+        match self {
+            Self::Device => kAudioDevicePropertyDeviceUID,
+            Self::Box => kAudioBoxPropertyBoxUID,
+            Self::ClockDevice => kAudioClockDevicePropertyDeviceUID,
+        }
+    }
+    /// The selector of the list of objects of this kind on the plug-in object
+    pub(crate) fn list_selector(self) -> u32 {
+        match self {
+            Self::Device => kAudioPlugInPropertyDeviceList,
+            Self::Box => kAudioPlugInPropertyBoxList,
+            Self::ClockDevice => kAudioPlugInPropertyClockDeviceList,
+        }
+    }
+}
+
+/// The devices, boxes and clock devices published by the plug-in object along with their UIDs, in the order they were
+/// published
+#[derive(Debug, Default)]
+pub(crate) struct Published {
+    entries: Vec<(PublishedKind, AudioObjectID, String)>,
+}
+
+pub(crate) type SharedPublished = Arc<RwLock<Published>>;
+
+impl Published {
+    pub(crate) fn ids(&self, kind: PublishedKind) -> Vec<AudioObjectID> {
+        self.entries
+            .iter()
+            .filter(|(k, _, _)| *k == kind)
+            .map(|(_, id, _)| *id)
+            .collect()
+    }
+    fn all(&self) -> impl Iterator<Item = (PublishedKind, AudioObjectID)> + '_ {
+        self.entries.iter().map(|(kind, id, _)| (*kind, *id))
+    }
+    fn lookup(&self, kind: PublishedKind, uid: &str) -> Option<AudioObjectID> {
+        self.entries
+            .iter()
+            .find(|(k, _, u)| *k == kind && u == uid)
+            .map(|(_, id, _)| *id)
+    }
+    /// Publish `id`, or update its UID if it is published already
+    pub(crate) fn insert(&mut self, kind: PublishedKind, id: AudioObjectID, uid: &str) {
+        match self.entries.iter_mut().find(|(_, i, _)| *i == id) {
+            Some(entry) => *entry = (kind, id, uid.to_owned()),
+            None => self.entries.push((kind, id, uid.to_owned())),
+        }
+    }
+    /// Stop publishing `id`, returning its kind if it was published
+    pub(crate) fn remove(&mut self, id: AudioObjectID) -> Option<PublishedKind> {
+        let index = self.entries.iter().position(|(_, i, _)| *i == id)?;
+        Some(self.entries.remove(index).0)
+    }
+}
+
+/// One of the object lists of the plug-in object, read from the shared [`Published`] set on every request
+struct PublishedListProp<const SEL: u32> {
+    published: SharedPublished,
+    kind: PublishedKind,
+}
+
+impl<const SEL: u32> PublishedListProp<SEL> {
+    fn new(published: &SharedPublished, kind: PublishedKind) -> Self {
+        Self {
+            published: published.clone(),
+            kind,
+        }
+    }
+    fn array(&self) -> ArrayProp<AudioObjectID, SEL> {
+        ArrayProp::new_with(
+            self.published
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .ids(self.kind),
+        )
+    }
+}
+
+impl<const SEL: u32> fmt::Debug for PublishedListProp<SEL> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.published
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .ids(self.kind),
+            )
+            .finish()
+    }
+}
+
+impl<const SEL: u32> RawProperty for PublishedListProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.array().byte_size()
+    }
+
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        self.array().value_bytes()
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        Err(OSStatusError::HW_UNSUPPORTED_OP)
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.array().get(out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+fn uid_translation<const SEL: u32>(
+    published: &SharedPublished,
+    kind: PublishedKind,
+) -> UidTranslationProp<SEL> {
+    let published = published.clone();
+    UidTranslationProp::uid_to_object(move |uid| {
+        published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .lookup(kind, uid)
+    })
+}
+
+/// The plug-in object (`kAudioObjectPlugInObject`) every driver has to publish, with the standard plug-in properties.
+///
+/// The device, box and clock device lists only hold ids, the objects themselves are registered separately (see
+/// [`ObjectRegistry`](super::ObjectRegistry)). After [`PluginObject::attach`]ing the plug-in object to a registry, the
+/// lists and UID translations follow the devices, boxes and clock devices owned by the plug-in object that are
+/// registered there, looking them up by their UID property. Otherwise use [`PluginObject::add_device`] and friends to
+/// publish objects by hand
+#[derive(Debug, HasProperties)]
+pub struct PluginObject {
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    pub manufacturer: CFStringProp<kAudioObjectPropertyManufacturer>,
+    pub bundle_id: CFStringProp<kAudioPlugInPropertyBundleID>,
+    /// Path of the resource bundle relative to the plug-in bundle, empty (the default) for the plug-in bundle itself
+    pub resource_bundle: CFStringProp<kAudioPlugInPropertyResourceBundle>,
+    device_list: PublishedListProp<kAudioPlugInPropertyDeviceList>,
+    box_list: PublishedListProp<kAudioPlugInPropertyBoxList>,
+    clock_device_list: PublishedListProp<kAudioPlugInPropertyClockDeviceList>,
+    translate_device: UidTranslationProp<kAudioPlugInPropertyTranslateUIDToDevice>,
+    translate_box: UidTranslationProp<kAudioPlugInPropertyTranslateUIDToBox>,
+    translate_clock_device: UidTranslationProp<kAudioPlugInPropertyTranslateUIDToClockDevice>,
+    #[property(skip)]
+    published: SharedPublished,
+}
+
+impl PluginObject {
+    pub fn new(manufacturer: &str, bundle_id: &str) -> Self {
+        let published = SharedPublished::default();
+        Self {
+            base: AudioObjectBase::new(
+                kAudioObjectClassID,
+                kAudioPlugInClassID,
+                kAudioObjectUnknown,
+                manufacturer.to_owned(),
+            ),
+            manufacturer: CFStringProp::new(CFString::new(manufacturer)),
+            bundle_id: CFStringProp::new(CFString::new(bundle_id)),
+            resource_bundle: CFStringProp::from_static(""),
+            device_list: PublishedListProp::new(&published, PublishedKind::Device),
+            box_list: PublishedListProp::new(&published, PublishedKind::Box),
+            clock_device_list: PublishedListProp::new(&published, PublishedKind::ClockDevice),
+            translate_device: uid_translation(&published, PublishedKind::Device),
+            translate_box: uid_translation(&published, PublishedKind::Box),
+            translate_clock_device: uid_translation(&published, PublishedKind::ClockDevice),
+            published,
+        }
+    }
+    /// Report `path`, relative to the plug-in bundle, as the bundle the HAL loads resources like icons from. The empty
+    /// string stands for the plug-in bundle itself
+    pub fn with_resource_bundle(mut self, path: &str) -> Self {
+        self.resource_bundle = CFStringProp::new(CFString::new(path));
+        self
+    }
+    /// The URL of the resource `name` in the resource bundle, e.g. for `kAudioDevicePropertyIcon`. See
+    /// [`UrlProp::bundled_resource`]
+    pub fn resource<const SEL: u32>(&self, name: &str) -> Option<UrlProp<SEL>> {
+        UrlProp::bundled_resource(
+            &self.bundle_id.0.to_string(),
+            &self.resource_bundle.0.to_string(),
+            name,
+        )
+    }
+    /// Derive the device, box and clock device lists and UID translations from the objects registered with `registry`,
+    /// see the type documentation. Objects published by hand so far are carried over. Register the plug-in object with
+    /// the same registry, so that its owned objects are kept up to date as well
+    pub fn attach(&mut self, registry: &ObjectRegistry) {
+        let published = registry.published().clone();
+        if !Arc::ptr_eq(&published, &self.published) {
+            let mut shared = published.write().unwrap_or_else(PoisonError::into_inner);
+            for (kind, id, uid) in self
+                .published
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entries
+                .iter()
+            {
+                shared.insert(*kind, *id, uid);
+            }
+        }
+        self.device_list = PublishedListProp::new(&published, PublishedKind::Device);
+        self.box_list = PublishedListProp::new(&published, PublishedKind::Box);
+        self.clock_device_list = PublishedListProp::new(&published, PublishedKind::ClockDevice);
+        self.translate_device = uid_translation(&published, PublishedKind::Device);
+        self.translate_box = uid_translation(&published, PublishedKind::Box);
+        self.translate_clock_device = uid_translation(&published, PublishedKind::ClockDevice);
+        self.published = published;
+        let published: Vec<_> = self
+            .published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .all()
+            .collect();
+        for (kind, id) in published {
+            self.base
+                .owned_objects
+                .insert(id, kind.class(), kAudioObjectClassID);
+        }
+    }
+    pub fn devices(&self) -> Vec<AudioObjectID> {
+        self.published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ids(PublishedKind::Device)
+    }
+    pub fn boxes(&self) -> Vec<AudioObjectID> {
+        self.published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ids(PublishedKind::Box)
+    }
+    pub fn clock_devices(&self) -> Vec<AudioObjectID> {
+        self.published
+            .read()
+            .unwrap()
+            .ids(PublishedKind::ClockDevice)
+    }
+    fn add_published(&mut self, kind: PublishedKind, id: AudioObjectID, uid: &str) {
+        self.published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(kind, id, uid);
+        self.base
+            .owned_objects
+            .insert(id, kind.class(), kAudioObjectClassID);
+    }
+    fn remove_published(&mut self, kind: PublishedKind, id: AudioObjectID) -> bool {
+        let mut published = self
+            .published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !published.ids(kind).contains(&id) {
+            return false;
+        }
+        published.remove(id);
+        self.base.owned_objects.remove(id);
+        true
+    }
+    /// Publish the device with the given id and UID. Adding a device twice only updates its UID
+    pub fn add_device(&mut self, id: AudioObjectID, uid: &str) {
+        self.add_published(PublishedKind::Device, id, uid);
+    }
+    /// Stop publishing a device, returning whether it was published
+    pub fn remove_device(&mut self, id: AudioObjectID) -> bool {
+        self.remove_published(PublishedKind::Device, id)
+    }
+    /// Publish the box with the given id and UID, see [`PluginObject::add_device`]
+    pub fn add_box(&mut self, id: AudioObjectID, uid: &str) {
+        self.add_published(PublishedKind::Box, id, uid);
+    }
+    pub fn remove_box(&mut self, id: AudioObjectID) -> bool {
+        self.remove_published(PublishedKind::Box, id)
+    }
+    /// Publish the clock device with the given id and UID, see [`PluginObject::add_device`]
+    pub fn add_clock_device(&mut self, id: AudioObjectID, uid: &str) {
+        self.add_published(PublishedKind::ClockDevice, id, uid);
+    }
+    pub fn remove_clock_device(&mut self, id: AudioObjectID) -> bool {
+        self.remove_published(PublishedKind::ClockDevice, id)
+    }
+}
+
+impl AudioObject for PluginObject {
+    fn id(&self) -> AudioObjectID {
+        kAudioObjectPlugInObject
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_foundation::{base::TCFType, string::CFStringRef};
+
+    use super::*;
+    use crate::{
+        audio_object::DeviceBuilder,
+        os_err::OSResult,
+        property::{PropertyAddress, Qualifier},
+    };
+
+    fn two_devices() -> (ObjectRegistry, AudioObjectID, AudioObjectID) {
+        let mut objects = ObjectRegistry::new();
+        let mut plugin = PluginObject::new("cahal", "com.example.test");
+        plugin.attach(&objects);
+        objects.register(Box::new(plugin)).unwrap();
+        let first = DeviceBuilder::new("First", "com.example.first")
+            .output_stream(2)
+            .build(&mut objects)
+            .unwrap();
+        let second = DeviceBuilder::new("Second", "com.example.second")
+            .input_stream(1)
+            .build(&mut objects)
+            .unwrap();
+        (objects, first.id(), second.id())
+    }
+
+    fn translate(objects: &ObjectRegistry, uid: &str) -> OSResult<AudioObjectID> {
+        let uid = CFString::new(uid);
+        let uid_ref = uid.as_concrete_TypeRef();
+        let qualifier = unsafe {
+            Qualifier::new(
+                std::mem::size_of::<CFStringRef>() as u32,
+                (&uid_ref as *const CFStringRef).cast(),
+            )
+        };
+        let prop = objects.property(
+            kAudioObjectPlugInObject,
+            PropertyAddress::global(kAudioPlugInPropertyTranslateUIDToDevice),
+        )?;
+        let mut id: AudioObjectID = 0;
+        let mut written = 0;
+        unsafe {
+            prop.get_qualified(
+                qualifier,
+                std::mem::size_of::<AudioObjectID>() as u32,
+                (&mut id as *mut AudioObjectID).cast(),
+                &mut written,
+            )?
+        };
+        assert_eq!(written as usize, std::mem::size_of::<AudioObjectID>());
+        Ok(id)
+    }
+
+    #[test]
+    fn device_list_follows_the_registry() {
+        let (mut objects, first, second) = two_devices();
+        let list = |objects: &ObjectRegistry| {
+            objects
+                .property(
+                    kAudioObjectPlugInObject,
+                    PropertyAddress::global(kAudioPlugInPropertyDeviceList),
+                )
+                .unwrap()
+                .value_bytes()
+                .unwrap()
+                .chunks_exact(4)
+                .map(|id| AudioObjectID::from_ne_bytes(id.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(list(&objects), [first, second]);
+        objects.unregister(first);
+        assert_eq!(list(&objects), [second]);
+    }
+
+    #[test]
+    fn uids_translate_to_their_device() {
+        let (objects, first, second) = two_devices();
+        assert_eq!(translate(&objects, "com.example.first").ok(), Some(first));
+        assert_eq!(translate(&objects, "com.example.second").ok(), Some(second));
+    }
+
+    #[test]
+    fn unknown_uids_translate_to_the_unknown_object() {
+        let (objects, _, _) = two_devices();
+        assert_eq!(
+            translate(&objects, "com.example.third").ok(),
+            Some(kAudioObjectUnknown)
+        );
+        // a miss is answered with the unknown object rather than an error, even for an empty UID
+        assert_eq!(translate(&objects, "").ok(), Some(kAudioObjectUnknown));
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    any::Any,
+    ffi::c_void,
+    mem,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use coreaudio_sys::{
+    kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyNominalSampleRate,
+    AudioObjectID,
+};
+
+use crate::{
+    notification::ChangeQueue,
+    os_err::{OSStatus, OSStatusError},
+    property::{
+        read_value, write_value, AtomicHandle, PropertyAddress, PropertySelector, RangeListProp,
+        RawProperty,
+    },
+};
+
+use super::HasProperties;
+
+/// Asks the host for a configuration change to switch to the given sample rate
+pub type RateRequestHook = Box<dyn FnMut(f64) -> OSStatus + Send>;
+
+/// The committed and requested rates of a device
+#[derive(Debug)]
+struct RateState {
+    rate: f64,
+    pending: Option<f64>,
+    changes: Option<ChangeQueue>,
+}
+
+/// What a [`SampleRateManager`] shares with its handles
+struct RateShared {
+    state: Mutex<RateState>,
+    request: Mutex<Option<RateRequestHook>>,
+}
+
+/// A handle to the sample rates of a device that stays usable once the device was registered, see
+/// [`SampleRateManager::handle`]. This is also the value of the device's `kAudioDevicePropertyNominalSampleRate` as
+/// seen through [`RawPropertyExt::value`](crate::property::RawPropertyExt::value)
+#[derive(Clone)]
+pub struct SampleRateHandle {
+    object: AudioObjectID,
+    shared: Arc<RateShared>,
+    seed: AtomicHandle<u32>,
+}
+
+impl SampleRateHandle {
+    fn lock(&self) -> MutexGuard<'_, RateState> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Run `request` with the new rate when the HAL asks for a rate change, see [`SampleRateManager::on_request`]
+    pub fn on_request(&self, request: impl FnMut(f64) -> OSStatus + Send + 'static) {
+        *self
+            .shared
+            .request
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(request));
+    }
+    /// The committed rate
+    pub fn rate(&self) -> f64 {
+        self.lock().rate
+    }
+    /// The rate of a request that hasn't been performed or aborted yet
+    pub fn pending(&self) -> Option<f64> {
+        self.lock().pending
+    }
+    /// Commit the pending rate, returning it. Call this from `perform_change`
+    pub fn perform(&self) -> Option<f64> {
+        let mut state = self.lock();
+        let rate = state.pending.take()?;
+        if mem::replace(&mut state.rate, rate) != rate {
+            self.seed.store(self.seed.load().wrapping_add(1).max(1));
+            if let Some(changes) = &state.changes {
+                changes.mark(
+                    self.object,
+                    PropertyAddress::global(kAudioDevicePropertyNominalSampleRate),
+                );
+            }
+        }
+        Some(rate)
+    }
+    /// Drop the pending rate, returning it. Call this from `abort_change`
+    pub fn abort(&self) -> Option<f64> {
+        self.lock().pending.take()
+    }
+    /// The zero time stamp seed, see [`SampleRateManager::seed_handle`]
+    pub fn seed_handle(&self) -> AtomicHandle<u32> {
+        self.seed.clone()
+    }
+    fn mark_changed(&self, selector: u32) {
+        if let Some(changes) = &self.lock().changes {
+            changes.mark(self.object, PropertyAddress::global(selector));
+        }
+    }
+}
+
+impl std::fmt::Debug for SampleRateHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("SampleRateHandle")
+            .field("object", &self.object)
+            .field("rate", &state.rate)
+            .field("pending", &state.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `kAudioDevicePropertyNominalSampleRate`: reports the committed rate and turns writes into requests
+struct NominalRateProp {
+    handle: SampleRateHandle,
+    available: RangeListProp<kAudioDevicePropertyAvailableNominalSampleRates>,
+}
+
+impl NominalRateProp {
+    fn request(&mut self, rate: f64) -> OSStatus {
+        if !self.available.contains(rate) {
+            log::error!("sample rate {rate} is not available");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        let mut request = self
+            .handle
+            .shared
+            .request
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let previous = {
+            let mut state = self.handle.lock();
+            if state.pending.is_none() && rate == state.rate {
+                return Ok(());
+            }
+            if request.is_none() {
+                log::error!("can't change the sample rate to {rate}, no request hook was set");
+                return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+            }
+            state.pending.replace(rate)
+        };
+        // the state isn't locked while the host is asked, in case it performs the change right away
+        if let Some(request) = request.as_mut()
+            && let Err(err) = request(rate)
+        {
+            self.handle.lock().pending = previous;
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for NominalRateProp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NominalRateProp")
+            .field(&self.handle)
+            .finish()
+    }
+}
+
+impl RawProperty for NominalRateProp {
+    fn selector(&self) -> PropertySelector {
+        kAudioDevicePropertyNominalSampleRate.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<f64>() as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    // writes only request the new rate, the handle reports it once it is committed
+    fn is_deferred(&self) -> bool {
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.handle
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.handle
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let rate: f64 = unsafe { read_value(data, data_size)? };
+        self.request(rate)
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.handle.rate(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// The nominal and available sample rates of a device, with the deferred commit the HAL expects for rate changes.
+///
+/// A write of `kAudioDevicePropertyNominalSampleRate` is checked against the available rates and then handed to the hook
+/// set with [`SampleRateManager::on_request`], which should ask the host for a configuration change. The property keeps
+/// reporting the old rate until the driver calls [`SampleRateManager::perform`] from
+/// `perform_change`, or drops the request with [`SampleRateManager::abort`] from
+/// `abort_change`. Committing a new rate bumps the zero time stamp seed, see
+/// [`SampleRateManager::seed_handle`].
+///
+/// Once the device is registered, the driver reaches its rates through a [`SampleRateHandle`]
+#[derive(Debug, HasProperties)]
+pub struct SampleRateManager {
+    rate: NominalRateProp,
+    available: RangeListProp<kAudioDevicePropertyAvailableNominalSampleRates>,
+}
+
+impl SampleRateManager {
+    /// Rates for the device `object`, supporting only `rate`
+    pub fn new(object: AudioObjectID, rate: f64) -> Self {
+        let handle = SampleRateHandle {
+            object,
+            shared: Arc::new(RateShared {
+                state: Mutex::new(RateState {
+                    rate,
+                    pending: None,
+                    changes: None,
+                }),
+                request: Mutex::new(None),
+            }),
+            seed: AtomicHandle::new(1),
+        };
+        Self {
+            rate: NominalRateProp {
+                handle,
+                available: RangeListProp::from_discrete(&[rate]),
+            },
+            available: RangeListProp::from_discrete(&[rate]),
+        }
+    }
+    /// Run `request` with the new rate when the HAL asks for a rate change. It should request a configuration change from
+    /// the host, e.g. with `PluginHostInterface::request_change`. Until a hook is set, the rate can't be changed by the
+    /// HAL
+    pub fn on_request(&mut self, request: impl FnMut(f64) -> OSStatus + Send + 'static) {
+        self.rate.handle.on_request(request);
+    }
+    pub(crate) fn set_changes(&mut self, changes: ChangeQueue) {
+        self.rate.handle.lock().changes = Some(changes);
+    }
+    /// A handle to these rates for after the device was registered
+    pub fn handle(&self) -> SampleRateHandle {
+        self.rate.handle.clone()
+    }
+    /// The committed rate
+    pub fn rate(&self) -> f64 {
+        self.rate.handle.rate()
+    }
+    /// The rate of a request that hasn't been performed or aborted yet
+    pub fn pending(&self) -> Option<f64> {
+        self.rate.handle.pending()
+    }
+    pub fn available(&self) -> &RangeListProp<kAudioDevicePropertyAvailableNominalSampleRates> {
+        &self.available
+    }
+    /// Replace the available rates. The current rate is kept even if it isn't among them
+    pub fn set_available(
+        &mut self,
+        available: RangeListProp<kAudioDevicePropertyAvailableNominalSampleRates>,
+    ) {
+        self.rate.available = available.clone();
+        self.available = available;
+        self.rate
+            .handle
+            .mark_changed(kAudioDevicePropertyAvailableNominalSampleRates);
+    }
+    /// Ask for a switch to `rate` from Rust, the same way a write by the HAL does
+    pub fn request(&mut self, rate: f64) -> OSStatus {
+        self.rate.request(rate)
+    }
+    /// Commit the pending rate, returning it. Call this from `perform_change`
+    pub fn perform(&mut self) -> Option<f64> {
+        self.rate.handle.perform()
+    }
+    /// Drop the pending rate, returning it. Call this from `abort_change`
+    pub fn abort(&mut self) -> Option<f64> {
+        self.rate.handle.abort()
+    }
+    /// The zero time stamp seed, which changes whenever a new rate is committed. Hand this to the IO path to report it
+    /// from `get_zero_time_stamp`
+    pub fn seed_handle(&self) -> AtomicHandle<u32> {
+        self.rate.handle.seed_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::PoisonError;
+
+    use super::*;
+    use crate::{
+        audio_object::{DeviceBuilder, ObjectRegistry},
+        property::{Qualifier, RawPropertyExt},
+    };
+
+    fn device(changes: &ChangeQueue) -> (ObjectRegistry, AudioObjectID, SampleRateHandle) {
+        let mut objects = ObjectRegistry::new();
+        objects.notify_via(changes.clone());
+        let device = DeviceBuilder::new("Device", "com.example.device")
+            .sample_rates(&[44100.0, 48000.0])
+            .notify_via(changes.clone())
+            .build(&mut objects)
+            .unwrap();
+        let _ = changes.take();
+        (objects, device.id(), device.sample_rate().clone())
+    }
+
+    fn write(objects: &mut ObjectRegistry, device: AudioObjectID, rate: f64) -> OSStatus {
+        unsafe {
+            objects.set_property(
+                device,
+                42,
+                PropertyAddress::global(kAudioDevicePropertyNominalSampleRate),
+                Qualifier::NONE,
+                (&rate as *const f64).cast(),
+                mem::size_of::<f64>() as u32,
+            )
+        }
+    }
+
+    fn changed(changes: &ChangeQueue) -> Vec<u32> {
+        changes
+            .take()
+            .iter()
+            .map(|(_, address)| address.selector.into())
+            .collect()
+    }
+
+    #[test]
+    fn requested_rates_are_reported_once_performed() {
+        let changes = ChangeQueue::new();
+        let (mut objects, id, handle) = device(&changes);
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        handle.on_request({
+            let requested = requested.clone();
+            move |rate| {
+                requested
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(rate);
+                Ok(())
+            }
+        });
+
+        assert!(write(&mut objects, id, 48000.0).is_ok());
+        assert_eq!(*requested.lock().unwrap(), [48000.0]);
+        assert_eq!(handle.pending(), Some(48000.0));
+        assert_eq!(handle.rate(), 44100.0);
+        // the write only asked for the change
+        assert_eq!(changed(&changes), [] as [u32; 0]);
+
+        let seed = handle.seed_handle().load();
+        assert_eq!(handle.perform(), Some(48000.0));
+        assert_eq!(handle.rate(), 48000.0);
+        assert_ne!(handle.seed_handle().load(), seed);
+        assert_eq!(changed(&changes), [kAudioDevicePropertyNominalSampleRate]);
+    }
+
+    #[test]
+    fn aborted_rates_are_never_reported() {
+        let changes = ChangeQueue::new();
+        let (mut objects, id, handle) = device(&changes);
+        handle.on_request(|_| Ok(()));
+
+        assert!(write(&mut objects, id, 48000.0).is_ok());
+        assert_eq!(handle.abort(), Some(48000.0));
+        assert_eq!(handle.pending(), None);
+        assert_eq!(handle.perform(), None);
+        assert_eq!(handle.rate(), 44100.0);
+        assert_eq!(changed(&changes), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn refused_requests_leave_nothing_pending() {
+        let changes = ChangeQueue::new();
+        let (mut objects, id, handle) = device(&changes);
+        assert!(write(&mut objects, id, 48000.0).is_err());
+        handle.on_request(|_| Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR));
+        assert!(write(&mut objects, id, 48000.0).is_err());
+        assert!(write(&mut objects, id, 96000.0).is_err());
+        assert_eq!(handle.pending(), None);
+        assert_eq!(changed(&changes), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn the_property_value_is_the_handle() {
+        let changes = ChangeQueue::new();
+        let (objects, id, _) = device(&changes);
+        let prop = objects
+            .property(
+                id,
+                PropertyAddress::global(kAudioDevicePropertyNominalSampleRate),
+            )
+            .unwrap();
+        let value = prop.value::<SampleRateHandle>().unwrap();
+        assert_eq!(value.rate(), 44100.0);
+        assert!(prop.is_deferred());
+    }
+}
@@ -0,0 +1,344 @@
+use coreaudio_sys::{
+    kAudioObjectClassID, kAudioStreamClassID, kAudioStreamPropertyAvailablePhysicalFormats,
+    kAudioStreamPropertyAvailableVirtualFormats, kAudioStreamPropertyDirection,
+    kAudioStreamPropertyIsActive, kAudioStreamPropertyLatency, kAudioStreamPropertyPhysicalFormat,
+    kAudioStreamPropertyStartingChannel, kAudioStreamPropertyVirtualFormat, AudioObjectID,
+    AudioStreamBasicDescription, AudioStreamRangedDescription,
+};
+
+use crate::{
+    os_err::{OSStatus, OSStatusError},
+    property::{ArrayProp, AsbdProp, BoolProp, EnumProp, FormatSet, Prop, PropertyScope},
+    property_enum,
+};
+
+use super::{AudioObject, AudioObjectBase, HasProperties};
+
+property_enum! {
+    /// The direction of a stream, as reported by `kAudioStreamPropertyDirection`
+    #[derive(Debug)]
+    pub enum StreamDirection {
+        Output = 0,
+        Input = 1,
+    }
+}
+
+impl StreamDirection {
+    /// The scope the streams of this direction are listed in on their device
+    pub fn scope(self) -> PropertyScope {
+        match self {
+            StreamDirection::Output => PropertyScope::OUTPUT,
+            StreamDirection::Input => PropertyScope::INPUT,
+        }
+    }
+}
+
+/// A stream of an [`AudioDeviceObject`](super::AudioDeviceObject), with its direction, channel range and formats.
+///
+/// The virtual and physical formats each keep a [`FormatSet`] of supported formats, which is published through the
+/// matching `Available*Formats` property and used to resolve format changes requested by the HAL. Use
+/// [`AudioStreamObject::add_format`] to extend both sets so they stay in sync
+#[derive(Debug, HasProperties)]
+pub struct AudioStreamObject {
+    #[property(skip)]
+    id: AudioObjectID,
+    #[property(flatten)]
+    pub base: AudioObjectBase,
+    pub is_active: BoolProp<kAudioStreamPropertyIsActive, true>,
+    direction: EnumProp<StreamDirection, kAudioStreamPropertyDirection>,
+    /// The device channel number of the first channel of this stream, starting at 1
+    pub starting_channel: Prop<u32, kAudioStreamPropertyStartingChannel>,
+    pub latency: Prop<u32, kAudioStreamPropertyLatency>,
+    virtual_format: AsbdProp<kAudioStreamPropertyVirtualFormat, true>,
+    physical_format: AsbdProp<kAudioStreamPropertyPhysicalFormat, true>,
+    available_virtual_formats:
+        ArrayProp<AudioStreamRangedDescription, kAudioStreamPropertyAvailableVirtualFormats>,
+    available_physical_formats:
+        ArrayProp<AudioStreamRangedDescription, kAudioStreamPropertyAvailablePhysicalFormats>,
+}
+
+impl AudioStreamObject {
+    /// A stream owned by the device `owner`, starting at the first device channel, with `format` as the only supported
+    /// virtual and physical format
+    pub fn new(
+        id: AudioObjectID,
+        owner: AudioObjectID,
+        direction: StreamDirection,
+        format: AudioStreamBasicDescription,
+    ) -> Self {
+        Self {
+            id,
+            base: AudioObjectBase::new(kAudioObjectClassID, kAudioStreamClassID, owner, ""),
+            is_active: BoolProp(true),
+            direction: EnumProp(direction),
+            starting_channel: Prop(1),
+            latency: Prop(0),
+            virtual_format: AsbdProp::new(format),
+            physical_format: AsbdProp::new(format),
+            available_virtual_formats: ArrayProp::new_with(
+                FormatSet::single(format).formats().to_vec(),
+            ),
+            available_physical_formats: ArrayProp::new_with(
+                FormatSet::single(format).formats().to_vec(),
+            ),
+        }
+    }
+    pub fn direction(&self) -> StreamDirection {
+        self.direction.get()
+    }
+    pub fn virtual_format(&self) -> &AudioStreamBasicDescription {
+        self.virtual_format.format()
+    }
+    pub fn physical_format(&self) -> &AudioStreamBasicDescription {
+        self.physical_format.format()
+    }
+    /// The supported virtual formats, as published through `kAudioStreamPropertyAvailableVirtualFormats`
+    pub fn available_virtual_formats(&self) -> &[AudioStreamRangedDescription] {
+        &self.available_virtual_formats
+    }
+    /// The supported physical formats, as published through `kAudioStreamPropertyAvailablePhysicalFormats`
+    pub fn available_physical_formats(&self) -> &[AudioStreamRangedDescription] {
+        &self.available_physical_formats
+    }
+    /// Add `format` to the supported virtual and physical formats
+    pub fn add_format(&mut self, format: AudioStreamBasicDescription) {
+        self.virtual_format.add_supported(format);
+        self.physical_format.add_supported(format);
+        self.sync_available_formats();
+    }
+    /// Add `format` at any sample rate from `min_rate` to `max_rate` to the supported virtual and physical formats
+    pub fn add_format_range(
+        &mut self,
+        format: AudioStreamBasicDescription,
+        min_rate: f64,
+        max_rate: f64,
+    ) {
+        self.virtual_format
+            .supported_mut()
+            .add_ranged(format, min_rate, max_rate);
+        self.physical_format
+            .supported_mut()
+            .add_ranged(format, min_rate, max_rate);
+        self.sync_available_formats();
+    }
+    fn sync_available_formats(&mut self) {
+        *self.available_virtual_formats = self.virtual_format.supported().formats().to_vec();
+        *self.available_physical_formats = self.physical_format.supported().formats().to_vec();
+    }
+    /// The supported virtual formats, which writes of `kAudioStreamPropertyVirtualFormat` are resolved against
+    pub fn virtual_formats(&self) -> &FormatSet {
+        self.virtual_format.supported()
+    }
+    /// The supported physical formats, which writes of `kAudioStreamPropertyPhysicalFormat` are resolved against
+    pub fn physical_formats(&self) -> &FormatSet {
+        self.physical_format.supported()
+    }
+    /// Switch the virtual and the physical format to `format`, which may contain wildcards (see [`FormatSet`]). Fails
+    /// with [`OSStatusError::DEV_UNSUPPORTED_FMT_ERR`], leaving both formats unchanged, unless `format` is supported as
+    /// both
+    pub fn set_format(&mut self, format: AudioStreamBasicDescription) -> OSStatus {
+        if !self.virtual_format.is_supported(&format) || !self.physical_format.is_supported(&format)
+        {
+            return Err(OSStatusError::DEV_UNSUPPORTED_FMT_ERR);
+        }
+        self.virtual_format.set_format(format)?;
+        self.physical_format.set_format(format).map(drop)
+    }
+    /// Switch only the virtual format, see [`AsbdProp::set_format`]
+    pub fn set_virtual_format(&mut self, format: AudioStreamBasicDescription) -> OSStatus {
+        self.virtual_format.set_format(format).map(drop)
+    }
+    /// Switch only the physical format, see [`AsbdProp::set_format`]
+    pub fn set_physical_format(&mut self, format: AudioStreamBasicDescription) -> OSStatus {
+        self.physical_format.set_format(format).map(drop)
+    }
+}
+
+impl AudioObject for AudioStreamObject {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use coreaudio_sys::{kAudioDeviceUnsupportedFormatError, AudioValueRange};
+
+    use super::*;
+    use crate::{
+        os_err::result_to_err_code,
+        property::{asbd_eq, float32_format, PropertyAddress, RawProperty},
+    };
+
+    fn stream() -> AudioStreamObject {
+        let mut stream =
+            AudioStreamObject::new(3, 2, StreamDirection::Output, float32_format(44_100.0, 2));
+        stream.add_format(float32_format(48_000.0, 2));
+        stream
+    }
+
+    fn property(stream: &mut AudioStreamObject, selector: u32) -> &mut dyn RawProperty {
+        stream
+            .get_object_property_mut(PropertyAddress::global(selector))
+            .unwrap()
+    }
+
+    /// Write `format` to the property `selector` of `stream` the way the HAL does, returning the status code
+    fn write_format(
+        stream: &mut AudioStreamObject,
+        selector: u32,
+        format: AudioStreamBasicDescription,
+    ) -> i32 {
+        let size = mem::size_of::<AudioStreamBasicDescription>() as u32;
+        let prop = property(stream, selector);
+        result_to_err_code(unsafe { prop.set((&raw const format).cast(), size) })
+    }
+
+    /// Read the ranged formats of the property `selector` of `stream` the way the HAL does
+    fn read_formats(
+        stream: &mut AudioStreamObject,
+        selector: u32,
+    ) -> Vec<(AudioStreamBasicDescription, f64, f64)> {
+        let prop = property(stream, selector);
+        let size = prop.byte_size();
+        let item_size = mem::size_of::<AudioStreamRangedDescription>();
+        assert_eq!(size as usize % item_size, 0);
+        let empty = AudioStreamRangedDescription {
+            mFormat: float32_format(0.0, 0),
+            mSampleRateRange: AudioValueRange {
+                mMinimum: 0.0,
+                mMaximum: 0.0,
+            },
+        };
+        let mut formats = vec![empty; size as usize / item_size];
+        let mut written = 0;
+        unsafe { prop.get(size, formats.as_mut_ptr().cast(), &mut written) }.unwrap();
+        assert_eq!(written, size);
+        formats
+            .iter()
+            .map(|ranged| {
+                let range = &ranged.mSampleRateRange;
+                (ranged.mFormat, range.mMinimum, range.mMaximum)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn formats_switch_together() {
+        let mut stream = stream();
+        assert_eq!(
+            result_to_err_code(stream.set_format(float32_format(48_000.0, 2))),
+            0
+        );
+        assert!(asbd_eq(
+            stream.virtual_format(),
+            &float32_format(48_000.0, 2)
+        ));
+        assert!(asbd_eq(
+            stream.physical_format(),
+            &float32_format(48_000.0, 2)
+        ));
+
+        let unsupported = kAudioDeviceUnsupportedFormatError as i32;
+        assert_eq!(
+            result_to_err_code(stream.set_format(float32_format(96_000.0, 2))),
+            unsupported
+        );
+        assert_eq!(
+            result_to_err_code(stream.set_format(float32_format(48_000.0, 1))),
+            unsupported
+        );
+        assert!(asbd_eq(
+            stream.virtual_format(),
+            &float32_format(48_000.0, 2)
+        ));
+        assert!(asbd_eq(
+            stream.physical_format(),
+            &float32_format(48_000.0, 2)
+        ));
+    }
+
+    #[test]
+    fn the_hal_switches_one_format_at_a_time() {
+        let mut stream = stream();
+        assert_eq!(
+            write_format(
+                &mut stream,
+                kAudioStreamPropertyVirtualFormat,
+                float32_format(48_000.0, 2)
+            ),
+            0
+        );
+        assert!(asbd_eq(
+            stream.virtual_format(),
+            &float32_format(48_000.0, 2)
+        ));
+        assert!(asbd_eq(
+            stream.physical_format(),
+            &float32_format(44_100.0, 2)
+        ));
+
+        // a wildcard sample rate resolves to the first supported format
+        let format = AudioStreamBasicDescription {
+            mSampleRate: 0.0,
+            ..float32_format(48_000.0, 2)
+        };
+        assert_eq!(
+            write_format(&mut stream, kAudioStreamPropertyPhysicalFormat, format),
+            0
+        );
+        assert!(asbd_eq(
+            stream.physical_format(),
+            &float32_format(44_100.0, 2)
+        ));
+
+        assert_eq!(
+            write_format(
+                &mut stream,
+                kAudioStreamPropertyPhysicalFormat,
+                float32_format(96_000.0, 2)
+            ),
+            kAudioDeviceUnsupportedFormatError as i32
+        );
+        assert!(asbd_eq(
+            stream.physical_format(),
+            &float32_format(44_100.0, 2)
+        ));
+    }
+
+    #[test]
+    fn available_formats_are_ranged_descriptions() {
+        let mut stream = stream();
+        stream.add_format_range(float32_format(0.0, 1), 8_000.0, 192_000.0);
+        for selector in [
+            kAudioStreamPropertyAvailableVirtualFormats,
+            kAudioStreamPropertyAvailablePhysicalFormats,
+        ] {
+            let formats = read_formats(&mut stream, selector);
+            assert_eq!(formats.len(), 3);
+            let expected = [
+                (float32_format(44_100.0, 2), 44_100.0, 44_100.0),
+                (float32_format(48_000.0, 2), 48_000.0, 48_000.0),
+                (float32_format(0.0, 1), 8_000.0, 192_000.0),
+            ];
+            for ((format, min, max), (expected, expected_min, expected_max)) in
+                formats.iter().zip(expected)
+            {
+                assert!(asbd_eq(format, &expected));
+                assert_eq!((*min, *max), (expected_min, expected_max));
+            }
+        }
+        // adding a format again doesn't list it twice
+        stream.add_format(float32_format(48_000.0, 2));
+        assert_eq!(stream.available_virtual_formats().len(), 3);
+        assert_eq!(stream.available_physical_formats().len(), 3);
+
+        assert_eq!(
+            result_to_err_code(stream.set_format(float32_format(22_050.0, 1))),
+            0
+        );
+        assert_eq!(stream.virtual_format().mChannelsPerFrame, 1);
+    }
+}
@@ -0,0 +1,285 @@
+//! The inheritance graph of the standard CoreAudio object classes. Clients walk it through `kAudioObjectPropertyClass`
+//! and `kAudioObjectPropertyBaseClass`, so an object must report the direct parent of its class as its base class, which
+//! [`validate_classes`] checks
+
+use std::fmt;
+
+use coreaudio_sys::*;
+
+use crate::{
+    audio_object::AudioObject,
+    property::{PropertyAddress, RawPropertyExt},
+};
+
+/// The class `class` directly derives from, `None` for `kAudioObjectClassID` and classes unknown to this table
+#[allow(non_upper_case_globals)]
+pub const fn parent_class(class: AudioClassID) -> Option<AudioClassID> {
+    Some(match class {
+        kAudioPlugInClassID
+        | kAudioBoxClassID
+        | kAudioDeviceClassID
+        | kAudioClockDeviceClassID
+        | kAudioStreamClassID
+        | kAudioControlClassID => kAudioObjectClassID,
+        kAudioTransportManagerClassID => kAudioPlugInClassID,
+        kAudioEndPointDeviceClassID
+        | kAudioEndPointClassID
+        | kAudioAggregateDeviceClassID
+        | kAudioSubDeviceClassID => kAudioDeviceClassID,
+        kAudioSliderControlClassID
+        | kAudioLevelControlClassID
+        | kAudioBooleanControlClassID
+        | kAudioSelectorControlClassID
+        | kAudioStereoPanControlClassID => kAudioControlClassID,
+        kAudioVolumeControlClassID | kAudioLFEVolumeControlClassID => kAudioLevelControlClassID,
+        kAudioMuteControlClassID
+        | kAudioSoloControlClassID
+        | kAudioJackControlClassID
+        | kAudioLFEMuteControlClassID
+        | kAudioPhantomPowerControlClassID
+        | kAudioPhaseInvertControlClassID
+        | kAudioClipLightControlClassID
+        | kAudioTalkbackControlClassID
+        | kAudioListenbackControlClassID => kAudioBooleanControlClassID,
+        kAudioDataSourceControlClassID
+        | kAudioDataDestinationControlClassID
+        | kAudioClockSourceControlClassID
+        | kAudioLineLevelControlClassID
+        | kAudioHighPassFilterControlClassID => kAudioSelectorControlClassID,
+        _ => return None,
+    })
+}
+
+/// Whether `class` is one of the standard classes
+pub const fn is_known(class: AudioClassID) -> bool {
+    class == kAudioObjectClassID || parent_class(class).is_some()
+}
+
+/// Whether `class` is `ancestor` or derives from it, directly or not
+pub const fn descends_from(mut class: AudioClassID, ancestor: AudioClassID) -> bool {
+    loop {
+        if class == ancestor {
+            return true;
+        }
+        match parent_class(class) {
+            Some(parent) => class = parent,
+            None => return false,
+        }
+    }
+}
+
+/// An object whose base class isn't the parent of its class, see [`validate_classes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassViolation {
+    pub object: AudioObjectID,
+    pub class: AudioClassID,
+    pub base_class: AudioClassID,
+}
+
+impl ClassViolation {
+    /// The base class the object should report
+    pub fn expected_base_class(&self) -> Option<AudioClassID> {
+        parent_class(self.class)
+    }
+}
+
+impl fmt::Display for ClassViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fourcc =
+            |class: AudioClassID| String::from_utf8_lossy(&class.to_be_bytes()).into_owned();
+        write!(
+            f,
+            "object {} of class '{}' reports base class '{}'",
+            self.object,
+            fourcc(self.class),
+            fourcc(self.base_class)
+        )?;
+        if let Some(expected) = self.expected_base_class() {
+            write!(f, ", expected '{}'", fourcc(expected))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the class and base class of `object` and its subobjects. Objects of a standard class must report its parent as
+/// their base class (`kAudioObjectClassID` reports itself), objects of custom classes must report a standard base
+/// class. Objects without both properties are skipped
+pub fn validate_classes(object: &dyn AudioObject) -> Vec<ClassViolation> {
+    let mut violations = Vec::new();
+    object.visit_matching(
+        PropertyAddress::global(kAudioObjectPropertyClass),
+        &mut |id, _, prop| {
+            let Some(&class) = prop.value::<AudioClassID>() else {
+                return;
+            };
+            let mut base_class = None;
+            object.visit_matching(
+                PropertyAddress::global(kAudioObjectPropertyBaseClass),
+                &mut |owner, _, prop| {
+                    if owner == id {
+                        base_class = prop.value::<AudioClassID>().copied();
+                    }
+                },
+            );
+            let Some(base_class) = base_class else {
+                return;
+            };
+            let valid = match parent_class(class) {
+                Some(parent) => base_class == parent,
+                None if class == kAudioObjectClassID => base_class == kAudioObjectClassID,
+                None => is_known(base_class),
+            };
+            if !valid {
+                violations.push(ClassViolation {
+                    object: id,
+                    class,
+                    base_class,
+                });
+            }
+        },
+    );
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio_object::{
+            AudioBoxObject, AudioObjectBase, ClockDeviceObject, DeviceBuilder, ObjectRegistry,
+            PluginObject, StreamDirection,
+        },
+        property::{PropertyElement, PropertyScope},
+    };
+
+    crate::audio_object! {
+        struct Bare {
+            base: AudioObjectBase,
+        }
+    }
+
+    fn object(base_class: AudioClassID, class: AudioClassID) -> Bare {
+        Bare::new(7, AudioObjectBase::new(base_class, class, 1, "Object"))
+    }
+
+    #[test]
+    fn classes_descend_through_their_parents() {
+        assert_eq!(
+            parent_class(kAudioVolumeControlClassID),
+            Some(kAudioLevelControlClassID)
+        );
+        assert!(descends_from(
+            kAudioVolumeControlClassID,
+            kAudioControlClassID
+        ));
+        assert!(descends_from(
+            kAudioVolumeControlClassID,
+            kAudioObjectClassID
+        ));
+        assert!(descends_from(kAudioDeviceClassID, kAudioDeviceClassID));
+        assert!(!descends_from(
+            kAudioVolumeControlClassID,
+            kAudioBooleanControlClassID
+        ));
+        assert!(!descends_from(kAudioObjectClassID, kAudioDeviceClassID));
+        assert_eq!(parent_class(kAudioObjectClassID), None);
+        assert!(is_known(kAudioObjectClassID));
+        assert!(!is_known(u32::from_be_bytes(*b"cust")));
+    }
+
+    #[test]
+    fn wrong_base_classes_are_reported() {
+        let violations = validate_classes(&object(kAudioObjectClassID, kAudioVolumeControlClassID));
+        assert_eq!(
+            violations,
+            [ClassViolation {
+                object: 7,
+                class: kAudioVolumeControlClassID,
+                base_class: kAudioObjectClassID,
+            }]
+        );
+        assert_eq!(
+            violations[0].expected_base_class(),
+            Some(kAudioLevelControlClassID)
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "object 7 of class 'vlme' reports base class 'aobj', expected 'levl'"
+        );
+        // a grandparent isn't enough
+        assert_eq!(
+            validate_classes(&object(kAudioControlClassID, kAudioVolumeControlClassID)).len(),
+            1
+        );
+        assert_eq!(
+            validate_classes(&object(
+                kAudioLevelControlClassID,
+                kAudioVolumeControlClassID
+            )),
+            []
+        );
+    }
+
+    #[test]
+    fn custom_classes_need_a_standard_base_class() {
+        let custom = u32::from_be_bytes(*b"cust");
+        assert_eq!(validate_classes(&object(kAudioDeviceClassID, custom)), []);
+        let violations = validate_classes(&object(u32::from_be_bytes(*b"base"), custom));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].expected_base_class(), None);
+        assert_eq!(
+            violations[0].to_string(),
+            "object 7 of class 'cust' reports base class 'base'"
+        );
+        // the root class is its own base
+        assert_eq!(
+            validate_classes(&object(kAudioObjectClassID, kAudioObjectClassID)),
+            []
+        );
+        assert_eq!(
+            validate_classes(&object(kAudioDeviceClassID, kAudioObjectClassID)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn prebuilt_objects_are_consistent() {
+        assert_eq!(
+            validate_classes(&PluginObject::new("Manufacturer", "com.example.plugin")),
+            []
+        );
+        assert_eq!(
+            validate_classes(&AudioBoxObject::new(2, "Box", "com.example.box")),
+            []
+        );
+        assert_eq!(
+            validate_classes(&ClockDeviceObject::new(3, "com.example.clock", &[48_000.0])),
+            []
+        );
+
+        let mut objects = ObjectRegistry::new();
+        let device = DeviceBuilder::new("Device", "com.example.device")
+            .input_stream(1)
+            .output_stream(2)
+            .volume_control(PropertyScope::OUTPUT)
+            .mute_control(PropertyScope::OUTPUT)
+            .stereo_pan_control(PropertyScope::OUTPUT, PropertyElement::MAIN, [1, 2])
+            .build(&mut objects)
+            .unwrap();
+        let ids = [device.id()]
+            .into_iter()
+            .chain(device.streams(StreamDirection::Input))
+            .chain(device.streams(StreamDirection::Output))
+            .chain(device.controls());
+        let mut checked = 0;
+        for id in ids {
+            assert_eq!(
+                validate_classes(objects.get(id).unwrap()),
+                [],
+                "object {id}"
+            );
+            checked += 1;
+        }
+        assert_eq!(checked, 6);
+    }
+}
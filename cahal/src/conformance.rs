@@ -0,0 +1,409 @@
+//! The wire sizes the HAL expects for well-known selectors. Answering e.g. a `Float64` selector with 4 bytes makes clients
+//! fail without any indication of what went wrong, so property types can be checked against this table, either at
+//! compile time with [`is_conformant_type`] or at runtime with [`check`], [`debug_assert_conformant`] and [`validate`].
+//!
+//! [`validate_required`] additionally checks that objects have the properties the HAL or its clients rely on for their
+//! class, and [`validate_classes`] that their base class matches the class hierarchy
+
+use std::{fmt, mem};
+
+use core_foundation::string::CFStringRef;
+use coreaudio_sys::*;
+
+pub use crate::class_hierarchy::{validate_classes, ClassViolation};
+use crate::{
+    audio_object::AudioObject,
+    property::{PropertyAddress, PropertySelector, RawProperty, RawPropertyExt},
+};
+
+/// The size of the value of a selector on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireSize {
+    /// Exactly this many bytes
+    Fixed(u32),
+    /// An array of elements of this many bytes each
+    ArrayOf(u32),
+    /// Variable length structs like `AudioChannelLayout`
+    Variable,
+}
+
+impl WireSize {
+    /// Whether a value of `size` bytes is acceptable
+    pub const fn accepts(self, size: u32) -> bool {
+        match self {
+            WireSize::Fixed(expected) => size == expected,
+            WireSize::ArrayOf(item) => size.is_multiple_of(item),
+            WireSize::Variable => true,
+        }
+    }
+}
+
+const fn size<T>() -> u32 {
+    mem::size_of::<T>() as u32
+}
+
+/// The expected wire size of `selector`, `None` if it isn't a selector known to this table (custom properties, ...)
+#[allow(non_upper_case_globals)]
+pub const fn expected_size(selector: u32) -> Option<WireSize> {
+    use WireSize::*;
+    const U32: WireSize = Fixed(size::<u32>());
+    const F32: WireSize = Fixed(size::<f32>());
+    const F64: WireSize = Fixed(size::<f64>());
+    const REF: WireSize = Fixed(size::<CFStringRef>());
+    const IDS: WireSize = ArrayOf(size::<AudioObjectID>());
+    Some(match selector {
+        // AudioObject
+        kAudioObjectPropertyBaseClass | kAudioObjectPropertyClass | kAudioObjectPropertyOwner => {
+            U32
+        }
+        kAudioObjectPropertyName
+        | kAudioObjectPropertyModelName
+        | kAudioObjectPropertyManufacturer
+        | kAudioObjectPropertyElementName
+        | kAudioObjectPropertyElementCategoryName
+        | kAudioObjectPropertyElementNumberName
+        | kAudioObjectPropertySerialNumber
+        | kAudioObjectPropertyFirmwareVersion => REF,
+        kAudioObjectPropertyOwnedObjects | kAudioObjectPropertyControlList => IDS,
+        kAudioObjectPropertyIdentify => U32,
+        // AudioPlugIn
+        kAudioPlugInPropertyBundleID | kAudioPlugInPropertyResourceBundle => REF,
+        kAudioPlugInPropertyDeviceList
+        | kAudioPlugInPropertyBoxList
+        | kAudioPlugInPropertyClockDeviceList => IDS,
+        kAudioPlugInPropertyTranslateUIDToDevice
+        | kAudioPlugInPropertyTranslateUIDToBox
+        | kAudioPlugInPropertyTranslateUIDToClockDevice => U32,
+        // AudioBox
+        kAudioBoxPropertyBoxUID => REF,
+        kAudioBoxPropertyHasAudio
+        | kAudioBoxPropertyHasVideo
+        | kAudioBoxPropertyHasMIDI
+        | kAudioBoxPropertyIsProtected
+        | kAudioBoxPropertyAcquired
+        | kAudioBoxPropertyAcquisitionFailed => U32,
+        kAudioBoxPropertyDeviceList | kAudioBoxPropertyClockDeviceList => IDS,
+        // AudioDevice (and AudioClockDevice, which shares most selectors)
+        kAudioDevicePropertyConfigurationApplication
+        | kAudioDevicePropertyDeviceUID
+        | kAudioDevicePropertyModelUID
+        | kAudioClockDevicePropertyDeviceUID
+        | kAudioDevicePropertyIcon => REF,
+        kAudioDevicePropertyTransportType
+        | kAudioDevicePropertyClockDomain
+        | kAudioDevicePropertyDeviceIsAlive
+        | kAudioDevicePropertyDeviceIsRunning
+        | kAudioDevicePropertyDeviceCanBeDefaultDevice
+        | kAudioDevicePropertyDeviceCanBeDefaultSystemDevice
+        | kAudioDevicePropertyLatency
+        | kAudioDevicePropertySafetyOffset
+        | kAudioDevicePropertyIsHidden
+        | kAudioDevicePropertyZeroTimeStampPeriod
+        | kAudioDevicePropertyClockAlgorithm
+        | kAudioDevicePropertyClockIsStable
+        | kAudioDevicePropertyHogMode
+        | kAudioDevicePropertyBufferFrameSize
+        | kAudioDevicePropertyClockSource
+        | kAudioDevicePropertyDataSource => U32,
+        kAudioDevicePropertyRelatedDevices | kAudioDevicePropertyStreams => IDS,
+        kAudioDevicePropertyNominalSampleRate => F64,
+        kAudioDevicePropertyAvailableNominalSampleRates => ArrayOf(size::<AudioValueRange>()),
+        kAudioDevicePropertyBufferFrameSizeRange => Fixed(size::<AudioValueRange>()),
+        kAudioDevicePropertyPreferredChannelsForStereo => Fixed(size::<[u32; 2]>()),
+        kAudioDevicePropertyPreferredChannelLayout => Variable,
+        // AudioStream
+        kAudioStreamPropertyIsActive
+        | kAudioStreamPropertyDirection
+        | kAudioStreamPropertyTerminalType
+        | kAudioStreamPropertyStartingChannel => U32,
+        kAudioStreamPropertyVirtualFormat | kAudioStreamPropertyPhysicalFormat => {
+            Fixed(size::<AudioStreamBasicDescription>())
+        }
+        kAudioStreamPropertyAvailableVirtualFormats
+        | kAudioStreamPropertyAvailablePhysicalFormats => {
+            ArrayOf(size::<AudioStreamRangedDescription>())
+        }
+        // AudioControl
+        kAudioControlPropertyScope | kAudioControlPropertyElement => U32,
+        kAudioLevelControlPropertyScalarValue
+        | kAudioLevelControlPropertyDecibelValue
+        | kAudioLevelControlPropertyConvertScalarToDecibels
+        | kAudioLevelControlPropertyConvertDecibelsToScalar
+        | kAudioStereoPanControlPropertyValue => F32,
+        kAudioLevelControlPropertyDecibelRange => Fixed(size::<AudioValueRange>()),
+        kAudioBooleanControlPropertyValue | kAudioSelectorControlPropertyItemKind => U32,
+        kAudioSelectorControlPropertyCurrentItem | kAudioSelectorControlPropertyAvailableItems => {
+            ArrayOf(size::<u32>())
+        }
+        kAudioSelectorControlPropertyItemName => REF,
+        kAudioStereoPanControlPropertyPanningChannels => Fixed(size::<[u32; 2]>()),
+        _ => return None,
+    })
+}
+
+/// Whether a property storing a single `T` conforms to the table for `selector`. Selectors not in the table are
+/// considered conformant. Meant for const assertions:
+/// ```
+/// # use cahal::{base::kAudioDevicePropertyNominalSampleRate, conformance::is_conformant_type};
+/// const _: () = assert!(is_conformant_type::<f64>(kAudioDevicePropertyNominalSampleRate));
+/// ```
+pub const fn is_conformant_type<T>(selector: u32) -> bool {
+    match expected_size(selector) {
+        Some(expected) => expected.accepts(size::<T>()),
+        None => true,
+    }
+}
+
+// the property types used by the objects in this crate
+const _: () = {
+    assert!(is_conformant_type::<AudioClassID>(
+        kAudioObjectPropertyClass
+    ));
+    assert!(is_conformant_type::<AudioObjectID>(
+        kAudioObjectPropertyOwner
+    ));
+    assert!(is_conformant_type::<AudioObjectID>(
+        kAudioObjectPropertyOwnedObjects
+    ));
+    assert!(is_conformant_type::<CFStringRef>(kAudioObjectPropertyName));
+    assert!(is_conformant_type::<f64>(
+        kAudioDevicePropertyNominalSampleRate
+    ));
+    assert!(is_conformant_type::<AudioValueRange>(
+        kAudioDevicePropertyAvailableNominalSampleRates
+    ));
+    assert!(is_conformant_type::<AudioStreamBasicDescription>(
+        kAudioStreamPropertyVirtualFormat
+    ));
+    assert!(is_conformant_type::<f32>(
+        kAudioLevelControlPropertyScalarValue
+    ));
+};
+
+/// A property whose size doesn't match the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nonconformance {
+    pub selector: PropertySelector,
+    pub expected: WireSize,
+    pub actual: u32,
+}
+
+impl fmt::Display for Nonconformance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "property {} is {} bytes, expected {:?}",
+            self.selector, self.actual, self.expected
+        )
+    }
+}
+
+/// Check the current [`RawProperty::byte_size`] of `prop` against the table
+pub fn check(prop: &dyn RawProperty) -> Result<(), Nonconformance> {
+    let selector = prop.selector();
+    let Some(expected) = expected_size(selector.into()) else {
+        return Ok(());
+    };
+    let actual = prop.byte_size();
+    if expected.accepts(actual) {
+        Ok(())
+    } else {
+        Err(Nonconformance {
+            selector,
+            expected,
+            actual,
+        })
+    }
+}
+
+/// Panic in debug builds if `prop` doesn't conform to the table, see [`check`]
+#[track_caller]
+pub fn debug_assert_conformant(prop: &dyn RawProperty) {
+    if cfg!(debug_assertions) {
+        check(prop).unwrap_or_else(|err| panic!("{err}"));
+    }
+}
+
+/// Check every present property of `object` and its subobjects, returning the id of the object each nonconformant
+/// property lives on along with the violation
+pub fn validate(object: &dyn AudioObject) -> Vec<(AudioObjectID, Nonconformance)> {
+    let mut violations = Vec::new();
+    object.visit_matching(
+        PropertyAddress::anywhere(PropertySelector::WILDCARD),
+        &mut |id, _, prop| {
+            if let Err(err) = check(prop) {
+                violations.push((id, err));
+            }
+        },
+    );
+    violations
+}
+
+/// The properties objects of `class` must have beyond the ones every object has
+pub fn required_properties(class: AudioClassID) -> &'static [u32] {
+    #[allow(non_upper_case_globals)]
+    match class {
+        kAudioPlugInClassID => &[kAudioObjectPropertyManufacturer],
+        _ => &[],
+    }
+}
+
+/// A property missing from an object, see [`validate_required`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingProperty {
+    pub object: AudioObjectID,
+    pub selector: PropertySelector,
+}
+
+impl fmt::Display for MissingProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "object {} is missing property {}",
+            self.object, self.selector
+        )
+    }
+}
+
+/// Check that `object` and its subobjects have all of their [`required_properties`] present
+pub fn validate_required(object: &dyn AudioObject) -> Vec<MissingProperty> {
+    let mut missing = Vec::new();
+    object.visit_matching(
+        PropertyAddress::global(kAudioObjectPropertyClass),
+        &mut |id, _, prop| {
+            let Some(&class) = prop.value::<AudioClassID>() else {
+                return;
+            };
+            for &selector in required_properties(class) {
+                let mut found = false;
+                object.visit_matching(PropertyAddress::global(selector), &mut |owner, _, _| {
+                    found |= owner == id;
+                });
+                if !found {
+                    missing.push(MissingProperty {
+                        object: id,
+                        selector: selector.into(),
+                    });
+                }
+            }
+        },
+    );
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio_object::{AudioObjectBase, PluginObject},
+        property::{ArrayProp, Prop},
+    };
+
+    crate::audio_object! {
+        struct Device {
+            base: AudioObjectBase,
+            #[prop] rate: Prop<f32, kAudioDevicePropertyNominalSampleRate>,
+            #[prop] streams: ArrayProp<u16, kAudioDevicePropertyStreams>,
+        }
+    }
+
+    crate::audio_object! {
+        struct Bare {
+            base: AudioObjectBase,
+        }
+    }
+
+    fn device(streams: Vec<u16>) -> Device {
+        let base = AudioObjectBase::new(kAudioObjectClassID, kAudioDeviceClassID, 1, "Device");
+        Device::new(2, base, Prop(48_000.0), ArrayProp::new_with(streams))
+    }
+
+    #[test]
+    fn wrongly_sized_types_are_nonconformant() {
+        assert!(is_conformant_type::<f64>(
+            kAudioDevicePropertyNominalSampleRate
+        ));
+        assert!(!is_conformant_type::<f32>(
+            kAudioDevicePropertyNominalSampleRate
+        ));
+        assert!(!is_conformant_type::<u32>(kAudioObjectPropertyName));
+        assert!(!is_conformant_type::<u16>(kAudioDevicePropertyStreams));
+        // unknown selectors are taken as they are
+        assert!(is_conformant_type::<u8>(u32::from_be_bytes(*b"cust")));
+    }
+
+    #[test]
+    fn wrongly_sized_properties_are_reported() {
+        let rate = Prop::<f32, kAudioDevicePropertyNominalSampleRate>(48_000.0);
+        assert_eq!(
+            check(&rate),
+            Err(Nonconformance {
+                selector: kAudioDevicePropertyNominalSampleRate.into(),
+                expected: WireSize::Fixed(8),
+                actual: 4,
+            })
+        );
+        assert_eq!(
+            check(&Prop::<f64, kAudioDevicePropertyNominalSampleRate>(
+                48_000.0
+            )),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn array_sizes_must_be_whole_elements() {
+        let streams = ArrayProp::<u16, kAudioDevicePropertyStreams>::new_with(vec![2, 3, 4]);
+        assert_eq!(
+            check(&streams),
+            Err(Nonconformance {
+                selector: kAudioDevicePropertyStreams.into(),
+                expected: WireSize::ArrayOf(4),
+                actual: 6,
+            })
+        );
+        // two u16s happen to make up a whole element
+        let streams = ArrayProp::<u16, kAudioDevicePropertyStreams>::new_with(vec![2, 3]);
+        assert_eq!(check(&streams), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "property 'nsrt' (0x6e737274) is 4 bytes, expected Fixed(8)")]
+    fn debug_assertions_panic_on_violations() {
+        debug_assert_conformant(&Prop::<f32, kAudioDevicePropertyNominalSampleRate>(
+            48_000.0,
+        ));
+    }
+
+    #[test]
+    fn trees_report_every_violation() {
+        let violations: Vec<_> = validate(&device(vec![2]))
+            .into_iter()
+            .map(|(id, violation)| (id, u32::from(violation.selector), violation.actual))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                (2, kAudioDevicePropertyNominalSampleRate, 4),
+                (2, kAudioDevicePropertyStreams, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_required_properties_are_reported() {
+        let plugin = PluginObject::new("Manufacturer", "com.example.plugin");
+        assert_eq!(validate_required(&plugin), []);
+        assert_eq!(validate_required(&device(Vec::new())), []);
+
+        let base = AudioObjectBase::new(kAudioObjectClassID, kAudioPlugInClassID, 0, "Plugin");
+        assert_eq!(
+            validate_required(&Bare::new(1, base)),
+            [MissingProperty {
+                object: 1,
+                selector: kAudioObjectPropertyManufacturer.into(),
+            }]
+        );
+    }
+}
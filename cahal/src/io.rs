@@ -0,0 +1,220 @@
+//! Types for the IO path of a device: the operations the HAL runs each cycle and the buffers they work on. Everything
+//! here is used from the IO thread, so none of it allocates or locks
+
+use std::{ffi::c_void, marker::PhantomData, mem, ops::BitOr, ptr::NonNull, slice};
+
+use coreaudio_sys::{
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsNonInterleaved, kAudioFormatLinearPCM,
+    kAudioServerPlugInIOOperationConvertInput, kAudioServerPlugInIOOperationConvertMix,
+    kAudioServerPlugInIOOperationCycle, kAudioServerPlugInIOOperationMixOutput,
+    kAudioServerPlugInIOOperationProcessInput, kAudioServerPlugInIOOperationProcessMix,
+    kAudioServerPlugInIOOperationProcessOutput, kAudioServerPlugInIOOperationReadInput,
+    kAudioServerPlugInIOOperationThread, kAudioServerPlugInIOOperationWriteMix,
+    AudioServerPlugInIOCycleInfo, AudioStreamBasicDescription,
+};
+
+/// The timing of the current IO cycle, as passed to the IO operations
+pub type IOCycleInfo = AudioServerPlugInIOCycleInfo;
+
+/// The operations the HAL runs on a device during IO, in the order they happen in a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum IOOperation {
+    /// This operation marks the beginning and the ending of the IO thread. `do_io_operation` is never called with it
+    Thread = kAudioServerPlugInIOOperationThread,
+    /// This operation marks the beginning and ending of each IO cycle. `do_io_operation` is never called with it
+    Cycle = kAudioServerPlugInIOOperationCycle,
+    /// This operation transfers the input data from the device's ring buffer to the provided buffer in the stream's
+    /// native format. It always happens in-place in the main buffer and is required if the device has input streams
+    ReadInput = kAudioServerPlugInIOOperationReadInput,
+    /// This operation converts the input data from its native format to the canonical format
+    ConvertInput = kAudioServerPlugInIOOperationConvertInput,
+    /// This operation performs arbitrary signal processing on the input data in the canonical format
+    ProcessInput = kAudioServerPlugInIOOperationProcessInput,
+    /// This operation performs arbitrary signal processing on the output data in the canonical format
+    ProcessOutput = kAudioServerPlugInIOOperationProcessOutput,
+    /// This operation mixes the output data into the device's ring buffer. If a plug-in implements it, no further
+    /// output operations happen in that cycle. It always happens in-place in the main buffer
+    MixOutput = kAudioServerPlugInIOOperationMixOutput,
+    /// This operation processes the full mix of all clients' data in the canonical format
+    ProcessMix = kAudioServerPlugInIOOperationProcessMix,
+    /// This operation converts the fully mixed data from the canonical format to the device's native format
+    ConvertMix = kAudioServerPlugInIOOperationConvertMix,
+    /// This operation puts the data into the device's ring buffer for consumption of the hardware. It always happens
+    /// in-place in the main buffer and is required if the device has output streams
+    WriteMix = kAudioServerPlugInIOOperationWriteMix,
+}
+
+impl IOOperation {
+    pub const ALL: [Self; 10] = [
+        Self::Thread,
+        Self::Cycle,
+        Self::ReadInput,
+        Self::ConvertInput,
+        Self::ProcessInput,
+        Self::ProcessOutput,
+        Self::MixOutput,
+        Self::ProcessMix,
+        Self::ConvertMix,
+        Self::WriteMix,
+    ];
+    /// The operation with the given id, `None` for ids unknown to this crate
+    pub fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.id() == id)
+    }
+    /// The four character code the HAL identifies the operation with
+    pub const fn id(self) -> u32 {
+        self as u32
+    }
+    /// Whether the main buffer of this operation is in the physical (native) format of the stream rather than the
+    /// canonical one, which has the layout of the virtual format
+    pub const fn uses_physical_format(self) -> bool {
+        matches!(self, Self::ReadInput | Self::MixOutput | Self::WriteMix)
+    }
+}
+
+/// A buffer the HAL passed to `do_io_operation`, holding `frames` frames of a stream in `format`
+#[derive(Debug)]
+pub struct IOBuffer<'a> {
+    data: NonNull<u8>,
+    frames: u32,
+    format: AudioStreamBasicDescription,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> IOBuffer<'a> {
+    /// # Safety
+    /// `data` must be valid for reads and writes of `frames * format.mBytesPerFrame` bytes for `'a`, with no other
+    /// references to them
+    pub unsafe fn new(
+        data: NonNull<c_void>,
+        frames: u32,
+        format: AudioStreamBasicDescription,
+    ) -> Self {
+        Self {
+            data: data.cast(),
+            frames,
+            format,
+            _buffer: PhantomData,
+        }
+    }
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+    /// The format of the data, see [`IOOperation::uses_physical_format`]
+    pub fn format(&self) -> &AudioStreamBasicDescription {
+        &self.format
+    }
+    pub fn channels(&self) -> u32 {
+        self.format.mChannelsPerFrame
+    }
+    pub fn byte_len(&self) -> usize {
+        self.frames as usize * self.format.mBytesPerFrame as usize
+    }
+    pub fn bytes(&self) -> &[u8] {
+        // Safety: guaranteed by the contract of `new`
+        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.byte_len()) }
+    }
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        // Safety: guaranteed by the contract of `new`
+        unsafe { slice::from_raw_parts_mut(self.data.as_ptr(), self.byte_len()) }
+    }
+    /// Whether the data is interleaved, packed native `f32` samples, the layout of the canonical format
+    pub fn is_f32(&self) -> bool {
+        let format = &self.format;
+        format.mFormatID == kAudioFormatLinearPCM
+            && format.mFormatFlags & kAudioFormatFlagIsFloat != 0
+            && format.mFormatFlags & kAudioFormatFlagIsNonInterleaved == 0
+            && format.mBitsPerChannel == 32
+            && format.mBytesPerFrame == format.mChannelsPerFrame * mem::size_of::<f32>() as u32
+            && self.data.cast::<f32>().is_aligned()
+    }
+    /// The interleaved samples, `frames * channels` of them, if the data is in `f32` (see [`IOBuffer::is_f32`])
+    pub fn samples(&self) -> Option<&[f32]> {
+        let len = self.frames as usize * self.channels() as usize;
+        // Safety: the layout is checked by `is_f32`, the size guaranteed by the contract of `new`
+        self.is_f32()
+            .then(|| unsafe { slice::from_raw_parts(self.data.cast::<f32>().as_ptr(), len) })
+    }
+    /// Mutable variant of [`IOBuffer::samples`]
+    pub fn samples_mut(&mut self) -> Option<&mut [f32]> {
+        let len = self.frames as usize * self.channels() as usize;
+        // Safety: see `samples`
+        self.is_f32()
+            .then(|| unsafe { slice::from_raw_parts_mut(self.data.cast::<f32>().as_ptr(), len) })
+    }
+}
+
+/// The IO operations a device takes part in, and which of them it does in place in the main buffer, as answered to
+/// `WillDoIOOperation`. The default takes part in none
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct IOCapabilities {
+    will_do: u16,
+    in_place: u16,
+}
+
+impl IOCapabilities {
+    pub const NONE: Self = Self {
+        will_do: 0,
+        in_place: 0,
+    };
+    const fn bit(op: IOOperation) -> u16 {
+        let mut index = 0;
+        while IOOperation::ALL[index] as u32 != op as u32 {
+            index += 1;
+        }
+        1 << index
+    }
+    /// Also do `op`, in place
+    pub const fn with(self, op: IOOperation) -> Self {
+        Self {
+            will_do: self.will_do | Self::bit(op),
+            in_place: self.in_place | Self::bit(op),
+        }
+    }
+    /// Also do `op`, writing the result to the secondary buffer
+    pub const fn with_out_of_place(self, op: IOOperation) -> Self {
+        Self {
+            will_do: self.will_do | Self::bit(op),
+            in_place: self.in_place & !Self::bit(op),
+        }
+    }
+    pub const fn will_do(self, op: IOOperation) -> bool {
+        self.will_do & Self::bit(op) != 0
+    }
+    /// Whether `op` is done in place, only meaningful if it is done at all
+    pub const fn in_place(self, op: IOOperation) -> bool {
+        self.in_place & Self::bit(op) != 0
+    }
+    /// The operations that are done
+    pub fn operations(self) -> impl Iterator<Item = IOOperation> {
+        IOOperation::ALL
+            .into_iter()
+            .filter(move |&op| self.will_do(op))
+    }
+}
+
+impl From<IOOperation> for IOCapabilities {
+    fn from(op: IOOperation) -> Self {
+        Self::NONE.with(op)
+    }
+}
+
+impl BitOr for IOCapabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self {
+            will_do: self.will_do | rhs.will_do,
+            in_place: self.in_place | rhs.in_place,
+        }
+    }
+}
+
+impl BitOr<IOOperation> for IOCapabilities {
+    type Output = Self;
+
+    fn bitor(self, op: IOOperation) -> Self {
+        self.with(op)
+    }
+}
@@ -1,7 +1,20 @@
+// lets the derive macros refer to `::cahal` from inside this crate too
+extern crate self as cahal;
+
 pub mod audio_object;
+pub mod class_hierarchy;
+pub mod conformance;
+pub mod io;
+pub mod notification;
+pub mod persist;
 pub mod plugin_driver_interface;
 pub mod property;
 pub mod raw_plugin_driver_interface;
+pub mod ring;
+pub mod snapshot;
+pub mod timing;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub use core_foundation;
 pub use coreaudio_sys as base;
 
@@ -89,6 +102,18 @@ pub mod os_err {
     }
 }
 
+/// Converts a four character code string to the `u32` CoreAudio uses for it, at compile time:
+/// ```no_run
+/// # use cahal::four_cc;
+/// const GLOBAL: u32 = four_cc!("glob");
+/// ```
+#[macro_export]
+macro_rules! four_cc {
+    ($code:expr) => {
+        const { $crate::property::four_cc($code) }
+    };
+}
+
 /// Creates the necessary CFPlugin entry point function (named `__create_driver`) that provides your plugin implementation to the runtime:
 /// ```no_run
 /// pub struct Driver {
@@ -0,0 +1,309 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use coreaudio_sys::{AudioObjectID, AudioObjectPropertyAddress};
+
+use crate::{
+    os_err::OSStatus,
+    plugin_driver_interface::AudioServerPluginDriverInterface,
+    property::{PropertyAddress, RawProperty},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+
+/// Accumulates the properties that changed on the driver's objects, so the host can be notified about all of them at once
+/// through [`PluginHostInterface::properties_changed`]. Changes are kept in the order they were first recorded, duplicates are ignored
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    changes: Vec<(AudioObjectID, PropertyAddress)>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Record that the property at `address` on `object` changed
+    pub fn mark(&mut self, object: AudioObjectID, address: PropertyAddress) {
+        if !self.changes.contains(&(object, address)) {
+            self.changes.push((object, address));
+        }
+    }
+    /// Record that the property `selector` on `object` changed, in the global scope on the main element
+    pub fn mark_selector(&mut self, object: AudioObjectID, selector: u32) {
+        self.mark(object, PropertyAddress::global(selector));
+    }
+    /// Get a handle to `prop` that records a change to it (in the global scope on the main element) when it is mutably accessed
+    pub fn track<'a, P: RawProperty + ?Sized>(
+        &'a mut self,
+        object: AudioObjectID,
+        prop: &'a mut P,
+    ) -> Tracked<'a, P> {
+        let address = PropertyAddress::global(prop.selector().into());
+        self.track_at(object, address, prop)
+    }
+    /// Get a handle to `prop` that records a change at `address` when it is mutably accessed
+    pub fn track_at<'a, P: ?Sized>(
+        &'a mut self,
+        object: AudioObjectID,
+        address: PropertyAddress,
+        prop: &'a mut P,
+    ) -> Tracked<'a, P> {
+        Tracked {
+            changes: self,
+            object,
+            address,
+            prop,
+            touched: false,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(AudioObjectID, PropertyAddress)> {
+        self.changes.iter()
+    }
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+    /// Notify the host about all recorded changes, with one [`PluginHostInterface::properties_changed`] call per object,
+    /// and clear the set. Flushing an empty set does nothing.
+    ///
+    /// If the host reports an error, the changes of the objects that weren't notified yet are kept
+    pub fn flush<T: AudioServerPluginDriverInterface>(
+        &mut self,
+        host: &PluginHostInterface<T>,
+    ) -> OSStatus {
+        while let Some(&(object, _)) = self.changes.first() {
+            let addresses: Vec<AudioObjectPropertyAddress> = self
+                .changes
+                .iter()
+                .filter(|(o, _)| *o == object)
+                .map(|(_, address)| (*address).into())
+                .collect();
+            host.properties_changed(object, &addresses)?;
+            self.changes.retain(|(o, _)| *o != object);
+        }
+        Ok(())
+    }
+}
+
+impl Extend<(AudioObjectID, PropertyAddress)> for ChangeSet {
+    fn extend<I: IntoIterator<Item = (AudioObjectID, PropertyAddress)>>(&mut self, iter: I) {
+        for (object, address) in iter {
+            self.mark(object, address);
+        }
+    }
+}
+
+/// A handle to a property that records a change in its [`ChangeSet`] once it has been mutably accessed, see [`ChangeSet::track`]
+pub struct Tracked<'a, P: ?Sized> {
+    changes: &'a mut ChangeSet,
+    object: AudioObjectID,
+    address: PropertyAddress,
+    prop: &'a mut P,
+    touched: bool,
+}
+
+impl<P: ?Sized> Deref for Tracked<'_, P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        self.prop
+    }
+}
+impl<P: ?Sized> DerefMut for Tracked<'_, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.touched = true;
+        self.prop
+    }
+}
+impl<P: ?Sized> Drop for Tracked<'_, P> {
+    fn drop(&mut self) {
+        if self.touched {
+            self.changes.mark(self.object, self.address);
+        }
+    }
+}
+
+/// A [`ChangeSet`] shared between the objects of a driver, for changes that happen where the host isn't at hand, like in
+/// property setters invoked by the HAL. The driver flushes it after handling the request (or from its own work queue)
+#[derive(Debug, Clone, Default)]
+pub struct ChangeQueue {
+    changes: Arc<Mutex<ChangeSet>>,
+}
+
+impl ChangeQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Record that the property at `address` on `object` changed, see [`ChangeSet::mark`]
+    pub fn mark(&self, object: AudioObjectID, address: PropertyAddress) {
+        self.changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .mark(object, address);
+    }
+    /// Take all recorded changes, leaving the queue empty
+    pub fn take(&self) -> ChangeSet {
+        std::mem::take(&mut *self.changes.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_empty()
+    }
+    /// Notify the host about all recorded changes, see [`ChangeSet::flush`]. Changes that couldn't be delivered stay
+    /// queued
+    pub fn flush<T: AudioServerPluginDriverInterface>(
+        &self,
+        host: &PluginHostInterface<T>,
+    ) -> OSStatus {
+        let mut changes = self.take();
+        let result = changes.flush(host);
+        if !changes.is_empty() {
+            let mut queued = self.changes.lock().unwrap_or_else(PoisonError::into_inner);
+            let newer = std::mem::replace(&mut *queued, changes);
+            queued.extend(newer.iter().copied());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use core_foundation::base::CFAllocatorRef;
+    use coreaudio_sys::{
+        kAudioDevicePropertyLatency, kAudioDevicePropertyNominalSampleRate,
+        kAudioHardwareUnspecifiedError, AudioServerPlugInHostInterface, AudioServerPlugInHostRef,
+        OSStatus as RawOSStatus,
+    };
+
+    use super::*;
+    use crate::{
+        audio_object::ObjectRegistry, plugin_driver_interface::LoggingConfig,
+        property::PropertyScope,
+    };
+
+    struct NoDriver {
+        objects: Mutex<ObjectRegistry>,
+    }
+
+    impl AudioServerPluginDriverInterface for NoDriver {
+        type DeviceConfigurationChangeInfo = ();
+        const NAME: &'static str = "notification";
+
+        fn configure_logging() -> LoggingConfig {
+            LoggingConfig::Disabled
+        }
+        fn create(_cf_allocator: CFAllocatorRef) -> Self {
+            Self {
+                objects: Mutex::default(),
+            }
+        }
+        fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+            Ok(())
+        }
+        fn objects(&self) -> &Mutex<ObjectRegistry> {
+            &self.objects
+        }
+    }
+
+    /// Records every `PropertiesChanged` call, failing the ones for `failing`
+    #[repr(C)]
+    struct RecordingHost {
+        // first, so the host ref is a pointer to the whole host
+        interface: AudioServerPlugInHostInterface,
+        calls: Mutex<Vec<(AudioObjectID, Vec<PropertyAddress>)>>,
+        failing: Option<AudioObjectID>,
+    }
+
+    impl RecordingHost {
+        fn new(failing: Option<AudioObjectID>) -> Box<Self> {
+            Box::new(Self {
+                interface: AudioServerPlugInHostInterface {
+                    PropertiesChanged: Some(Self::properties_changed),
+                    CopyFromStorage: None,
+                    WriteToStorage: None,
+                    DeleteFromStorage: None,
+                    RequestDeviceConfigurationChange: None,
+                },
+                calls: Mutex::default(),
+                failing,
+            })
+        }
+        fn host(&self) -> PluginHostInterface<NoDriver> {
+            unsafe { PluginHostInterface::new(&self.interface) }.unwrap()
+        }
+        fn take_calls(&self) -> Vec<(AudioObjectID, Vec<PropertyAddress>)> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+        unsafe extern "C" fn properties_changed(
+            host: AudioServerPlugInHostRef,
+            object: AudioObjectID,
+            count: u32,
+            addresses: *const AudioObjectPropertyAddress,
+        ) -> RawOSStatus {
+            let host = unsafe { &*host.cast::<Self>() };
+            if host.failing == Some(object) {
+                return kAudioHardwareUnspecifiedError as RawOSStatus;
+            }
+            let addresses = unsafe { slice::from_raw_parts(addresses, count as usize) };
+            host.calls.lock().unwrap().push((
+                object,
+                addresses.iter().map(|&address| address.into()).collect(),
+            ));
+            0
+        }
+    }
+
+    const LATENCY: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyLatency);
+    const RATE: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyNominalSampleRate);
+
+    #[test]
+    fn duplicate_changes_are_reported_once() {
+        let host = RecordingHost::new(None);
+        let mut changes = ChangeSet::new();
+        changes.mark(2, LATENCY);
+        changes.mark(3, RATE);
+        changes.mark_selector(2, kAudioDevicePropertyLatency);
+        changes.mark(2, RATE);
+        // another scope is another address
+        let input = PropertyAddress::scoped(kAudioDevicePropertyLatency, PropertyScope::INPUT);
+        changes.mark(2, input);
+        assert_eq!(changes.len(), 4);
+
+        assert!(changes.flush(&host.host()).is_ok());
+        assert!(changes.is_empty());
+        assert_eq!(
+            host.take_calls(),
+            [(2, vec![LATENCY, RATE, input]), (3, vec![RATE])]
+        );
+    }
+
+    #[test]
+    fn empty_sets_dont_reach_the_host() {
+        let host = RecordingHost::new(None);
+        assert!(ChangeSet::new().flush(&host.host()).is_ok());
+        assert!(ChangeQueue::new().flush(&host.host()).is_ok());
+        assert!(host.take_calls().is_empty());
+    }
+
+    #[test]
+    fn undelivered_changes_stay_queued() {
+        let host = RecordingHost::new(Some(3));
+        let queue = ChangeQueue::new();
+        queue.mark(2, LATENCY);
+        queue.mark(3, RATE);
+        assert!(queue.flush(&host.host()).is_err());
+        assert_eq!(host.take_calls(), [(2, vec![LATENCY])]);
+        let left: Vec<_> = queue.take().iter().copied().collect();
+        assert_eq!(left, [(3, RATE)]);
+    }
+}
@@ -0,0 +1,562 @@
+//! Conversion of property values to and from CoreFoundation property lists, for persisting user adjustable state (volume,
+//! selected data source, sample rate, ...) through [`PluginHostInterface::write_to_storage`] and restoring it later.
+//!
+//! Values are recognized by their type as exposed through [`RawPropertyExt::value`]. Supported are `u32`, `i32`, `f32`,
+//! `f64`, `bool`, `CFString` and `Vec`s of the numeric types and `bool`. Values shared with the IO path are read and
+//! written through their handle, so the IO path sees restored values: `AtomicHandle<bool>`, `AtomicHandle<f32>` and
+//! [`VolumeHandle`], which is stored as its scalar value
+//!
+//! Whole object trees can be dumped with [`to_plist`] and restored with [`apply_plist`].
+//!
+//! [`RawPropertyExt::value`]: crate::property::RawPropertyExt::value
+//! [`PluginHostInterface::write_to_storage`]: crate::raw_plugin_driver_interface::PluginHostInterface::write_to_storage
+
+use std::ops::ControlFlow;
+
+use core_foundation::{
+    array::CFArray,
+    base::{CFType, TCFType},
+    boolean::CFBoolean,
+    dictionary::CFDictionary,
+    number::CFNumber,
+    propertylist::{CFPropertyList, CFPropertyListSubClass},
+    string::CFString,
+};
+
+use coreaudio_sys::{kAudioObjectPropertyClass, AudioObjectID};
+
+use crate::{
+    audio_object::{AudioObject, VolumeHandle},
+    os_err::{OSResult, OSStatus, OSStatusError},
+    property::{
+        AtomicHandle, PropertyAddress, PropertyElement, PropertyScope, PropertySelector,
+        RawProperty, RawPropertyExt,
+    },
+};
+
+/// A Rust value with a property list representation
+trait PlistValue: Sized + 'static {
+    fn to_plist(&self) -> CFPropertyList;
+    fn from_plist(value: &CFPropertyList) -> Option<Self>;
+}
+
+impl PlistValue for u32 {
+    fn to_plist(&self) -> CFPropertyList {
+        CFNumber::from(*self as i64).to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        value.downcast::<CFNumber>()?.to_i64()?.try_into().ok()
+    }
+}
+impl PlistValue for i32 {
+    fn to_plist(&self) -> CFPropertyList {
+        CFNumber::from(*self).to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        value.downcast::<CFNumber>()?.to_i64()?.try_into().ok()
+    }
+}
+impl PlistValue for f32 {
+    fn to_plist(&self) -> CFPropertyList {
+        CFNumber::from(*self).to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        value.downcast::<CFNumber>()?.to_f32()
+    }
+}
+impl PlistValue for f64 {
+    fn to_plist(&self) -> CFPropertyList {
+        CFNumber::from(*self).to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        value.downcast::<CFNumber>()?.to_f64()
+    }
+}
+impl PlistValue for bool {
+    fn to_plist(&self) -> CFPropertyList {
+        CFBoolean::from(*self).to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        Some(value.downcast::<CFBoolean>()?.into())
+    }
+}
+impl PlistValue for CFString {
+    fn to_plist(&self) -> CFPropertyList {
+        self.to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        value.downcast::<CFString>()
+    }
+}
+impl<T: PlistValue> PlistValue for Vec<T> {
+    fn to_plist(&self) -> CFPropertyList {
+        let items: Vec<CFType> = self
+            .iter()
+            .map(|item| item.to_plist().as_CFType())
+            .collect();
+        CFArray::from_CFTypes(&items)
+            .into_untyped()
+            .to_CFPropertyList()
+    }
+    fn from_plist(value: &CFPropertyList) -> Option<Self> {
+        let array = value.downcast::<CFArray>()?;
+        array
+            .iter()
+            .map(|item| {
+                // Safety: the elements of a property list array are property lists themselves
+                let item = unsafe { CFPropertyList::wrap_under_get_rule(*item) };
+                T::from_plist(&item)
+            })
+            .collect()
+    }
+}
+
+macro_rules! for_each_plist_type {
+    ($mac:ident!($($args:tt)*)) => {
+        $mac!($($args)*; u32, i32, f32, f64, bool, CFString, Vec<u32>, Vec<i32>, Vec<f32>, Vec<f64>, Vec<bool>)
+    };
+}
+
+fn value_to_plist(prop: &dyn RawProperty) -> Option<CFPropertyList> {
+    macro_rules! try_types {
+        ($prop:expr; $($ty:ty),*) => {
+            $(
+                if let Some(value) = $prop.value::<$ty>() {
+                    return Some(value.to_plist());
+                }
+            )*
+        };
+    }
+    for_each_plist_type!(try_types!(prop));
+    if let Some(volume) = prop.value::<VolumeHandle>() {
+        return Some(volume.scalar().to_plist());
+    }
+    if let Some(value) = prop.value::<AtomicHandle<bool>>() {
+        return Some(value.load().to_plist());
+    }
+    if let Some(value) = prop.value::<AtomicHandle<f32>>() {
+        return Some(value.load().to_plist());
+    }
+    None
+}
+
+fn apply_to_value(prop: &mut dyn RawProperty, value: &CFPropertyList) -> OSStatus {
+    macro_rules! try_types {
+        ($prop:expr, $value:expr; $($ty:ty),*) => {
+            $(
+                if let Some(slot) = $prop.value_mut::<$ty>() {
+                    *slot = <$ty>::from_plist($value).ok_or(OSStatusError::HW_ILLEGAL_OPERATION_ERR)?;
+                    return Ok(());
+                }
+            )*
+        };
+    }
+    for_each_plist_type!(try_types!(prop, value));
+    let invalid = || OSStatusError::HW_ILLEGAL_OPERATION_ERR;
+    if let Some(volume) = prop.value::<VolumeHandle>() {
+        volume.set_scalar(f32::from_plist(value).ok_or_else(invalid)?);
+        return Ok(());
+    }
+    if let Some(handle) = prop.value::<AtomicHandle<bool>>() {
+        handle.store(bool::from_plist(value).ok_or_else(invalid)?);
+        return Ok(());
+    }
+    if let Some(handle) = prop.value::<AtomicHandle<f32>>() {
+        handle.store(f32::from_plist(value).ok_or_else(invalid)?);
+        return Ok(());
+    }
+    Err(OSStatusError::HW_UNSUPPORTED_OP)
+}
+
+/// The value of `prop` as a property list. Fails with [`OSStatusError::HW_UNSUPPORTED_OP`] if the type of the value isn't
+/// supported
+pub fn to_plist_value(prop: &dyn RawProperty) -> OSResult<CFPropertyList> {
+    value_to_plist(prop).ok_or_else(|| {
+        log::error!("can't convert the value of {} to a plist", prop.selector());
+        OSStatusError::HW_UNSUPPORTED_OP
+    })
+}
+
+/// Replace the value of `prop` with `value`, as produced by [`to_plist_value`]. This goes through
+/// [`RawPropertyExt::value_mut`] (or the handle of values shared with the IO path), so it works on read-only properties
+/// too.
+///
+/// Fails with [`OSStatusError::HW_UNSUPPORTED_OP`] if the type of the value isn't supported, and with
+/// [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if `value` doesn't hold a value of that type. The property is left
+/// untouched on failure
+pub fn apply_plist_value(prop: &mut dyn RawProperty, value: &CFPropertyList) -> OSStatus {
+    let selector = prop.selector();
+    apply_to_value(prop, value).inspect_err(|_| {
+        log::error!("can't apply a plist value to {selector}");
+    })
+}
+
+const CLASS_KEY: &str = "class";
+const PROPERTIES_KEY: &str = "properties";
+
+/// The dictionary key of a property, `selector.scope.element` in decimal
+fn address_key(address: PropertyAddress) -> String {
+    format!(
+        "{}.{}.{}",
+        u32::from(address.selector),
+        u32::from(address.scope),
+        u32::from(address.element)
+    )
+}
+
+fn parse_address_key(key: &str) -> Option<PropertyAddress> {
+    let mut parts = key.split('.').map(|part| part.parse::<u32>().ok());
+    let address = PropertyAddress::new(
+        PropertySelector::new(parts.next()??),
+        PropertyScope::new(parts.next()??),
+        PropertyElement::new(parts.next()??),
+    );
+    parts.next().is_none().then_some(address)
+}
+
+fn string_key(key: &str) -> CFType {
+    CFString::new(key).as_CFType()
+}
+
+/// Values that can't be represented are stored as an empty dictionary, which none of the supported types convert to
+fn unrepresentable() -> CFType {
+    CFDictionary::<CFType, CFType>::from_CFType_pairs(&[]).as_CFType()
+}
+
+fn object_to_plist(object: &dyn AudioObject, objects: &mut Vec<(CFType, CFType)>) {
+    let mut properties = Vec::new();
+    object.visit_properties(&mut |address, prop| {
+        if !prop.is_present() {
+            return;
+        }
+        let value = value_to_plist(prop).map_or_else(unrepresentable, |value| value.as_CFType());
+        properties.push((string_key(&address_key(address)), value));
+    });
+    let class = object
+        .get_property(PropertyAddress::global(kAudioObjectPropertyClass))
+        .and_then(|prop| prop.value::<u32>().copied())
+        .unwrap_or_default();
+    let entry = CFDictionary::from_CFType_pairs(&[
+        (string_key(CLASS_KEY), class.to_plist().as_CFType()),
+        (
+            string_key(PROPERTIES_KEY),
+            CFDictionary::from_CFType_pairs(&properties).as_CFType(),
+        ),
+    ]);
+    objects.push((string_key(&object.id().to_string()), entry.as_CFType()));
+    let _ = object.visit_children(&mut |child| {
+        object_to_plist(child, objects);
+        ControlFlow::Continue(())
+    });
+}
+
+/// Dump `root` and all of its subobjects into a dictionary keyed by object id. Each object is described by a dictionary
+/// holding its class under `"class"` and its present properties under `"properties"`, keyed by
+/// `"selector.scope.element"` in decimal. Values of types not supported by this module are stored as an empty
+/// dictionary
+pub fn to_plist(root: &dyn AudioObject) -> CFPropertyList {
+    let mut objects = Vec::new();
+    object_to_plist(root, &mut objects);
+    CFDictionary::from_CFType_pairs(&objects)
+        .into_untyped()
+        .to_CFPropertyList()
+}
+
+/// The entries of a property list dictionary with string keys. Entries with other keys are skipped
+fn string_entries(dict: &CFDictionary) -> Vec<(String, CFPropertyList)> {
+    let (keys, values) = dict.get_keys_and_values();
+    keys.into_iter()
+        .zip(values)
+        .filter_map(|(key, value)| {
+            // Safety: the keys and values of a property list dictionary are property lists themselves
+            let key = unsafe { CFPropertyList::wrap_under_get_rule(key) };
+            let value = unsafe { CFPropertyList::wrap_under_get_rule(value) };
+            Some((key.downcast::<CFString>()?.to_string(), value))
+        })
+        .collect()
+}
+
+/// Restore the settable property values in `plist`, as produced by [`to_plist`], onto `root` and its subobjects, matching
+/// objects by id and properties by address. Objects, properties and values that don't match anything in the tree, values
+/// that couldn't be represented and read-only properties are skipped.
+///
+/// Values are applied through [`apply_plist_value`], so no hooks run. Fails with the error of the last value that
+/// couldn't be applied, after applying all others
+pub fn apply_plist(root: &mut dyn AudioObject, plist: &CFPropertyList) -> OSStatus {
+    let Some(objects) = plist.downcast::<CFDictionary>() else {
+        log::error!("object tree plist isn't a dictionary");
+        return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+    };
+    let mut result = Ok(());
+    for (id, entry) in string_entries(&objects) {
+        let Ok(id) = id.parse::<AudioObjectID>() else {
+            continue;
+        };
+        let Some(object) = root.find_by_id_mut(id) else {
+            log::warn!("object {id} from the plist doesn't exist anymore");
+            continue;
+        };
+        let Some(properties) = entry
+            .downcast::<CFDictionary>()
+            .and_then(|entry| {
+                string_entries(&entry)
+                    .into_iter()
+                    .find(|(key, _)| key == PROPERTIES_KEY)
+            })
+            .and_then(|(_, properties)| properties.downcast::<CFDictionary>())
+        else {
+            continue;
+        };
+        for (key, value) in string_entries(&properties) {
+            if value.downcast::<CFDictionary>().is_some() {
+                continue;
+            }
+            let Some(address) = parse_address_key(&key) else {
+                continue;
+            };
+            let Some(mut prop) = object.get_object_property_mut(address) else {
+                continue;
+            };
+            // properties with an instance per scope are stored (and restored) per instance
+            if prop.visit_instances(&mut |_, _| {}) {
+                match prop.instance_at_mut(address) {
+                    Some(instance) => prop = instance,
+                    None => continue,
+                }
+            }
+            if !prop.is_mut() {
+                continue;
+            }
+            if let Err(err) = apply_plist_value(prop, &value) {
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioBooleanControlPropertyValue, kAudioDevicePropertyAvailableNominalSampleRates,
+        kAudioDevicePropertyLatency, kAudioDevicePropertyNominalSampleRate,
+        kAudioDevicePropertyStreams, kAudioHardwareIllegalOperationError,
+        kAudioHardwareUnsupportedOperationError, kAudioLevelControlPropertyScalarValue,
+        kAudioMuteControlClassID, kAudioObjectPropertyName, kAudioStreamPropertyIsActive,
+        kAudioStreamPropertyPhysicalFormat, kAudioVolumeControlClassID,
+    };
+
+    use super::*;
+    use crate::{
+        audio_object::{
+            AudioDeviceObject, AudioStreamObject, MuteControlObject, StreamDirection,
+            VolumeControlObject,
+        },
+        os_err::result_to_err_code,
+        property::{float32_format, ArrayProp, BoolProp, Prop},
+    };
+
+    crate::audio_object! {
+        struct Device {
+            base: AudioDeviceObject,
+            #[child] input: AudioStreamObject,
+            #[child] output: AudioStreamObject,
+            #[child] volume: VolumeControlObject,
+            #[child] mute: MuteControlObject,
+        }
+    }
+
+    /// A device with an input and an output stream, and a volume and a mute control on the output
+    fn device(name: &'static str) -> Device {
+        let mut device = AudioDeviceObject::new(2, name, "com.example.device", 48_000.0);
+        device.add_stream(3, PropertyScope::INPUT, 1);
+        device.add_stream(4, PropertyScope::OUTPUT, 2);
+        device.add_control(5, kAudioVolumeControlClassID);
+        device.add_control(6, kAudioMuteControlClassID);
+        let stream = |id, direction, channels| {
+            AudioStreamObject::new(id, 2, direction, float32_format(48_000.0, channels))
+        };
+        Device::new(
+            2,
+            device,
+            stream(3, StreamDirection::Input, 1),
+            stream(4, StreamDirection::Output, 2),
+            VolumeControlObject::new(
+                5,
+                2,
+                PropertyScope::OUTPUT,
+                PropertyElement::MAIN,
+                -96.0,
+                0.0,
+            ),
+            MuteControlObject::new(6, 2, PropertyScope::OUTPUT),
+        )
+    }
+
+    /// The entry stored for the property `selector` of the object `id`, which doesn't depend on the scope or element
+    fn stored(plist: &CFPropertyList, id: AudioObjectID, selector: u32) -> Option<CFPropertyList> {
+        let key = address_key(PropertyAddress::anywhere(selector.into()));
+        let (_, entry) = string_entries(&plist.downcast::<CFDictionary>()?)
+            .into_iter()
+            .find(|(key, _)| *key == id.to_string())?;
+        let (_, properties) = string_entries(&entry.downcast::<CFDictionary>()?)
+            .into_iter()
+            .find(|(key, _)| key == PROPERTIES_KEY)?;
+        string_entries(&properties.downcast::<CFDictionary>()?)
+            .into_iter()
+            .find(|(stored, _)| *stored == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Convert `from` to a plist and apply it onto `to`, returning the status
+    fn round_trip(from: &dyn RawProperty, to: &mut dyn RawProperty) -> i32 {
+        let value = to_plist_value(from).expect("supported value");
+        result_to_err_code(apply_plist_value(to, &value))
+    }
+
+    #[test]
+    fn u32_values_round_trip() {
+        let from = Prop::<u32, kAudioDevicePropertyLatency>(u32::MAX);
+        let mut to = Prop::<u32, kAudioDevicePropertyLatency>(0);
+        assert_eq!(round_trip(&from, &mut to), 0);
+        assert_eq!(to.0, u32::MAX);
+    }
+
+    #[test]
+    fn f32_values_round_trip() {
+        let from = Prop::<f32, kAudioLevelControlPropertyScalarValue>(0.1);
+        let mut to = Prop::<f32, kAudioLevelControlPropertyScalarValue>(1.0);
+        assert_eq!(round_trip(&from, &mut to), 0);
+        assert_eq!(to.0, 0.1);
+    }
+
+    #[test]
+    fn f64_values_round_trip() {
+        let from = Prop::<f64, kAudioDevicePropertyNominalSampleRate>(44_100.1);
+        let mut to = Prop::<f64, kAudioDevicePropertyNominalSampleRate>(48_000.0);
+        assert_eq!(round_trip(&from, &mut to), 0);
+        assert_eq!(to.0, 44_100.1);
+    }
+
+    #[test]
+    fn arrays_round_trip() {
+        let from = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![2, 3, 5]);
+        let mut to = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![7]);
+        assert_eq!(round_trip(&from, &mut to), 0);
+        assert_eq!(*to, [2, 3, 5]);
+
+        let from =
+            ArrayProp::<f64, kAudioDevicePropertyAvailableNominalSampleRates>::new_with(vec![
+                44_100.0, 48_000.0,
+            ]);
+        let mut to = ArrayProp::<f64, kAudioDevicePropertyAvailableNominalSampleRates>::new();
+        assert_eq!(round_trip(&from, &mut to), 0);
+        assert_eq!(*to, [44_100.0, 48_000.0]);
+
+        let empty = ArrayProp::<u32, kAudioDevicePropertyStreams>::new();
+        let mut streams = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![7]);
+        assert_eq!(round_trip(&empty, &mut streams), 0);
+        assert!(streams.is_empty());
+    }
+
+    #[test]
+    fn mismatched_values_are_rejected() {
+        let rate = Prop::<f64, kAudioDevicePropertyNominalSampleRate>(44_100.5);
+        let mut latency = Prop::<u32, kAudioDevicePropertyLatency>(10);
+        assert_eq!(
+            round_trip(&rate, &mut latency),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(latency.0, 10);
+
+        let negative = Prop::<i32, kAudioDevicePropertyLatency>(-1);
+        assert_eq!(
+            round_trip(&negative, &mut latency),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(latency.0, 10);
+
+        let streams = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![2]);
+        assert_eq!(
+            round_trip(&streams, &mut latency),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(latency.0, 10);
+    }
+
+    #[test]
+    fn unsupported_types_are_rejected() {
+        let mut prop = Prop::<u64, kAudioDevicePropertyLatency>(10);
+        assert_eq!(
+            result_to_err_code(to_plist_value(&prop).map(drop)),
+            kAudioHardwareUnsupportedOperationError as i32
+        );
+        let value = 20u32.to_plist();
+        assert_eq!(
+            result_to_err_code(apply_plist_value(&mut prop, &value)),
+            kAudioHardwareUnsupportedOperationError as i32
+        );
+        assert_eq!(prop.0, 10);
+    }
+
+    #[test]
+    fn trees_round_trip() {
+        let mut from = device("Device");
+        from.volume.handle().set_scalar(0.25);
+        from.mute.handle().store(true);
+        from.output.is_active = BoolProp(false);
+        let plist = to_plist(&from);
+
+        let mut to = device("Device");
+        assert_eq!(result_to_err_code(apply_plist(&mut to, &plist)), 0);
+        assert_eq!(to.volume.handle().scalar(), 0.25);
+        assert!(to.mute.handle().load());
+        assert!(to.input.is_active.0);
+        assert!(!to.output.is_active.0);
+        assert!(to_plist(&to) == plist);
+        assert!(to_plist(&device("Device")) != plist);
+    }
+
+    #[test]
+    fn trees_are_stored_by_object_id() {
+        let object = device("Device");
+        let plist = to_plist(&object);
+        let ids: Vec<_> = string_entries(&plist.downcast::<CFDictionary>().unwrap())
+            .into_iter()
+            .map(|(id, _)| id.parse::<AudioObjectID>().unwrap())
+            .collect();
+        assert_eq!(ids, [2, 3, 4, 5, 6]);
+
+        let value = |id, selector| stored(&plist, id, selector).unwrap();
+        let name = value(2, kAudioObjectPropertyName)
+            .downcast::<CFString>()
+            .unwrap();
+        assert_eq!(name.to_string(), "Device");
+        assert_eq!(
+            bool::from_plist(&value(4, kAudioStreamPropertyIsActive)),
+            Some(true)
+        );
+        assert_eq!(
+            f32::from_plist(&value(5, kAudioLevelControlPropertyScalarValue)),
+            Some(1.0)
+        );
+        assert_eq!(
+            bool::from_plist(&value(6, kAudioBooleanControlPropertyValue)),
+            Some(false)
+        );
+        // formats can't be represented, and are marked as such
+        let format = value(3, kAudioStreamPropertyPhysicalFormat)
+            .downcast::<CFDictionary>()
+            .unwrap();
+        assert_eq!(format.len(), 0);
+    }
+
+    #[test]
+    fn read_only_values_are_not_restored() {
+        let plist = to_plist(&device("Device"));
+        let mut other = device("Other");
+        assert_eq!(result_to_err_code(apply_plist(&mut other, &plist)), 0);
+        let name = stored(&to_plist(&other), 2, kAudioObjectPropertyName).unwrap();
+        assert_eq!(name.downcast::<CFString>().unwrap().to_string(), "Other");
+    }
+}
@@ -1,24 +1,347 @@
 use core_foundation::{
-    base::{kCFAllocatorDefault, CFAllocatorRef, CFEqual, CFRelease, CFRetain},
+    base::{kCFAllocatorDefault, CFAllocatorRef, CFEqual, CFRelease, CFRetain, TCFType},
+    dictionary::CFDictionary,
+    string::CFString,
     uuid::{CFUUIDCreateFromUUIDBytes, CFUUIDGetConstantUUIDWithBytes, CFUUIDRef},
 };
 use coreaudio_sys::{
-    kAudioHardwareIllegalOperationError, AudioServerPlugInDriverInterface, REFIID,
+    kAudioDevicePropertyDeviceIsRunning, kAudioHardwareIllegalOperationError,
+    kAudioHardwareUnspecifiedError, kAudioObjectPropertyOwner, kAudioStreamPropertyPhysicalFormat,
+    kAudioStreamPropertyVirtualFormat, pid_t, AudioObjectID, AudioObjectPropertyAddress,
+    AudioServerPlugInClientInfo, AudioServerPlugInDriverInterface, AudioStreamBasicDescription,
+    REFIID,
 };
-use log::{error, info, warn};
+use log::{error, info, warn, LevelFilter};
 use std::{
+    any::TypeId,
     cell::OnceCell,
+    collections::{HashMap, HashSet},
     mem::transmute,
-    ptr,
-    sync::atomic::{AtomicU32, Ordering},
+    panic::{self, AssertUnwindSafe},
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, Once, OnceLock, PoisonError, RwLock, TryLockError,
+    },
     thread::LocalKey,
 };
 
 use crate::{
-    os_err::result_to_err_code,
+    audio_object::ObjectRegistry,
+    io::{IOBuffer, IOCapabilities, IOCycleInfo, IOOperation},
+    notification::ChangeSet,
+    os_err::{result_to_err_code, OSResult, OSStatus, OSStatusError},
+    property::{PropertyAddress, Qualifier, RawPropertyExt},
     raw_plugin_driver_interface::{PluginHostInterface, RawAudioServerPlugInDriverInterface},
 };
 
+#[cfg(feature = "trace")]
+use crate::trace::AccessKind;
+
+/// The id the HAL assigns to a client of a device, as in `AudioServerPlugInClientInfo::mClientID`
+pub type ClientId = u32;
+
+/// A client of a device, as passed to `AddDeviceClient` and `RemoveDeviceClient`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub client_id: ClientId,
+    /// The process the client lives in
+    pub pid: pid_t,
+    /// Whether the client uses the native byte order of the machine
+    pub is_native_endian: bool,
+    /// The bundle id of the client's process, `None` for processes without one
+    pub bundle_id: Option<CFString>,
+}
+
+impl ClientInfo {
+    /// Copy the client info the HAL passed, `None` if it is null. The bundle id is retained
+    /// # Safety
+    /// `info` must either be null or point to a valid client info, whose bundle id is null or a valid `CFString`
+    pub unsafe fn from_raw(info: *const AudioServerPlugInClientInfo) -> Option<Self> {
+        let info = unsafe { info.as_ref() }?;
+        let bundle_id = (!info.mBundleID.is_null())
+            // Safety: the HAL keeps ownership of the string, so take our own reference
+            .then(|| unsafe { CFString::wrap_under_get_rule(info.mBundleID.cast()) });
+        Some(Self {
+            client_id: info.mClientID,
+            pid: info.mProcessID,
+            is_native_endian: info.mIsNativeEndian != 0,
+            bundle_id,
+        })
+    }
+}
+
+/// The interface of drivers that implement the transport manager semantics: they create and destroy endpoint devices
+/// on request of the HAL. A driver is a transport manager if it returns this from
+/// [`AudioServerPluginDriverInterface::transport_manager`]
+pub trait TransportManagerInterface {
+    /// Create an endpoint device for `client` from the endpoints in `desc`, returning the id of the device. Register the
+    /// device (and publish it on the transport manager object) before returning, the HAL looks it up right away
+    fn create_endpoint_device(
+        &self,
+        desc: CFDictionary,
+        client: ClientInfo,
+    ) -> OSResult<AudioObjectID>;
+    /// Destroy the endpoint device `device` that was created with
+    /// [`TransportManagerInterface::create_endpoint_device`]
+    fn destroy_endpoint_device(&self, device: AudioObjectID) -> OSStatus;
+}
+
+/// State kept for each client of a device in a [`ClientStateMap`]
+pub trait ClientState: Default + Sync {
+    /// Prepare the state for `client` of `device`, which takes it over. States are reused once their client is removed,
+    /// so reset whatever the previous client left here
+    fn reset(&self, device: AudioObjectID, client: ClientId) {
+        let _ = (device, client);
+    }
+}
+
+/// A table of clients the crate keeps up to date, see [`AudioServerPluginDriverInterface::client_states`]
+pub trait ClientTable: Sync {
+    /// Add `client` of `device` unless it is listed already, returning false if there is no room for it
+    fn insert(&self, device: AudioObjectID, client: ClientId) -> bool;
+    /// Remove `client` of `device`, returning whether it was listed
+    fn remove(&self, device: AudioObjectID, client: ClientId) -> bool;
+    fn contains(&self, device: AudioObjectID, client: ClientId) -> bool;
+}
+
+/// Per-client state for up to a fixed number of clients, keyed by device and client id. Returned from
+/// [`AudioServerPluginDriverInterface::client_states`], clients are inserted when the HAL adds them or starts IO for
+/// them and removed when the HAL removes them (or they stop IO, if the HAL never added them).
+///
+/// Lookups and iteration neither lock nor allocate, so they can be used from the IO path, e.g. to mix the data of all
+/// clients of a device. Inserting and removing lock against each other. The states are allocated up front and reused,
+/// see [`ClientState::reset`]. A reader racing with the removal of a client may still see its state, or the state of the
+/// next client taking over the slot while it is reset
+pub struct ClientStateMap<S> {
+    slots: Box<[ClientSlot<S>]>,
+    writer: Mutex<()>,
+}
+
+struct ClientSlot<S> {
+    used: AtomicBool,
+    key: AtomicU64,
+    state: S,
+}
+
+impl<S: ClientState> ClientStateMap<S> {
+    /// A map with room for `capacity` clients over all devices
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| ClientSlot {
+                    used: AtomicBool::new(false),
+                    key: AtomicU64::new(0),
+                    state: S::default(),
+                })
+                .collect(),
+            writer: Mutex::new(()),
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+    fn key(device: AudioObjectID, client: ClientId) -> u64 {
+        (device as u64) << 32 | client as u64
+    }
+    fn slot(&self, device: AudioObjectID, client: ClientId) -> Option<&ClientSlot<S>> {
+        let key = Self::key(device, client);
+        self.slots.iter().find(|slot| {
+            slot.used.load(Ordering::Acquire) && slot.key.load(Ordering::Acquire) == key
+        })
+    }
+    /// The state of `client` of `device`
+    pub fn get(&self, device: AudioObjectID, client: ClientId) -> Option<&S> {
+        self.slot(device, client).map(|slot| &slot.state)
+    }
+    /// The clients of `device` and their states
+    pub fn iter(&self, device: AudioObjectID) -> impl Iterator<Item = (ClientId, &S)> {
+        self.slots.iter().filter_map(move |slot| {
+            if !slot.used.load(Ordering::Acquire) {
+                return None;
+            }
+            let key = slot.key.load(Ordering::Acquire);
+            ((key >> 32) as AudioObjectID == device).then_some((key as ClientId, &slot.state))
+        })
+    }
+}
+
+impl<S: ClientState> ClientTable for ClientStateMap<S> {
+    fn insert(&self, device: AudioObjectID, client: ClientId) -> bool {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.slot(device, client).is_some() {
+            return true;
+        }
+        let Some(slot) = self
+            .slots
+            .iter()
+            .find(|slot| !slot.used.load(Ordering::Acquire))
+        else {
+            return false;
+        };
+        slot.key.store(Self::key(device, client), Ordering::Release);
+        slot.state.reset(device, client);
+        slot.used.store(true, Ordering::Release);
+        true
+    }
+
+    fn remove(&self, device: AudioObjectID, client: ClientId) -> bool {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(slot) = self.slot(device, client) else {
+            return false;
+        };
+        slot.used.store(false, Ordering::Release);
+        true
+    }
+
+    fn contains(&self, device: AudioObjectID, client: ClientId) -> bool {
+        self.slot(device, client).is_some()
+    }
+}
+
+/// How logging is set up when the HAL creates the driver, see [`AudioServerPluginDriverInterface::configure_logging`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggingConfig {
+    /// Log to the unified logging system as `subsystem`, at `level` unless `categories` lists another level for the
+    /// category of a message, which is the module path it was logged from
+    OsLog {
+        subsystem: String,
+        level: LevelFilter,
+        categories: Vec<(String, LevelFilter)>,
+    },
+    /// Install no logger, the driver sets up its own
+    Disabled,
+}
+
+impl LoggingConfig {
+    /// Log to the unified logging system as `com.rustaudio.<name>`, everything in debug builds and only errors in
+    /// release builds
+    pub fn default_for(name: &str) -> Self {
+        Self::OsLog {
+            subsystem: format!("com.rustaudio.{name}"),
+            level: if cfg!(debug_assertions) {
+                LevelFilter::Trace
+            } else {
+                LevelFilter::Error
+            },
+            categories: Vec::new(),
+        }
+    }
+    /// Install the logger, unless one is installed already
+    fn install(self) {
+        let Self::OsLog {
+            subsystem,
+            level,
+            categories,
+        } = self
+        else {
+            return;
+        };
+        let logger = categories.iter().fold(
+            oslog::OsLogger::new(&subsystem).level_filter(level),
+            |logger, (category, level)| logger.category_level_filter(category, *level),
+        );
+        if logger.init().is_err() {
+            warn!("a logger is installed already, not logging as {subsystem}");
+        }
+    }
+}
+
+/// Logging is set up once per process, by the first driver created
+static LOGGING: Once = Once::new();
+
+/// What a process is about to do, see [`AudioServerPluginDriverInterface::check_access`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessOp {
+    /// Read a property, or its size
+    Get,
+    /// Write a property
+    Set,
+    /// Start IO on a device
+    StartIo,
+}
+
+/// A mutation of the driver's objects waiting for the HAL to perform a configuration change, see [`ConfigChangeGuard`]
+type ApplyChange = Box<dyn FnOnce(&mut ObjectRegistry, &mut ChangeSet) -> OSStatus + Send>;
+
+/// What the host holds on to between the request of a configuration change and `perform` or `abort`
+pub(crate) struct ChangeRequest<T: AudioServerPluginDriverInterface> {
+    info: T::DeviceConfigurationChangeInfo,
+    apply: Option<ApplyChange>,
+}
+
+impl<T: AudioServerPluginDriverInterface> ChangeRequest<T> {
+    pub(crate) fn new(info: T::DeviceConfigurationChangeInfo) -> Self {
+        Self { info, apply: None }
+    }
+}
+
+/// A configuration change of a device that mutates the driver's objects only once the HAL performs it.
+///
+/// The mutation must not happen before: the HAL stops IO on the device and then calls `perform`, or drops the request
+/// with `abort`. On perform, the crate runs the mutation on the objects of the driver, notifies the host about the
+/// changes it recorded in the [`ChangeSet`], and then calls [`AudioServerPluginDriverInterface::perform_change`] with
+/// the info. On abort, the mutation is dropped without running and
+/// [`AudioServerPluginDriverInterface::abort_change`] is called. A mutation that fails aborts the change as well.
+///
+/// Only one change made this way can be outstanding per device
+pub struct ConfigChangeGuard<T: AudioServerPluginDriverInterface> {
+    device: AudioObjectID,
+    request: ChangeRequest<T>,
+}
+
+impl<T: AudioServerPluginDriverInterface + 'static> ConfigChangeGuard<T> {
+    pub fn new(
+        device: AudioObjectID,
+        info: T::DeviceConfigurationChangeInfo,
+        apply: impl FnOnce(&mut ObjectRegistry, &mut ChangeSet) -> OSStatus + Send + 'static,
+    ) -> Self {
+        Self {
+            device,
+            request: ChangeRequest {
+                info,
+                apply: Some(Box::new(apply)),
+            },
+        }
+    }
+    /// Ask the host of the driver to perform the change. Fails with [`OSStatusError::HW_NOT_READ_ERR`] before the
+    /// driver is initialized and with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] while another change of the device is
+    /// outstanding. If the request fails, the change is dropped
+    pub fn request(self, context: &DriverContext<'_, T>) -> OSStatus {
+        let host = context.host().filter(|_| context.is_initialized());
+        let Some(host) = host else {
+            error!(
+                "configuration change of device {} requested before initialization",
+                self.device
+            );
+            return Err(OSStatusError::HW_NOT_READ_ERR);
+        };
+        if !context.driver.lock_pending().insert(self.device) {
+            error!(
+                "configuration change of device {} requested while another one is outstanding",
+                self.device
+            );
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        let result = host.request(self.device, self.request);
+        if result.is_err() {
+            context.driver.lock_pending().remove(&self.device);
+        }
+        result
+    }
+}
+
+/// The time stamp of the most recent zero crossing of the ring buffer of a device, as reported by `GetZeroTimeStamp`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZeroTimeStamp {
+    /// The sample time of the zero crossing, a multiple of the ring buffer size
+    pub sample_time: f64,
+    /// The host time (`mach_absolute_time`) of the zero crossing
+    pub host_time: u64,
+    /// Changes whenever the relation between sample and host time changes, e.g. after a sample rate change
+    pub seed: u64,
+}
+
 /// ## Audio Server Plugin Interface
 ///
 /// This is the interface that contains all of the functions CoreAudio needs to interact with your driver.
@@ -33,15 +356,195 @@ use crate::{
 /// Functions in this interface implementation can be called from any thread, at any time, at the disgression of the OS
 /// and its client applications. As such, functions are passed a *shared* reference to the implementation's global state and it is required that that state implement `Sync`.
 ///
+/// #### Panics
+/// A panic in any of these functions is caught before it reaches the HAL and logged. The HAL sees
+/// `kAudioHardwareUnspecifiedError`, or a missing property for `has_property`
+///
 pub trait AudioServerPluginDriverInterface {
     /// The type (likely either an enum or `()`) used to communicate changes in device state through the CoreAudio HAL machinery
     type DeviceConfigurationChangeInfo: Send;
     const NAME: &'static str;
+    /// Whether to free the driver, running [`AudioServerPluginDriverInterface::shutdown`] and the `Drop` impl of the
+    /// state, once the HAL released every reference to it.
+    ///
+    /// `coreaudiod` never unloads a driver bundle while it runs, it keeps its reference until the process exits, at which
+    /// point nothing is released. Drivers are only released to zero by hosts that load and unload them, such as test
+    /// harnesses, and only those may free the driver: a host that calls into the driver after dropping its last
+    /// reference would use freed memory. Off by default, which keeps the driver alive for the life of the process
+    const DEALLOCATE_ON_ZERO: bool = false;
+    /// How to set up logging, called once when the HAL creates the first driver of the process. Defaults to
+    /// [`LoggingConfig::default_for`] the name of the driver
+    fn configure_logging() -> LoggingConfig
+    where
+        Self: Sized,
+    {
+        LoggingConfig::default_for(Self::NAME)
+    }
     /// This is the constructor of your driver. You will probably want to allocate resources here, as this is the last time you will have exclusive access to global state
     fn create(cf_allocator: CFAllocatorRef) -> Self;
     /// This function is called when the HAL tries to bring your driver up, this is where you'll want to do any complex computation, and query the host for information via the host interface
-    /// You're also expected to store the host interface for later use.
+    /// The host is stored by the crate as well once this succeeds and can be reached later through
+    /// [`AudioServerPluginDriverInterface::context`]. This runs once, unless it fails and the HAL initializes again
     fn init(&self, host: PluginHostInterface<Self>) -> crate::os_err::OSStatus;
+    /// The objects of the driver, starting with the [`PluginObject`](crate::audio_object::PluginObject). Property calls
+    /// from the HAL are answered by looking up the object and then the property in this registry.
+    ///
+    /// Changes to the objects are recorded in the [`ChangeQueue`](crate::notification::ChangeQueue) of the registry
+    /// (see [`ObjectRegistry::changes`]). The crate notifies the host about them once it is done with the objects after
+    /// `SetPropertyData`, starting or stopping IO and performing a configuration change. Flush the queue yourself after
+    /// changing objects at other times, e.g. from a work queue of the driver
+    fn objects(&self) -> &Mutex<ObjectRegistry>;
+    /// Called right before the driver is freed, see [`AudioServerPluginDriverInterface::DEALLOCATE_ON_ZERO`]. Stop and
+    /// join the threads of the driver here, which may still reference the state
+    fn shutdown(&self) {}
+    /// The transport manager side of the driver, if it is one. Requests to create or destroy devices fail with
+    /// [`OSStatusError::HW_UNSUPPORTED_OP`] for drivers that aren't
+    fn transport_manager(&self) -> Option<&dyn TransportManagerInterface> {
+        None
+    }
+    /// The state the crate keeps for this driver, such as the host and the clients of each device. `None` unless `self`
+    /// is the state of a driver created by the HAL through [`RawAudioServerPlugInDriverInterface::create`]
+    fn context(&self) -> Option<DriverContext<'_, Self>>
+    where
+        Self: Sized + 'static,
+    {
+        DriverContext::of(self)
+    }
+    /// The per-client states of the driver, usually a [`ClientStateMap`] kept in the state. The crate inserts clients
+    /// when they are added or start IO and removes them when they are removed, or with their last stop of IO if the HAL
+    /// didn't add them. The driver reads the states through its own map, which it can do from the IO path
+    fn client_states(&self) -> Option<&dyn ClientTable> {
+        None
+    }
+    /// Whether the process `pid` may do `op` on `address` of `object`, asked before the property is read or written and
+    /// before a client starts IO on a device (with `kAudioDevicePropertyDeviceIsRunning` as the address, and a `pid` of
+    /// -1 if the HAL didn't add the client). Any error denies, which the HAL sees as
+    /// [`OSStatusError::DEV_PERMISSIONS_ERR`]. Everything is allowed by default
+    fn check_access(
+        &self,
+        pid: pid_t,
+        object: AudioObjectID,
+        address: &PropertyAddress,
+        op: AccessOp,
+    ) -> OSStatus {
+        let _ = (pid, object, address, op);
+        Ok(())
+    }
+    /// A client started using `device`. The crate keeps track of the clients of each device, see
+    /// [`DriverContext::clients`]. An error keeps the client from being added
+    fn add_client(&self, device: AudioObjectID, client: &ClientInfo) -> OSStatus {
+        let _ = (device, client);
+        Ok(())
+    }
+    /// A client stopped using `device`. It is forgotten by the crate regardless of the result
+    fn remove_client(&self, device: AudioObjectID, client: &ClientInfo) -> OSStatus {
+        let _ = (device, client);
+        Ok(())
+    }
+    /// Run the configuration change of `device` requested with [`PluginHostInterface::request_change`], receiving the
+    /// `info` passed there. The HAL stops IO on the device while this runs
+    ///
+    /// For changes requested through a [`ConfigChangeGuard`], this runs after its mutation was applied
+    fn perform_change(
+        &self,
+        device: AudioObjectID,
+        info: Self::DeviceConfigurationChangeInfo,
+    ) -> OSStatus {
+        let _ = (device, info);
+        Ok(())
+    }
+    /// The HAL dropped the configuration change of `device` requested with [`PluginHostInterface::request_change`]
+    /// without performing it. The mutation of a [`ConfigChangeGuard`] was dropped without running
+    fn abort_change(
+        &self,
+        device: AudioObjectID,
+        info: Self::DeviceConfigurationChangeInfo,
+    ) -> OSStatus {
+        let _ = (device, info);
+        Ok(())
+    }
+    /// Called when the first client starts IO on `device`. The HAL starts IO once per client, further starts are only
+    /// counted until as many stops arrived. An error leaves the device stopped
+    fn start_io(&self, device: AudioObjectID, client: ClientId) -> OSStatus {
+        let _ = (device, client);
+        Ok(())
+    }
+    /// Called when the last client doing IO on `device` stops it
+    fn stop_io(&self, device: AudioObjectID, client: ClientId) -> OSStatus {
+        let _ = (device, client);
+        Ok(())
+    }
+    /// The current zero time stamp of `device`.
+    ///
+    /// **Real time**: this is called from the IO thread and must not allocate, lock or block. Keep the clock state in
+    /// atomics (see [`AtomicHandle`](crate::property::AtomicHandle)), or use a
+    /// [`TimestampGenerator`](crate::timing::TimestampGenerator). Fails with
+    /// [`OSStatusError::HW_UNSUPPORTED_OP`] unless implemented
+    fn zero_time_stamp(&self, device: AudioObjectID, client: ClientId) -> OSResult<ZeroTimeStamp> {
+        let _ = (device, client);
+        Err(OSStatusError::HW_UNSUPPORTED_OP)
+    }
+    /// The IO operations `device` takes part in, which are the ones [`AudioServerPluginDriverInterface::io_operation`]
+    /// is called with. Devices take part in none by default.
+    ///
+    /// **Real time**: this is called from the IO thread and must not allocate, lock or block
+    fn io_capabilities(&self, device: AudioObjectID) -> IOCapabilities {
+        let _ = device;
+        IOCapabilities::NONE
+    }
+    /// Called before `op` runs for the streams of `device` in a cycle, with the same `cycle` info
+    /// [`AudioServerPluginDriverInterface::io_operation`] gets. Snapshot state for the cycle here, such as the current
+    /// gain, so all streams see the same. Only called for the operations in
+    /// [`AudioServerPluginDriverInterface::io_capabilities`], which may include [`IOOperation::Cycle`] to bracket the
+    /// whole cycle.
+    ///
+    /// **Real time**: this is called from the IO thread and must not allocate, lock or block
+    fn begin_io(
+        &self,
+        device: AudioObjectID,
+        client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        let _ = (device, client, op, frames, cycle);
+        Ok(())
+    }
+    /// Called after `op` ran for the streams of `device`, see [`AudioServerPluginDriverInterface::begin_io`].
+    ///
+    /// **Real time**: this is called from the IO thread and must not allocate, lock or block
+    fn end_io(
+        &self,
+        device: AudioObjectID,
+        client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        let _ = (device, client, op, frames, cycle);
+        Ok(())
+    }
+    /// Run `op` for `frames` frames of `stream` on `device`. `main` is the buffer the operation works on, in the format
+    /// [`IOOperation::uses_physical_format`] names, as the stream had it when IO started. `secondary` is the second
+    /// buffer the HAL passes to some operations, in the same format.
+    ///
+    /// **Real time**: this is called from the IO thread and must not allocate, lock or block. Fails with
+    /// [`OSStatusError::HW_UNSUPPORTED_OP`] unless implemented
+    #[allow(clippy::too_many_arguments)]
+    fn io_operation(
+        &self,
+        device: AudioObjectID,
+        stream: AudioObjectID,
+        client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+        main: IOBuffer<'_>,
+        secondary: Option<IOBuffer<'_>>,
+    ) -> OSStatus {
+        let _ = (device, stream, client, op, frames, cycle, main, secondary);
+        Err(OSStatusError::HW_UNSUPPORTED_OP)
+    }
 }
 
 #[repr(C)]
@@ -49,7 +552,431 @@ pub struct PluginDriverImplementation<T> {
     implementation: *const AudioServerPlugInDriverInterface,
     state: T,
     refcount: AtomicU32,
+    io: Mutex<IoClients>,
+    formats: RwLock<HashMap<AudioObjectID, StreamFormats>>,
+    clients: Mutex<DeviceClients>,
+    /// Set once `init` succeeded
+    host: OnceLock<PluginHostInterface<T>>,
+    /// Whether `init` runs or succeeded, so it doesn't run again unless it failed
+    initializing: AtomicBool,
+    initialized: AtomicBool,
+    /// The devices with a change requested through a [`ConfigChangeGuard`] that wasn't performed or aborted yet
+    pending_changes: Mutex<HashSet<AudioObjectID>>,
+}
+
+/// The drivers created by [`RawAudioServerPlugInDriverInterface::create`] as the address and type of their state and
+/// their own address, so [`DriverContext::of`] can find the driver around a state
+static DRIVERS: RwLock<Vec<(usize, TypeId, usize)>> = RwLock::new(Vec::new());
+
+/// The state the crate keeps for a driver next to the driver's own, see [`AudioServerPluginDriverInterface::context`]
+pub struct DriverContext<'a, T> {
+    driver: &'a PluginDriverImplementation<T>,
+}
+
+impl<'a, T: AudioServerPluginDriverInterface + 'static> DriverContext<'a, T> {
+    /// The context of the driver `state` belongs to, `None` if it wasn't created through
+    /// [`RawAudioServerPlugInDriverInterface::create`]
+    pub fn of(state: &'a T) -> Option<Self> {
+        let address = ptr::from_ref(state) as usize;
+        let drivers = DRIVERS.read().unwrap_or_else(PoisonError::into_inner);
+        let &(_, _, driver) = drivers
+            .iter()
+            .find(|&&(known, ty, _)| known == address && ty == TypeId::of::<T>())?;
+        // Safety: drivers are listed for as long as they live, and `state` borrows from the driver
+        Some(Self {
+            driver: unsafe { &*(driver as *const PluginDriverImplementation<T>) },
+        })
+    }
+    /// Whether `init` ran and succeeded. The HAL initializes a driver once, further attempts fail without calling `init`
+    pub fn is_initialized(&self) -> bool {
+        self.driver.initialized.load(Ordering::Acquire)
+    }
+    /// The host the driver was initialized with, `None` until `init` succeeded
+    pub fn host(&self) -> Option<&'a PluginHostInterface<T>> {
+        self.driver.host.get()
+    }
+    /// The clients currently using `device`, in the order they were added
+    pub fn clients(&self, device: AudioObjectID) -> Vec<ClientInfo> {
+        self.driver.lock_clients().of(device).to_vec()
+    }
+    /// The client `client` of `device`, if the HAL added it
+    pub fn client(&self, device: AudioObjectID, client: ClientId) -> Option<ClientInfo> {
+        self.driver
+            .lock_clients()
+            .of(device)
+            .iter()
+            .find(|known| known.client_id == client)
+            .cloned()
+    }
+}
+
+/// The formats of a stream of a running device, captured when IO starts so the IO path doesn't have to look them up
+#[derive(Debug, Clone, Copy)]
+struct StreamFormats {
+    device: AudioObjectID,
+    physical: AudioStreamBasicDescription,
+    virtual_format: AudioStreamBasicDescription,
+}
+
+/// The clients doing IO on each device
+#[derive(Debug, Default)]
+struct IoClients {
+    devices: HashMap<AudioObjectID, HashMap<ClientId, IoClient>>,
+}
+
+/// A client doing IO on a device
+#[derive(Debug)]
+struct IoClient {
+    /// The starts not matched by a stop yet
+    starts: u32,
+    /// Whether the per-client state of the client was inserted for IO, and goes once it stops
+    forget: bool,
+}
+
+/// What a stop of IO ended, see [`IoClients::stop`]
+#[derive(Debug, Clone, Copy)]
+struct IoStopped {
+    /// No client is doing IO on the device anymore
+    device: bool,
+    /// That was the last stop of the client, whose per-client state was only kept for IO
+    forget_client: bool,
 }
+
+impl IoClients {
+    /// Count a start by `client`, returning whether `device` was stopped before. `forget` is whether the per-client
+    /// state of the client was inserted for this start, which only counts for its first start
+    fn start(&mut self, device: AudioObjectID, client: ClientId, forget: bool) -> bool {
+        let clients = self.devices.entry(device).or_default();
+        let was_stopped = clients.is_empty();
+        clients
+            .entry(client)
+            .or_insert(IoClient { starts: 0, forget })
+            .starts += 1;
+        was_stopped
+    }
+    /// Keep the per-client state of `client` after its last stop, as the HAL added it
+    fn keep(&mut self, device: AudioObjectID, client: ClientId) {
+        if let Some(io) = self
+            .devices
+            .get_mut(&device)
+            .and_then(|clients| clients.get_mut(&client))
+        {
+            io.forget = false;
+        }
+    }
+    /// Count a stop by `client`. Fails with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if the client isn't doing IO
+    /// on the device
+    fn stop(&mut self, device: AudioObjectID, client: ClientId) -> OSResult<IoStopped> {
+        let Some(clients) = self.devices.get_mut(&device) else {
+            error!("client {client} stopped IO on device {device}, which isn't running");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        };
+        let Some(io) = clients.get_mut(&client) else {
+            error!("client {client} stopped IO on device {device} without starting it");
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        };
+        io.starts -= 1;
+        let forget_client = io.starts == 0 && io.forget;
+        if io.starts == 0 {
+            clients.remove(&client);
+        }
+        let device_stopped = clients.is_empty();
+        if device_stopped {
+            self.devices.remove(&device);
+        }
+        Ok(IoStopped {
+            device: device_stopped,
+            forget_client,
+        })
+    }
+}
+
+/// The clients the HAL added to each device
+#[derive(Debug, Default)]
+struct DeviceClients {
+    devices: HashMap<AudioObjectID, Vec<ClientInfo>>,
+}
+
+impl DeviceClients {
+    /// Add `client` to `device`, replacing a client with the same id
+    fn add(&mut self, device: AudioObjectID, client: ClientInfo) {
+        let clients = self.devices.entry(device).or_default();
+        match clients
+            .iter_mut()
+            .find(|known| known.client_id == client.client_id)
+        {
+            Some(known) => *known = client,
+            None => clients.push(client),
+        }
+    }
+    /// Remove the client `client` from `device`, returning it if it was added
+    fn remove(&mut self, device: AudioObjectID, client: ClientId) -> Option<ClientInfo> {
+        let clients = self.devices.get_mut(&device)?;
+        let index = clients.iter().position(|known| known.client_id == client)?;
+        let removed = clients.remove(index);
+        if clients.is_empty() {
+            self.devices.remove(&device);
+        }
+        Some(removed)
+    }
+    fn of(&self, device: AudioObjectID) -> &[ClientInfo] {
+        self.devices.get(&device).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl<T: AudioServerPluginDriverInterface> PluginDriverImplementation<T> {
+    /// Ask the driver whether `pid` may do `op`, see [`AudioServerPluginDriverInterface::check_access`]
+    fn check_access(
+        &self,
+        pid: pid_t,
+        object: AudioObjectID,
+        address: &PropertyAddress,
+        op: AccessOp,
+    ) -> OSStatus {
+        self.state
+            .check_access(pid, object, address, op)
+            .map_err(|err| {
+                warn!("denied {op:?} of {address:?} on object {object} to process {pid}: {err:?}");
+                OSStatusError::DEV_PERMISSIONS_ERR
+            })
+    }
+    /// Add `client` of `device` to the per-client states of the driver, if it has them
+    fn track_client(&self, device: AudioObjectID, client: ClientId) -> OSStatus {
+        match self.state.client_states() {
+            Some(states) if !states.insert(device, client) => {
+                error!("no room for the state of client {client} of device {device}");
+                Err(OSStatusError::HW_UNSPECIFIED_ERR)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Remove `client` of `device` from the per-client states of the driver, if it has them
+    fn forget_client(&self, device: AudioObjectID, client: ClientId) {
+        if let Some(states) = self.state.client_states() {
+            states.remove(device, client);
+        }
+    }
+    /// Shut down and free `driver`, forgetting it in [`DRIVERS`]
+    /// # Safety
+    /// `driver` must have been created by [`RawAudioServerPlugInDriverInterface::create`], with no references to it left
+    /// and no further calls into it
+    unsafe fn destroy(driver: NonNull<Self>) {
+        info!("destroying driver {}", T::NAME);
+        DRIVERS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|&(_, _, known)| known != driver.as_ptr() as usize);
+        // Safety: guaranteed by the caller
+        let driver = unsafe { Box::from_raw(driver.as_ptr()) };
+        driver.state.shutdown();
+        drop(driver);
+    }
+    fn lock_pending(&self) -> MutexGuard<'_, HashSet<AudioObjectID>> {
+        self.pending_changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Run the mutation of a change requested through a [`ConfigChangeGuard`] and notify the host about what it changed
+    fn apply_change(&self, device: AudioObjectID, apply: ApplyChange) -> OSStatus {
+        self.lock_pending().remove(&device);
+        let mut changes = ChangeSet::new();
+        self.with_objects(|objects| apply(objects, &mut changes))?;
+        if let Some(host) = self.host.get()
+            && let Err(err) = changes.flush(host)
+        {
+            warn!("failed to notify the host about the configuration change of device {device}: {err:?}");
+        }
+        Ok(())
+    }
+    /// Notify the host about the changes recorded in the queue of the objects, without holding their lock
+    fn flush_changes(&self) {
+        let Some(host) = self.host.get() else {
+            return;
+        };
+        let changes = self.with_objects(|objects| objects.changes().clone());
+        if let Err(err) = changes.flush(host) {
+            warn!("failed to notify the host about changed properties: {err:?}");
+        }
+    }
+    fn lock_clients(&self) -> MutexGuard<'_, DeviceClients> {
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Run `f` on the objects of the driver. A panic while they were locked doesn't make them unusable
+    fn with_objects<R>(&self, f: impl FnOnce(&mut ObjectRegistry) -> R) -> R {
+        let mut objects = self
+            .state
+            .objects()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f(&mut objects)
+    }
+    /// Fail with [`OSStatusError::HW_BAD_OBJECT_ERR`] unless `device` is a registered device
+    fn check_device(&self, device: AudioObjectID) -> OSStatus {
+        self.with_objects(|objects| {
+            objects
+                .property(
+                    device,
+                    PropertyAddress::global(kAudioDevicePropertyDeviceIsRunning),
+                )
+                .map(drop)
+        })
+        .map_err(|_| {
+            error!("object {device} is not a device");
+            OSStatusError::HW_BAD_OBJECT_ERR
+        })
+    }
+    /// Capture the formats of the streams of `device` for the IO path, or forget them if `running` is false
+    fn capture_formats(&self, device: AudioObjectID, running: bool) {
+        let streams: Vec<_> = if running {
+            self.with_objects(|objects| {
+                objects
+                    .ids()
+                    .filter_map(|id| {
+                        let object = objects.get(id).ok()?;
+                        let global = |selector| {
+                            object.get_object_property(PropertyAddress::global(selector))
+                        };
+                        let owner = global(kAudioObjectPropertyOwner)?.value::<AudioObjectID>()?;
+                        let format = |selector| {
+                            global(selector)?
+                                .value::<AudioStreamBasicDescription>()
+                                .copied()
+                        };
+                        (*owner == device).then_some(())?;
+                        Some((
+                            id,
+                            StreamFormats {
+                                device,
+                                physical: format(kAudioStreamPropertyPhysicalFormat)?,
+                                virtual_format: format(kAudioStreamPropertyVirtualFormat)?,
+                            },
+                        ))
+                    })
+                    .collect()
+            })
+        } else {
+            Vec::new()
+        };
+        let mut formats = self.formats.write().unwrap_or_else(PoisonError::into_inner);
+        formats.retain(|_, stream| stream.device != device);
+        formats.extend(streams);
+    }
+    /// Update `kAudioDevicePropertyDeviceIsRunning` of `device`, recording the change with the registry
+    fn set_running(&self, device: AudioObjectID, running: bool) {
+        let address = PropertyAddress::global(kAudioDevicePropertyDeviceIsRunning);
+        self.with_objects(|objects| {
+            let Some(is_running) = objects
+                .property(device, address)
+                .ok()
+                .and_then(|prop| prop.value::<Arc<AtomicBool>>())
+            else {
+                return;
+            };
+            if is_running.swap(running, Ordering::AcqRel) != running {
+                objects.mark_changed(device, address);
+            }
+        });
+    }
+}
+
+/// Run the body of the callback `callback`, returning `fallback` if it panics. Unwinding into the HAL is undefined
+/// behavior and takes down all audio on the machine, so every callback goes through this. It doesn't allocate unless
+/// `f` panics
+fn guard<R>(callback: &str, fallback: R, f: impl FnOnce() -> R) -> R {
+    // the state of the driver stays usable after a panic, its locks are taken ignoring poisoning
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("no message");
+        error!("panic in {callback}: {message}");
+        fallback
+    })
+}
+
+/// Report a property call of the HAL on behalf of `client_pid`, see [`crate::trace`]
+#[cfg(feature = "trace")]
+fn trace_access(
+    kind: AccessKind,
+    object: AudioObjectID,
+    client_pid: pid_t,
+    address: PropertyAddress,
+    requested_size: u32,
+    returned_size: Option<u32>,
+    status: OSStatus,
+) {
+    crate::trace::emit(crate::trace::TraceEvent {
+        kind,
+        object,
+        address,
+        requested_size,
+        returned_size,
+        status,
+        client_pid: Some(client_pid),
+    });
+}
+
+/// The property address the HAL passed, [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`] if it is null
+/// # Safety
+/// `address` must either be null or point to a valid address
+unsafe fn read_address(address: *const AudioObjectPropertyAddress) -> OSResult<PropertyAddress> {
+    match unsafe { address.as_ref() } {
+        Some(&address) => Ok(address.into()),
+        None => {
+            error!("no property address passed");
+            Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR)
+        }
+    }
+}
+
+/// Reclaim the change request passed to the host by [`PluginHostInterface::request_change`] or
+/// [`ConfigChangeGuard::request`] from the pointer the HAL hands back
+/// # Safety
+/// `change_info` must be null or a pointer created by one of those that wasn't reclaimed yet
+unsafe fn take_change_info<T: AudioServerPluginDriverInterface>(
+    change_info: *mut std::ffi::c_void,
+) -> Option<ChangeRequest<T>> {
+    let Some(request) = NonNull::new(change_info.cast::<ChangeRequest<T>>()) else {
+        error!(
+            "configuration change without change info, it wasn't requested with `request_change`"
+        );
+        return None;
+    };
+    // Safety: guaranteed by the caller, the HAL passes every pointer back exactly once
+    Some(*unsafe { Box::from_raw(request.as_ptr()) })
+}
+
+/// The state of the driver behind `driver`, for code that only has the driver ref, such as callbacks registered with
+/// CoreFoundation. `None` if `driver` is null. Prefer [`checked_driver_state`], which also checks the type
+/// # Safety
+/// `driver` must be null or a driver ref created by [`RawAudioServerPlugInDriverInterface::create`] for `T`, which
+/// stays alive for `'a`
+pub unsafe fn driver_state<'a, T: AudioServerPluginDriverInterface>(
+    driver: coreaudio_sys::AudioServerPlugInDriverRef,
+) -> Option<&'a T> {
+    let driver = unsafe { driver.cast::<PluginDriverImplementation<T>>().as_ref() }?;
+    Some(&driver.state)
+}
+
+/// Like [`driver_state`], but `None` unless `driver` is a live driver created for `T`, so a ref of another driver type
+/// (or any other pointer) is rejected instead of being cast
+/// # Safety
+/// If `driver` is a driver created for `T`, it must stay alive for `'a`, which it does unless it frees itself (see
+/// [`AudioServerPluginDriverInterface::DEALLOCATE_ON_ZERO`])
+pub unsafe fn checked_driver_state<'a, T: AudioServerPluginDriverInterface + 'static>(
+    driver: coreaudio_sys::AudioServerPlugInDriverRef,
+) -> Option<&'a T> {
+    let address = driver as usize;
+    let known = DRIVERS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|&(_, ty, known)| known == address && ty == TypeId::of::<T>());
+    // Safety: `driver` is a live driver for `T`, its lifetime is guaranteed by the caller
+    known.then(|| unsafe { driver_state(driver) }).flatten()
+}
+
 macro_rules! validate_impl_ref {
     ($ptr:expr) => {{
         let Some(f) = $ptr.cast::<PluginDriverImplementation<Self>>().as_ref() else {
@@ -58,7 +985,7 @@ macro_rules! validate_impl_ref {
         f
     }};
 }
-// Until this is fully implemented
+// Not every callback needs all of its arguments
 #[allow(unused_variables)]
 impl<Implementation> RawAudioServerPlugInDriverInterface for Implementation
 where
@@ -68,44 +995,45 @@ where
         alloc: coreaudio_sys::CFAllocatorRef,
         requested_uuid: crate::base::CFUUIDRef,
     ) -> *mut std::ffi::c_void {
-        let mut logger = oslog::OsLogger::new(&format!("com.rustaudio.{}", Self::NAME));
+        guard("create", ptr::null_mut(), || {
+            LOGGING.call_once(|| Self::configure_logging().install());
 
-        #[cfg(not(debug_assertions))]
-        {
-            logger = logger.level_filter(log::LevelFilter::Error);
-        }
-        #[cfg(debug_assertions)]
-        {
-            logger = logger.level_filter(log::LevelFilter::Trace);
-        }
+            info!("Driver Plugin Driver Constructor: {}", Self::NAME);
+            if unsafe {
+                CFEqual(
+                    requested_uuid.cast(),
+                    AUDIO_SERVER_DRIVER_PLUGIN_TYPE.get().cast(),
+                ) == 1
+            } {
+                // Init and allocate driver
+                let state = Implementation::create(alloc.cast());
 
-        let Ok(()) = logger.init() else {
-            panic!("failed to initialize logger from Rust CoreAudio Driver");
-        };
+                // explicitly borrow IMPLEMENTATION for 'static (to make it clear that it gets promoted to a static)
+                let impl_borrow: &'static AudioServerPlugInDriverInterface = &Self::IMPLEMENTATION;
 
-        info!("Driver Plugin Driver Constructor: {}", Self::NAME);
-        if unsafe {
-            CFEqual(
-                requested_uuid.cast(),
-                AUDIO_SERVER_DRIVER_PLUGIN_TYPE.get().cast(),
-            ) == 1
-        } {
-            // Init and allocate driver
-            let state = Implementation::create(alloc.cast());
-
-            // explicitly borrow IMPLEMENTATION for 'static (to make it clear that it gets promoted to a static)
-            let impl_borrow: &'static AudioServerPlugInDriverInterface = &Self::IMPLEMENTATION;
-
-            // Allocate implementation container
-            Box::<_>::into_raw(Box::new(PluginDriverImplementation {
-                implementation: impl_borrow as *const AudioServerPlugInDriverInterface,
-                refcount: AtomicU32::new(1),
-                state,
-            }))
-            .cast()
-        } else {
-            ptr::null_mut()
-        }
+                // Allocate implementation container
+                let driver = Box::<_>::into_raw(Box::new(PluginDriverImplementation {
+                    implementation: impl_borrow as *const AudioServerPlugInDriverInterface,
+                    refcount: AtomicU32::new(1),
+                    state,
+                    io: Mutex::default(),
+                    formats: RwLock::default(),
+                    clients: Mutex::default(),
+                    host: OnceLock::new(),
+                    initializing: AtomicBool::new(false),
+                    initialized: AtomicBool::new(false),
+                    pending_changes: Mutex::default(),
+                }));
+                let state = unsafe { ptr::addr_of!((*driver).state) } as usize;
+                DRIVERS
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push((state, TypeId::of::<Self>(), driver as usize));
+                driver.cast()
+            } else {
+                ptr::null_mut()
+            }
+        })
     }
 
     unsafe extern "C" fn query_interface(
@@ -113,86 +1041,118 @@ where
         in_uuid: coreaudio_sys::REFIID,
         out_interface: *mut coreaudio_sys::LPVOID,
     ) -> coreaudio_sys::HRESULT {
-        if out_interface.is_null() {
-            error!("no space for output of query_interface");
-            return kAudioHardwareIllegalOperationError as i32;
-        }
-        let requested_uuid = unsafe {
-            CFUUIDCreateFromUUIDBytes(
-                ptr::null_mut(),
-                transmute::<REFIID, core_foundation::uuid::CFUUIDBytes>(in_uuid),
-            )
-        };
-        if requested_uuid.is_null() {
-            error!("failed to create new uuid from device in {}", file!());
-            return kAudioHardwareIllegalOperationError as i32;
-        }
-        //HRESULT ok
-        let mut ret = 0;
-        if unsafe {
-            CFEqual(
-                requested_uuid.cast(),
-                AUDIO_SERVER_DRIVER_PLUGIN_INTERFACE.get().cast(),
-            ) == 1
-                || CFEqual(requested_uuid.cast(), I_UNKNOWN_INTERFACE.get().cast()) == 1
-        } {
-            info!("query interface matched");
-            unsafe { ptr::write(out_interface, driver) }
-        } else {
-            // E_NOINTERFACE, CFPlugInCOM.h
-            ret = 0x80000004u32 as i32;
-            warn!("Requested interface did not match in QueryInterface!");
-        }
-        unsafe { CFRelease(requested_uuid.cast()) }
-        ret
+        guard(
+            "query_interface",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if out_interface.is_null() {
+                    error!("no space for output of query_interface");
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let requested_uuid = unsafe {
+                    CFUUIDCreateFromUUIDBytes(
+                        ptr::null_mut(),
+                        transmute::<REFIID, core_foundation::uuid::CFUUIDBytes>(in_uuid),
+                    )
+                };
+                if requested_uuid.is_null() {
+                    error!("failed to create new uuid from device in {}", file!());
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                //HRESULT ok
+                let mut ret = 0;
+                if unsafe {
+                    CFEqual(
+                        requested_uuid.cast(),
+                        AUDIO_SERVER_DRIVER_PLUGIN_INTERFACE.get().cast(),
+                    ) == 1
+                        || CFEqual(requested_uuid.cast(), I_UNKNOWN_INTERFACE.get().cast()) == 1
+                } {
+                    info!("query interface matched");
+                    // COM hands out interfaces retained, the host releases what it got from here
+                    implementation.refcount.fetch_add(1, Ordering::SeqCst);
+                    unsafe { ptr::write(out_interface, driver) }
+                } else {
+                    // E_NOINTERFACE, CFPlugInCOM.h
+                    ret = 0x80000004u32 as i32;
+                    warn!("Requested interface did not match in QueryInterface!");
+                }
+                unsafe { CFRelease(requested_uuid.cast()) }
+                ret
+            },
+        )
     }
 
     unsafe extern "C" fn retain(driver: *mut std::ffi::c_void) -> coreaudio_sys::ULONG {
-        let Some(r) = (unsafe { driver.cast::<PluginDriverImplementation<Self>>().as_ref() }) else {
-            //0 refcount for null implementation
-            error!("attempted to retain null implementation");
-            return 0;
-        };
-        // Add the reference we added
-        let prev_count = r.refcount.fetch_add(1, Ordering::SeqCst);
-        info!("retain called, new refcount: {}", prev_count + 1);
-        //Pointer is non-null, refcount is 0
-        if prev_count == 0 {
-            0
-        } else {
-            // Do the increment we just did on the previous value
-            prev_count + 1
-        }
+        guard("retain", 0, || {
+            let Some(r) = (unsafe { driver.cast::<PluginDriverImplementation<Self>>().as_ref() })
+            else {
+                //0 refcount for null implementation
+                error!("attempted to retain null implementation");
+                return 0;
+            };
+            // AddRef returns the new count, whatever it was before
+            let count = r.refcount.fetch_add(1, Ordering::SeqCst).saturating_add(1);
+            info!("retain called, new refcount: {}", count);
+            count
+        })
     }
 
     unsafe extern "C" fn release(driver: *mut std::ffi::c_void) -> coreaudio_sys::ULONG {
-        // We are not actually supposed to deallocate anything when this reaches 0 for whatever reason
-        let Some(r) = (unsafe { driver.cast::<PluginDriverImplementation<Self>>().as_ref() }) else {
-            warn!("attempted to release null implementation");
-            //0 refcount for null implementation
-            return 0;
-        };
+        guard("release", 0, || {
+            // The driver is only freed at 0 if it opted in, see `DEALLOCATE_ON_ZERO`
+            let Some(r) = NonNull::new(driver.cast::<PluginDriverImplementation<Self>>()) else {
+                warn!("attempted to release null implementation");
+                //0 refcount for null implementation
+                return 0;
+            };
 
-        let ret = r
-            .refcount
-            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
-                Some(val.saturating_sub(1))
-            })
-            .unwrap()
-            .saturating_sub(1);
-        info!("release called, new refcount: {}", ret);
-        ret
+            // Release returns the new count. Releasing more often than retained is a bug of the host, the count stays 0
+            let Ok(prev_count) = unsafe { r.as_ref() }.refcount.fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |count| count.checked_sub(1),
+            ) else {
+                error!("release called with a refcount of 0");
+                return 0;
+            };
+            let ret = prev_count - 1;
+            info!("release called, new refcount: {}", ret);
+            // only the release that took the count from 1 to 0 frees, so the driver is freed once
+            if prev_count == 1 && Self::DEALLOCATE_ON_ZERO {
+                // Safety: that was the last reference, the host doesn't call into the driver anymore
+                unsafe { PluginDriverImplementation::destroy(r) };
+            }
+            ret
+        })
     }
     unsafe extern "C" fn initialize(
         driver: coreaudio_sys::AudioServerPlugInDriverRef,
         host: coreaudio_sys::AudioServerPlugInHostRef,
     ) -> coreaudio_sys::OSStatus {
-        info!("Initialize called: {}", Self::NAME);
-        let Some(hostref) = (unsafe { PluginHostInterface::new(host) }) else {
-            return kAudioHardwareIllegalOperationError as i32;
-        };
-        let implementation = unsafe { validate_impl_ref!(driver) };
-        result_to_err_code(implementation.state.init(hostref))
+        guard("initialize", kAudioHardwareUnspecifiedError as i32, || {
+            info!("Initialize called: {}", Self::NAME);
+            let Some(hostref) = (unsafe { PluginHostInterface::new(host) }) else {
+                return kAudioHardwareIllegalOperationError as i32;
+            };
+            let implementation = unsafe { validate_impl_ref!(driver) };
+            // only the first call reaches `init`, or the first after it failed
+            if implementation.initializing.swap(true, Ordering::AcqRel) {
+                error!("driver {} initialized twice", Self::NAME);
+                return kAudioHardwareIllegalOperationError as i32;
+            }
+            let result = implementation.state.init(hostref);
+            if result.is_err() {
+                implementation.initializing.store(false, Ordering::Release);
+                return result_to_err_code(result);
+            }
+            // the host reads every object once initialized, what changed while building them is old news
+            implementation.with_objects(|objects| objects.changes().take());
+            let _ = implementation.host.set(hostref);
+            implementation.initialized.store(true, Ordering::Release);
+            0
+        })
     }
 
     unsafe extern "C" fn create_device(
@@ -201,14 +1161,45 @@ where
         client_info: *const coreaudio_sys::AudioServerPlugInClientInfo,
         device_object_id: *mut coreaudio_sys::AudioObjectID,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "create_device",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if desc.is_null() || device_object_id.is_null() {
+                    error!("no description or no space for output of create_device");
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let Some(client) = (unsafe { ClientInfo::from_raw(client_info) }) else {
+                    error!("no client info passed to create_device");
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                let Some(transport_manager) = implementation.state.transport_manager() else {
+                    return result_to_err_code(Err(OSStatusError::HW_UNSUPPORTED_OP));
+                };
+                // Safety: the HAL keeps ownership of the description, so take our own reference
+                let desc = unsafe { CFDictionary::wrap_under_get_rule(desc.cast()) };
+                let device = transport_manager.create_endpoint_device(desc, client);
+                result_to_err_code(device.map(|device| unsafe { device_object_id.write(device) }))
+            },
+        )
     }
 
     unsafe extern "C" fn destroy_device(
         driver: coreaudio_sys::AudioServerPlugInDriverRef,
         device_id: coreaudio_sys::AudioObjectID,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "destroy_device",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let Some(transport_manager) = implementation.state.transport_manager() else {
+                    return result_to_err_code(Err(OSStatusError::HW_UNSUPPORTED_OP));
+                };
+                result_to_err_code(transport_manager.destroy_endpoint_device(device_id))
+            },
+        )
     }
 
     unsafe extern "C" fn add_device_client(
@@ -216,7 +1207,34 @@ where
         device_id: coreaudio_sys::AudioObjectID,
         client_info: *const coreaudio_sys::AudioServerPlugInClientInfo,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "add_device_client",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let Some(client) = (unsafe { ClientInfo::from_raw(client_info) }) else {
+                    error!("no client info passed to add_device_client");
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                if let Err(err) = implementation.check_device(device_id) {
+                    return result_to_err_code(Err(err));
+                }
+                if let Err(err) = implementation.track_client(device_id, client.client_id) {
+                    return result_to_err_code(Err(err));
+                }
+                if let Err(err) = implementation.state.add_client(device_id, &client) {
+                    implementation.forget_client(device_id, client.client_id);
+                    return result_to_err_code(Err(err));
+                }
+                implementation
+                    .io
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .keep(device_id, client.client_id);
+                implementation.lock_clients().add(device_id, client);
+                0
+            },
+        )
     }
 
     unsafe extern "C" fn remove_device_client(
@@ -224,7 +1242,29 @@ where
         device_id: coreaudio_sys::AudioObjectID,
         client_info: *const coreaudio_sys::AudioServerPlugInClientInfo,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "remove_device_client",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let Some(client) = (unsafe { ClientInfo::from_raw(client_info) }) else {
+                    error!("no client info passed to remove_device_client");
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                if implementation
+                    .lock_clients()
+                    .remove(device_id, client.client_id)
+                    .is_none()
+                {
+                    warn!(
+                        "client {} removed from device {device_id} without being added",
+                        client.client_id
+                    );
+                }
+                implementation.forget_client(device_id, client.client_id);
+                result_to_err_code(implementation.state.remove_client(device_id, &client))
+            },
+        )
     }
 
     unsafe extern "C" fn perform_device_configuration_change(
@@ -233,7 +1273,27 @@ where
         action: u64,
         change_info: *mut std::ffi::c_void,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "perform_device_configuration_change",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                // reclaim the change info first, so it is dropped on every path
+                let Some(request) = (unsafe { take_change_info::<Self>(change_info) }) else {
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if let Some(apply) = request.apply
+                    && let Err(err) = implementation.apply_change(device_id, apply)
+                {
+                    error!("configuration change of device {device_id} failed: {err:?}");
+                    let _ = implementation.state.abort_change(device_id, request.info);
+                    return result_to_err_code(Err(err));
+                }
+                let result = implementation.state.perform_change(device_id, request.info);
+                implementation.flush_changes();
+                result_to_err_code(result)
+            },
+        )
     }
 
     unsafe extern "C" fn abort_device_configuration_change(
@@ -242,7 +1302,21 @@ where
         action: u64,
         change_info: *mut std::ffi::c_void,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "abort_device_configuration_change",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                // reclaim the change info first, so it is dropped on every path
+                let Some(request) = (unsafe { take_change_info::<Self>(change_info) }) else {
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if request.apply.is_some() {
+                    implementation.lock_pending().remove(&device_id);
+                }
+                result_to_err_code(implementation.state.abort_change(device_id, request.info))
+            },
+        )
     }
 
     unsafe extern "C" fn has_property(
@@ -251,7 +1325,30 @@ where
         client_pid: coreaudio_sys::pid_t,
         property_address: *const coreaudio_sys::AudioObjectPropertyAddress,
     ) -> u8 {
-        todo!()
+        guard("has_property", 0, || {
+            let Some(implementation) =
+                (unsafe { driver.cast::<PluginDriverImplementation<Self>>().as_ref() })
+            else {
+                error!("has_property called on a null implementation");
+                return 0;
+            };
+            let Ok(address) = (unsafe { read_address(property_address) }) else {
+                return 0;
+            };
+            let found = implementation
+                .with_objects(|objects| objects.property(object_id, address).map(drop));
+            #[cfg(feature = "trace")]
+            trace_access(
+                AccessKind::HasProperty,
+                object_id,
+                client_pid,
+                address,
+                0,
+                None,
+                found,
+            );
+            found.is_ok() as u8
+        })
     }
 
     unsafe extern "C" fn is_property_settable(
@@ -261,7 +1358,36 @@ where
         property_address: *const coreaudio_sys::AudioObjectPropertyAddress,
         out: *mut u8,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "is_property_settable",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if out.is_null() {
+                    error!("no space for output of is_property_settable");
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let settable = unsafe { read_address(property_address) }.and_then(|address| {
+                    let settable = implementation.with_objects(|objects| {
+                        objects
+                            .property(object_id, address)
+                            .map(|prop| prop.is_mut())
+                    });
+                    #[cfg(feature = "trace")]
+                    trace_access(
+                        AccessKind::IsSettable,
+                        object_id,
+                        client_pid,
+                        address,
+                        0,
+                        None,
+                        settable.map(drop),
+                    );
+                    settable
+                });
+                result_to_err_code(settable.map(|settable| unsafe { out.write(settable as u8) }))
+            },
+        )
     }
 
     unsafe extern "C" fn get_property_data_size(
@@ -273,7 +1399,41 @@ where
         qualifier_data: *const std::ffi::c_void,
         out: *mut u32,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "get_property_data_size",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if out.is_null() {
+                    error!("no space for output of get_property_data_size");
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let qualifier = unsafe { Qualifier::new(qualifier_data_size, qualifier_data) };
+                let size = unsafe { read_address(property_address) }.and_then(|address| {
+                    let size = implementation
+                        .check_access(client_pid, object_id, &address, AccessOp::Get)
+                        .and_then(|()| {
+                            implementation.with_objects(|objects| {
+                                objects
+                                    .property(object_id, address)
+                                    .map(|prop| prop.byte_size_at(address, qualifier))
+                            })
+                        });
+                    #[cfg(feature = "trace")]
+                    trace_access(
+                        AccessKind::GetSize,
+                        object_id,
+                        client_pid,
+                        address,
+                        0,
+                        size.ok(),
+                        size.map(drop),
+                    );
+                    size
+                });
+                result_to_err_code(size.map(|size| unsafe { out.write(size) }))
+            },
+        )
     }
 
     unsafe extern "C" fn get_property_data(
@@ -287,7 +1447,42 @@ where
         out_size: *mut u32,
         out_data: *mut std::ffi::c_void,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "get_property_data",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if out_size.is_null() || out_data.is_null() {
+                    error!("no space for output of get_property_data");
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let qualifier = unsafe { Qualifier::new(qualifier_data_size, qualifier_data) };
+                let result = unsafe { read_address(property_address) }.and_then(|address| {
+                    let result = implementation
+                        .check_access(client_pid, object_id, &address, AccessOp::Get)
+                        .and_then(|()| {
+                            implementation.with_objects(|objects| {
+                                let prop = objects.property(object_id, address)?;
+                                unsafe {
+                                    prop.get_at(address, qualifier, data_size, out_data, out_size)
+                                }
+                            })
+                        });
+                    #[cfg(feature = "trace")]
+                    trace_access(
+                        AccessKind::Get,
+                        object_id,
+                        client_pid,
+                        address,
+                        data_size,
+                        result.is_ok().then(|| unsafe { *out_size }),
+                        result,
+                    );
+                    result
+                });
+                result_to_err_code(result)
+            },
+        )
     }
 
     unsafe extern "C" fn set_property_data(
@@ -300,7 +1495,38 @@ where
         data_size: u32,
         to_write: *const std::ffi::c_void,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "set_property_data",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let qualifier = unsafe { Qualifier::new(qualifier_data_size, qualifier_data) };
+                let result = unsafe { read_address(property_address) }.and_then(|address| {
+                    let result = implementation
+                        .check_access(client_pid, object_id, &address, AccessOp::Set)
+                        .and_then(|()| {
+                            implementation.with_objects(|objects| unsafe {
+                                objects.set_property(
+                                    object_id, client_pid, address, qualifier, to_write, data_size,
+                                )
+                            })
+                        });
+                    #[cfg(feature = "trace")]
+                    trace_access(
+                        AccessKind::Set,
+                        object_id,
+                        client_pid,
+                        address,
+                        data_size,
+                        None,
+                        result,
+                    );
+                    result
+                });
+                implementation.flush_changes();
+                result_to_err_code(result)
+            },
+        )
     }
 
     unsafe extern "C" fn start_io(
@@ -308,7 +1534,50 @@ where
         device_id: coreaudio_sys::AudioObjectID,
         client_id: u32,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard("start_io", kAudioHardwareUnspecifiedError as i32, || {
+            let implementation = unsafe { validate_impl_ref!(driver) };
+            let pid = implementation
+                .lock_clients()
+                .of(device_id)
+                .iter()
+                .find(|known| known.client_id == client_id)
+                .map_or(-1, |client| client.pid);
+            let running = PropertyAddress::global(kAudioDevicePropertyDeviceIsRunning);
+            // clients the HAL added keep their state until they are removed, others only while doing IO
+            let tracked = implementation
+                .state
+                .client_states()
+                .is_some_and(|states| states.contains(device_id, client_id));
+            if let Err(err) = implementation
+                .check_device(device_id)
+                .and_then(|()| {
+                    implementation.check_access(pid, device_id, &running, AccessOp::StartIo)
+                })
+                .and_then(|()| implementation.track_client(device_id, client_id))
+            {
+                return result_to_err_code(Err(err));
+            }
+            let mut io = implementation
+                .io
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if !io.start(device_id, client_id, !tracked) {
+                return 0;
+            }
+            if let Err(err) = implementation.state.start_io(device_id, client_id) {
+                if io
+                    .stop(device_id, client_id)
+                    .is_ok_and(|stopped| stopped.forget_client)
+                {
+                    implementation.forget_client(device_id, client_id);
+                }
+                return result_to_err_code(Err(err));
+            }
+            implementation.capture_formats(device_id, true);
+            implementation.set_running(device_id, true);
+            implementation.flush_changes();
+            0
+        })
     }
 
     unsafe extern "C" fn stop_io(
@@ -316,7 +1585,31 @@ where
         device_id: coreaudio_sys::AudioObjectID,
         client_id: u32,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard("stop_io", kAudioHardwareUnspecifiedError as i32, || {
+            let implementation = unsafe { validate_impl_ref!(driver) };
+            if let Err(err) = implementation.check_device(device_id) {
+                return result_to_err_code(Err(err));
+            }
+            let mut io = implementation
+                .io
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let stopped = match io.stop(device_id, client_id) {
+                Ok(stopped) => stopped,
+                Err(err) => return result_to_err_code(Err(err)),
+            };
+            if stopped.forget_client {
+                implementation.forget_client(device_id, client_id);
+            }
+            if !stopped.device {
+                return 0;
+            }
+            let result = implementation.state.stop_io(device_id, client_id);
+            implementation.capture_formats(device_id, false);
+            implementation.set_running(device_id, false);
+            implementation.flush_changes();
+            result_to_err_code(result)
+        })
     }
 
     unsafe extern "C" fn get_zero_time_stamp(
@@ -327,7 +1620,22 @@ where
         out_host_time: *mut u64,
         out_seed: *mut u64,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "get_zero_time_stamp",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                if out_sample_time.is_null() || out_host_time.is_null() || out_seed.is_null() {
+                    return kAudioHardwareIllegalOperationError as i32;
+                }
+                let time_stamp = implementation.state.zero_time_stamp(device_id, client_id);
+                result_to_err_code(time_stamp.map(|time_stamp| unsafe {
+                    out_sample_time.write(time_stamp.sample_time);
+                    out_host_time.write(time_stamp.host_time);
+                    out_seed.write(time_stamp.seed);
+                }))
+            },
+        )
     }
 
     unsafe extern "C" fn will_do_io_operation(
@@ -338,7 +1646,26 @@ where
         out_will_do: *mut u8,          /* bool */
         out_will_do_in_place: *mut u8, /* bool */
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "will_do_io_operation",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let capabilities = implementation.state.io_capabilities(device_id);
+                // operations unknown to this crate are never done
+                let (will_do, in_place) = IOOperation::from_id(operation_id)
+                    .map_or((false, true), |op| {
+                        (capabilities.will_do(op), capabilities.in_place(op))
+                    });
+                if !out_will_do.is_null() {
+                    unsafe { out_will_do.write(will_do as u8) };
+                }
+                if !out_will_do_in_place.is_null() {
+                    unsafe { out_will_do_in_place.write(in_place as u8) };
+                }
+                0
+            },
+        )
     }
 
     unsafe extern "C" fn begin_io_operation(
@@ -349,7 +1676,25 @@ where
         io_buffer_frame_size: u32,
         io_cycle_info: *const coreaudio_sys::AudioServerPlugInIOCycleInfo,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "begin_io_operation",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let (Some(op), Some(cycle)) = (IOOperation::from_id(operation_id), unsafe {
+                    io_cycle_info.as_ref()
+                }) else {
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                result_to_err_code(implementation.state.begin_io(
+                    device_id,
+                    client_id,
+                    op,
+                    io_buffer_frame_size,
+                    cycle,
+                ))
+            },
+        )
     }
 
     unsafe extern "C" fn do_io_operation(
@@ -363,7 +1708,51 @@ where
         io_main_buffer: *mut std::ffi::c_void,
         io_secondary_buffer: *mut std::ffi::c_void,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "do_io_operation",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let (Some(op), Some(cycle), Some(main)) = (
+                    IOOperation::from_id(operation_id),
+                    unsafe { io_cycle_info.as_ref() },
+                    NonNull::new(io_main_buffer),
+                ) else {
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                // a writer only holds the lock while IO on some device starts or stops, don't wait for it
+                let formats = match implementation.formats.try_read() {
+                    Ok(formats) => formats,
+                    Err(TryLockError::Poisoned(formats)) => formats.into_inner(),
+                    Err(TryLockError::WouldBlock) => {
+                        return result_to_err_code(Err(OSStatusError::HW_NOT_RUNNING_ERR));
+                    }
+                };
+                let Some(stream) = formats.get(&stream_id).copied() else {
+                    return result_to_err_code(Err(OSStatusError::HW_BAD_STREAM_ERR));
+                };
+                drop(formats);
+                let format = if op.uses_physical_format() {
+                    stream.physical
+                } else {
+                    stream.virtual_format
+                };
+                // Safety: the HAL passes buffers large enough for `io_buffer_frame_size` frames of the stream
+                let main = unsafe { IOBuffer::new(main, io_buffer_frame_size, format) };
+                let secondary = NonNull::new(io_secondary_buffer)
+                    .map(|buffer| unsafe { IOBuffer::new(buffer, io_buffer_frame_size, format) });
+                result_to_err_code(implementation.state.io_operation(
+                    device_id,
+                    stream_id,
+                    client_id,
+                    op,
+                    io_buffer_frame_size,
+                    cycle,
+                    main,
+                    secondary,
+                ))
+            },
+        )
     }
 
     unsafe extern "C" fn end_io_operation(
@@ -374,7 +1763,25 @@ where
         io_buffer_frame_size: u32,
         io_cycle_info: *const coreaudio_sys::AudioServerPlugInIOCycleInfo,
     ) -> coreaudio_sys::OSStatus {
-        todo!()
+        guard(
+            "end_io_operation",
+            kAudioHardwareUnspecifiedError as i32,
+            || {
+                let implementation = unsafe { validate_impl_ref!(driver) };
+                let (Some(op), Some(cycle)) = (IOOperation::from_id(operation_id), unsafe {
+                    io_cycle_info.as_ref()
+                }) else {
+                    return kAudioHardwareIllegalOperationError as i32;
+                };
+                result_to_err_code(implementation.state.end_io(
+                    device_id,
+                    client_id,
+                    op,
+                    io_buffer_frame_size,
+                    cycle,
+                ))
+            },
+        )
     }
 }
 const fn uuid_u128(uuid: [u8; 16]) -> u128 {
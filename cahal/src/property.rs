@@ -2,14 +2,28 @@ use core::slice;
 use std::{
     any::Any,
     ffi::c_void,
-    mem::MaybeUninit,
+    fmt,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
     ops::{Deref, DerefMut},
     ptr,
 };
 
-use crate::os_err::{OSStatus, OSStatusError, ResultExt};
+use core_foundation::{
+    base::TCFType,
+    string::{CFString, CFStringRef},
+};
+use coreaudio_sys::{
+    kAudioObjectPropertyElementMain, kAudioObjectPropertyElementWildcard,
+    kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyScopeInput,
+    kAudioObjectPropertyScopeOutput, kAudioObjectPropertyScopePlayThrough,
+    kAudioObjectPropertyScopeWildcard, kAudioObjectPropertySelectorWildcard, pid_t,
+    AudioObjectPropertyAddress,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use crate::os_err::{OSResult, OSStatus, OSStatusError, ResultExt};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct PropertySelector(u32);
 
@@ -23,6 +37,275 @@ impl From<PropertySelector> for u32 {
         value.0
     }
 }
+impl PropertySelector {
+    pub const WILDCARD: Self = Self(kAudioObjectPropertySelectorWildcard);
+
+    pub const fn new(selector: u32) -> Self {
+        Self(selector)
+    }
+    pub fn is_wildcard(self) -> bool {
+        self == Self::WILDCARD
+    }
+    /// Whether these selectors are equal, treating a wildcard on either side as matching anything
+    pub fn matches(self, other: Self) -> bool {
+        self.is_wildcard() || other.is_wildcard() || self == other
+    }
+    /// Create a selector from its four character code, e.g. `PropertySelector::from_fourcc(b"glob")`
+    pub const fn from_fourcc(code: &[u8; 4]) -> Self {
+        Self(u32::from_be_bytes(*code))
+    }
+    /// The four character code of this selector
+    pub const fn fourcc(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
+impl fmt::Debug for PropertySelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_fourcc(self.0, f)
+    }
+}
+impl fmt::Display for PropertySelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_fourcc(self.0, f)
+    }
+}
+
+/// Convert a four character code like `"glob"` to the `u32` CoreAudio uses for it. Panics (at compile time when used in a
+/// const context, see [`four_cc!`](crate::four_cc)) if `code` isn't exactly 4 bytes long
+pub const fn four_cc(code: &str) -> u32 {
+    let bytes = code.as_bytes();
+    assert!(
+        bytes.len() == 4,
+        "four character codes must be 4 bytes long"
+    );
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Format a four character code as `'glob' (0x676c6f62)`, or just the hex value if any of its bytes aren't printable ASCII
+pub fn fmt_fourcc(code: u32, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let bytes = code.to_be_bytes();
+    if bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        let [a, b, c, d] = bytes.map(char::from);
+        write!(f, "'{a}{b}{c}{d}' ({code:#010x})")
+    } else {
+        write!(f, "{code:#010x}")
+    }
+}
+
+/// The scope part of a property address. The standard scopes are provided as associated constants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct PropertyScope(u32);
+
+impl PropertyScope {
+    pub const GLOBAL: Self = Self(kAudioObjectPropertyScopeGlobal);
+    pub const INPUT: Self = Self(kAudioObjectPropertyScopeInput);
+    pub const OUTPUT: Self = Self(kAudioObjectPropertyScopeOutput);
+    pub const PLAY_THROUGH: Self = Self(kAudioObjectPropertyScopePlayThrough);
+    pub const WILDCARD: Self = Self(kAudioObjectPropertyScopeWildcard);
+
+    pub const fn new(scope: u32) -> Self {
+        Self(scope)
+    }
+    pub fn is_wildcard(self) -> bool {
+        self == Self::WILDCARD
+    }
+    /// Whether these scopes are equal, treating a wildcard on either side as matching anything
+    pub fn matches(self, other: Self) -> bool {
+        self.is_wildcard() || other.is_wildcard() || self == other
+    }
+}
+impl From<u32> for PropertyScope {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+impl From<PropertyScope> for u32 {
+    fn from(value: PropertyScope) -> Self {
+        value.0
+    }
+}
+
+/// The element part of a property address. Element 0 is the main element, channels are numbered from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct PropertyElement(u32);
+
+impl PropertyElement {
+    pub const MAIN: Self = Self(kAudioObjectPropertyElementMain);
+    pub const WILDCARD: Self = Self(kAudioObjectPropertyElementWildcard);
+
+    pub const fn new(element: u32) -> Self {
+        Self(element)
+    }
+    pub fn is_wildcard(self) -> bool {
+        self == Self::WILDCARD
+    }
+    /// Whether these elements are equal, treating a wildcard on either side as matching anything
+    pub fn matches(self, other: Self) -> bool {
+        self.is_wildcard() || other.is_wildcard() || self == other
+    }
+}
+impl From<u32> for PropertyElement {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+impl From<PropertyElement> for u32 {
+    fn from(value: PropertyElement) -> Self {
+        value.0
+    }
+}
+
+/// The full address of a property: which property, in which scope, on which element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PropertyAddress {
+    pub selector: PropertySelector,
+    pub scope: PropertyScope,
+    pub element: PropertyElement,
+}
+
+impl PropertyAddress {
+    pub const fn new(
+        selector: PropertySelector,
+        scope: PropertyScope,
+        element: PropertyElement,
+    ) -> Self {
+        Self {
+            selector,
+            scope,
+            element,
+        }
+    }
+    /// The address of `selector` in the global scope on the main element
+    pub const fn global(selector: u32) -> Self {
+        Self::new(
+            PropertySelector(selector),
+            PropertyScope::GLOBAL,
+            PropertyElement::MAIN,
+        )
+    }
+    /// The address of `selector` in the given scope on the main element
+    pub const fn scoped(selector: u32, scope: PropertyScope) -> Self {
+        Self::new(PropertySelector(selector), scope, PropertyElement::MAIN)
+    }
+    /// The address of `selector` in any scope on any element, i.e. with wildcard scope and element. This is how properties
+    /// that don't depend on the scope or element are reported by [`HasProperties::visit_properties`]
+    ///
+    /// [`HasProperties::visit_properties`]: crate::audio_object::HasProperties::visit_properties
+    pub const fn anywhere(selector: PropertySelector) -> Self {
+        Self::new(selector, PropertyScope::WILDCARD, PropertyElement::WILDCARD)
+    }
+    /// Whether this address refers to the same property as `other`, treating wildcards on either side as matching anything
+    pub fn matches(&self, other: &PropertyAddress) -> bool {
+        self.selector.matches(other.selector)
+            && self.scope.matches(other.scope)
+            && self.element.matches(other.element)
+    }
+}
+impl From<AudioObjectPropertyAddress> for PropertyAddress {
+    fn from(value: AudioObjectPropertyAddress) -> Self {
+        Self {
+            selector: value.mSelector.into(),
+            scope: value.mScope.into(),
+            element: value.mElement.into(),
+        }
+    }
+}
+impl From<PropertyAddress> for AudioObjectPropertyAddress {
+    fn from(value: PropertyAddress) -> Self {
+        Self {
+            mSelector: value.selector.into(),
+            mScope: value.scope.into(),
+            mElement: value.element.into(),
+        }
+    }
+}
+
+/// The qualifier data the HAL passes along with some property accesses, e.g. the UID to translate for
+/// `kAudioPlugInPropertyTranslateUIDToDevice` or the class filter for `kAudioObjectPropertyOwnedObjects`
+#[derive(Debug, Clone, Copy)]
+pub struct Qualifier<'a> {
+    size: u32,
+    data: *const c_void,
+    _boo: PhantomData<&'a [u8]>,
+}
+
+impl Qualifier<'static> {
+    /// A qualifier for requests that don't carry any qualifier data
+    pub const NONE: Self = Self {
+        size: 0,
+        data: ptr::null(),
+        _boo: PhantomData,
+    };
+}
+
+impl<'a> Qualifier<'a> {
+    /// # Safety
+    /// `data` must either be null or valid for reads of `size` bytes for the lifetime `'a`
+    pub unsafe fn new(size: u32, data: *const c_void) -> Self {
+        Self {
+            size,
+            data,
+            _boo: PhantomData,
+        }
+    }
+    /// Whether the HAL passed no qualifier data (a null pointer or a size of 0)
+    pub fn is_empty(&self) -> bool {
+        self.data.is_null() || self.size == 0
+    }
+    pub fn size(&self) -> u32 {
+        if self.data.is_null() {
+            0
+        } else {
+            self.size
+        }
+    }
+    pub fn as_ptr(&self) -> *const c_void {
+        self.data
+    }
+    /// The raw qualifier bytes, empty if no qualifier was passed
+    pub fn bytes(&self) -> &'a [u8] {
+        if self.is_empty() {
+            return &[];
+        }
+        // Safety: guaranteed by the contract of `new`
+        unsafe { slice::from_raw_parts(self.data.cast(), self.size as usize) }
+    }
+    /// Read the qualifier as a single value of type `T`, if its size matches exactly
+    pub fn read<T: Copy>(&self) -> Option<T> {
+        let bytes = self.bytes();
+        if bytes.len() != mem::size_of::<T>() {
+            return None;
+        }
+        // Safety: size checked above, the HAL makes no alignment guarantees for qualifiers
+        Some(unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<T>()) })
+    }
+    /// Read the qualifier as an array of `T`, if its size is a whole multiple of the size of `T`
+    pub fn read_array<T: Copy>(&self) -> Option<Vec<T>> {
+        let bytes = self.bytes();
+        let item_size = mem::size_of::<T>();
+        if item_size == 0 || !bytes.len().is_multiple_of(item_size) {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(item_size)
+                // Safety: every chunk is exactly the size of `T`
+                .map(|chunk| unsafe { ptr::read_unaligned(chunk.as_ptr().cast::<T>()) })
+                .collect(),
+        )
+    }
+    /// Read the qualifier as a `CFStringRef`. The reference is retained, the HAL keeps ownership of its own
+    pub fn cf_string(&self) -> Option<CFString> {
+        let string = self.read::<CFStringRef>()?;
+        if string.is_null() {
+            return None;
+        }
+        // Safety: the HAL passes a valid CFStringRef for string qualified properties
+        Some(unsafe { CFString::wrap_under_get_rule(string) })
+    }
+}
 
 pub trait RawProperty {
     /// Invariant: this function must always return the correct selector for this property or bad things will happen
@@ -31,15 +314,51 @@ pub trait RawProperty {
     fn byte_size(&self) -> u32;
     /// Whether to advertise this property as settable or not
     fn is_mut(&self) -> bool;
-    /// Utility function for reading this property's value from Rust
+    /// Whether this property currently exists at all. Absent properties are treated as if the object didn't have them
+    fn is_present(&self) -> bool {
+        true
+    }
+    /// Selectors of the properties on the same object, at the same scope and element, whose value changes along with
+    /// this one when it is written. The HAL is told about them too after a successful write. Defaults to none
+    fn dependents(&self) -> &'static [u32] {
+        &[]
+    }
+    /// Whether a successful write only requests a change that is applied later, like the nominal sample rate, which the
+    /// host has to approve first. Such writes aren't reported as changes, whoever applies the change reports it then.
+    /// Defaults to `false`
+    fn is_deferred(&self) -> bool {
+        false
+    }
+    /// Call `visitor` with each of the instances this property is made of and the address it answers at, for properties
+    /// that keep a separate instance per scope or element (like [`ScopedProps`]). Returns `false` for properties that are
+    /// a single instance, which is the default
+    fn visit_instances(&self, visitor: &mut dyn FnMut(PropertyAddress, &dyn RawProperty)) -> bool {
+        let _ = visitor;
+        false
+    }
+    /// The instance answering requests at `address`, for properties reporting their instances through
+    /// [`RawProperty::visit_instances`]. Defaults to `None`
+    fn instance_at_mut(&mut self, address: PropertyAddress) -> Option<&mut dyn RawProperty> {
+        let _ = address;
+        None
+    }
+    /// The Rust value of this property as [`Any`], which implementations provide for [`RawPropertyExt::value`]. For plain
+    /// properties this is the stored value itself (`T` for a [`Prop`], the `Vec<T>` for an [`ArrayProp`])
+    #[deprecated(note = "use `RawPropertyExt::value` to read a property's value from Rust")]
     fn as_any(&self) -> &dyn Any;
-    /// Utility function for mutating this property's value from Rust
+    /// Mutable counterpart of [`RawProperty::as_any`], provided for [`RawPropertyExt::value_mut`]
+    #[deprecated(note = "use `RawPropertyExt::value_mut` to mutate a property's value from Rust")]
     fn as_any_mut(&mut self) -> &mut dyn Any;
     /// Read a value of the type of the property of this implementation from the `data` pointer and write it to the internal storage
     /// # Safety
     /// data must point to a valid, initialized value or array of values with the same type as this property, with data size being a multiple of the size of that property
     unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus;
-    /// Write a value stored in this instance to the allocation at `data_out`
+    /// Write a value stored in this instance to the allocation at `data_out`.
+    ///
+    /// A null `data_out` is a size probe: implementations must write the size of the value (as
+    /// [`RawProperty::byte_size`] would report it) to `data_len_out` and return `Ok` without touching `data_out`. A
+    /// buffer too small for the value, including one of zero bytes, fails with
+    /// [`OSStatusError::HW_BAD_PROPERTY_SIZE_ERR`] unless the property truncates to whole elements
     /// # Safety
     /// see discussion under [`RawProperty::set`]
     unsafe fn get(
@@ -48,6 +367,135 @@ pub trait RawProperty {
         data_out: *mut c_void,
         data_len_out: *mut u32,
     ) -> OSStatus;
+    /// The current value as it would be written out by [`RawProperty::get`], for comparing values over time (see
+    /// [`PropertySnapshot`](crate::snapshot::PropertySnapshot)). Returns `None` if the value can't be read.
+    ///
+    /// Implementations that hand out references (like CoreFoundation objects) must override this to return a
+    /// representation of the referenced value instead, without leaking a reference
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        let size = self.byte_size();
+        // u64 backing storage keeps the buffer aligned for every property type
+        let mut buf = vec![0u64; (size as usize).div_ceil(mem::size_of::<u64>())];
+        let mut written = 0;
+        unsafe { self.get(size, buf.as_mut_ptr() as *mut c_void, &mut written) }.ok()?;
+        let bytes = unsafe { slice::from_raw_parts(buf.as_ptr() as *const u8, written as usize) };
+        Some(bytes.to_vec())
+    }
+    /// Size in bytes of the value that would be returned for the given qualifier.
+    /// Defaults to ignoring the qualifier
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        let _ = qualifier;
+        self.byte_size()
+    }
+    /// Like [`RawProperty::set`], with access to the qualifier data the HAL passed along with the request.
+    /// Defaults to ignoring the qualifier
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn set_qualified(
+        &mut self,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let _ = qualifier;
+        unsafe { self.set(data, data_size) }
+    }
+    /// Like [`RawProperty::get`], with access to the qualifier data the HAL passed along with the request.
+    /// Defaults to ignoring the qualifier
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let _ = qualifier;
+        unsafe { self.get(out_alloc_size, data_out, data_len_out) }
+    }
+    /// Size in bytes of the value at the full `address` of a request (which always has this property's selector).
+    /// Defaults to ignoring the scope and element
+    fn byte_size_at(&self, address: PropertyAddress, qualifier: Qualifier<'_>) -> u32 {
+        let _ = address;
+        self.byte_size_qualified(qualifier)
+    }
+    /// Like [`RawProperty::set_qualified`], with access to the full address of the request, for properties that store
+    /// distinct values per scope or element. Defaults to ignoring the scope and element
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let _ = address;
+        unsafe { self.set_qualified(qualifier, data, data_size) }
+    }
+    /// Like [`RawProperty::set_at`], for a write made on behalf of the client process `client_pid`, for properties whose
+    /// behavior depends on who asks (like hog mode). Defaults to ignoring the client
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn set_by_client(
+        &mut self,
+        client_pid: pid_t,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let _ = client_pid;
+        unsafe { self.set_at(address, qualifier, data, data_size) }
+    }
+    /// Like [`RawProperty::get_qualified`], with access to the full address of the request, for properties that store
+    /// distinct values per scope or element. Defaults to ignoring the scope and element
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let _ = address;
+        unsafe { self.get_qualified(qualifier, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// Typed access to the Rust value of any property, including trait objects
+pub trait RawPropertyExt: RawProperty {
+    /// The value of this property if it is a `T`: the stored value for plain properties like [`Prop`] (`T`) and
+    /// [`ArrayProp`] (`Vec<T>`), see the documentation of each property type otherwise
+    #[allow(deprecated)]
+    fn value<T: Any>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+    /// Mutable variant of [`RawPropertyExt::value`]. Changes made through this don't go through any of the checks a
+    /// write from the HAL would
+    #[allow(deprecated)]
+    fn value_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+}
+
+impl<P: RawProperty + ?Sized> RawPropertyExt for P {}
+
+/// A property with a single, statically typed value, which lets generic wrappers like [`Validated`] inspect writes before
+/// they are committed
+pub trait TypedProperty: RawProperty {
+    type Value;
+    fn value(&self) -> &Self::Value;
+    /// Replace the value from Rust, bypassing any checks the HAL side would perform
+    fn replace_value(&mut self, value: Self::Value) -> Self::Value;
+    /// Decode a value in the format the HAL writes this property in, performing all the checks [`RawProperty::set`] would
+    /// (except for [`RawProperty::is_mut`]), without storing it
+    /// # Safety
+    /// see discussion under [`RawProperty::set`]
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<Self::Value>;
 }
 
 macro_rules! ret_assert {
@@ -75,7 +523,103 @@ macro_rules! ret_assert {
     };
 }
 
+/// Validate the arguments of a fixed size `set` call and read the incoming value
+/// # Safety
+/// see discussion under [`RawProperty::set`]
+pub(crate) unsafe fn read_value<T>(data: *const c_void, data_size: u32) -> OSResult<T> {
+    ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+    let data = data as *const T;
+    ret_assert!(data.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+    ret_assert!(
+        data_size as usize == mem::size_of::<T>(),
+        OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+    );
+    Ok(unsafe { ptr::read(data) })
+}
+
+/// Handle a `get` call that only asks for the size of the property (a null `data_out`) by writing `size` to
+/// `data_len_out`. Returns whether the call was a probe, in which case the caller must not touch `data_out`. A buffer
+/// of zero bytes is not a probe, the caller checks it against the size like any other
+/// # Safety
+/// see discussion under [`RawProperty::set`]
+pub(crate) unsafe fn probe_size(
+    size: u32,
+    data_out: *mut c_void,
+    data_len_out: *mut u32,
+) -> OSResult<bool> {
+    ret_assert!(
+        !data_len_out.is_null(),
+        OSStatusError::HW_ILLEGAL_OPERATION_ERR
+    );
+    if data_out.is_null() {
+        unsafe { *data_len_out = size };
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Validate the arguments of a fixed size `get` call and write `value` out
+/// # Safety
+/// see discussion under [`RawProperty::set`]
+pub(crate) unsafe fn write_value<T>(
+    value: T,
+    out_alloc_size: u32,
+    data_out: *mut c_void,
+    data_len_out: *mut u32,
+) -> OSStatus {
+    let size = mem::size_of::<T>() as u32;
+    if unsafe { probe_size(size, data_out, data_len_out)? } {
+        return Ok(());
+    }
+    ret_assert!(
+        out_alloc_size >= size,
+        OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+    );
+    let data_out = data_out as *mut T;
+    ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+    unsafe {
+        ptr::write(data_out, value);
+        *data_len_out = size;
+    }
+    Ok(())
+}
+
+mod cf;
+mod format;
+pub mod selector;
+mod sync;
+mod table;
+mod translate;
+mod typed;
+mod wrappers;
+pub use cf::{
+    preferred_languages, CFStringProp, LocalizedNameProp, LocalizedString, PlistProp,
+    StringListProp, UrlProp,
+};
+pub use format::{
+    asbd_eq, float32_format, AsbdProp, ChannelLayoutProp, FormatSet, RangeListProp, ResolvedFormat,
+    StreamConfigurationProp,
+};
+pub use selector::{
+    ControlSelector, DeviceSelector, ObjectSelector, SelectorClass, StreamSelector,
+};
+pub use sync::{
+    AtomicHandle, AtomicProp, AtomicValue, HogModeProp, SeqlockHandle, SeqlockProp, HOG_MODE_FREE,
+};
+pub use table::PropertyTable;
+pub use translate::{ConversionProp, TranslationInput, TranslationProp, UidTranslationProp};
+pub use typed::{BoolProp, ChannelPairProp, ElementProp, EnumProp};
+pub use wrappers::{
+    HookedProp, InRange, OneOf, OptionProp, RuntimeMutProp, ScopedArrayProp, ScopedProps,
+    Validated, Validator,
+};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 /// A convenient wrapper for Copy types that implements [RawProperty] for them, given the correct selector and mutability in the const generic parameters
 pub struct Prop<T, const SEL: u32, const MUTABLE_PROP: bool = false>(pub T);
 
@@ -88,6 +632,48 @@ impl<T, const SEL: u32, const MUTABLE_PROP: bool> Prop<T, SEL, MUTABLE_PROP> {
     pub fn new(val: T) -> Self {
         Self(val)
     }
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+    /// Replace the value from Rust, returning the previous one
+    pub fn set_value(&mut self, val: T) -> T {
+        mem::replace(&mut self.0, val)
+    }
+    /// Like [`Prop::new`], but fails to compile if `SEL` isn't a known selector of the object class `C`. Selectors of
+    /// base classes are accepted:
+    /// ```no_run
+    /// # use cahal::property::{DeviceSelector, ObjectSelector, Prop};
+    /// let rate = Prop::<f64, { DeviceSelector::NOMINAL_SAMPLE_RATE.get() }>::for_class::<DeviceSelector>(48000.0);
+    /// let owner = Prop::<u32, { ObjectSelector::OWNER.get() }>::for_class::<DeviceSelector>(1);
+    /// ```
+    /// Stream selectors aren't valid on a device:
+    /// ```compile_fail
+    /// # use cahal::property::{DeviceSelector, Prop, StreamSelector};
+    /// let active = Prop::<u32, { StreamSelector::IS_ACTIVE.get() }>::for_class::<DeviceSelector>(1);
+    /// ```
+    /// and selectors of derived classes aren't valid on their base class:
+    /// ```compile_fail
+    /// # use cahal::property::{DeviceSelector, ObjectSelector, Prop};
+    /// let rate = Prop::<f64, { DeviceSelector::NOMINAL_SAMPLE_RATE.get() }>::for_class::<ObjectSelector>(48000.0);
+    /// ```
+    pub fn for_class<C: SelectorClass>(val: T) -> Self {
+        const {
+            assert!(
+                selector::is_valid_for::<C>(SEL),
+                "selector is not valid for this object class"
+            )
+        };
+        Self(val)
+    }
+}
+impl<T, const SEL: u32> Prop<T, SEL, true> {
+    /// Run `on_set` whenever the HAL writes this property, see [`HookedProp`]
+    pub fn on_set(
+        self,
+        on_set: impl FnMut(&T, &T) -> OSStatus + Send + 'static,
+    ) -> HookedProp<T, SEL> {
+        HookedProp::new(self.0, on_set)
+    }
 }
 
 //SAFETY: selector invariants and
@@ -107,20 +693,8 @@ impl<T: Clone + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
     }
 
     unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
-        ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
-        ret_assert!(
-            (data as *const T).is_aligned(),
-            OSStatusError::HW_BAD_OBJECT_ERR
-        );
-        ret_assert!(
-            data_size == Self::SIZE,
-            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
-        );
         ret_assert!(self.is_mut());
-
-        let val = unsafe { ptr::read(data as *const T) };
-
-        self.0 = val;
+        self.0 = unsafe { read_value(data, data_size)? };
         Ok(())
     }
 
@@ -130,18 +704,7 @@ impl<T: Clone + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
         data_out: *mut c_void,
         data_len_out: *mut u32,
     ) -> OSStatus {
-        ret_assert!(
-            !data_out.is_null() && !data_len_out.is_null(),
-            OSStatusError::HW_ILLEGAL_OPERATION_ERR
-        );
-        ret_assert!(
-            out_alloc_size >= Self::SIZE,
-            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
-        );
-        let data_out = data_out as *mut T;
-        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
-        unsafe { ptr::write(data_out, self.0.clone()) };
-        Ok(())
+        unsafe { write_value(self.0.clone(), out_alloc_size, data_out, data_len_out) }
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -153,10 +716,46 @@ impl<T: Clone + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
     }
 }
 
+impl<T: Clone + 'static, const SEL: u32, const MUTABLE_PROP: bool> TypedProperty
+    for Prop<T, SEL, MUTABLE_PROP>
+{
+    type Value = T;
+
+    fn value(&self) -> &T {
+        &self.0
+    }
+
+    fn replace_value(&mut self, value: T) -> T {
+        mem::replace(&mut self.0, value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<T> {
+        unsafe { read_value(data, data_size) }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// A convenient wrapper for an array of Copy types as a [RawProperty]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct ArrayProp<T, const SEL: u32, const MUTABLE_PROP: bool = false> {
     props: Vec<T>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    truncation: TruncationPolicy,
+}
+
+/// How an [`ArrayProp`] answers a `get` whose buffer can't hold all of its elements
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationPolicy {
+    /// Copy as many whole elements as fit and report the number of bytes copied. A buffer too small for even a single
+    /// element is still rejected with [`OSStatusError::HW_BAD_PROPERTY_SIZE_ERR`], unless the array is empty
+    #[default]
+    Truncate,
+    /// Reject the call with [`OSStatusError::HW_BAD_PROPERTY_SIZE_ERR`]
+    Error,
 }
 impl<T, const SEL: u32, const MUTABLE_PROP: bool> Deref for ArrayProp<T, SEL, MUTABLE_PROP> {
     type Target = Vec<T>;
@@ -177,10 +776,21 @@ impl<T, const SEL: u32, const MUTABLE_PROP: bool> ArrayProp<T, SEL, MUTABLE_PROP
         size as u32
     };
     pub fn new_with(props: Vec<T>) -> Self {
-        Self { props }
+        Self {
+            props,
+            truncation: TruncationPolicy::default(),
+        }
     }
     pub fn new() -> Self {
-        Self { props: Vec::new() }
+        Self::new_with(Vec::new())
+    }
+    /// Set what happens when the HAL asks for this property with a buffer too small for all elements
+    pub fn with_truncation(mut self, truncation: TruncationPolicy) -> Self {
+        self.truncation = truncation;
+        self
+    }
+    pub fn truncation(&self) -> TruncationPolicy {
+        self.truncation
     }
 }
 
@@ -213,15 +823,24 @@ impl<T: Copy + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
         &mut self.props
     }
 
+    /// Replace the elements with the ones in `data`. A `data_size` of zero clears the array, whatever `data` points to.
+    /// Otherwise `data` has to be non-null, aligned for `T` and hold a whole number of elements; anything else is
+    /// rejected with [`OSStatusError::HW_BAD_PROPERTY_SIZE_ERR`]
     unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
-        ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        ret_assert!(self.is_mut());
+        if data_size == 0 {
+            self.props.clear();
+            return Ok(());
+        }
+        let data = data as *const T;
         ret_assert!(
-            data_size == Self::ITEM_SIZE,
+            !data.is_null() && data.is_aligned(),
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        ret_assert!(
+            data_size.checked_rem(Self::ITEM_SIZE) == Some(0),
             OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
         );
-        let data = data as *const T;
-        ret_assert!(data.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
-        ret_assert!(self.is_mut());
 
         let r = unsafe { slice::from_raw_parts(data, (data_size / Self::ITEM_SIZE) as usize) };
         self.props.clear();
@@ -235,31 +854,440 @@ impl<T: Copy + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
         data_out: *mut c_void,
         data_len_out: *mut u32,
     ) -> OSStatus {
-        ret_assert!(
-            !data_out.is_null() && !data_len_out.is_null(),
-            OSStatusError::HW_ILLEGAL_OPERATION_ERR
-        );
-        ret_assert!(
-            out_alloc_size >= Self::ITEM_SIZE,
-            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
-        );
+        if unsafe { probe_size(self.byte_size(), data_out, data_len_out)? } {
+            return Ok(());
+        }
+        let fits = (out_alloc_size / Self::ITEM_SIZE) as usize;
+        match self.truncation {
+            TruncationPolicy::Truncate => ret_assert!(
+                fits > 0 || self.props.is_empty(),
+                OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+            ),
+            TruncationPolicy::Error => ret_assert!(
+                fits >= self.props.len(),
+                OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+            ),
+        }
         let data_out = data_out as *mut T;
         ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
 
-        let s = unsafe { slice::from_raw_parts_mut(
-                    data_out as *mut MaybeUninit<T>,
-                    (out_alloc_size / Self::ITEM_SIZE) as usize,
-                ) };
-        let to_copy = s.len().min(self.props.len());
-        let slice = &self.props[0..s.len().min(self.props.len())];
+        let s = unsafe { slice::from_raw_parts_mut(data_out as *mut MaybeUninit<T>, fits) };
+        let to_copy = fits.min(self.props.len());
+        let slice = &self.props[0..to_copy];
         let slice =
             unsafe { slice::from_raw_parts(slice.as_ptr() as *const MaybeUninit<T>, slice.len()) };
 
-        s.copy_from_slice(slice);
-        
-        unsafe { *data_len_out = (to_copy * Self::ITEM_SIZE as usize)
-            .try_into()
-            .replace_err(OSStatusError::HW_BAD_PROPERTY_SIZE_ERR)? };
+        s[..to_copy].copy_from_slice(slice);
+
+        // only whole elements are ever copied, so this is always a multiple of the item size
+        unsafe {
+            *data_len_out = (to_copy * Self::ITEM_SIZE as usize)
+                .try_into()
+                .replace_err(OSStatusError::HW_BAD_PROPERTY_SIZE_ERR)?
+        };
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioDevicePropertyLatency, kAudioDevicePropertyStreams,
+        kAudioHardwareBadPropertySizeError,
+    };
+
+    use super::*;
+    use crate::os_err::result_to_err_code;
+
+    type Streams = ArrayProp<u32, kAudioDevicePropertyStreams, true>;
+
+    fn set(prop: &mut Streams, data: *const c_void, data_size: u32) -> i32 {
+        result_to_err_code(unsafe { prop.set(data, data_size) })
+    }
+
+    /// `get` into `buffer`, or a size probe without one, returning the status and the size written to `data_len_out`
+    fn get(prop: &dyn RawProperty, buffer: Option<&mut [u32]>, alloc_size: u32) -> (i32, u32) {
+        let data_out = buffer.map_or(ptr::null_mut(), |buffer| buffer.as_mut_ptr().cast());
+        let mut len = u32::MAX;
+        let status = result_to_err_code(unsafe { prop.get(alloc_size, data_out, &mut len) });
+        (status, len)
+    }
+
+    const BAD_SIZE: i32 = kAudioHardwareBadPropertySizeError as i32;
+
+    #[test]
+    fn null_buffers_probe_the_size() {
+        let prop = Prop::<u32, kAudioDevicePropertyLatency>(10);
+        assert_eq!(get(&prop, None, 0), (0, 4));
+        assert_eq!(get(&prop, None, 64), (0, 4));
+
+        let prop = Streams::new_with(vec![1, 2, 3]);
+        assert_eq!(get(&prop, None, 0), (0, 12));
+        let prop = Streams::new_with(vec![]);
+        assert_eq!(get(&prop, None, 0), (0, 0));
+    }
+
+    #[test]
+    fn empty_buffers_are_too_small() {
+        let mut buffer = [7u32; 4];
+        let prop = Prop::<u32, kAudioDevicePropertyLatency>(10);
+        assert_eq!(get(&prop, Some(&mut buffer), 0).0, BAD_SIZE);
+        assert_eq!(get(&prop, Some(&mut buffer), 3).0, BAD_SIZE);
+
+        let prop = Streams::new_with(vec![1, 2, 3]);
+        assert_eq!(get(&prop, Some(&mut buffer), 0).0, BAD_SIZE);
+        // nothing was written
+        assert_eq!(buffer, [7; 4]);
+
+        // unless nothing needs to be written
+        let prop = Streams::new_with(vec![]);
+        assert_eq!(get(&prop, Some(&mut buffer), 0), (0, 0));
+    }
+
+    #[test]
+    fn buffers_receive_the_value() {
+        let mut buffer = [7u32; 4];
+        let prop = Prop::<u32, kAudioDevicePropertyLatency>(10);
+        assert_eq!(get(&prop, Some(&mut buffer), 16), (0, 4));
+        assert_eq!(buffer, [10, 7, 7, 7]);
+
+        let prop = Streams::new_with(vec![1, 2, 3]);
+        assert_eq!(get(&prop, Some(&mut buffer), 16), (0, 12));
+        assert_eq!(buffer, [1, 2, 3, 7]);
+    }
+
+    #[test]
+    fn truncated_gets_copy_whole_elements() {
+        let prop = Streams::new_with(vec![1, 2, 3]);
+        assert_eq!(prop.truncation(), TruncationPolicy::Truncate);
+        let mut buffer = [7u32; 4];
+        // smaller than one element
+        assert_eq!(get(&prop, Some(&mut buffer), 3).0, BAD_SIZE);
+        assert_eq!(buffer, [7; 4]);
+        // exactly one element
+        assert_eq!(get(&prop, Some(&mut buffer), 4), (0, 4));
+        assert_eq!(buffer, [1, 7, 7, 7]);
+        // not a multiple of the element size
+        let mut buffer = [7u32; 4];
+        assert_eq!(get(&prop, Some(&mut buffer), 10), (0, 8));
+        assert_eq!(buffer, [1, 2, 7, 7]);
+    }
+
+    #[test]
+    fn strict_gets_need_room_for_every_element() {
+        let prop = Streams::new_with(vec![1, 2, 3]).with_truncation(TruncationPolicy::Error);
+        let mut buffer = [7u32; 4];
+        for alloc_size in [3, 4, 10, 11] {
+            assert_eq!(
+                get(&prop, Some(&mut buffer), alloc_size).0,
+                BAD_SIZE,
+                "{alloc_size} bytes"
+            );
+        }
+        assert_eq!(buffer, [7; 4]);
+        assert_eq!(get(&prop, Some(&mut buffer), 14), (0, 12));
+        assert_eq!(buffer, [1, 2, 3, 7]);
+    }
+
+    #[test]
+    fn prop_value_is_the_stored_value() {
+        let mut prop = Prop::<u32, kAudioDevicePropertyLatency>(10);
+        assert_eq!(RawPropertyExt::value::<u32>(&prop), Some(&10));
+        *prop.value_mut::<u32>().unwrap() = 20;
+        assert_eq!(prop.0, 20);
+
+        assert!(RawPropertyExt::value::<i32>(&prop).is_none());
+        assert!(prop.value_mut::<u64>().is_none());
+        assert!(RawPropertyExt::value::<Prop<u32, kAudioDevicePropertyLatency>>(&prop).is_none());
+    }
+
+    #[test]
+    fn array_prop_value_is_the_vec() {
+        let mut prop = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![1, 2]);
+        assert_eq!(
+            prop.value::<Vec<u32>>().map(Vec::as_slice),
+            Some(&[1, 2][..])
+        );
+        prop.value_mut::<Vec<u32>>().unwrap().push(3);
+        assert_eq!(&prop[..], [1, 2, 3]);
+
+        assert!(prop.value::<u32>().is_none());
+        assert!(prop.value::<Box<[u32]>>().is_none());
+        assert!(prop.value_mut::<Vec<i32>>().is_none());
+    }
+
+    #[test]
+    fn trait_objects_downcast_like_the_property() {
+        let mut props: [Box<dyn RawProperty>; 2] = [
+            Box::new(Prop::<u32, kAudioDevicePropertyLatency>(10)),
+            Box::new(ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(
+                vec![4],
+            )),
+        ];
+        assert_eq!(props[0].value::<u32>(), Some(&10));
+        assert!(props[0].value::<Vec<u32>>().is_none());
+        assert_eq!(props[1].value_mut::<Vec<u32>>().map(|v| v.len()), Some(1));
+        assert!(props[1].value_mut::<u32>().is_none());
+    }
+
+    #[test]
+    fn array_set_with_zero_size_clears_through_any_pointer() {
+        let mut prop = Streams::new_with(vec![1, 2]);
+        assert_eq!(set(&mut prop, ptr::null(), 0), 0);
+        assert!(prop.is_empty());
+
+        let mut prop = Streams::new_with(vec![1, 2]);
+        let data = [7u32];
+        assert_eq!(set(&mut prop, data.as_ptr().cast(), 0), 0);
+        assert!(prop.is_empty());
+    }
+
+    #[test]
+    fn array_set_with_a_null_pointer_and_nonzero_size_is_rejected() {
+        let mut prop = Streams::new_with(vec![1, 2]);
+        assert_eq!(
+            set(&mut prop, ptr::null(), 8),
+            kAudioHardwareBadPropertySizeError as i32
+        );
+        assert_eq!(&prop[..], [1, 2]);
+    }
+
+    #[test]
+    fn array_set_replaces_the_elements() {
+        let mut prop = Streams::new_with(vec![1, 2]);
+        let data = [7u32, 8, 9];
+        assert_eq!(set(&mut prop, data.as_ptr().cast(), 12), 0);
+        assert_eq!(&prop[..], data);
+    }
+
+    #[test]
+    fn array_set_rejects_partial_elements_and_misaligned_data() {
+        let mut prop = Streams::new_with(vec![1, 2]);
+        let data = [7u32, 8, 9];
+        assert_eq!(
+            set(&mut prop, data.as_ptr().cast(), 6),
+            kAudioHardwareBadPropertySizeError as i32
+        );
+        let misaligned = unsafe { data.as_ptr().cast::<u8>().add(1) };
+        assert_eq!(
+            set(&mut prop, misaligned.cast(), 4),
+            kAudioHardwareBadPropertySizeError as i32
+        );
+        assert_eq!(&prop[..], [1, 2]);
+    }
+
+    #[test]
+    fn array_set_on_an_immutable_array_is_rejected() {
+        let mut prop = ArrayProp::<u32, kAudioDevicePropertyStreams>::new_with(vec![1]);
+        let data = [7u32];
+        assert!(unsafe { prop.set(data.as_ptr().cast(), 4) }.is_err());
+        assert!(unsafe { prop.set(ptr::null(), 0) }.is_err());
+        assert_eq!(&prop[..], [1]);
+    }
+
+    /// Answers with the length of a string qualifier, or `u32::MAX` without one, and remembers the size of the
+    /// qualifier of the last write
+    struct StringLength {
+        written_qualifier: Option<usize>,
+    }
+
+    impl RawProperty for StringLength {
+        fn selector(&self) -> PropertySelector {
+            kAudioDevicePropertyLatency.into()
+        }
+        fn byte_size(&self) -> u32 {
+            mem::size_of::<u32>() as u32
+        }
+        fn is_mut(&self) -> bool {
+            true
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+        unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+            unsafe { self.set_qualified(Qualifier::NONE, data, data_size) }
+        }
+        unsafe fn get(
+            &self,
+            out_alloc_size: u32,
+            data_out: *mut c_void,
+            data_len_out: *mut u32,
+        ) -> OSStatus {
+            unsafe { self.get_qualified(Qualifier::NONE, out_alloc_size, data_out, data_len_out) }
+        }
+        unsafe fn set_qualified(
+            &mut self,
+            qualifier: Qualifier<'_>,
+            _data: *const c_void,
+            _data_size: u32,
+        ) -> OSStatus {
+            self.written_qualifier = Some(qualifier.bytes().len());
+            Ok(())
+        }
+        unsafe fn get_qualified(
+            &self,
+            qualifier: Qualifier<'_>,
+            out_alloc_size: u32,
+            data_out: *mut c_void,
+            data_len_out: *mut u32,
+        ) -> OSStatus {
+            let length = qualifier
+                .cf_string()
+                .map_or(u32::MAX, |string| string.char_len() as u32);
+            unsafe { write_value(length, out_alloc_size, data_out, data_len_out) }
+        }
+    }
+
+    fn get_qualified(prop: &dyn RawProperty, qualifier: Qualifier<'_>) -> u32 {
+        let mut value = 0u32;
+        let mut len = 0;
+        unsafe { prop.get_qualified(qualifier, 4, (&raw mut value).cast(), &mut len) }.unwrap();
+        assert_eq!(len, 4);
+        value
+    }
+
+    fn set_qualified(prop: &mut dyn RawProperty, qualifier: Qualifier<'_>, value: u32) {
+        unsafe { prop.set_qualified(qualifier, (&raw const value).cast(), 4) }.unwrap();
+    }
+
+    #[test]
+    fn null_qualifiers_are_empty() {
+        let qualifier = unsafe { Qualifier::new(8, ptr::null()) };
+        assert!(qualifier.is_empty());
+        assert_eq!(qualifier.size(), 0);
+        assert!(qualifier.bytes().is_empty());
+        assert_eq!(qualifier.read::<u32>(), None);
+        assert!(qualifier.cf_string().is_none());
+
+        let mut prop = StringLength {
+            written_qualifier: None,
+        };
+        assert_eq!(get_qualified(&prop, qualifier), u32::MAX);
+        set_qualified(&mut prop, qualifier, 1);
+        assert_eq!(prop.written_qualifier, Some(0));
+    }
+
+    #[test]
+    fn zero_size_qualifiers_are_empty() {
+        let data = 7u32;
+        let qualifier = unsafe { Qualifier::new(0, (&raw const data).cast()) };
+        assert!(qualifier.is_empty());
+        assert_eq!(qualifier.size(), 0);
+        assert_eq!(qualifier.read::<u32>(), None);
+        assert_eq!(qualifier.read_array::<u32>(), Some(vec![]));
+
+        let mut prop = StringLength {
+            written_qualifier: None,
+        };
+        assert_eq!(get_qualified(&prop, qualifier), u32::MAX);
+        set_qualified(&mut prop, qualifier, 1);
+        assert_eq!(prop.written_qualifier, Some(0));
+    }
+
+    #[test]
+    fn string_qualifiers_reach_the_property() {
+        let string = CFString::new("Speakers");
+        let string_ref = string.as_concrete_TypeRef();
+        let size = mem::size_of::<CFStringRef>() as u32;
+        let qualifier = unsafe { Qualifier::new(size, (&raw const string_ref).cast()) };
+        assert!(!qualifier.is_empty());
+        let before = string.retain_count();
+        assert_eq!(qualifier.cf_string(), Some(string.clone()));
+        // the HAL keeps its reference
+        assert_eq!(string.retain_count(), before);
+
+        let mut prop = StringLength {
+            written_qualifier: None,
+        };
+        assert_eq!(get_qualified(&prop, qualifier), 8);
+        set_qualified(&mut prop, qualifier, 1);
+        assert_eq!(prop.written_qualifier, Some(size as usize));
+        assert_eq!(string.retain_count(), before);
+    }
+
+    #[test]
+    fn qualifiers_are_ignored_by_default() {
+        let string = CFString::new("Speakers");
+        let string_ref = string.as_concrete_TypeRef();
+        let size = mem::size_of::<CFStringRef>() as u32;
+        let qualifier = unsafe { Qualifier::new(size, (&raw const string_ref).cast()) };
+        let mut prop = Prop::<u32, kAudioDevicePropertyLatency, true>(10);
+        assert_eq!(get_qualified(&prop, qualifier), 10);
+        set_qualified(&mut prop, qualifier, 20);
+        assert_eq!(prop.0, 20);
+        assert_eq!(get_qualified(&prop, Qualifier::NONE), 20);
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde {
+        use coreaudio_sys::{
+            kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyNominalSampleRate,
+            kAudioLevelControlPropertyScalarValue,
+        };
+
+        use super::*;
+
+        #[test]
+        fn values_serialize_as_themselves() {
+            let latency = Prop::<u32, kAudioDevicePropertyLatency>(512);
+            assert_eq!(serde_json::to_string(&latency).unwrap(), "512");
+            let streams = Streams::new_with(vec![2, 3]);
+            assert_eq!(serde_json::to_string(&streams).unwrap(), "[2,3]");
+        }
+
+        #[test]
+        fn u32_values_round_trip() {
+            let json =
+                serde_json::to_string(&Prop::<u32, kAudioDevicePropertyLatency>(u32::MAX)).unwrap();
+            let prop: Prop<u32, kAudioDevicePropertyLatency> = serde_json::from_str(&json).unwrap();
+            assert_eq!(prop.0, u32::MAX);
+        }
+
+        #[test]
+        fn f32_values_round_trip() {
+            let json =
+                serde_json::to_string(&Prop::<f32, kAudioLevelControlPropertyScalarValue>(0.1))
+                    .unwrap();
+            let prop: Prop<f32, kAudioLevelControlPropertyScalarValue> =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(prop.0, 0.1);
+        }
+
+        #[test]
+        fn f64_values_round_trip() {
+            let json = serde_json::to_string(&Prop::<f64, kAudioDevicePropertyNominalSampleRate>(
+                44_100.1,
+            ))
+            .unwrap();
+            let prop: Prop<f64, kAudioDevicePropertyNominalSampleRate> =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(prop.0, 44_100.1);
+        }
+
+        #[test]
+        fn arrays_round_trip() {
+            let streams = Streams::new_with(vec![2, 3, 5]).with_truncation(TruncationPolicy::Error);
+            let json = serde_json::to_string(&streams).unwrap();
+            let prop: Streams = serde_json::from_str(&json).unwrap();
+            assert_eq!(*prop, [2, 3, 5]);
+            // the truncation policy is configuration, not state
+            assert_eq!(prop.truncation(), TruncationPolicy::default());
+
+            type Rates = ArrayProp<f64, kAudioDevicePropertyAvailableNominalSampleRates>;
+            let json = serde_json::to_string(&Rates::new_with(vec![44_100.0, 48_000.0])).unwrap();
+            let prop: Rates = serde_json::from_str(&json).unwrap();
+            assert_eq!(*prop, [44_100.0, 48_000.0]);
+        }
+
+        #[test]
+        fn mismatched_values_are_rejected() {
+            assert!(serde_json::from_str::<Prop<u32, kAudioDevicePropertyLatency>>("-1").is_err());
+            assert!(serde_json::from_str::<Prop<u32, kAudioDevicePropertyLatency>>("0.5").is_err());
+            assert!(serde_json::from_str::<Prop<u32, kAudioDevicePropertyLatency>>("[1]").is_err());
+            assert!(serde_json::from_str::<Streams>("1").is_err());
+        }
+    }
+}
@@ -0,0 +1,781 @@
+use std::{any::Any, collections::HashMap, ffi::c_void, mem, path::Path, ptr};
+
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFRetain, TCFType},
+    bundle::CFBundle,
+    propertylist::{
+        create_data, kCFPropertyListBinaryFormat_v1_0, CFPropertyList, CFPropertyListRef,
+        CFPropertyListSubClass,
+    },
+    string::{CFString, CFStringRef},
+    url::{CFURLRef, CFURL},
+};
+
+use crate::os_err::{OSStatus, OSStatusError};
+
+use super::{
+    probe_size, PropertyAddress, PropertyElement, PropertySelector, Qualifier, RawProperty,
+};
+
+#[derive(Debug, Clone)]
+/// A [RawProperty] for `CFString` values. The HAL expects these to be transported as a bare `CFStringRef`:
+/// * on `get`, a +1 retained reference is written out, which the caller is responsible for releasing
+/// * on `set`, the incoming reference is owned by the caller, so it is retained before being stored
+pub struct CFStringProp<const SEL: u32, const MUTABLE_PROP: bool = false>(pub CFString);
+
+// SAFETY: the stored string is immutable and only ever replaced wholesale, and immutable CoreFoundation objects are safe to share between threads
+unsafe impl<const SEL: u32, const MUTABLE_PROP: bool> Send for CFStringProp<SEL, MUTABLE_PROP> {}
+unsafe impl<const SEL: u32, const MUTABLE_PROP: bool> Sync for CFStringProp<SEL, MUTABLE_PROP> {}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> CFStringProp<SEL, MUTABLE_PROP> {
+    const SIZE: u32 = mem::size_of::<CFStringRef>() as u32;
+    pub fn new(val: CFString) -> Self {
+        Self(val)
+    }
+    pub fn from_static(val: &'static str) -> Self {
+        Self(CFString::from_static_string(val))
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for CFStringProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::SIZE
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.0.to_string().into_bytes())
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        let data = data as *const CFStringRef;
+        ret_assert!(data.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        ret_assert!(
+            data_size == Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        ret_assert!(self.is_mut());
+
+        let string = unsafe { ptr::read(data) };
+        ret_assert!(!string.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        // The caller keeps its reference, so take our own
+        self.0 = unsafe { CFString::wrap_under_get_rule(string) };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(Self::SIZE, data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size >= Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let data_out = data_out as *mut CFStringRef;
+        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        // The caller releases the reference we hand out
+        unsafe {
+            CFRetain(self.0.as_CFTypeRef());
+            ptr::write(data_out, self.0.as_concrete_TypeRef());
+            *data_len_out = Self::SIZE;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A read-only [RawProperty] for `CFURL` values such as `kAudioDevicePropertyIcon`, transported like a [`CFStringProp`]
+/// as a +1 retained `CFURLRef` the caller is responsible for releasing
+pub struct UrlProp<const SEL: u32>(pub CFURL);
+
+// SAFETY: see CFStringProp
+unsafe impl<const SEL: u32> Send for UrlProp<SEL> {}
+unsafe impl<const SEL: u32> Sync for UrlProp<SEL> {}
+
+impl<const SEL: u32> UrlProp<SEL> {
+    const SIZE: u32 = mem::size_of::<CFURLRef>() as u32;
+    pub fn new(url: CFURL) -> Self {
+        Self(url)
+    }
+    /// The file URL of `path`, `None` if it can't be represented as one
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        CFURL::from_path(path, false).map(Self)
+    }
+    /// The URL of the resource `name` (e.g. `"icon.icns"`), as found by the HAL in the resource bundle of the plug-in with
+    /// the bundle id `bundle_id`. `resource_bundle` is the path of the resource bundle relative to the plug-in bundle, as
+    /// reported by `kAudioPlugInPropertyResourceBundle`, with the empty string standing for the plug-in bundle itself.
+    ///
+    /// Returns `None` if either bundle can't be found
+    pub fn bundled_resource(bundle_id: &str, resource_bundle: &str, name: &str) -> Option<Self> {
+        let mut bundle = CFBundle::bundle_with_identifier(CFString::new(bundle_id))?;
+        if !resource_bundle.is_empty() {
+            let path = bundle.path()?.join(resource_bundle);
+            bundle = CFBundle::new(CFURL::from_path(path, true)?)?;
+        }
+        Self::from_path(bundle.resources_path()?.join(name))
+    }
+}
+
+impl<const SEL: u32> RawProperty for UrlProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::SIZE
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.0.get_string().to_string().into_bytes())
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(Self::SIZE, data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size >= Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let data_out = data_out as *mut CFURLRef;
+        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        // The caller releases the reference we hand out
+        unsafe {
+            CFRetain(self.0.as_CFTypeRef());
+            ptr::write(data_out, self.0.as_concrete_TypeRef());
+            *data_len_out = Self::SIZE;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [RawProperty] for CoreFoundation property list values of variable size (`CFDictionary`, `CFData`, `CFArray`, ...),
+/// such as custom properties. Like [`CFStringProp`], the value is transported as a bare `CFPropertyListRef`:
+/// * on `get`, a +1 retained reference is written out, which the caller is responsible for releasing
+/// * on `set`, the incoming reference is owned by the caller, so it is retained before being stored
+pub struct PlistProp<const SEL: u32, const MUTABLE_PROP: bool = false>(pub CFPropertyList);
+
+// SAFETY: the stored value is only ever replaced wholesale, never mutated through this wrapper. Callers storing a mutable
+// collection here must not mutate it while it is shared
+unsafe impl<const SEL: u32, const MUTABLE_PROP: bool> Send for PlistProp<SEL, MUTABLE_PROP> {}
+unsafe impl<const SEL: u32, const MUTABLE_PROP: bool> Sync for PlistProp<SEL, MUTABLE_PROP> {}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> PlistProp<SEL, MUTABLE_PROP> {
+    const SIZE: u32 = mem::size_of::<CFPropertyListRef>() as u32;
+    pub fn new(val: CFPropertyList) -> Self {
+        Self(val)
+    }
+    /// Store any property list type (`CFDictionary`, `CFData`, ...)
+    pub fn from_value<T: CFPropertyListSubClass>(val: &T) -> Self {
+        Self(val.to_CFPropertyList())
+    }
+    /// The stored value as a concrete property list type, if it is one
+    pub fn downcast<T: CFPropertyListSubClass>(&self) -> Option<T> {
+        self.0.downcast()
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for PlistProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::SIZE
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        let data = create_data(self.0.as_CFTypeRef(), kCFPropertyListBinaryFormat_v1_0).ok()?;
+        Some(data.bytes().to_vec())
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        let data = data as *const CFPropertyListRef;
+        ret_assert!(data.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        ret_assert!(
+            data_size == Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        ret_assert!(self.is_mut());
+
+        let plist = unsafe { ptr::read(data) };
+        ret_assert!(!plist.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        // The caller keeps its reference, so take our own
+        self.0 = unsafe { CFPropertyList::wrap_under_get_rule(plist) };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(Self::SIZE, data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size >= Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let data_out = data_out as *mut CFPropertyListRef;
+        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        // The caller releases the reference we hand out
+        unsafe {
+            CFRetain(self.0.as_CFTypeRef());
+            ptr::write(data_out, self.0.as_concrete_TypeRef());
+            *data_len_out = Self::SIZE;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A read-only list of `CFString`s, e.g. the item names of a data source or clock source control. Transported as an array
+/// of bare `CFStringRef`s, each of which is retained for the caller, who is responsible for releasing them.
+///
+/// If the caller's buffer can't hold the whole list, only the whole elements that fit are written.
+/// With a `UInt32` qualifier, the single string at that index is returned instead of the list
+pub struct StringListProp<const SEL: u32> {
+    strings: Vec<CFString>,
+}
+
+// SAFETY: see CFStringProp
+unsafe impl<const SEL: u32> Send for StringListProp<SEL> {}
+unsafe impl<const SEL: u32> Sync for StringListProp<SEL> {}
+
+impl<const SEL: u32> StringListProp<SEL> {
+    const ITEM_SIZE: u32 = mem::size_of::<CFStringRef>() as u32;
+    pub fn new(strings: Vec<CFString>) -> Self {
+        Self { strings }
+    }
+    pub fn from_static(strings: &[&'static str]) -> Self {
+        Self::new(
+            strings
+                .iter()
+                .map(|s| CFString::from_static_string(s))
+                .collect(),
+        )
+    }
+    pub fn strings(&self) -> &[CFString] {
+        &self.strings
+    }
+    pub fn strings_mut(&mut self) -> &mut Vec<CFString> {
+        &mut self.strings
+    }
+    /// Write +1 retained references to `strings` to `data_out`, truncating to whole elements
+    unsafe fn write_strings(
+        strings: &[CFString],
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let size = Self::ITEM_SIZE * strings.len() as u32;
+        if unsafe { probe_size(size, data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size >= Self::ITEM_SIZE || strings.is_empty(),
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let data_out = data_out as *mut CFStringRef;
+        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        let count = strings
+            .len()
+            .min((out_alloc_size / Self::ITEM_SIZE) as usize);
+        for (i, string) in strings[..count].iter().enumerate() {
+            // The caller releases every reference we hand out
+            unsafe {
+                CFRetain(string.as_CFTypeRef());
+                ptr::write(data_out.add(i), string.as_concrete_TypeRef());
+            }
+        }
+        unsafe { *data_len_out = Self::ITEM_SIZE * count as u32 };
+        Ok(())
+    }
+}
+
+impl<const SEL: u32> RawProperty for StringListProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::ITEM_SIZE * self.strings.len() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.strings
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.strings
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        // NUL separated, so the boundaries between the strings are part of the value
+        let mut bytes = Vec::new();
+        for string in &self.strings {
+            bytes.extend_from_slice(string.to_string().as_bytes());
+            bytes.push(0);
+        }
+        Some(bytes)
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { Self::write_strings(&self.strings, out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        match qualifier.read::<u32>() {
+            Some(_) => Self::ITEM_SIZE,
+            None => self.byte_size(),
+        }
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(index) = qualifier.read::<u32>() else {
+            return unsafe { self.get(out_alloc_size, data_out, data_len_out) };
+        };
+        let Some(string) = self.strings.get(index as usize) else {
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        };
+        unsafe {
+            Self::write_strings(
+                std::slice::from_ref(string),
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+}
+
+// Not exposed by core-foundation, which links the framework for us
+unsafe extern "C" {
+    fn CFLocaleCopyPreferredLanguages() -> CFArrayRef;
+}
+
+/// The user's preferred languages as BCP 47 identifiers (e.g. `de-CH`), most preferred first
+pub fn preferred_languages() -> Vec<String> {
+    let languages = unsafe { CFLocaleCopyPreferredLanguages() };
+    if languages.is_null() {
+        return Vec::new();
+    }
+    let languages: CFArray<CFString> = unsafe { CFArray::wrap_under_create_rule(languages) };
+    languages
+        .iter()
+        .map(|language| language.to_string())
+        .collect()
+}
+
+/// A string with translations keyed by locale identifier (`"en"`, `"de-CH"`, `"zh_Hans"`, ...) and a default for locales
+/// without one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalizedString {
+    pub default: String,
+    pub translations: Vec<(String, String)>,
+}
+
+impl LocalizedString {
+    /// A string with the given translations, defaulting to the first one
+    pub fn new(translations: &[(&str, &str)]) -> Self {
+        Self {
+            default: translations
+                .first()
+                .map(|(_, name)| (*name).to_owned())
+                .unwrap_or_default(),
+            translations: translations
+                .iter()
+                .map(|(locale, name)| ((*locale).to_owned(), (*name).to_owned()))
+                .collect(),
+        }
+    }
+    /// The translation for the first of `languages` there is one for, or the default. A translation matches a language
+    /// if their identifiers are equal, or if it is for the language without a region or script (`"de"` for `"de-CH"`)
+    pub fn resolve<S: AsRef<str>>(&self, languages: &[S]) -> &str {
+        let normalize = |locale: &str| locale.replace('_', "-").to_ascii_lowercase();
+        for language in languages {
+            let language = normalize(language.as_ref());
+            let base = language.split('-').next().unwrap_or_default();
+            let found = self
+                .translations
+                .iter()
+                .find(|(locale, _)| normalize(locale) == language)
+                .or_else(|| {
+                    self.translations
+                        .iter()
+                        .find(|(locale, _)| normalize(locale) == base)
+                });
+            if let Some((_, name)) = found {
+                return name;
+            }
+        }
+        &self.default
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A read-only, localized `CFString` property such as `kAudioObjectPropertyName`. The translation matching the user's
+/// preferred languages (see [`LocalizedString::resolve`]) is looked up on every `get`, and transported like a
+/// [`CFStringProp`].
+///
+/// Different names can be given to individual elements with [`LocalizedNameProp::with_element`], which is how
+/// `kAudioObjectPropertyElementName` is answered per channel. Requests for any other element get the main name
+pub struct LocalizedNameProp<const SEL: u32> {
+    name: LocalizedString,
+    elements: HashMap<PropertyElement, LocalizedString>,
+    languages: Option<Vec<String>>,
+}
+
+impl<const SEL: u32> LocalizedNameProp<SEL> {
+    const SIZE: u32 = mem::size_of::<CFStringRef>() as u32;
+    /// A name with the given translations, e.g. `&[("en", "My Device"), ("de", "Mein Gerät")]`. The first one is used
+    /// for languages without a translation
+    pub fn new(translations: &[(&str, &str)]) -> Self {
+        Self::from_localized(LocalizedString::new(translations))
+    }
+    pub fn from_localized(name: LocalizedString) -> Self {
+        Self {
+            name,
+            elements: HashMap::new(),
+            languages: None,
+        }
+    }
+    /// Give `element` its own translations
+    pub fn with_element(mut self, element: PropertyElement, translations: &[(&str, &str)]) -> Self {
+        self.elements
+            .insert(element, LocalizedString::new(translations));
+        self
+    }
+    /// Resolve against `languages` instead of the user's preferred languages
+    pub fn with_languages(mut self, languages: &[&str]) -> Self {
+        self.languages = Some(languages.iter().map(|&l| l.to_owned()).collect());
+        self
+    }
+    pub fn name(&self) -> &LocalizedString {
+        &self.name
+    }
+    pub fn name_mut(&mut self) -> &mut LocalizedString {
+        &mut self.name
+    }
+    pub fn element_mut(&mut self, element: PropertyElement) -> &mut LocalizedString {
+        self.elements.entry(element).or_default()
+    }
+    /// The name that is currently reported for `element`
+    pub fn resolved(&self, element: PropertyElement) -> String {
+        let name = self.elements.get(&element).unwrap_or(&self.name);
+        match &self.languages {
+            Some(languages) => name.resolve(languages),
+            None => name.resolve(&preferred_languages()),
+        }
+        .to_owned()
+    }
+    unsafe fn write_name(
+        &self,
+        element: PropertyElement,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(Self::SIZE, data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size >= Self::SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let data_out = data_out as *mut CFStringRef;
+        ret_assert!(data_out.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        let name = CFString::new(&self.resolved(element));
+        // The caller releases the reference we hand out
+        unsafe {
+            ptr::write(data_out, name.as_concrete_TypeRef());
+            *data_len_out = Self::SIZE;
+        }
+        mem::forget(name);
+        Ok(())
+    }
+}
+
+impl<const SEL: u32> RawProperty for LocalizedNameProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::SIZE
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.name
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.name
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.resolved(PropertyElement::MAIN).into_bytes())
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            self.write_name(
+                PropertyElement::MAIN,
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.write_name(address.element, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_foundation::base::CFType;
+    use coreaudio_sys::{
+        kAudioDevicePropertyIcon, kAudioObjectPropertyCustomPropertyInfoList,
+        kAudioObjectPropertyName, kAudioSelectorControlPropertyItemName,
+    };
+
+    use super::*;
+
+    const REF_SIZE: u32 = mem::size_of::<CFStringRef>() as u32;
+
+    /// Read `prop` the way the HAL does, returning the reference it handed out
+    fn get_ref(prop: &dyn RawProperty) -> *const c_void {
+        let mut out: *const c_void = ptr::null();
+        let mut len = 0;
+        unsafe { prop.get(prop.byte_size(), (&raw mut out).cast(), &mut len) }.unwrap();
+        assert_eq!(len, prop.byte_size());
+        assert!(!out.is_null());
+        out
+    }
+
+    #[test]
+    fn cf_string_get_hands_out_a_retained_ref() {
+        let prop = CFStringProp::<kAudioObjectPropertyName>::new(CFString::new("Device"));
+        let before = prop.0.retain_count();
+        let out = get_ref(&prop);
+        assert_eq!(out, prop.0.as_CFTypeRef());
+        assert_eq!(prop.0.retain_count(), before + 1);
+        // the HAL releases what it got
+        drop(unsafe { CFString::wrap_under_create_rule(out.cast()) });
+        assert_eq!(prop.0.retain_count(), before);
+    }
+
+    #[test]
+    fn cf_string_size_is_a_ref() {
+        let prop = CFStringProp::<kAudioObjectPropertyName>::new(CFString::new("Device"));
+        assert_eq!(prop.byte_size(), REF_SIZE);
+        let before = prop.0.retain_count();
+        let mut len = 0;
+        unsafe { prop.get(0, ptr::null_mut(), &mut len) }.unwrap();
+        assert_eq!(len, prop.byte_size());
+        // a size probe hands nothing out
+        assert_eq!(prop.0.retain_count(), before);
+    }
+
+    #[test]
+    fn cf_string_set_takes_its_own_ref() {
+        let mut prop = CFStringProp::<kAudioObjectPropertyName, true>::new(CFString::new("old"));
+        let incoming = CFString::new("new");
+        let before = incoming.retain_count();
+        let incoming_ref = incoming.as_concrete_TypeRef();
+        unsafe { prop.set((&raw const incoming_ref).cast(), REF_SIZE) }.unwrap();
+        assert_eq!(incoming.retain_count(), before + 1);
+        assert_eq!(prop.0, incoming);
+        drop(prop);
+        assert_eq!(incoming.retain_count(), before);
+    }
+
+    #[test]
+    fn cf_string_rejected_set_keeps_the_value() {
+        let mut prop = CFStringProp::<kAudioObjectPropertyName>::new(CFString::new("old"));
+        let incoming = CFString::new("new");
+        let before = incoming.retain_count();
+        let incoming_ref = incoming.as_concrete_TypeRef();
+        assert!(unsafe { prop.set((&raw const incoming_ref).cast(), REF_SIZE) }.is_err());
+        assert_eq!(incoming.retain_count(), before);
+        assert_eq!(prop.0.to_string(), "old");
+    }
+
+    #[test]
+    fn url_get_hands_out_a_retained_ref() {
+        let prop = UrlProp::<kAudioDevicePropertyIcon>::from_path("/tmp/icon.icns").unwrap();
+        let before = prop.0.retain_count();
+        let out = get_ref(&prop);
+        assert_eq!(prop.0.retain_count(), before + 1);
+        drop(unsafe { CFURL::wrap_under_create_rule(out.cast()) });
+        assert_eq!(prop.0.retain_count(), before);
+    }
+
+    #[test]
+    fn plist_get_and_set_retain() {
+        let value = CFString::new("value");
+        let mut prop =
+            PlistProp::<kAudioObjectPropertyCustomPropertyInfoList, true>::from_value(&value);
+        let before = value.retain_count();
+        let out = get_ref(&prop);
+        assert_eq!(value.retain_count(), before + 1);
+        drop(unsafe { CFType::wrap_under_create_rule(out) });
+        assert_eq!(value.retain_count(), before);
+
+        let incoming = CFString::new("incoming");
+        let incoming_before = incoming.retain_count();
+        let incoming_ref = incoming.as_CFTypeRef();
+        unsafe { prop.set((&raw const incoming_ref).cast(), REF_SIZE) }.unwrap();
+        assert_eq!(incoming.retain_count(), incoming_before + 1);
+        // the old value was released
+        assert_eq!(value.retain_count(), before - 1);
+    }
+
+    #[test]
+    fn string_list_get_retains_every_element() {
+        let strings = vec![CFString::new("one"), CFString::new("two")];
+        let prop = StringListProp::<kAudioSelectorControlPropertyItemName>::new(strings.clone());
+        let before: Vec<_> = strings.iter().map(|s| s.retain_count()).collect();
+        let mut out = [ptr::null::<c_void>(); 2];
+        let mut len = 0;
+        unsafe { prop.get(prop.byte_size(), out.as_mut_ptr().cast(), &mut len) }.unwrap();
+        assert_eq!(len, prop.byte_size());
+        for ((string, before), out) in strings.iter().zip(&before).zip(out) {
+            assert_eq!(out, string.as_CFTypeRef());
+            assert_eq!(string.retain_count(), before + 1);
+            drop(unsafe { CFString::wrap_under_create_rule(out.cast()) });
+            assert_eq!(string.retain_count(), *before);
+        }
+    }
+
+    #[test]
+    fn string_list_truncation_retains_only_what_was_written() {
+        let strings = vec![CFString::new("one"), CFString::new("two")];
+        let prop = StringListProp::<kAudioSelectorControlPropertyItemName>::new(strings.clone());
+        let before: Vec<_> = strings.iter().map(|s| s.retain_count()).collect();
+        let mut out: CFStringRef = ptr::null();
+        let mut len = 0;
+        unsafe { prop.get(REF_SIZE, (&raw mut out).cast(), &mut len) }.unwrap();
+        assert_eq!(len, REF_SIZE);
+        assert_eq!(strings[0].retain_count(), before[0] + 1);
+        assert_eq!(strings[1].retain_count(), before[1]);
+        drop(unsafe { CFString::wrap_under_create_rule(out) });
+    }
+
+    #[test]
+    fn localized_name_hands_out_an_owned_string() {
+        let prop = LocalizedNameProp::<kAudioObjectPropertyName>::new(&[
+            ("en", "Device"),
+            ("de", "Gerät"),
+        ])
+        .with_languages(&["de-CH"]);
+        let out = get_ref(&prop);
+        // created for the caller, who holds the only reference
+        let name = unsafe { CFString::wrap_under_create_rule(out.cast()) };
+        assert_eq!(name.retain_count(), 1);
+        assert_eq!(name.to_string(), "Gerät");
+    }
+}
@@ -0,0 +1,944 @@
+use std::{
+    any::Any,
+    ffi::c_void,
+    mem::{self, offset_of},
+    ptr,
+};
+
+use coreaudio_sys::{
+    kAudioChannelLayoutTag_UseChannelDescriptions, kAudioFormatFlagIsFloat,
+    kAudioFormatFlagIsNonInterleaved, kAudioFormatFlagsNativeFloatPacked, kAudioFormatLinearPCM,
+    AudioBuffer, AudioBufferList, AudioChannelBitmap, AudioChannelDescription, AudioChannelLabel,
+    AudioChannelLayout, AudioChannelLayoutTag, AudioStreamBasicDescription,
+    AudioStreamRangedDescription, AudioValueRange,
+};
+
+use crate::os_err::{OSResult, OSStatus, OSStatusError};
+
+use super::{
+    probe_size, read_value, write_value, ArrayProp, PropertyAddress, PropertyScope,
+    PropertySelector, Qualifier, RawProperty,
+};
+
+#[derive(Debug, Clone, Default)]
+/// A list of [`AudioValueRange`]s, as used by `kAudioDevicePropertyAvailableNominalSampleRates` and similar properties.
+/// Discrete values are represented as ranges with an equal minimum and maximum. Marshalled exactly like an [`ArrayProp`]
+pub struct RangeListProp<const SEL: u32, const MUTABLE_PROP: bool = false> {
+    ranges: ArrayProp<AudioValueRange, SEL, MUTABLE_PROP>,
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RangeListProp<SEL, MUTABLE_PROP> {
+    pub fn new() -> Self {
+        Self {
+            ranges: ArrayProp::new(),
+        }
+    }
+    /// A list of continuous `(min, max)` ranges
+    pub fn from_ranges(ranges: &[(f64, f64)]) -> Self {
+        let mut this = Self::new();
+        for &(min, max) in ranges {
+            this.push_range(min, max);
+        }
+        this
+    }
+    /// A list of discrete values
+    pub fn from_discrete(values: &[f64]) -> Self {
+        let mut this = Self::new();
+        for &value in values {
+            this.push_discrete(value);
+        }
+        this
+    }
+    pub fn push_range(&mut self, min: f64, max: f64) {
+        self.ranges.push(AudioValueRange {
+            mMinimum: min.min(max),
+            mMaximum: max.max(min),
+        });
+    }
+    pub fn push_discrete(&mut self, value: f64) {
+        self.push_range(value, value);
+    }
+    pub fn ranges(&self) -> &[AudioValueRange] {
+        &self.ranges
+    }
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+    /// Whether `value` falls inside any of the ranges in this list
+    pub fn contains(&self, value: f64) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.mMinimum <= value && value <= range.mMaximum)
+    }
+    /// The value closest to `value` that is inside one of the ranges in this list, or `value` itself if the list is empty
+    pub fn closest(&self, value: f64) -> f64 {
+        self.ranges
+            .iter()
+            .map(|range| value.clamp(range.mMinimum, range.mMaximum))
+            .min_by(|a, b| (a - value).abs().total_cmp(&(b - value).abs()))
+            .unwrap_or(value)
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for RangeListProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        self.ranges.selector()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.ranges.byte_size()
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    #[allow(deprecated)]
+    fn as_any(&self) -> &dyn Any {
+        self.ranges.as_any()
+    }
+
+    #[allow(deprecated)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self.ranges.as_any_mut()
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        unsafe { self.ranges.set(data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.ranges.get(out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// Field-wise equality for [`AudioStreamBasicDescription`]s, ignoring the reserved field
+pub fn asbd_eq(a: &AudioStreamBasicDescription, b: &AudioStreamBasicDescription) -> bool {
+    a.mSampleRate == b.mSampleRate
+        && a.mFormatID == b.mFormatID
+        && a.mFormatFlags == b.mFormatFlags
+        && a.mBytesPerPacket == b.mBytesPerPacket
+        && a.mFramesPerPacket == b.mFramesPerPacket
+        && a.mBytesPerFrame == b.mBytesPerFrame
+        && a.mChannelsPerFrame == b.mChannelsPerFrame
+        && a.mBitsPerChannel == b.mBitsPerChannel
+}
+
+/// Interleaved, packed native-endian 32 bit float linear PCM with `channels` channels, the canonical format of the HAL
+pub fn float32_format(sample_rate: f64, channels: u32) -> AudioStreamBasicDescription {
+    let bytes_per_frame = channels * mem::size_of::<f32>() as u32;
+    AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: kAudioFormatLinearPCM,
+        mFormatFlags: kAudioFormatFlagsNativeFloatPacked,
+        mBytesPerPacket: bytes_per_frame,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: bytes_per_frame,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: 32,
+        mReserved: 0,
+    }
+}
+
+/// A supported format as found by [`FormatSet::matches`]
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedFormat {
+    /// The requested format with its wildcard fields filled in from the matching supported format
+    pub format: AudioStreamBasicDescription,
+    /// The index of the matching format in the set
+    pub index: usize,
+}
+
+/// The formats a stream supports, as published through `kAudioStreamPropertyAvailableVirtualFormats` and
+/// `kAudioStreamPropertyAvailablePhysicalFormats`: each an [`AudioStreamBasicDescription`] along with the range of sample
+/// rates it is available at.
+///
+/// Requested formats are matched the way the HAL does it: any field of the request that is 0 is a wildcard matching any
+/// value. A wildcard sample rate resolves to the sample rate of the supported format, or the lower end of its range if
+/// that is 0 too. All other fields must be equal, and the sample rate must lie within the range
+#[derive(Debug, Clone, Default)]
+pub struct FormatSet {
+    formats: Vec<AudioStreamRangedDescription>,
+}
+
+impl FormatSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A set containing `format` at its own sample rate only
+    pub fn single(format: AudioStreamBasicDescription) -> Self {
+        let mut set = Self::new();
+        set.add(format);
+        set
+    }
+    pub fn formats(&self) -> &[AudioStreamRangedDescription] {
+        &self.formats
+    }
+    pub fn is_empty(&self) -> bool {
+        self.formats.is_empty()
+    }
+    /// Add `format` at its own sample rate, unless it is supported already
+    pub fn add(&mut self, format: AudioStreamBasicDescription) {
+        self.add_ranged(format, format.mSampleRate, format.mSampleRate);
+    }
+    /// Add `format` at any sample rate from `min_rate` to `max_rate`, unless exactly that is in the set already
+    pub fn add_ranged(
+        &mut self,
+        format: AudioStreamBasicDescription,
+        min_rate: f64,
+        max_rate: f64,
+    ) {
+        let exists = self.formats.iter().any(|ranged| {
+            asbd_eq(&ranged.mFormat, &format)
+                && ranged.mSampleRateRange.mMinimum == min_rate
+                && ranged.mSampleRateRange.mMaximum == max_rate
+        });
+        if !exists {
+            self.formats.push(AudioStreamRangedDescription {
+                mFormat: format,
+                mSampleRateRange: AudioValueRange {
+                    mMinimum: min_rate,
+                    mMaximum: max_rate,
+                },
+            });
+        }
+    }
+    /// The first supported format `request` matches, see the type documentation for the rules
+    pub fn matches(&self, request: &AudioStreamBasicDescription) -> Option<ResolvedFormat> {
+        self.formats.iter().enumerate().find_map(|(index, ranged)| {
+            Some(ResolvedFormat {
+                format: resolve(request, ranged)?,
+                index,
+            })
+        })
+    }
+}
+
+/// `request` with its wildcards filled in from `supported`, if it matches
+fn resolve(
+    request: &AudioStreamBasicDescription,
+    supported: &AudioStreamRangedDescription,
+) -> Option<AudioStreamBasicDescription> {
+    fn field(requested: u32, supported: u32) -> Option<u32> {
+        (requested == 0 || requested == supported).then_some(supported)
+    }
+    let format = &supported.mFormat;
+    let range = &supported.mSampleRateRange;
+    let sample_rate = if request.mSampleRate == 0.0 {
+        if format.mSampleRate != 0.0 {
+            format.mSampleRate
+        } else {
+            range.mMinimum
+        }
+    } else {
+        request.mSampleRate
+    };
+    if !(range.mMinimum..=range.mMaximum).contains(&sample_rate) {
+        return None;
+    }
+    Some(AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: field(request.mFormatID, format.mFormatID)?,
+        mFormatFlags: field(request.mFormatFlags, format.mFormatFlags)?,
+        mBytesPerPacket: field(request.mBytesPerPacket, format.mBytesPerPacket)?,
+        mFramesPerPacket: field(request.mFramesPerPacket, format.mFramesPerPacket)?,
+        mBytesPerFrame: field(request.mBytesPerFrame, format.mBytesPerFrame)?,
+        mChannelsPerFrame: field(request.mChannelsPerFrame, format.mChannelsPerFrame)?,
+        mBitsPerChannel: field(request.mBitsPerChannel, format.mBitsPerChannel)?,
+        mReserved: 0,
+    })
+}
+
+#[derive(Debug, Clone)]
+/// A stream format property (`kAudioStreamPropertyVirtualFormat`, `kAudioStreamPropertyPhysicalFormat`) holding the current
+/// [`AudioStreamBasicDescription`] and the [`FormatSet`] of formats the stream supports.
+/// Writes are resolved against the supported formats (see [`FormatSet::matches`]), and rejected with
+/// [`OSStatusError::DEV_UNSUPPORTED_FMT_ERR`] if none matches
+pub struct AsbdProp<const SEL: u32, const MUTABLE_PROP: bool = false> {
+    current: AudioStreamBasicDescription,
+    supported: FormatSet,
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> AsbdProp<SEL, MUTABLE_PROP> {
+    /// Create a format property with `current` as the only supported format
+    pub fn new(current: AudioStreamBasicDescription) -> Self {
+        Self {
+            current,
+            supported: FormatSet::single(current),
+        }
+    }
+    /// Create a format property supporting all of `supported`, with the first entry as the current format
+    ///
+    /// Returns `None` if `supported` is empty
+    pub fn with_supported(supported: Vec<AudioStreamBasicDescription>) -> Option<Self> {
+        let mut set = FormatSet::new();
+        for format in &supported {
+            set.add(*format);
+        }
+        Some(Self {
+            current: *supported.first()?,
+            supported: set,
+        })
+    }
+    pub fn format(&self) -> &AudioStreamBasicDescription {
+        &self.current
+    }
+    pub fn supported(&self) -> &FormatSet {
+        &self.supported
+    }
+    pub fn supported_mut(&mut self) -> &mut FormatSet {
+        &mut self.supported
+    }
+    pub fn is_supported(&self, format: &AudioStreamBasicDescription) -> bool {
+        self.supported.matches(format).is_some()
+    }
+    /// Switch to the supported format matching `format`, returning the format that is now current
+    pub fn set_format(
+        &mut self,
+        format: AudioStreamBasicDescription,
+    ) -> OSResult<AudioStreamBasicDescription> {
+        let Some(resolved) = self.supported.matches(&format) else {
+            return Err(OSStatusError::DEV_UNSUPPORTED_FMT_ERR);
+        };
+        self.current = resolved.format;
+        Ok(resolved.format)
+    }
+    /// Add a format to the supported list
+    pub fn add_supported(&mut self, format: AudioStreamBasicDescription) {
+        self.supported.add(format);
+    }
+    pub fn sample_rate(&self) -> f64 {
+        self.current.mSampleRate
+    }
+    pub fn channels(&self) -> u32 {
+        self.current.mChannelsPerFrame
+    }
+    pub fn bytes_per_frame(&self) -> u32 {
+        self.current.mBytesPerFrame
+    }
+    /// Size in bytes of `frames` frames in the current format. For non-interleaved formats this is the size of a single channel's buffer
+    pub fn frames_to_bytes(&self, frames: u32) -> u32 {
+        frames.saturating_mul(self.current.mBytesPerFrame)
+    }
+    /// Number of whole frames that fit into `bytes` bytes in the current format
+    pub fn bytes_to_frames(&self, bytes: u32) -> u32 {
+        bytes
+            .checked_div(self.current.mBytesPerFrame)
+            .unwrap_or_default()
+    }
+    pub fn is_float(&self) -> bool {
+        self.current.mFormatFlags & kAudioFormatFlagIsFloat != 0
+    }
+    pub fn is_interleaved(&self) -> bool {
+        self.current.mFormatFlags & kAudioFormatFlagIsNonInterleaved == 0
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for AsbdProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<AudioStreamBasicDescription>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.current
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.current
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        let format = unsafe { read_value(data, data_size)? };
+        self.set_format(format).map(drop)
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.current, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An [`AudioChannelLayout`], as used by `kAudioDevicePropertyPreferredChannelLayout`. On the wire this is a variable
+/// length struct: the fixed header followed by `mNumberChannelDescriptions` [`AudioChannelDescription`]s.
+///
+/// If the caller's buffer is too small for all descriptions, as many as fit are written and
+/// `mNumberChannelDescriptions` is adjusted to match
+pub struct ChannelLayoutProp<const SEL: u32, const MUTABLE_PROP: bool = false> {
+    pub tag: AudioChannelLayoutTag,
+    pub bitmap: AudioChannelBitmap,
+    pub descriptions: Vec<AudioChannelDescription>,
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> ChannelLayoutProp<SEL, MUTABLE_PROP> {
+    const HEADER_SIZE: usize = offset_of!(AudioChannelLayout, mChannelDescriptions);
+    const DESCRIPTION_SIZE: usize = mem::size_of::<AudioChannelDescription>();
+
+    /// A layout described entirely by its channel descriptions
+    pub fn new(descriptions: Vec<AudioChannelDescription>) -> Self {
+        Self {
+            tag: kAudioChannelLayoutTag_UseChannelDescriptions,
+            bitmap: 0,
+            descriptions,
+        }
+    }
+    /// A layout with one description per label, in channel order
+    pub fn from_labels(labels: &[AudioChannelLabel]) -> Self {
+        Self::new(
+            labels
+                .iter()
+                .map(|&label| AudioChannelDescription {
+                    mChannelLabel: label,
+                    mChannelFlags: 0,
+                    mCoordinates: [0.0; 3],
+                })
+                .collect(),
+        )
+    }
+    /// A layout identified by a predefined layout tag, without channel descriptions
+    pub fn from_tag(tag: AudioChannelLayoutTag) -> Self {
+        Self {
+            tag,
+            bitmap: 0,
+            descriptions: Vec::new(),
+        }
+    }
+    fn size_for(descriptions: usize) -> usize {
+        Self::HEADER_SIZE + descriptions * Self::DESCRIPTION_SIZE
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty
+    for ChannelLayoutProp<SEL, MUTABLE_PROP>
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::size_for(self.descriptions.len()) as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        ret_assert!(!data.is_null(), OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        let layout = data as *const AudioChannelLayout;
+        ret_assert!(layout.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        ret_assert!(
+            data_size as usize >= Self::HEADER_SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let (tag, bitmap, count) = unsafe {
+            (
+                ptr::addr_of!((*layout).mChannelLayoutTag).read(),
+                ptr::addr_of!((*layout).mChannelBitmap).read(),
+                ptr::addr_of!((*layout).mNumberChannelDescriptions).read() as usize,
+            )
+        };
+        ret_assert!(
+            data_size as usize >= Self::size_for(count),
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let descriptions = unsafe {
+            let first =
+                ptr::addr_of!((*layout).mChannelDescriptions) as *const AudioChannelDescription;
+            std::slice::from_raw_parts(first, count)
+        };
+        self.tag = tag;
+        self.bitmap = bitmap;
+        self.descriptions.clear();
+        self.descriptions.extend_from_slice(descriptions);
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(self.byte_size(), data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size as usize >= Self::HEADER_SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let layout = data_out as *mut AudioChannelLayout;
+        ret_assert!(layout.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        let count = self
+            .descriptions
+            .len()
+            .min((out_alloc_size as usize - Self::HEADER_SIZE) / Self::DESCRIPTION_SIZE);
+        unsafe {
+            ptr::addr_of_mut!((*layout).mChannelLayoutTag).write(self.tag);
+            ptr::addr_of_mut!((*layout).mChannelBitmap).write(self.bitmap);
+            ptr::addr_of_mut!((*layout).mNumberChannelDescriptions).write(count as u32);
+            let first =
+                ptr::addr_of_mut!((*layout).mChannelDescriptions) as *mut AudioChannelDescription;
+            ptr::copy_nonoverlapping(self.descriptions.as_ptr(), first, count);
+            *data_len_out = Self::size_for(count) as u32;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// `kAudioDevicePropertyStreamConfiguration`: an [`AudioBufferList`] with one [`AudioBuffer`] per stream, giving the
+/// number of channels of each. Like [`ScopedArrayProp`](super::ScopedArrayProp), requests in the input or output scope
+/// see only the streams of that direction, all others see every stream, inputs first. The buffers carry no data.
+///
+/// If the caller's buffer is too small for all streams, as many buffers as fit are written and `mNumberBuffers` is
+/// adjusted to match
+pub struct StreamConfigurationProp<const SEL: u32> {
+    /// Channel counts of the input streams, in stream order
+    pub input: Vec<u32>,
+    /// Channel counts of the output streams, in stream order
+    pub output: Vec<u32>,
+}
+
+impl<const SEL: u32> StreamConfigurationProp<SEL> {
+    const HEADER_SIZE: usize = offset_of!(AudioBufferList, mBuffers);
+    const BUFFER_SIZE: usize = mem::size_of::<AudioBuffer>();
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The channel counts seen by a request in `scope`
+    pub fn scoped(&self, scope: PropertyScope) -> Vec<u32> {
+        match scope {
+            PropertyScope::INPUT => self.input.clone(),
+            PropertyScope::OUTPUT => self.output.clone(),
+            _ => self.input.iter().chain(&self.output).copied().collect(),
+        }
+    }
+    /// The channel counts of the input or output `scope`, `None` for any other scope
+    pub fn scope_mut(&mut self, scope: PropertyScope) -> Option<&mut Vec<u32>> {
+        match scope {
+            PropertyScope::INPUT => Some(&mut self.input),
+            PropertyScope::OUTPUT => Some(&mut self.output),
+            _ => None,
+        }
+    }
+    fn size_for(buffers: usize) -> u32 {
+        (Self::HEADER_SIZE + buffers * Self::BUFFER_SIZE) as u32
+    }
+    unsafe fn write(
+        channels: &[u32],
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(Self::size_for(channels.len()), data_out, data_len_out)? } {
+            return Ok(());
+        }
+        ret_assert!(
+            out_alloc_size as usize >= Self::HEADER_SIZE,
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR
+        );
+        let list = data_out as *mut AudioBufferList;
+        ret_assert!(list.is_aligned(), OSStatusError::HW_BAD_OBJECT_ERR);
+        let count = channels
+            .len()
+            .min((out_alloc_size as usize - Self::HEADER_SIZE) / Self::BUFFER_SIZE);
+        unsafe {
+            ptr::addr_of_mut!((*list).mNumberBuffers).write(count as u32);
+            let first = ptr::addr_of_mut!((*list).mBuffers) as *mut AudioBuffer;
+            for (i, &channels) in channels[..count].iter().enumerate() {
+                first.add(i).write(AudioBuffer {
+                    mNumberChannels: channels,
+                    mDataByteSize: 0,
+                    mData: ptr::null_mut(),
+                });
+            }
+            *data_len_out = Self::size_for(count);
+        }
+        Ok(())
+    }
+}
+
+impl<const SEL: u32> RawProperty for StreamConfigurationProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        Self::size_for(self.input.len() + self.output.len())
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            Self::write(
+                &self.scoped(PropertyScope::GLOBAL),
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+
+    fn byte_size_at(&self, address: PropertyAddress, _qualifier: Qualifier<'_>) -> u32 {
+        Self::size_for(self.scoped(address.scope).len())
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            Self::write(
+                &self.scoped(address.scope),
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioDevicePropertyAvailableNominalSampleRates,
+        kAudioDevicePropertyPreferredChannelLayout, kAudioDeviceUnsupportedFormatError,
+        kAudioFormatFlagIsPacked, kAudioFormatFlagIsSignedInteger,
+        kAudioStreamPropertyVirtualFormat,
+    };
+
+    use super::*;
+    use crate::os_err::result_to_err_code;
+
+    type Rates = RangeListProp<kAudioDevicePropertyAvailableNominalSampleRates>;
+    type Format = AsbdProp<kAudioStreamPropertyVirtualFormat, true>;
+    type Layout = ChannelLayoutProp<kAudioDevicePropertyPreferredChannelLayout, true>;
+
+    /// Read all of `prop` the way the HAL does
+    fn read_ranges(prop: &dyn RawProperty) -> Vec<(f64, f64)> {
+        let mut ranges = vec![
+            AudioValueRange {
+                mMinimum: 0.0,
+                mMaximum: 0.0,
+            };
+            prop.byte_size() as usize / mem::size_of::<AudioValueRange>()
+        ];
+        let mut written = 0;
+        unsafe { prop.get(prop.byte_size(), ranges.as_mut_ptr().cast(), &mut written) }.unwrap();
+        assert_eq!(written, prop.byte_size());
+        ranges
+            .iter()
+            .map(|range| (range.mMinimum, range.mMaximum))
+            .collect()
+    }
+
+    #[test]
+    fn discrete_rates_contain_only_themselves() {
+        let rates = Rates::from_discrete(&[44100.0, 48000.0, 96000.0]);
+        assert_eq!(
+            read_ranges(&rates),
+            [(44100.0, 44100.0), (48000.0, 48000.0), (96000.0, 96000.0)]
+        );
+        assert!(rates.contains(48000.0));
+        assert!(!rates.contains(47999.0));
+        assert_eq!(rates.closest(50000.0), 48000.0);
+        assert_eq!(rates.closest(8000.0), 44100.0);
+        assert_eq!(rates.closest(192000.0), 96000.0);
+    }
+
+    #[test]
+    fn continuous_ranges_contain_everything_in_between() {
+        let rates = Rates::from_ranges(&[(8000.0, 48000.0), (192000.0, 88200.0)]);
+        // the bounds of a range are put in order
+        assert_eq!(
+            read_ranges(&rates),
+            [(8000.0, 48000.0), (88200.0, 192000.0)]
+        );
+        assert!(rates.contains(8000.0));
+        assert!(rates.contains(22050.0));
+        assert!(rates.contains(192000.0));
+        assert!(!rates.contains(64000.0));
+        assert_eq!(rates.closest(22050.0), 22050.0);
+        assert_eq!(rates.closest(60000.0), 48000.0);
+        assert_eq!(rates.closest(80000.0), 88200.0);
+        assert_eq!(rates.closest(4000.0), 8000.0);
+    }
+
+    #[test]
+    fn empty_range_lists_contain_nothing() {
+        let rates = Rates::new();
+        assert_eq!(rates.byte_size(), 0);
+        assert!(!rates.contains(48000.0));
+        assert_eq!(rates.closest(48000.0), 48000.0);
+    }
+
+    /// Write `format` to `prop` the way the HAL does, returning the status code
+    fn write_format(prop: &mut Format, format: AudioStreamBasicDescription) -> i32 {
+        let size = mem::size_of::<AudioStreamBasicDescription>() as u32;
+        result_to_err_code(unsafe { prop.set((&raw const format).cast(), size) })
+    }
+
+    fn stereo_format() -> Format {
+        Format::with_supported(vec![float32_format(44100.0, 2), float32_format(48000.0, 2)])
+            .unwrap()
+    }
+
+    #[test]
+    fn listed_formats_are_accepted() {
+        let mut prop = stereo_format();
+        assert_eq!(prop.sample_rate(), 44100.0);
+        assert_eq!(write_format(&mut prop, float32_format(48000.0, 2)), 0);
+        assert!(asbd_eq(prop.format(), &float32_format(48000.0, 2)));
+
+        let format = AudioStreamBasicDescription {
+            mBytesPerPacket: 0,
+            mBytesPerFrame: 0,
+            ..float32_format(44100.0, 2)
+        };
+        // wildcards resolve to the supported format
+        assert_eq!(write_format(&mut prop, format), 0);
+        assert!(asbd_eq(prop.format(), &float32_format(44100.0, 2)));
+    }
+
+    #[test]
+    fn unlisted_formats_are_rejected() {
+        let mut prop = stereo_format();
+        let unsupported = kAudioDeviceUnsupportedFormatError as i32;
+        assert_eq!(
+            write_format(&mut prop, float32_format(96000.0, 2)),
+            unsupported
+        );
+        assert_eq!(
+            write_format(&mut prop, float32_format(48000.0, 1)),
+            unsupported
+        );
+        assert!(asbd_eq(prop.format(), &float32_format(44100.0, 2)));
+        assert!(!prop.is_supported(&float32_format(96000.0, 2)));
+        assert!(Format::with_supported(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn exact_formats_match_their_entry() {
+        let mut set = FormatSet::single(float32_format(44100.0, 2));
+        set.add(float32_format(48000.0, 2));
+        // adding a format again doesn't list it twice
+        set.add(float32_format(48000.0, 2));
+        assert_eq!(set.formats().len(), 2);
+
+        let resolved = set.matches(&float32_format(48000.0, 2)).unwrap();
+        assert_eq!(resolved.index, 1);
+        assert!(asbd_eq(&resolved.format, &float32_format(48000.0, 2)));
+        assert!(set.matches(&float32_format(48000.0, 1)).is_none());
+        assert!(FormatSet::new()
+            .matches(&float32_format(48000.0, 2))
+            .is_none());
+    }
+
+    #[test]
+    fn wildcard_sample_rates_resolve_to_the_supported_rate() {
+        let any_rate = float32_format(0.0, 2);
+        let set = FormatSet::single(float32_format(48000.0, 2));
+        let resolved = set.matches(&any_rate).unwrap();
+        assert!(asbd_eq(&resolved.format, &float32_format(48000.0, 2)));
+
+        // a ranged format without a rate of its own resolves to the lower end of its range
+        let mut set = FormatSet::new();
+        set.add_ranged(float32_format(0.0, 2), 8000.0, 192000.0);
+        let resolved = set.matches(&any_rate).unwrap();
+        assert_eq!(resolved.format.mSampleRate, 8000.0);
+        let resolved = set.matches(&float32_format(96000.0, 2)).unwrap();
+        assert_eq!(resolved.format.mSampleRate, 96000.0);
+        assert!(set.matches(&float32_format(384000.0, 2)).is_none());
+    }
+
+    #[test]
+    fn unsupported_bit_depths_are_rejected() {
+        let mut prop = stereo_format();
+        let int16 = AudioStreamBasicDescription {
+            mFormatFlags: kAudioFormatFlagIsSignedInteger | kAudioFormatFlagIsPacked,
+            mBytesPerPacket: 4,
+            mBytesPerFrame: 4,
+            mBitsPerChannel: 16,
+            ..float32_format(48000.0, 2)
+        };
+        assert!(prop.supported().matches(&int16).is_none());
+        assert_eq!(
+            result_to_err_code(prop.set_format(int16).map(drop)),
+            kAudioDeviceUnsupportedFormatError as i32
+        );
+        // only the bit depth differs from a supported format
+        let float16 = AudioStreamBasicDescription {
+            mBitsPerChannel: 16,
+            ..float32_format(48000.0, 2)
+        };
+        assert_eq!(
+            write_format(&mut prop, float16),
+            kAudioDeviceUnsupportedFormatError as i32
+        );
+        assert!(asbd_eq(prop.format(), &float32_format(44100.0, 2)));
+
+        // a wildcard bit depth resolves to the supported one
+        let any_depth = AudioStreamBasicDescription {
+            mBitsPerChannel: 0,
+            ..float32_format(48000.0, 2)
+        };
+        assert_eq!(write_format(&mut prop, any_depth), 0);
+        assert_eq!(prop.format().mBitsPerChannel, 32);
+    }
+
+    #[test]
+    fn bytes_per_frame_follow_the_format() {
+        let prop = stereo_format();
+        assert_eq!(prop.channels(), 2);
+        assert_eq!(prop.bytes_per_frame(), 8);
+        assert_eq!(prop.frames_to_bytes(512), 4096);
+        assert_eq!(prop.frames_to_bytes(u32::MAX), u32::MAX);
+        assert_eq!(prop.bytes_to_frames(4100), 512);
+        assert!(prop.is_float());
+        assert!(prop.is_interleaved());
+
+        // a format without frames doesn't divide by zero
+        let empty = Format::new(AudioStreamBasicDescription {
+            mBytesPerFrame: 0,
+            ..float32_format(48000.0, 0)
+        });
+        assert_eq!(empty.bytes_to_frames(4096), 0);
+        assert_eq!(empty.frames_to_bytes(512), 0);
+    }
+
+    /// A layout of `channels` channels labelled 1 to `channels`, each with flags and coordinates derived from its label
+    fn layout(channels: u32) -> Layout {
+        let mut layout = Layout::from_labels(&(1..=channels).collect::<Vec<_>>());
+        for description in &mut layout.descriptions {
+            let label = description.mChannelLabel as f32;
+            description.mChannelFlags = description.mChannelLabel * 10;
+            description.mCoordinates = [label, -label, label / 2.0];
+        }
+        layout
+    }
+
+    /// The words of `AudioChannelLayout` as the C struct lays them out: the tag, the bitmap, the number of descriptions
+    /// and then each description as its label, flags and three coordinates
+    fn c_words(channels: u32) -> Vec<u32> {
+        let mut words = vec![kAudioChannelLayoutTag_UseChannelDescriptions, 0, channels];
+        for label in 1..=channels {
+            let coordinate = label as f32;
+            words.extend([
+                label,
+                label * 10,
+                coordinate.to_bits(),
+                (-coordinate).to_bits(),
+                (coordinate / 2.0).to_bits(),
+            ]);
+        }
+        words
+    }
+
+    /// Read `prop` into a buffer of `alloc_size` bytes, returning the words written
+    fn read_words(prop: &Layout, alloc_size: u32) -> Vec<u32> {
+        let mut buffer = vec![u32::MAX; alloc_size as usize / 4];
+        let mut written = 0;
+        unsafe { prop.get(alloc_size, buffer.as_mut_ptr().cast(), &mut written) }.unwrap();
+        buffer.truncate(written as usize / 4);
+        buffer
+    }
+
+    #[test]
+    fn channel_layouts_match_the_c_struct() {
+        // the header is three words, each description five
+        assert_eq!(offset_of!(AudioChannelLayout, mChannelDescriptions), 12);
+        assert_eq!(mem::size_of::<AudioChannelDescription>(), 20);
+        for channels in [0, 1, 2, 8] {
+            let prop = layout(channels);
+            assert_eq!(prop.byte_size(), 12 + 20 * channels, "{channels} channels");
+            assert_eq!(
+                read_words(&prop, prop.byte_size()),
+                c_words(channels),
+                "{channels} channels"
+            );
+        }
+    }
+
+    #[test]
+    fn channel_layouts_are_truncated_to_whole_descriptions() {
+        let prop = layout(8);
+        // room for two and a half descriptions
+        assert_eq!(read_words(&prop, 12 + 50), c_words(2));
+
+        let mut buffer = [0u32; 2];
+        let mut written = 0;
+        let status = unsafe { prop.get(8, buffer.as_mut_ptr().cast(), &mut written) };
+        assert!(status.is_err());
+    }
+
+    #[test]
+    fn channel_layouts_are_parsed_on_set() {
+        for channels in [0, 1, 2, 8] {
+            let words = c_words(channels);
+            let size = (words.len() * 4) as u32;
+            let mut prop = Layout::from_tag(0);
+            unsafe { prop.set(words.as_ptr().cast(), size) }.unwrap();
+            assert_eq!(read_words(&prop, size), words, "{channels} channels");
+        }
+        // a layout claiming more descriptions than were passed is rejected
+        let words = c_words(2);
+        let mut prop = Layout::from_tag(0);
+        let status = unsafe { prop.set(words.as_ptr().cast(), (words.len() * 4 - 4) as u32) };
+        assert!(status.is_err());
+        assert!(prop.descriptions.is_empty());
+    }
+}
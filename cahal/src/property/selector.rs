@@ -0,0 +1,209 @@
+//! Selectors grouped by the class of object they are valid on, so that putting e.g. a stream property on a device can be
+//! caught at compile time, see [`Prop::for_class`](super::Prop::for_class). Custom selectors aren't known here, so they
+//! keep using the raw `u32` path (`Prop::new`)
+
+use coreaudio_sys::*;
+
+/// A class of audio object, listing the selectors that are valid on it
+pub trait SelectorClass {
+    /// Name of the class, e.g. `"AudioDevice"`
+    const CLASS_NAME: &'static str;
+    /// All selectors known to be valid on objects of this class, including those inherited from base classes
+    const SELECTORS: &'static [u32];
+}
+
+/// Whether `selector` is valid on objects of class `C`, usable in const contexts
+pub const fn is_valid_for<C: SelectorClass>(selector: u32) -> bool {
+    let mut i = 0;
+    while i < C::SELECTORS.len() {
+        if C::SELECTORS[i] == selector {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+macro_rules! selector_class {
+    (
+        $(#[$meta:meta])*
+        $name:ident($class_name:literal) $(: $base:ident)? {
+            $($(#[$cmeta:meta])* $const_name:ident = $sel:ident,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        pub struct $name(u32);
+
+        impl $name {
+            $($(#[$cmeta])* pub const $const_name: Self = Self($sel);)*
+
+            /// The raw selector, e.g. for use as the `SEL` parameter of a property
+            pub const fn get(self) -> u32 {
+                self.0
+            }
+        }
+
+        impl SelectorClass for $name {
+            const CLASS_NAME: &'static str = $class_name;
+            const SELECTORS: &'static [u32] = &selector_class!(@concat [$($sel),*] $($base)?);
+        }
+
+        impl From<$name> for super::PropertySelector {
+            fn from(value: $name) -> Self {
+                Self::new(value.0)
+            }
+        }
+    };
+    (@concat [$($sel:ident),*]) => { [$($sel),*] };
+    (@concat [$($sel:ident),*] $base:ident) => {{
+        const OWN: &[u32] = &[$($sel),*];
+        const LEN: usize = OWN.len() + $base::SELECTORS.len();
+        let mut all = [0u32; LEN];
+        let mut i = 0;
+        while i < LEN {
+            all[i] = if i < OWN.len() {
+                OWN[i]
+            } else {
+                $base::SELECTORS[i - OWN.len()]
+            };
+            i += 1;
+        }
+        all
+    }};
+}
+
+selector_class! {
+    /// Selectors valid on every audio object
+    ObjectSelector("AudioObject") {
+        BASE_CLASS = kAudioObjectPropertyBaseClass,
+        CLASS = kAudioObjectPropertyClass,
+        OWNER = kAudioObjectPropertyOwner,
+        NAME = kAudioObjectPropertyName,
+        MODEL_NAME = kAudioObjectPropertyModelName,
+        MANUFACTURER = kAudioObjectPropertyManufacturer,
+        ELEMENT_NAME = kAudioObjectPropertyElementName,
+        ELEMENT_CATEGORY_NAME = kAudioObjectPropertyElementCategoryName,
+        ELEMENT_NUMBER_NAME = kAudioObjectPropertyElementNumberName,
+        OWNED_OBJECTS = kAudioObjectPropertyOwnedObjects,
+        IDENTIFY = kAudioObjectPropertyIdentify,
+        SERIAL_NUMBER = kAudioObjectPropertySerialNumber,
+        FIRMWARE_VERSION = kAudioObjectPropertyFirmwareVersion,
+        CUSTOM_PROPERTY_INFO_LIST = kAudioObjectPropertyCustomPropertyInfoList,
+    }
+}
+
+selector_class! {
+    /// Selectors valid on audio devices
+    DeviceSelector("AudioDevice"): ObjectSelector {
+        CONFIGURATION_APPLICATION = kAudioDevicePropertyConfigurationApplication,
+        DEVICE_UID = kAudioDevicePropertyDeviceUID,
+        MODEL_UID = kAudioDevicePropertyModelUID,
+        TRANSPORT_TYPE = kAudioDevicePropertyTransportType,
+        RELATED_DEVICES = kAudioDevicePropertyRelatedDevices,
+        CLOCK_DOMAIN = kAudioDevicePropertyClockDomain,
+        DEVICE_IS_ALIVE = kAudioDevicePropertyDeviceIsAlive,
+        DEVICE_IS_RUNNING = kAudioDevicePropertyDeviceIsRunning,
+        CAN_BE_DEFAULT_DEVICE = kAudioDevicePropertyDeviceCanBeDefaultDevice,
+        CAN_BE_DEFAULT_SYSTEM_DEVICE = kAudioDevicePropertyDeviceCanBeDefaultSystemDevice,
+        LATENCY = kAudioDevicePropertyLatency,
+        STREAMS = kAudioDevicePropertyStreams,
+        CONTROL_LIST = kAudioObjectPropertyControlList,
+        SAFETY_OFFSET = kAudioDevicePropertySafetyOffset,
+        NOMINAL_SAMPLE_RATE = kAudioDevicePropertyNominalSampleRate,
+        AVAILABLE_NOMINAL_SAMPLE_RATES = kAudioDevicePropertyAvailableNominalSampleRates,
+        ICON = kAudioDevicePropertyIcon,
+        IS_HIDDEN = kAudioDevicePropertyIsHidden,
+        PREFERRED_CHANNELS_FOR_STEREO = kAudioDevicePropertyPreferredChannelsForStereo,
+        PREFERRED_CHANNEL_LAYOUT = kAudioDevicePropertyPreferredChannelLayout,
+        ZERO_TIME_STAMP_PERIOD = kAudioDevicePropertyZeroTimeStampPeriod,
+        CLOCK_ALGORITHM = kAudioDevicePropertyClockAlgorithm,
+        CLOCK_IS_STABLE = kAudioDevicePropertyClockIsStable,
+        HOG_MODE = kAudioDevicePropertyHogMode,
+        BUFFER_FRAME_SIZE = kAudioDevicePropertyBufferFrameSize,
+        BUFFER_FRAME_SIZE_RANGE = kAudioDevicePropertyBufferFrameSizeRange,
+        CLOCK_SOURCE = kAudioDevicePropertyClockSource,
+        DATA_SOURCE = kAudioDevicePropertyDataSource,
+    }
+}
+
+selector_class! {
+    /// Selectors valid on audio streams
+    StreamSelector("AudioStream"): ObjectSelector {
+        IS_ACTIVE = kAudioStreamPropertyIsActive,
+        DIRECTION = kAudioStreamPropertyDirection,
+        TERMINAL_TYPE = kAudioStreamPropertyTerminalType,
+        STARTING_CHANNEL = kAudioStreamPropertyStartingChannel,
+        LATENCY = kAudioStreamPropertyLatency,
+        VIRTUAL_FORMAT = kAudioStreamPropertyVirtualFormat,
+        AVAILABLE_VIRTUAL_FORMATS = kAudioStreamPropertyAvailableVirtualFormats,
+        PHYSICAL_FORMAT = kAudioStreamPropertyPhysicalFormat,
+        AVAILABLE_PHYSICAL_FORMATS = kAudioStreamPropertyAvailablePhysicalFormats,
+    }
+}
+
+selector_class! {
+    /// Selectors valid on controls (level, boolean, selector and stereo pan controls)
+    ControlSelector("AudioControl"): ObjectSelector {
+        SCOPE = kAudioControlPropertyScope,
+        ELEMENT = kAudioControlPropertyElement,
+        SCALAR_VALUE = kAudioLevelControlPropertyScalarValue,
+        DECIBEL_VALUE = kAudioLevelControlPropertyDecibelValue,
+        DECIBEL_RANGE = kAudioLevelControlPropertyDecibelRange,
+        CONVERT_SCALAR_TO_DECIBELS = kAudioLevelControlPropertyConvertScalarToDecibels,
+        CONVERT_DECIBELS_TO_SCALAR = kAudioLevelControlPropertyConvertDecibelsToScalar,
+        BOOLEAN_VALUE = kAudioBooleanControlPropertyValue,
+        CURRENT_ITEM = kAudioSelectorControlPropertyCurrentItem,
+        AVAILABLE_ITEMS = kAudioSelectorControlPropertyAvailableItems,
+        ITEM_NAME = kAudioSelectorControlPropertyItemName,
+        ITEM_KIND = kAudioSelectorControlPropertyItemKind,
+        STEREO_PAN_VALUE = kAudioStereoPanControlPropertyValue,
+        PANNING_CHANNELS = kAudioStereoPanControlPropertyPanningChannels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_include_the_selectors_of_their_base() {
+        assert_eq!(
+            DeviceSelector::SELECTORS.len(),
+            28 + ObjectSelector::SELECTORS.len()
+        );
+        assert!(DeviceSelector::SELECTORS.ends_with(ObjectSelector::SELECTORS));
+        for class in [StreamSelector::SELECTORS, ControlSelector::SELECTORS] {
+            assert!(class.ends_with(ObjectSelector::SELECTORS));
+        }
+        assert!(is_valid_for::<DeviceSelector>(ObjectSelector::NAME.get()));
+        assert!(is_valid_for::<StreamSelector>(ObjectSelector::OWNER.get()));
+    }
+
+    #[test]
+    fn selectors_of_other_classes_are_invalid() {
+        assert!(is_valid_for::<DeviceSelector>(kAudioDevicePropertyLatency));
+        assert!(!is_valid_for::<ObjectSelector>(kAudioDevicePropertyLatency));
+        assert!(!is_valid_for::<DeviceSelector>(
+            kAudioStreamPropertyIsActive
+        ));
+        assert!(!is_valid_for::<StreamSelector>(
+            kAudioDevicePropertyNominalSampleRate
+        ));
+        assert!(!is_valid_for::<ControlSelector>(u32::from_be_bytes(
+            *b"mine"
+        )));
+    }
+
+    #[test]
+    fn constants_convert_to_their_selector() {
+        assert_eq!(DeviceSelector::LATENCY.get(), kAudioDevicePropertyLatency);
+        assert_eq!(StreamSelector::LATENCY.get(), kAudioStreamPropertyLatency);
+        assert_eq!(
+            super::super::PropertySelector::from(ControlSelector::SCALAR_VALUE),
+            kAudioLevelControlPropertyScalarValue.into()
+        );
+        assert_eq!(DeviceSelector::CLASS_NAME, "AudioDevice");
+    }
+}
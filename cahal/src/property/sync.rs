@@ -0,0 +1,756 @@
+use std::{
+    any::Any,
+    cell::UnsafeCell,
+    ffi::c_void,
+    fmt::Debug,
+    hint, ptr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use coreaudio_sys::pid_t;
+
+use crate::os_err::{OSStatus, OSStatusError};
+
+use super::{read_value, write_value, PropertyAddress, PropertySelector, Qualifier, RawProperty};
+
+/// A value that can be stored in an [`AtomicProp`]
+pub trait AtomicValue: Copy + Send + Sync + 'static {
+    /// The atomic the value lives in
+    type Storage: Send + Sync + 'static;
+    /// The representation of the value on the wire, i.e. what the HAL reads and writes
+    type Raw: Copy + 'static;
+
+    fn new_storage(value: Self) -> Self::Storage;
+    fn load(storage: &Self::Storage, order: Ordering) -> Self;
+    fn store(storage: &Self::Storage, value: Self, order: Ordering);
+    fn to_raw(self) -> Self::Raw;
+    fn from_raw(raw: Self::Raw) -> Self;
+}
+
+macro_rules! atomic_value {
+    ($ty:ty, $atomic:ty) => {
+        impl AtomicValue for $ty {
+            type Storage = $atomic;
+            type Raw = $ty;
+
+            fn new_storage(value: Self) -> Self::Storage {
+                <$atomic>::new(value)
+            }
+            #[inline]
+            fn load(storage: &Self::Storage, order: Ordering) -> Self {
+                storage.load(order)
+            }
+            #[inline]
+            fn store(storage: &Self::Storage, value: Self, order: Ordering) {
+                storage.store(value, order)
+            }
+            fn to_raw(self) -> Self::Raw {
+                self
+            }
+            fn from_raw(raw: Self::Raw) -> Self {
+                raw
+            }
+        }
+    };
+}
+atomic_value!(u32, AtomicU32);
+atomic_value!(i32, AtomicI32);
+
+/// Stored as its bit pattern in an [`AtomicU32`]
+impl AtomicValue for f32 {
+    type Storage = AtomicU32;
+    type Raw = f32;
+
+    fn new_storage(value: Self) -> Self::Storage {
+        AtomicU32::new(value.to_bits())
+    }
+    #[inline]
+    fn load(storage: &Self::Storage, order: Ordering) -> Self {
+        f32::from_bits(storage.load(order))
+    }
+    #[inline]
+    fn store(storage: &Self::Storage, value: Self, order: Ordering) {
+        storage.store(value.to_bits(), order)
+    }
+    fn to_raw(self) -> Self::Raw {
+        self
+    }
+    fn from_raw(raw: Self::Raw) -> Self {
+        raw
+    }
+}
+
+/// CoreAudio represents booleans as a `UInt32`, any nonzero value is `true`
+impl AtomicValue for bool {
+    type Storage = AtomicBool;
+    type Raw = u32;
+
+    fn new_storage(value: Self) -> Self::Storage {
+        AtomicBool::new(value)
+    }
+    #[inline]
+    fn load(storage: &Self::Storage, order: Ordering) -> Self {
+        storage.load(order)
+    }
+    #[inline]
+    fn store(storage: &Self::Storage, value: Self, order: Ordering) {
+        storage.store(value, order)
+    }
+    fn to_raw(self) -> Self::Raw {
+        self as u32
+    }
+    fn from_raw(raw: Self::Raw) -> Self {
+        raw != 0
+    }
+}
+
+/// A property backed by an atomic, for values that are written by property setters on arbitrary threads but read on the
+/// real-time IO path (volume, mute, is-running, ...), where taking a lock is not an option.
+///
+/// The value lives behind an [`Arc`], so the IO side can hold on to an [`AtomicHandle`] (see [`AtomicProp::handle`])
+/// while the property itself stays in the object tree.
+///
+/// # Memory ordering
+/// Writes (from the HAL or through [`AtomicProp::store`]) use [`Ordering::Release`]. [`AtomicProp::load`] and
+/// [`AtomicHandle::load`] use [`Ordering::Relaxed`]: they always observe some whole value that was written, but make no
+/// guarantees about other memory. Use `load_acquire` if the reader needs to see everything the writer did before storing
+///
+/// The value seen through [`RawPropertyExt::value`](super::RawPropertyExt::value) is the shared `Arc<A::Storage>`
+pub struct AtomicProp<A: AtomicValue, const SEL: u32, const MUTABLE_PROP: bool = false> {
+    value: Arc<A::Storage>,
+}
+
+impl<A: AtomicValue, const SEL: u32, const MUTABLE_PROP: bool> AtomicProp<A, SEL, MUTABLE_PROP> {
+    pub fn new(value: A) -> Self {
+        Self {
+            value: Arc::new(A::new_storage(value)),
+        }
+    }
+    /// Get a handle to the value that can be moved to the IO thread
+    pub fn handle(&self) -> AtomicHandle<A> {
+        AtomicHandle {
+            value: self.value.clone(),
+        }
+    }
+    /// Read the value with [`Ordering::Relaxed`], cheap enough for `do_io_operation`
+    #[inline]
+    pub fn load(&self) -> A {
+        A::load(&self.value, Ordering::Relaxed)
+    }
+    #[inline]
+    pub fn load_acquire(&self) -> A {
+        A::load(&self.value, Ordering::Acquire)
+    }
+    /// Write the value with [`Ordering::Release`]
+    #[inline]
+    pub fn store(&self, value: A) {
+        A::store(&self.value, value, Ordering::Release)
+    }
+}
+
+impl<A: AtomicValue + Default, const SEL: u32, const MUTABLE_PROP: bool> Default
+    for AtomicProp<A, SEL, MUTABLE_PROP>
+{
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+impl<A: AtomicValue + Debug, const SEL: u32, const MUTABLE_PROP: bool> Debug
+    for AtomicProp<A, SEL, MUTABLE_PROP>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicProp").field(&self.load()).finish()
+    }
+}
+
+impl<A: AtomicValue, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
+    for AtomicProp<A, SEL, MUTABLE_PROP>
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<A::Raw>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        // the atomic is shared with the handles and can't be borrowed mutably, so hand out the Arc both ways
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        let raw: A::Raw = unsafe { read_value(data, data_size)? };
+        self.store(A::from_raw(raw));
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            write_value(
+                self.load_acquire().to_raw(),
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+}
+
+/// A shared handle to the value of an [`AtomicProp`], see the memory ordering notes there
+pub struct AtomicHandle<A: AtomicValue> {
+    value: Arc<A::Storage>,
+}
+
+impl<A: AtomicValue> Clone for AtomicHandle<A> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<A: AtomicValue> AtomicHandle<A> {
+    /// A value that isn't backed by a property (yet), for building properties that share it
+    pub fn new(value: A) -> Self {
+        Self {
+            value: Arc::new(A::new_storage(value)),
+        }
+    }
+    #[inline]
+    pub fn load(&self) -> A {
+        A::load(&self.value, Ordering::Relaxed)
+    }
+    #[inline]
+    pub fn load_acquire(&self) -> A {
+        A::load(&self.value, Ordering::Acquire)
+    }
+    #[inline]
+    pub fn store(&self, value: A) {
+        A::store(&self.value, value, Ordering::Release)
+    }
+}
+
+impl<A: AtomicValue + Debug> Debug for AtomicHandle<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicHandle").field(&self.load()).finish()
+    }
+}
+
+/// Storage of a [`SeqlockProp`]: the value plus a sequence counter that is odd while a write is in progress
+struct Seqlock<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+    /// Serializes writers, readers never touch it
+    writer: Mutex<()>,
+}
+
+// SAFETY: the value is only written while holding the writer lock, and readers only keep copies that were read while the
+// sequence counter didn't change
+unsafe impl<T: Copy + Send> Sync for Seqlock<T> {}
+
+impl<T: Copy> Seqlock<T> {
+    fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+            writer: Mutex::new(()),
+        }
+    }
+    #[inline]
+    fn read(&self) -> T {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            // SAFETY: the read may race with a writer, in which case the sequence check below discards it
+            let value = unsafe { ptr::read_volatile(self.value.get()) };
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return value;
+            }
+            hint::spin_loop();
+        }
+    }
+    fn write(&self, value: T) {
+        let _guard = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        // SAFETY: writers are serialized by the lock, readers retry since the counter is odd
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// A property for multi-word `Copy` values (a stream format, a pair of zero timestamp anchors, ...) that are written from
+/// control code but read on the real-time IO path.
+///
+/// Readers never block or take locks: [`SeqlockProp::read`] retries until it gets a copy that wasn't torn by a concurrent
+/// write, which is cheap as long as writes are rare. Writers are serialized by a lock, so [`SeqlockProp::write`] must not
+/// be called from real-time code. As with [`AtomicProp`], the value can be shared with the IO side through a
+/// [`SeqlockHandle`]
+pub struct SeqlockProp<T, const SEL: u32, const MUTABLE_PROP: bool = false> {
+    value: Arc<Seqlock<T>>,
+}
+
+impl<T: Copy + Send, const SEL: u32, const MUTABLE_PROP: bool> SeqlockProp<T, SEL, MUTABLE_PROP> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: Arc::new(Seqlock::new(value)),
+        }
+    }
+    /// Get a handle to the value that can be moved to the IO thread
+    pub fn handle(&self) -> SeqlockHandle<T> {
+        SeqlockHandle {
+            value: self.value.clone(),
+        }
+    }
+    /// Get a consistent copy of the value without blocking
+    #[inline]
+    pub fn read(&self) -> T {
+        self.value.read()
+    }
+    /// Replace the value. Not real-time safe
+    pub fn write(&self, value: T) {
+        self.value.write(value)
+    }
+}
+
+impl<T: Copy + Send + Debug, const SEL: u32, const MUTABLE_PROP: bool> Debug
+    for SeqlockProp<T, SEL, MUTABLE_PROP>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SeqlockProp").field(&self.read()).finish()
+    }
+}
+
+impl<T: Copy + Send + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
+    for SeqlockProp<T, SEL, MUTABLE_PROP>
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<T>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        // the value can't be borrowed, so hand out the shared storage
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        self.write(unsafe { read_value(data, data_size)? });
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.read(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// A shared handle to the value of a [`SeqlockProp`]
+pub struct SeqlockHandle<T> {
+    value: Arc<Seqlock<T>>,
+}
+
+impl<T> Clone for SeqlockHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: Copy + Send> SeqlockHandle<T> {
+    /// Get a consistent copy of the value without blocking
+    #[inline]
+    pub fn read(&self) -> T {
+        self.value.read()
+    }
+    /// Replace the value. Not real-time safe
+    pub fn write(&self, value: T) {
+        self.value.write(value)
+    }
+}
+
+impl<T: Copy + Send + Debug> Debug for SeqlockHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SeqlockHandle").field(&self.read()).finish()
+    }
+}
+
+/// The pid stored by a [`HogModeProp`] while no process has hog mode
+pub const HOG_MODE_FREE: pid_t = -1;
+
+/// `kAudioDevicePropertyHogMode`: the pid of the process with exclusive access to a device, or [`HOG_MODE_FREE`].
+///
+/// Writes have compare-and-set semantics and only work through [`RawProperty::set_by_client`]: a client can take hog
+/// mode for itself while it is free, and only the hogging client can give it back by writing [`HOG_MODE_FREE`]. Writes
+/// that don't say who is asking fail with [`OSStatusError::DEV_PERMISSIONS_ERR`].
+///
+/// The client pid alone doesn't tell whether the client is privileged, so no client can release hog mode on behalf of
+/// another one. The driver overrides the hogging client with [`HogModeProp::release`] instead, e.g. when that process
+/// exits.
+///
+/// The driver can check [`HogModeProp::hogging_pid`] (or a [`HogModeProp::handle`] on the IO path) to deny IO to other
+/// clients. The value seen through [`RawPropertyExt::value`](super::RawPropertyExt::value) is the shared
+/// `Arc<AtomicI32>`
+pub struct HogModeProp<const SEL: u32> {
+    pid: Arc<AtomicI32>,
+}
+
+impl<const SEL: u32> HogModeProp<SEL> {
+    /// Hog mode that no process has taken yet
+    pub fn new() -> Self {
+        Self {
+            pid: Arc::new(AtomicI32::new(HOG_MODE_FREE)),
+        }
+    }
+    /// The process that has hog mode, if any
+    pub fn hogging_pid(&self) -> Option<pid_t> {
+        Some(self.pid.load(Ordering::Acquire)).filter(|&pid| pid != HOG_MODE_FREE)
+    }
+    /// A handle to the hogging pid for the IO path. It holds [`HOG_MODE_FREE`] while no process has hog mode
+    pub fn handle(&self) -> AtomicHandle<i32> {
+        AtomicHandle {
+            value: self.pid.clone(),
+        }
+    }
+    /// Take hog mode away from whichever process has it, e.g. because it died. This is the only way to release hog mode
+    /// on behalf of another process. Returns that process
+    pub fn release(&self) -> Option<pid_t> {
+        Some(self.pid.swap(HOG_MODE_FREE, Ordering::AcqRel)).filter(|&pid| pid != HOG_MODE_FREE)
+    }
+    /// Apply a write of `pid` by the process `client_pid`, see the type documentation for the rules
+    pub fn request(&self, client_pid: pid_t, pid: pid_t) -> OSStatus {
+        if pid == HOG_MODE_FREE {
+            let result = self
+                .pid
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                    (current == client_pid).then_some(HOG_MODE_FREE)
+                });
+            return match result {
+                Ok(_) => Ok(()),
+                Err(HOG_MODE_FREE) => Ok(()),
+                Err(_) => Err(OSStatusError::DEV_PERMISSIONS_ERR),
+            };
+        }
+        if pid != client_pid {
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        match self
+            .pid
+            .compare_exchange(HOG_MODE_FREE, pid, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => Ok(()),
+            Err(current) if current == pid => Ok(()),
+            Err(_) => Err(OSStatusError::DEV_PERMISSIONS_ERR),
+        }
+    }
+}
+
+impl<const SEL: u32> Default for HogModeProp<SEL> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SEL: u32> Debug for HogModeProp<SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HogModeProp")
+            .field(&self.hogging_pid())
+            .finish()
+    }
+}
+
+impl<const SEL: u32> RawProperty for HogModeProp<SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<pid_t>() as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.pid
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.pid
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        Err(OSStatusError::DEV_PERMISSIONS_ERR)
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let pid = self.pid.load(Ordering::Acquire);
+        unsafe { write_value(pid, out_alloc_size, data_out, data_len_out) }
+    }
+
+    unsafe fn set_by_client(
+        &mut self,
+        client_pid: pid_t,
+        _address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let pid: pid_t = unsafe { read_value(data, data_size)? };
+        self.request(client_pid, pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, sync::Barrier, thread};
+
+    use coreaudio_sys::{
+        kAudioDevicePermissionsError, kAudioDevicePropertyDeviceIsRunning,
+        kAudioDevicePropertyHogMode, kAudioHardwareIllegalOperationError,
+    };
+
+    use super::*;
+    use crate::os_err::result_to_err_code;
+    use crate::property::RawPropertyExt;
+
+    #[test]
+    fn atomic_prop_exposes_the_shared_storage() {
+        let mut prop = AtomicProp::<bool, kAudioDevicePropertyDeviceIsRunning>::new(false);
+        let handle = prop.handle();
+        prop.value::<Arc<AtomicBool>>()
+            .unwrap()
+            .store(true, Ordering::Release);
+        assert!(handle.load());
+        prop.value_mut::<Arc<AtomicBool>>()
+            .unwrap()
+            .store(false, Ordering::Release);
+        assert!(!prop.load());
+
+        assert!(prop.value::<AtomicBool>().is_none());
+        assert!(prop.value_mut::<AtomicBool>().is_none());
+        assert!(prop.value::<bool>().is_none());
+        assert!(prop.value_mut::<Arc<AtomicU32>>().is_none());
+    }
+
+    /// A selector for values that aren't any property of the HAL
+    const TEST_SELECTOR: u32 = u32::from_be_bytes(*b"test");
+
+    #[test]
+    fn atomic_prop_readers_see_every_write_in_order() {
+        const WRITES: u32 = 20_000;
+        let mut prop = AtomicProp::<f32, TEST_SELECTOR, true>::new(0.0);
+        let handle = prop.handle();
+        let start = Barrier::new(3);
+        thread::scope(|scope| {
+            for acquire in [false, true] {
+                let handle = handle.clone();
+                let start = &start;
+                scope.spawn(move || {
+                    start.wait();
+                    let mut last = 0.0;
+                    while last < WRITES as f32 {
+                        let value = if acquire {
+                            handle.load_acquire()
+                        } else {
+                            handle.load()
+                        };
+                        // only whole values that were written, never older than one seen before
+                        assert_eq!(value.fract(), 0.0, "{value}");
+                        assert!(
+                            (last..=WRITES as f32).contains(&value),
+                            "{value} after {last}"
+                        );
+                        last = value;
+                    }
+                });
+            }
+            start.wait();
+            // the HAL writes on its own thread
+            for i in 1..=WRITES {
+                let value = i as f32;
+                let result = unsafe {
+                    prop.set((&value as *const f32).cast(), mem::size_of::<f32>() as u32)
+                };
+                assert!(result.is_ok());
+            }
+        });
+        assert_eq!(prop.load(), WRITES as f32);
+    }
+
+    #[test]
+    fn seqlock_reads_are_never_torn() {
+        const WRITES: u64 = 100_000;
+        // every word of a written value is the same, so a torn read mixes words of different writes
+        let prop = SeqlockProp::<[u64; 64], TEST_SELECTOR>::new([0; 64]);
+        let handle = prop.handle();
+        let start = Barrier::new(3);
+        thread::scope(|scope| {
+            for _ in 0..2 {
+                let handle = handle.clone();
+                let start = &start;
+                scope.spawn(move || {
+                    start.wait();
+                    let mut last = 0;
+                    while last < WRITES {
+                        let value = handle.read();
+                        assert!(value.iter().all(|&word| word == value[0]), "{value:?}");
+                        // writes are seen in order
+                        assert!(value[0] >= last);
+                        last = value[0];
+                    }
+                });
+            }
+            start.wait();
+            for i in 1..=WRITES {
+                prop.write([i; 64]);
+            }
+        });
+        assert_eq!(prop.read(), [WRITES; 64]);
+    }
+
+    fn write(
+        prop: &mut HogModeProp<kAudioDevicePropertyHogMode>,
+        client_pid: pid_t,
+        pid: pid_t,
+    ) -> i32 {
+        let address = PropertyAddress::global(kAudioDevicePropertyHogMode);
+        result_to_err_code(unsafe {
+            prop.set_by_client(
+                client_pid,
+                address,
+                Qualifier::NONE,
+                (&pid as *const pid_t).cast(),
+                mem::size_of::<pid_t>() as u32,
+            )
+        })
+    }
+
+    #[test]
+    fn hog_mode_can_be_taken_while_free() {
+        let mut prop = HogModeProp::<kAudioDevicePropertyHogMode>::new();
+        assert_eq!(prop.hogging_pid(), None);
+        assert_eq!(write(&mut prop, 42, 42), 0);
+        assert_eq!(prop.hogging_pid(), Some(42));
+        // taking it again is a no-op
+        assert_eq!(write(&mut prop, 42, 42), 0);
+        assert_eq!(prop.hogging_pid(), Some(42));
+    }
+
+    #[test]
+    fn hog_mode_cant_be_stolen() {
+        let mut prop = HogModeProp::<kAudioDevicePropertyHogMode>::new();
+        assert_eq!(write(&mut prop, 42, 42), 0);
+        assert_eq!(write(&mut prop, 7, 7), kAudioDevicePermissionsError as i32);
+        assert_eq!(
+            write(&mut prop, 7, HOG_MODE_FREE),
+            kAudioDevicePermissionsError as i32
+        );
+        // a client can't take it on behalf of another one either
+        assert_eq!(
+            write(&mut prop, 7, 42),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        // pid 0 isn't privileged
+        assert_eq!(
+            write(&mut prop, 0, HOG_MODE_FREE),
+            kAudioDevicePermissionsError as i32
+        );
+        assert_eq!(prop.hogging_pid(), Some(42));
+        // writes without a client are refused
+        let pid = HOG_MODE_FREE;
+        assert_eq!(
+            result_to_err_code(unsafe {
+                prop.set(
+                    (&pid as *const pid_t).cast(),
+                    mem::size_of::<pid_t>() as u32,
+                )
+            }),
+            kAudioDevicePermissionsError as i32
+        );
+        assert_eq!(prop.hogging_pid(), Some(42));
+    }
+
+    #[test]
+    fn hog_mode_is_released_by_its_client_or_the_driver() {
+        let mut prop = HogModeProp::<kAudioDevicePropertyHogMode>::new();
+        assert_eq!(write(&mut prop, 42, 42), 0);
+        assert_eq!(write(&mut prop, 42, HOG_MODE_FREE), 0);
+        assert_eq!(prop.hogging_pid(), None);
+        // releasing it while free is a no-op
+        assert_eq!(write(&mut prop, 7, HOG_MODE_FREE), 0);
+
+        assert_eq!(write(&mut prop, 7, 7), 0);
+        let handle = prop.handle();
+        assert_eq!(prop.release(), Some(7));
+        assert_eq!(handle.load(), HOG_MODE_FREE);
+        assert_eq!(prop.release(), None);
+        assert_eq!(write(&mut prop, 42, 42), 0);
+    }
+
+    #[test]
+    fn hog_mode_exposes_the_shared_pid() {
+        let mut prop = HogModeProp::<kAudioDevicePropertyHogMode>::new();
+        prop.value_mut::<Arc<AtomicI32>>()
+            .unwrap()
+            .store(42, Ordering::Release);
+        assert_eq!(
+            prop.value::<Arc<AtomicI32>>()
+                .unwrap()
+                .load(Ordering::Acquire),
+            42
+        );
+        assert_eq!(prop.hogging_pid(), Some(42));
+
+        assert!(prop.value::<AtomicI32>().is_none());
+        assert!(prop.value_mut::<AtomicI32>().is_none());
+        assert!(prop.value::<pid_t>().is_none());
+    }
+}
@@ -0,0 +1,220 @@
+use std::{any::Any, collections::HashMap, fmt::Debug};
+
+use crate::audio_object::{visit_property, HasProperties};
+
+use super::{PropertyAddress, PropertySelector, RawProperty, RawPropertyExt};
+
+/// A property store keyed by selector, as an alternative to matching on the selector by hand in
+/// [`HasProperties::get_object_property`]. Lookups ignore the scope and element of the address
+#[derive(Default)]
+pub struct PropertyTable {
+    props: HashMap<PropertySelector, Box<dyn RawProperty + Send>>,
+}
+
+impl PropertyTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add `prop` under its own selector, returning the property it replaced, if any
+    pub fn insert(
+        &mut self,
+        prop: impl RawProperty + Send + 'static,
+    ) -> Option<Box<dyn RawProperty + Send>> {
+        self.insert_boxed(Box::new(prop))
+    }
+    pub fn insert_boxed(
+        &mut self,
+        prop: Box<dyn RawProperty + Send>,
+    ) -> Option<Box<dyn RawProperty + Send>> {
+        self.props.insert(prop.selector(), prop)
+    }
+    /// Builder style variant of [`PropertyTable::insert`]
+    pub fn with(mut self, prop: impl RawProperty + Send + 'static) -> Self {
+        self.insert(prop);
+        self
+    }
+    pub fn remove(
+        &mut self,
+        selector: impl Into<PropertySelector>,
+    ) -> Option<Box<dyn RawProperty + Send>> {
+        self.props.remove(&selector.into())
+    }
+    pub fn contains(&self, selector: impl Into<PropertySelector>) -> bool {
+        self.props.contains_key(&selector.into())
+    }
+    pub fn get(&self, selector: impl Into<PropertySelector>) -> Option<&dyn RawProperty> {
+        self.props
+            .get(&selector.into())
+            .map(|prop| prop.as_ref() as &dyn RawProperty)
+    }
+    pub fn get_mut(
+        &mut self,
+        selector: impl Into<PropertySelector>,
+    ) -> Option<&mut dyn RawProperty> {
+        self.props
+            .get_mut(&selector.into())
+            .map(|prop| prop.as_mut() as &mut dyn RawProperty)
+    }
+    /// Get the value of the property at `selector` if it is a `T`, see [`RawPropertyExt::value`]
+    pub fn get_as<T: Any>(&self, selector: impl Into<PropertySelector>) -> Option<&T> {
+        self.get(selector)?.value()
+    }
+    /// Mutable variant of [`PropertyTable::get_as`]
+    pub fn get_as_mut<T: Any>(&mut self, selector: impl Into<PropertySelector>) -> Option<&mut T> {
+        self.get_mut(selector)?.value_mut()
+    }
+    /// Iterate over the selectors of all properties in the table, in no particular order
+    pub fn selectors(&self) -> impl Iterator<Item = PropertySelector> + '_ {
+        self.props.keys().copied()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (PropertySelector, &dyn RawProperty)> {
+        self.props
+            .iter()
+            .map(|(sel, prop)| (*sel, prop.as_ref() as &dyn RawProperty))
+    }
+    pub fn len(&self) -> usize {
+        self.props.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.props.is_empty()
+    }
+}
+
+impl Debug for PropertyTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.props.keys()).finish()
+    }
+}
+
+impl HasProperties for PropertyTable {
+    fn get_object_property(&self, address: PropertyAddress) -> Option<&dyn RawProperty> {
+        self.get(address.selector)
+    }
+
+    fn get_object_property_mut(
+        &mut self,
+        address: PropertyAddress,
+    ) -> Option<&mut dyn RawProperty> {
+        self.get_mut(address.selector)
+    }
+
+    fn visit_properties(&self, visitor: &mut dyn FnMut(PropertyAddress, &dyn RawProperty)) {
+        for (_, prop) in self.iter() {
+            visit_property(prop, visitor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioDeviceClassID, kAudioDevicePropertyLatency, kAudioDevicePropertySafetyOffset,
+        kAudioObjectClassID, kAudioObjectPlugInObject, kAudioObjectPropertyBaseClass,
+        kAudioObjectPropertyClass, kAudioObjectPropertyName, kAudioObjectPropertyOwnedObjects,
+        kAudioObjectPropertyOwner,
+    };
+
+    use crate::{
+        audio_object::AudioObjectBase,
+        property::{Prop, PropertyScope},
+    };
+
+    use super::*;
+
+    fn table() -> PropertyTable {
+        PropertyTable::new()
+            .with(Prop::<u32, kAudioDevicePropertyLatency>(10))
+            .with(Prop::<u32, kAudioDevicePropertySafetyOffset, true>(20))
+    }
+
+    #[test]
+    fn values_are_looked_up_by_selector() {
+        let mut table = table();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_as::<u32>(kAudioDevicePropertyLatency), Some(&10));
+        assert_eq!(table.get_as::<f64>(kAudioDevicePropertyLatency), None);
+        assert!(table.get(kAudioObjectPropertyClass).is_none());
+        *table
+            .get_as_mut::<u32>(kAudioDevicePropertySafetyOffset)
+            .unwrap() = 30;
+        assert_eq!(
+            table.get_as::<u32>(kAudioDevicePropertySafetyOffset),
+            Some(&30)
+        );
+        assert!(table
+            .get_mut(kAudioDevicePropertySafetyOffset)
+            .unwrap()
+            .is_mut());
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_removes() {
+        let mut table = table();
+        let replaced = table
+            .insert(Prop::<u32, kAudioDevicePropertyLatency>(11))
+            .unwrap();
+        assert_eq!(replaced.value::<u32>(), Some(&10));
+        assert_eq!(table.get_as::<u32>(kAudioDevicePropertyLatency), Some(&11));
+        assert!(table.remove(kAudioDevicePropertyLatency).is_some());
+        assert!(!table.contains(kAudioDevicePropertyLatency));
+        assert!(table.remove(kAudioDevicePropertyLatency).is_none());
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn lookups_ignore_scope_and_element() {
+        let table = table();
+        let address = PropertyAddress::scoped(kAudioDevicePropertyLatency, PropertyScope::OUTPUT);
+        let prop = table.get_object_property(address).unwrap();
+        assert_eq!(prop.value::<u32>(), Some(&10));
+    }
+
+    #[test]
+    fn every_selector_is_visited() {
+        let table = table();
+        let mut selectors: Vec<u32> = table.selectors().map(u32::from).collect();
+        selectors.sort_unstable();
+        let mut expected = vec![
+            kAudioDevicePropertyLatency,
+            kAudioDevicePropertySafetyOffset,
+        ];
+        expected.sort_unstable();
+        assert_eq!(selectors, expected);
+        let mut visited = Vec::new();
+        table.visit_properties(&mut |address, prop| {
+            assert_eq!(address, PropertyAddress::anywhere(prop.selector()));
+            visited.push(u32::from(address.selector));
+        });
+        visited.sort_unstable();
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn object_base_converts_into_a_table() {
+        let base = AudioObjectBase::new(
+            kAudioObjectClassID,
+            kAudioDeviceClassID,
+            kAudioObjectPlugInObject,
+            "Device",
+        );
+        let table = PropertyTable::from(base);
+        assert_eq!(table.len(), 5);
+        for selector in [
+            kAudioObjectPropertyBaseClass,
+            kAudioObjectPropertyClass,
+            kAudioObjectPropertyOwner,
+            kAudioObjectPropertyOwnedObjects,
+            kAudioObjectPropertyName,
+        ] {
+            assert!(table.contains(selector));
+        }
+        assert_eq!(
+            table.get_as::<u32>(kAudioObjectPropertyClass),
+            Some(&kAudioDeviceClassID)
+        );
+        assert_eq!(
+            table.get_as::<u32>(kAudioObjectPropertyOwner),
+            Some(&kAudioObjectPlugInObject)
+        );
+    }
+}
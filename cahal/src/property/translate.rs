@@ -0,0 +1,207 @@
+use std::{any::Any, ffi::c_void, marker::PhantomData, mem};
+
+use core_foundation::string::CFString;
+use coreaudio_sys::{kAudioObjectUnknown, AudioObjectID};
+
+use crate::os_err::{OSResult, OSStatus, OSStatusError};
+
+use super::{probe_size, read_value, write_value, PropertySelector, Qualifier, RawProperty};
+
+/// A value that a [`TranslationProp`] can take as its input from the qualifier of a request
+pub trait TranslationInput: Sized {
+    /// Decode the qualifier, `None` if it has the wrong size or is otherwise unusable
+    fn from_qualifier(qualifier: Qualifier<'_>) -> Option<Self>;
+}
+
+impl TranslationInput for CFString {
+    fn from_qualifier(qualifier: Qualifier<'_>) -> Option<Self> {
+        qualifier.cf_string()
+    }
+}
+
+macro_rules! copy_translation_input {
+    ($($ty:ty),*) => {
+        $(impl TranslationInput for $ty {
+            fn from_qualifier(qualifier: Qualifier<'_>) -> Option<Self> {
+                qualifier.read()
+            }
+        })*
+    };
+}
+copy_translation_input!(u32, i32, f32, f64);
+
+type TranslateFn<I, O> = Box<dyn Fn(I) -> OSResult<O> + Send + Sync>;
+
+/// A read-only property whose value is computed from the qualifier of each request rather than stored, like
+/// `kAudioPlugInPropertyTranslateUIDToDevice` or `kAudioBoxPropertyTranslateUIDToBox`.
+///
+/// Requests without a qualifier of the right size fail with [`OSStatusError::HW_BAD_PROPERTY_SIZE_ERR`], errors returned
+/// by the translation function are passed on to the HAL
+pub struct TranslationProp<I, O, const SEL: u32> {
+    translate: TranslateFn<I, O>,
+    _boo: PhantomData<fn(I) -> O>,
+}
+
+/// Translates a UID to the id of the object that has it, see [`TranslationProp::uid_to_object`]
+pub type UidTranslationProp<const SEL: u32> = TranslationProp<CFString, AudioObjectID, SEL>;
+
+impl<I, O, const SEL: u32> TranslationProp<I, O, SEL> {
+    pub fn new(translate: impl Fn(I) -> OSResult<O> + Send + Sync + 'static) -> Self {
+        Self {
+            translate: Box::new(translate),
+            _boo: PhantomData,
+        }
+    }
+    /// Run the translation from Rust
+    pub fn translate(&self, input: I) -> OSResult<O> {
+        (self.translate)(input)
+    }
+}
+
+impl<const SEL: u32> UidTranslationProp<SEL> {
+    /// Translate UIDs with `lookup`. Following the HAL's convention, unknown UIDs translate to `kAudioObjectUnknown`
+    /// rather than failing
+    pub fn uid_to_object(
+        lookup: impl Fn(&str) -> Option<AudioObjectID> + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(move |uid: CFString| Ok(lookup(&uid.to_string()).unwrap_or(kAudioObjectUnknown)))
+    }
+}
+
+impl<I, O, const SEL: u32> std::fmt::Debug for TranslationProp<I, O, SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranslationProp")
+            .field("selector", &PropertySelector::new(SEL))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I, O, const SEL: u32> RawProperty for TranslationProp<I, O, SEL>
+where
+    I: TranslationInput + 'static,
+    O: Copy + 'static,
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<O>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.get_qualified(Qualifier::NONE, out_alloc_size, data_out, data_len_out) }
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(self.byte_size(), data_out, data_len_out)? } {
+            return Ok(());
+        }
+        let Some(input) = I::from_qualifier(qualifier) else {
+            log::error!(
+                "bad qualifier of {} bytes for {}",
+                qualifier.size(),
+                self.selector()
+            );
+            return Err(OSStatusError::HW_BAD_PROPERTY_SIZE_ERR);
+        };
+        let output = (self.translate)(input)?;
+        unsafe { write_value(output, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+/// A read-only property that converts a value passed in the data buffer itself, writing the result back in place, like
+/// `kAudioLevelControlPropertyConvertScalarToDecibels`. No qualifier is involved
+pub struct ConversionProp<T, const SEL: u32> {
+    convert: TranslateFn<T, T>,
+}
+
+impl<T, const SEL: u32> ConversionProp<T, SEL> {
+    pub fn new(convert: impl Fn(T) -> OSResult<T> + Send + Sync + 'static) -> Self {
+        Self {
+            convert: Box::new(convert),
+        }
+    }
+    /// Run the conversion from Rust
+    pub fn convert(&self, input: T) -> OSResult<T> {
+        (self.convert)(input)
+    }
+}
+
+impl<T, const SEL: u32> std::fmt::Debug for ConversionProp<T, SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConversionProp")
+            .field("selector", &PropertySelector::new(SEL))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Copy + 'static, const SEL: u32> RawProperty for ConversionProp<T, SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<T>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        if unsafe { probe_size(self.byte_size(), data_out, data_len_out)? } {
+            return Ok(());
+        }
+        // the input is whatever the caller put in the buffer
+        let input: T = unsafe { read_value(data_out, out_alloc_size)? };
+        let output = (self.convert)(input)?;
+        unsafe { write_value(output, out_alloc_size, data_out, data_len_out) }
+    }
+}
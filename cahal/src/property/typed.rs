@@ -0,0 +1,508 @@
+use std::{any::Any, ffi::c_void, fmt::Debug};
+
+use crate::os_err::{OSResult, OSStatus, OSStatusError};
+
+use super::{
+    read_value, write_value, PropertyAddress, PropertyElement, PropertySelector, Qualifier,
+    RawProperty, TypedProperty,
+};
+
+/// A boolean property. CoreAudio transports booleans as a `UInt32`: the HAL reads `0` or `1`, and any nonzero value it
+/// writes is taken as `true`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BoolProp<const SEL: u32, const MUTABLE_PROP: bool = false>(pub bool);
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> BoolProp<SEL, MUTABLE_PROP> {
+    pub fn new(value: bool) -> Self {
+        Self(value)
+    }
+    pub fn get(&self) -> bool {
+        self.0
+    }
+    /// Replace the value from Rust, returning the previous one
+    pub fn set(&mut self, value: bool) -> bool {
+        std::mem::replace(&mut self.0, value)
+    }
+    /// Flip the value, returning the new one
+    pub fn toggle(&mut self) -> bool {
+        self.0 = !self.0;
+        self.0
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for BoolProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<u32>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        self.0 = unsafe { self.decode(data, data_size)? };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.0 as u32, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> TypedProperty for BoolProp<SEL, MUTABLE_PROP> {
+    type Value = bool;
+
+    fn value(&self) -> &bool {
+        &self.0
+    }
+
+    fn replace_value(&mut self, value: bool) -> bool {
+        self.set(value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<bool> {
+        let raw: u32 = unsafe { read_value(data, data_size)? };
+        Ok(raw != 0)
+    }
+}
+
+/// A property whose `UInt32` value is drawn from a closed set, represented by `E` on the Rust side (see [`property_enum!`]
+/// for a convenient way to define one). Writes of values that don't map to a variant of `E` are rejected with
+/// [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`]
+///
+/// [`property_enum!`]: crate::property_enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnumProp<E, const SEL: u32, const MUTABLE_PROP: bool = false>(pub E);
+
+impl<E: Copy, const SEL: u32, const MUTABLE_PROP: bool> EnumProp<E, SEL, MUTABLE_PROP> {
+    pub fn new(value: E) -> Self {
+        Self(value)
+    }
+    pub fn get(&self) -> E {
+        self.0
+    }
+    /// Replace the value from Rust, returning the previous one
+    pub fn set(&mut self, value: E) -> E {
+        std::mem::replace(&mut self.0, value)
+    }
+}
+
+impl<E, const SEL: u32, const MUTABLE_PROP: bool> RawProperty for EnumProp<E, SEL, MUTABLE_PROP>
+where
+    E: Copy + Into<u32> + TryFrom<u32> + 'static,
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<u32>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.0
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        self.0 = unsafe { self.decode(data, data_size)? };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.0.into(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+impl<E, const SEL: u32, const MUTABLE_PROP: bool> TypedProperty for EnumProp<E, SEL, MUTABLE_PROP>
+where
+    E: Copy + Into<u32> + TryFrom<u32> + 'static,
+{
+    type Value = E;
+
+    fn value(&self) -> &E {
+        &self.0
+    }
+
+    fn replace_value(&mut self, value: E) -> E {
+        self.set(value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<E> {
+        let raw: u32 = unsafe { read_value(data, data_size)? };
+        E::try_from(raw).map_err(|_| {
+            log::error!(
+                "rejected write of {} to {}: not a valid value",
+                PropertySelector::new(raw),
+                self.selector()
+            );
+            OSStatusError::HW_ILLEGAL_OPERATION_ERR
+        })
+    }
+}
+
+/// Define a fieldless enum backed by `UInt32` property values (typically four-char codes), for use with [`EnumProp`].
+///
+/// Generates `From<Enum> for u32` and `TryFrom<u32> for Enum`, which fails with the unknown value.
+/// ```
+/// # use cahal::{four_cc, property_enum};
+/// property_enum! {
+///     #[derive(Debug)]
+///     pub enum Source {
+///         Line = four_cc!("line"),
+///         Mic = four_cc!("mic "),
+///     }
+/// }
+/// assert_eq!(Source::try_from(four_cc!("mic ")), Ok(Source::Mic));
+/// assert_eq!(Source::try_from(0), Err(0));
+/// ```
+#[macro_export]
+macro_rules! property_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$vmeta:meta])* $variant:ident = $value:expr),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u32)]
+        $vis enum $name {
+            $($(#[$vmeta])* $variant = $value),*
+        }
+        impl ::core::convert::From<$name> for u32 {
+            fn from(value: $name) -> u32 {
+                value as u32
+            }
+        }
+        impl ::core::convert::TryFrom<u32> for $name {
+            type Error = u32;
+
+            fn try_from(value: u32) -> ::core::result::Result<Self, u32> {
+                $(
+                    if value == $name::$variant as u32 {
+                        return ::core::result::Result::Ok($name::$variant);
+                    }
+                )*
+                ::core::result::Result::Err(value)
+            }
+        }
+    };
+}
+
+/// A pair of channel numbers, like `kAudioDevicePropertyPreferredChannelsForStereo`. Channel numbers start at 1.
+///
+/// If a channel count is configured (see [`ChannelPairProp::with_channel_count`]), writes naming a channel outside of
+/// `1..=channel_count` are rejected with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelPairProp<const SEL: u32, const MUTABLE_PROP: bool = false> {
+    channels: [u32; 2],
+    channel_count: Option<u32>,
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> ChannelPairProp<SEL, MUTABLE_PROP> {
+    pub fn new(left: u32, right: u32) -> Self {
+        Self {
+            channels: [left, right],
+            channel_count: None,
+        }
+    }
+    /// Only accept channel numbers up to and including `channel_count` from the HAL
+    pub fn with_channel_count(mut self, channel_count: u32) -> Self {
+        self.channel_count = Some(channel_count);
+        self
+    }
+    pub fn set_channel_count(&mut self, channel_count: Option<u32>) {
+        self.channel_count = channel_count;
+    }
+    pub fn channel_count(&self) -> Option<u32> {
+        self.channel_count
+    }
+    pub fn left(&self) -> u32 {
+        self.channels[0]
+    }
+    pub fn right(&self) -> u32 {
+        self.channels[1]
+    }
+    pub fn channels(&self) -> [u32; 2] {
+        self.channels
+    }
+    /// Replace the pair from Rust, without checking it against the channel count
+    pub fn set(&mut self, left: u32, right: u32) {
+        self.channels = [left, right];
+    }
+    fn is_valid_channel(&self, channel: u32) -> bool {
+        channel >= 1 && self.channel_count.is_none_or(|count| channel <= count)
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> RawProperty for ChannelPairProp<SEL, MUTABLE_PROP> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<[u32; 2]>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.channels
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.channels
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        self.channels = unsafe { self.decode(data, data_size)? };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.channels, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+impl<const SEL: u32, const MUTABLE_PROP: bool> TypedProperty
+    for ChannelPairProp<SEL, MUTABLE_PROP>
+{
+    type Value = [u32; 2];
+
+    fn value(&self) -> &[u32; 2] {
+        &self.channels
+    }
+
+    fn replace_value(&mut self, value: [u32; 2]) -> [u32; 2] {
+        std::mem::replace(&mut self.channels, value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<[u32; 2]> {
+        let channels: [u32; 2] = unsafe { read_value(data, data_size)? };
+        ret_assert!(
+            channels.iter().all(|c| self.is_valid_channel(*c)),
+            OSStatusError::HW_ILLEGAL_OPERATION_ERR
+        );
+        Ok(channels)
+    }
+}
+
+/// A property with a distinct value per element, like the volume of each channel of a device. Index 0 holds the value of
+/// the main element, index `n` the value of channel `n`.
+///
+/// Requests are resolved by the element of their address (see [`RawProperty::get_at`]), the plain accessors use the main
+/// element. Requests for an element without a value fail with [`OSStatusError::HW_BAD_OBJECT_ERR`]
+#[derive(Debug, Clone, Default)]
+pub struct ElementProp<T, const SEL: u32, const MUTABLE_PROP: bool = false> {
+    values: Vec<T>,
+}
+
+impl<T, const SEL: u32, const MUTABLE_PROP: bool> ElementProp<T, SEL, MUTABLE_PROP> {
+    /// `values[0]` is the main element, `values[n]` channel `n`
+    pub fn new(values: Vec<T>) -> Self {
+        Self { values }
+    }
+    /// The same value on the main element and each of `channels` channels
+    pub fn uniform(value: T, channels: u32) -> Self
+    where
+        T: Clone,
+    {
+        Self::new(vec![value; channels as usize + 1])
+    }
+    /// Number of elements with a value, including the main element
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn element(&self, element: PropertyElement) -> Option<&T> {
+        self.values.get(Self::index(element)?)
+    }
+    pub fn element_mut(&mut self, element: PropertyElement) -> Option<&mut T> {
+        self.values.get_mut(Self::index(element)?)
+    }
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+    pub fn values_mut(&mut self) -> &mut Vec<T> {
+        &mut self.values
+    }
+    fn index(element: PropertyElement) -> Option<usize> {
+        if element.is_wildcard() {
+            return None;
+        }
+        Some(u32::from(element) as usize)
+    }
+}
+
+impl<T: Clone + 'static, const SEL: u32, const MUTABLE_PROP: bool> RawProperty
+    for ElementProp<T, SEL, MUTABLE_PROP>
+{
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        std::mem::size_of::<T>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        MUTABLE_PROP
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.values
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.values
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let address = PropertyAddress::global(SEL);
+        unsafe { self.set_at(address, Qualifier::NONE, data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let address = PropertyAddress::global(SEL);
+        unsafe {
+            self.get_at(
+                address,
+                Qualifier::NONE,
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        ret_assert!(self.is_mut());
+        let value = unsafe { read_value(data, data_size)? };
+        let Some(slot) = self.element_mut(address.element) else {
+            return Err(OSStatusError::HW_BAD_OBJECT_ERR);
+        };
+        *slot = value;
+        Ok(())
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(value) = self.element(address.element) else {
+            return Err(OSStatusError::HW_BAD_OBJECT_ERR);
+        };
+        unsafe { write_value(value.clone(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{kAudioDevicePropertyDeviceIsAlive, kAudioHardwareBadPropertySizeError};
+
+    use super::*;
+    use crate::os_err::result_to_err_code;
+
+    type Alive = BoolProp<kAudioDevicePropertyDeviceIsAlive, true>;
+
+    /// What the HAL reads from `prop`
+    fn read(prop: &Alive) -> u32 {
+        let mut value = u32::MAX;
+        let mut written = 0;
+        unsafe { RawProperty::get(prop, 4, (&raw mut value).cast(), &mut written) }.unwrap();
+        assert_eq!(written, 4);
+        value
+    }
+
+    #[test]
+    fn reads_are_exactly_zero_or_one() {
+        let mut prop = Alive::new(false);
+        assert_eq!(read(&prop), 0);
+        prop.set(true);
+        assert_eq!(read(&prop), 1);
+        assert!(!prop.toggle());
+        assert_eq!(read(&prop), 0);
+    }
+
+    #[test]
+    fn any_nonzero_write_is_true() {
+        let mut prop = Alive::new(false);
+        for (raw, value) in [(7u32, true), (0, false), (u32::MAX, true), (1, true)] {
+            unsafe { RawProperty::set(&mut prop, (&raw const raw).cast(), 4) }.unwrap();
+            assert_eq!(prop.get(), value, "{raw}");
+            assert_eq!(read(&prop), value as u32);
+        }
+    }
+
+    #[test]
+    fn writes_of_other_sizes_are_rejected() {
+        let mut prop = Alive::new(true);
+        let data = [0u64; 1];
+        for size in [2, 8] {
+            let status = unsafe { RawProperty::set(&mut prop, data.as_ptr().cast(), size) };
+            assert_eq!(
+                result_to_err_code(status),
+                kAudioHardwareBadPropertySizeError as i32,
+                "{size} bytes"
+            );
+        }
+        assert!(prop.get());
+    }
+}
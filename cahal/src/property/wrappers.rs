@@ -0,0 +1,1082 @@
+use std::{
+    any::Any,
+    ffi::c_void,
+    fmt::Debug,
+    mem,
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use coreaudio_sys::pid_t;
+
+use crate::os_err::{OSResult, OSStatus, OSStatusError};
+
+use super::{
+    read_value, write_value, ArrayProp, PropertyAddress, PropertyElement, PropertyScope,
+    PropertySelector, Qualifier, RawProperty, TypedProperty,
+};
+
+/// Callback invoked with the old and the new value of a property when the HAL writes it
+pub type SetHook<T> = Box<dyn FnMut(&T, &T) -> OSStatus + Send>;
+
+/// A settable property (see [`Prop`](super::Prop)) that runs a callback whenever the HAL writes it, so the driver can react
+/// to the change (flip hardware state, request a configuration change, ...).
+///
+/// The callback receives the old and the new value after the incoming data has been validated. Returning an error vetoes
+/// the write: the error is passed on to the HAL and the stored value is left unchanged
+pub struct HookedProp<T, const SEL: u32> {
+    value: T,
+    on_set: SetHook<T>,
+}
+
+impl<T, const SEL: u32> HookedProp<T, SEL> {
+    pub fn new(value: T, on_set: impl FnMut(&T, &T) -> OSStatus + Send + 'static) -> Self {
+        Self {
+            value,
+            on_set: Box::new(on_set),
+        }
+    }
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+    /// Replace the stored value from Rust, without running the hook
+    pub fn set(&mut self, value: T) -> T {
+        mem::replace(&mut self.value, value)
+    }
+}
+
+impl<T: Debug, const SEL: u32> Debug for HookedProp<T, SEL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookedProp")
+            .field("value", &self.value)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Clone + 'static, const SEL: u32> RawProperty for HookedProp<T, SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<T>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let new = unsafe { read_value(data, data_size)? };
+        (self.on_set)(&self.value, &new)?;
+        self.value = new;
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.value.clone(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A property whose settability can change over the driver's lifetime, e.g. a sample rate that can't be changed while IO is
+/// running. Settability is read from a shared flag, so the driver can keep a clone of [`RuntimeMutProp::flag`] and flip it
+/// from wherever the relevant state changes.
+///
+/// Writes while the property isn't settable are rejected with [`OSStatusError::HW_UNSUPPORTED_OP`]
+pub struct RuntimeMutProp<T, const SEL: u32> {
+    value: T,
+    settable: Arc<AtomicBool>,
+}
+
+impl<T, const SEL: u32> RuntimeMutProp<T, SEL> {
+    pub fn new(value: T, settable: bool) -> Self {
+        Self::with_flag(value, Arc::new(AtomicBool::new(settable)))
+    }
+    /// Create a property sharing its settability with other holders of `settable`
+    pub fn with_flag(value: T, settable: Arc<AtomicBool>) -> Self {
+        Self { value, settable }
+    }
+    /// The shared flag controlling whether this property is settable
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.settable.clone()
+    }
+    pub fn set_settable(&self, settable: bool) {
+        self.settable.store(settable, Ordering::Release);
+    }
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+    /// Replace the stored value from Rust, regardless of settability
+    pub fn set(&mut self, value: T) -> T {
+        mem::replace(&mut self.value, value)
+    }
+}
+
+impl<T: Clone + 'static, const SEL: u32> RawProperty for RuntimeMutProp<T, SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        mem::size_of::<T>() as u32
+    }
+    #[inline]
+    fn is_mut(&self) -> bool {
+        self.settable.load(Ordering::Acquire)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.value
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut(), OSStatusError::HW_UNSUPPORTED_OP);
+        self.value = unsafe { read_value(data, data_size)? };
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { write_value(self.value.clone(), out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A property that may or may not exist at runtime, e.g. `kAudioDevicePropertyIcon` when an icon is bundled.
+/// While empty, property lookups through [`AudioObject`](crate::audio_object::AudioObject) behave as if the property
+/// didn't exist. While filled, everything is delegated to the inner property
+pub struct OptionProp<P> {
+    selector: PropertySelector,
+    inner: Option<P>,
+}
+
+impl<P: RawProperty> OptionProp<P> {
+    pub fn some(prop: P) -> Self {
+        Self {
+            selector: prop.selector(),
+            inner: Some(prop),
+        }
+    }
+    pub fn none(selector: u32) -> Self {
+        Self {
+            selector: selector.into(),
+            inner: None,
+        }
+    }
+    pub fn get(&self) -> Option<&P> {
+        self.inner.as_ref()
+    }
+    pub fn get_mut(&mut self) -> Option<&mut P> {
+        self.inner.as_mut()
+    }
+    /// Fill in the property, returning the previous one if there was any
+    pub fn replace(&mut self, prop: P) -> Option<P> {
+        debug_assert_eq!(prop.selector(), self.selector);
+        self.inner.replace(prop)
+    }
+    /// Remove the property, returning it if there was any
+    pub fn take(&mut self) -> Option<P> {
+        self.inner.take()
+    }
+}
+
+impl<P: RawProperty + 'static> RawProperty for OptionProp<P> {
+    fn selector(&self) -> PropertySelector {
+        self.selector
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.inner.as_ref().map_or(0, |prop| prop.byte_size())
+    }
+
+    fn is_mut(&self) -> bool {
+        self.inner.as_ref().is_some_and(|prop| prop.is_mut())
+    }
+
+    fn is_present(&self) -> bool {
+        self.inner.as_ref().is_some_and(|prop| prop.is_present())
+    }
+
+    fn dependents(&self) -> &'static [u32] {
+        self.inner.as_ref().map_or(&[], |prop| prop.dependents())
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.inner.as_ref().is_some_and(|prop| prop.is_deferred())
+    }
+
+    /// The inner property's value, or the empty `Option<P>` while absent
+    #[allow(deprecated)]
+    fn as_any(&self) -> &dyn Any {
+        match &self.inner {
+            Some(prop) => prop.as_any(),
+            None => &self.inner,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        if self.inner.is_none() {
+            return &mut self.inner;
+        }
+        self.inner.as_mut().unwrap().as_any_mut()
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        self.inner.as_ref()?.value_bytes()
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let Some(prop) = &mut self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.set(data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(prop) = &self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.get(out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        self.inner
+            .as_ref()
+            .map_or(0, |prop| prop.byte_size_qualified(qualifier))
+    }
+
+    unsafe fn set_qualified(
+        &mut self,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let Some(prop) = &mut self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.set_qualified(qualifier, data, data_size) }
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(prop) = &self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.get_qualified(qualifier, out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_at(&self, address: PropertyAddress, qualifier: Qualifier<'_>) -> u32 {
+        self.inner
+            .as_ref()
+            .map_or(0, |prop| prop.byte_size_at(address, qualifier))
+    }
+
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let Some(prop) = &mut self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.set_at(address, qualifier, data, data_size) }
+    }
+
+    unsafe fn set_by_client(
+        &mut self,
+        client_pid: pid_t,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let Some(prop) = &mut self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.set_by_client(client_pid, address, qualifier, data, data_size) }
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let Some(prop) = &self.inner else {
+            return Err(OSStatusError::HW_UNKNOWN_PROP_ERR);
+        };
+        unsafe { prop.get_at(address, qualifier, out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+impl<T: Clone + 'static, const SEL: u32> TypedProperty for RuntimeMutProp<T, SEL> {
+    type Value = T;
+
+    fn value(&self) -> &T {
+        &self.value
+    }
+
+    fn replace_value(&mut self, value: T) -> T {
+        mem::replace(&mut self.value, value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<T> {
+        unsafe { read_value(data, data_size) }
+    }
+}
+
+/// Decides whether a value written by the HAL is acceptable, see [`Validated`]
+pub trait Validator<T> {
+    fn is_valid(&self, value: &T) -> bool;
+}
+
+impl<T, F: Fn(&T) -> bool> Validator<T> for F {
+    fn is_valid(&self, value: &T) -> bool {
+        self(value)
+    }
+}
+
+/// Accepts values within an inclusive range
+#[derive(Debug, Clone)]
+pub struct InRange<T>(pub RangeInclusive<T>);
+
+impl<T: PartialOrd> Validator<T> for InRange<T> {
+    fn is_valid(&self, value: &T) -> bool {
+        self.0.contains(value)
+    }
+}
+
+/// Accepts values equal to one of a list of allowed values
+#[derive(Debug, Clone)]
+pub struct OneOf<T>(pub Vec<T>);
+
+impl<T: PartialEq> Validator<T> for OneOf<T> {
+    fn is_valid(&self, value: &T) -> bool {
+        self.0.contains(value)
+    }
+}
+
+/// A settable property whose incoming values are checked by a [`Validator`] after they have been decoded (i.e. after the
+/// size and alignment checks). Rejected writes fail with the configured error and leave the stored value untouched, accepted
+/// ones are passed on to [`RawProperty::set`] of the inner property
+#[derive(Debug, Clone)]
+pub struct Validated<P, V> {
+    inner: P,
+    validator: V,
+    error: OSStatusError,
+}
+
+impl<P: TypedProperty, V: Validator<P::Value>> Validated<P, V> {
+    /// Reject invalid writes with [`OSStatusError::HW_ILLEGAL_OPERATION_ERR`]
+    pub fn new(inner: P, validator: V) -> Self {
+        Self::with_error(inner, validator, OSStatusError::HW_ILLEGAL_OPERATION_ERR)
+    }
+    pub fn with_error(inner: P, validator: V, error: OSStatusError) -> Self {
+        Self {
+            inner,
+            validator,
+            error,
+        }
+    }
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+    /// Access the wrapped property from Rust. Changes made through this are not validated
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P, V> RawProperty for Validated<P, V>
+where
+    P: TypedProperty,
+    V: Validator<P::Value>,
+{
+    fn selector(&self) -> PropertySelector {
+        self.inner.selector()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.inner.byte_size()
+    }
+
+    fn is_mut(&self) -> bool {
+        self.inner.is_mut()
+    }
+
+    fn is_present(&self) -> bool {
+        self.inner.is_present()
+    }
+
+    fn dependents(&self) -> &'static [u32] {
+        self.inner.dependents()
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.inner.is_deferred()
+    }
+
+    #[allow(deprecated)]
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    #[allow(deprecated)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self.inner.as_any_mut()
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        self.inner.value_bytes()
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        // writes to a property that isn't settable are left to the inner property to reject
+        if self.is_mut() {
+            let value = unsafe { self.inner.decode(data, data_size)? };
+            if !self.validator.is_valid(&value) {
+                log::error!("rejected invalid value for {}", self.selector());
+                return Err(self.error);
+            }
+        }
+        unsafe { self.inner.set(data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.inner.get(out_alloc_size, data_out, data_len_out) }
+    }
+}
+
+impl<P, V> TypedProperty for Validated<P, V>
+where
+    P: TypedProperty,
+    V: Validator<P::Value>,
+{
+    type Value = P::Value;
+
+    fn value(&self) -> &P::Value {
+        self.inner.value()
+    }
+
+    fn replace_value(&mut self, value: P::Value) -> P::Value {
+        self.inner.replace_value(value)
+    }
+
+    unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<P::Value> {
+        let value = unsafe { self.inner.decode(data, data_size)? };
+        ret_assert!(self.validator.is_valid(&value), self.error);
+        Ok(value)
+    }
+}
+
+/// Separate instances of the same property for the global, input and output scopes, for device properties that exist
+/// once per direction (latency, safety offset, streams, ...). Requests are answered by the instance for the scope of
+/// the incoming address through the `*_at` methods of [`RawProperty`]. Any other scope resolves to the global instance,
+/// as do the address-less methods.
+///
+/// A wildcard scope matches all three: the instances are reported separately at their own scope by
+/// [`RawProperty::visit_instances`], so wildcard queries (and [`HasProperties::visit_properties`]) see each of them.
+/// Only a request that names the wildcard scope directly, which the HAL doesn't send for reads and writes, is answered
+/// by the global instance
+///
+/// [`HasProperties::visit_properties`]: crate::audio_object::HasProperties::visit_properties
+#[derive(Debug, Clone, Default)]
+pub struct ScopedProps<P> {
+    pub global: P,
+    pub input: P,
+    pub output: P,
+}
+
+impl<P: RawProperty> ScopedProps<P> {
+    pub fn new(global: P, input: P, output: P) -> Self {
+        debug_assert!(
+            global.selector() == input.selector() && global.selector() == output.selector(),
+            "all scopes of a ScopedProps must have the same selector"
+        );
+        Self {
+            global,
+            input,
+            output,
+        }
+    }
+    /// The same property in every scope
+    pub fn uniform(prop: P) -> Self
+    where
+        P: Clone,
+    {
+        Self::new(prop.clone(), prop.clone(), prop)
+    }
+    /// The instance answering requests in `scope`. Scopes other than input and output, including the wildcard, get the
+    /// global instance
+    pub fn scope(&self, scope: PropertyScope) -> &P {
+        match scope {
+            PropertyScope::INPUT => &self.input,
+            PropertyScope::OUTPUT => &self.output,
+            _ => &self.global,
+        }
+    }
+    pub fn scope_mut(&mut self, scope: PropertyScope) -> &mut P {
+        match scope {
+            PropertyScope::INPUT => &mut self.input,
+            PropertyScope::OUTPUT => &mut self.output,
+            _ => &mut self.global,
+        }
+    }
+}
+
+impl<P: RawProperty + 'static> RawProperty for ScopedProps<P> {
+    fn selector(&self) -> PropertySelector {
+        self.global.selector()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.global.byte_size()
+    }
+
+    fn is_mut(&self) -> bool {
+        self.global.is_mut()
+    }
+
+    fn is_present(&self) -> bool {
+        self.global.is_present()
+    }
+
+    fn dependents(&self) -> &'static [u32] {
+        self.global.dependents()
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.global.is_deferred()
+    }
+
+    fn visit_instances(&self, visitor: &mut dyn FnMut(PropertyAddress, &dyn RawProperty)) -> bool {
+        let selector = self.selector();
+        for (scope, prop) in [
+            (PropertyScope::GLOBAL, &self.global),
+            (PropertyScope::INPUT, &self.input),
+            (PropertyScope::OUTPUT, &self.output),
+        ] {
+            visitor(
+                PropertyAddress::new(selector, scope, PropertyElement::WILDCARD),
+                prop,
+            );
+        }
+        true
+    }
+
+    fn instance_at_mut(&mut self, address: PropertyAddress) -> Option<&mut dyn RawProperty> {
+        Some(self.scope_mut(address.scope))
+    }
+
+    /// The global instance's value
+    #[allow(deprecated)]
+    fn as_any(&self) -> &dyn Any {
+        self.global.as_any()
+    }
+
+    #[allow(deprecated)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self.global.as_any_mut()
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        self.global.value_bytes()
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        unsafe { self.global.set(data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe { self.global.get(out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        self.global.byte_size_qualified(qualifier)
+    }
+
+    unsafe fn set_qualified(
+        &mut self,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        unsafe { self.global.set_qualified(qualifier, data, data_size) }
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            self.global
+                .get_qualified(qualifier, out_alloc_size, data_out, data_len_out)
+        }
+    }
+
+    fn byte_size_at(&self, address: PropertyAddress, qualifier: Qualifier<'_>) -> u32 {
+        self.scope(address.scope).byte_size_at(address, qualifier)
+    }
+
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        unsafe {
+            self.scope_mut(address.scope)
+                .set_at(address, qualifier, data, data_size)
+        }
+    }
+
+    unsafe fn set_by_client(
+        &mut self,
+        client_pid: pid_t,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        unsafe {
+            self.scope_mut(address.scope)
+                .set_by_client(client_pid, address, qualifier, data, data_size)
+        }
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            self.scope(address.scope).get_at(
+                address,
+                qualifier,
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+}
+
+/// A read-only list with separate input and output entries, like `kAudioDevicePropertyStreams`. Requests in the input
+/// or output scope see only the entries of that direction, all other scopes (global and wildcard) see both, inputs
+/// first. Marshalled like an [`ArrayProp`]
+#[derive(Debug, Clone)]
+pub struct ScopedArrayProp<T, const SEL: u32> {
+    pub input: Vec<T>,
+    pub output: Vec<T>,
+}
+
+impl<T, const SEL: u32> Default for ScopedArrayProp<T, SEL> {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy, const SEL: u32> ScopedArrayProp<T, SEL> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The entries seen by a request in `scope`
+    pub fn scoped(&self, scope: PropertyScope) -> Vec<T> {
+        match scope {
+            PropertyScope::INPUT => self.input.clone(),
+            PropertyScope::OUTPUT => self.output.clone(),
+            _ => self.input.iter().chain(&self.output).copied().collect(),
+        }
+    }
+    /// The list of the input or output scope, `None` for any other scope
+    pub fn scope_mut(&mut self, scope: PropertyScope) -> Option<&mut Vec<T>> {
+        match scope {
+            PropertyScope::INPUT => Some(&mut self.input),
+            PropertyScope::OUTPUT => Some(&mut self.output),
+            _ => None,
+        }
+    }
+    fn array(&self, scope: PropertyScope) -> ArrayProp<T, SEL> {
+        ArrayProp::new_with(self.scoped(scope))
+    }
+}
+
+impl<T: Copy + 'static, const SEL: u32> RawProperty for ScopedArrayProp<T, SEL> {
+    fn selector(&self) -> PropertySelector {
+        SEL.into()
+    }
+
+    fn byte_size(&self) -> u32 {
+        ((self.input.len() + self.output.len()) * mem::size_of::<T>()) as u32
+    }
+
+    fn is_mut(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    unsafe fn set(&mut self, _data: *const c_void, _data_size: u32) -> OSStatus {
+        ret_assert!(self.is_mut());
+        Ok(())
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            self.array(PropertyScope::GLOBAL)
+                .get(out_alloc_size, data_out, data_len_out)
+        }
+    }
+
+    fn byte_size_at(&self, address: PropertyAddress, _qualifier: Qualifier<'_>) -> u32 {
+        match address.scope {
+            PropertyScope::INPUT => (self.input.len() * mem::size_of::<T>()) as u32,
+            PropertyScope::OUTPUT => (self.output.len() * mem::size_of::<T>()) as u32,
+            _ => self.byte_size(),
+        }
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        _qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        unsafe {
+            self.array(address.scope)
+                .get(out_alloc_size, data_out, data_len_out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{
+        kAudioDevicePropertyLatency, kAudioHardwareBadPropertySizeError,
+        kAudioHardwareIllegalOperationError, kAudioHardwareUnsupportedOperationError,
+    };
+
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::audio_object::HasProperties;
+    use crate::os_err::result_to_err_code;
+    use crate::property::{Prop, PropertyTable, RawPropertyExt};
+
+    /// Counts the writes that reach it through [`RawProperty::set`]
+    #[derive(Default)]
+    struct CountingProp {
+        value: u32,
+        sets: u32,
+    }
+
+    impl RawProperty for CountingProp {
+        fn selector(&self) -> PropertySelector {
+            kAudioDevicePropertyLatency.into()
+        }
+        fn byte_size(&self) -> u32 {
+            mem::size_of::<u32>() as u32
+        }
+        fn is_mut(&self) -> bool {
+            true
+        }
+        fn as_any(&self) -> &dyn Any {
+            &self.value
+        }
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            &mut self.value
+        }
+        unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+            self.value = unsafe { read_value(data, data_size)? };
+            self.sets += 1;
+            Ok(())
+        }
+        unsafe fn get(
+            &self,
+            out_alloc_size: u32,
+            data_out: *mut c_void,
+            data_len_out: *mut u32,
+        ) -> OSStatus {
+            unsafe { write_value(self.value, out_alloc_size, data_out, data_len_out) }
+        }
+    }
+
+    impl TypedProperty for CountingProp {
+        type Value = u32;
+        fn value(&self) -> &u32 {
+            &self.value
+        }
+        fn replace_value(&mut self, value: u32) -> u32 {
+            mem::replace(&mut self.value, value)
+        }
+        unsafe fn decode(&self, data: *const c_void, data_size: u32) -> OSResult<u32> {
+            unsafe { read_value(data, data_size) }
+        }
+    }
+
+    /// The status code of writing `value` to `prop`
+    fn set<P: RawProperty>(prop: &mut P, value: u32) -> i32 {
+        let status = unsafe {
+            prop.set(
+                &value as *const u32 as *const c_void,
+                mem::size_of::<u32>() as u32,
+            )
+        };
+        result_to_err_code(status)
+    }
+
+    /// A latency whose hook vetoes values above 10 and records the writes it saw
+    fn hooked(seen: &Arc<Mutex<Vec<(u32, u32)>>>) -> HookedProp<u32, kAudioDevicePropertyLatency> {
+        let seen = seen.clone();
+        HookedProp::new(1, move |&old, &new| {
+            seen.lock().unwrap().push((old, new));
+            if new > 10 {
+                return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn hooks_see_the_old_and_the_new_value() {
+        let seen = Arc::default();
+        let mut prop = hooked(&seen);
+        assert_eq!(set(&mut prop, 5), 0);
+        assert_eq!(set(&mut prop, 7), 0);
+        assert_eq!(*prop.get(), 7);
+        assert_eq!(*seen.lock().unwrap(), [(1, 5), (5, 7)]);
+        // writes from Rust don't run the hook
+        prop.set(3);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn vetoed_writes_keep_the_value() {
+        let seen = Arc::default();
+        let mut prop = hooked(&seen);
+        assert_eq!(
+            set(&mut prop, 11),
+            kAudioHardwareIllegalOperationError as i32
+        );
+        assert_eq!(*prop.get(), 1);
+        let mut value = 0u32;
+        let mut written = 0;
+        unsafe { RawProperty::get(&prop, 4, (&raw mut value).cast(), &mut written) }.unwrap();
+        assert_eq!(value, 1);
+        assert_eq!(*seen.lock().unwrap(), [(1, 11)]);
+    }
+
+    #[test]
+    fn malformed_writes_never_reach_the_hook() {
+        let seen = Arc::default();
+        let mut prop = hooked(&seen);
+        let value = 5u32;
+        let status = unsafe { RawProperty::set(&mut prop, (&raw const value).cast(), 2) };
+        assert_eq!(
+            result_to_err_code(status),
+            kAudioHardwareBadPropertySizeError as i32
+        );
+        assert_eq!(*prop.get(), 1);
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn accepted_writes_go_through_the_inner_set() {
+        let mut prop = Validated::new(CountingProp::default(), InRange(1..=10));
+        assert_eq!(set(&mut prop, 5), 0);
+        assert_eq!(prop.inner().value, 5);
+        assert_eq!(prop.inner().sets, 1);
+    }
+
+    #[test]
+    fn rejected_writes_never_reach_the_inner_property() {
+        let mut prop = Validated::with_error(
+            CountingProp::default(),
+            OneOf(vec![1, 2]),
+            OSStatusError::HW_BAD_PROPERTY_SIZE_ERR,
+        );
+        assert_eq!(set(&mut prop, 3), kAudioHardwareBadPropertySizeError as i32);
+        assert_eq!(prop.inner().sets, 0);
+        assert_eq!(prop.inner().value, 0);
+    }
+
+    #[test]
+    fn malformed_writes_fail_before_validation() {
+        let mut prop = Validated::new(CountingProp::default(), |_: &u32| -> bool {
+            panic!("validator ran on a malformed write")
+        });
+        let value = 1u32;
+        let status = unsafe { prop.set(&value as *const u32 as *const c_void, 2) };
+        assert_eq!(
+            result_to_err_code(status),
+            kAudioHardwareBadPropertySizeError as i32
+        );
+    }
+
+    #[test]
+    fn unsettable_properties_keep_their_error() {
+        let mut plain = Prop::<u32, kAudioDevicePropertyLatency>(1);
+        let mut read_only = Validated::new(plain.clone(), InRange(0..=10));
+        assert_ne!(set(&mut read_only, 2), 0);
+        assert_eq!(set(&mut read_only, 2), set(&mut plain, 2));
+
+        let mut runtime = Validated::new(
+            RuntimeMutProp::<u32, kAudioDevicePropertyLatency>::new(1, false),
+            InRange(0..=10),
+        );
+        assert!(!runtime.is_mut());
+        assert_eq!(
+            set(&mut runtime, 2),
+            kAudioHardwareUnsupportedOperationError as i32
+        );
+        runtime.inner().set_settable(true);
+        assert_eq!(set(&mut runtime, 2), 0);
+        assert_eq!(*runtime.inner().get(), 2);
+        assert_eq!(
+            set(&mut runtime, 11),
+            kAudioHardwareIllegalOperationError as i32
+        );
+    }
+
+    fn latency(value: u32) -> Prop<u32, kAudioDevicePropertyLatency> {
+        Prop(value)
+    }
+
+    fn get_at(prop: &dyn RawProperty, scope: PropertyScope) -> u32 {
+        let address = PropertyAddress::scoped(kAudioDevicePropertyLatency, scope);
+        let mut value = 0u32;
+        let mut written = 0;
+        let status = unsafe {
+            prop.get_at(
+                address,
+                Qualifier::NONE,
+                mem::size_of::<u32>() as u32,
+                &mut value as *mut u32 as *mut c_void,
+                &mut written,
+            )
+        };
+        assert_eq!(result_to_err_code(status), 0);
+        value
+    }
+
+    #[test]
+    fn scoped_props_answer_each_scope_with_its_own_instance() {
+        let table = PropertyTable::new().with(ScopedProps::new(latency(1), latency(2), latency(3)));
+        let prop = table
+            .get_object_property(PropertyAddress::global(kAudioDevicePropertyLatency))
+            .unwrap();
+        assert_eq!(get_at(prop, PropertyScope::GLOBAL), 1);
+        assert_eq!(get_at(prop, PropertyScope::INPUT), 2);
+        assert_eq!(get_at(prop, PropertyScope::OUTPUT), 3);
+    }
+
+    #[test]
+    fn scoped_props_report_every_instance_to_wildcard_queries() {
+        let table = PropertyTable::new().with(ScopedProps::new(latency(1), latency(2), latency(3)));
+        let wildcard = PropertyAddress::anywhere(kAudioDevicePropertyLatency.into());
+        let mut found = Vec::new();
+        table.visit_properties(&mut |address, prop| {
+            if address.matches(&wildcard) {
+                found.push((address.scope, *prop.value::<u32>().unwrap()));
+            }
+        });
+        assert_eq!(
+            found,
+            [
+                (PropertyScope::GLOBAL, 1),
+                (PropertyScope::INPUT, 2),
+                (PropertyScope::OUTPUT, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn scoped_props_resolve_writes_to_the_instance_of_the_scope() {
+        let mut prop = ScopedProps::uniform(latency(0));
+        let input = PropertyAddress::scoped(kAudioDevicePropertyLatency, PropertyScope::INPUT);
+        *prop
+            .instance_at_mut(input)
+            .unwrap()
+            .value_mut::<u32>()
+            .unwrap() = 5;
+        assert_eq!(prop.input.0, 5);
+        assert_eq!(prop.global.0, 0);
+        assert_eq!(prop.output.0, 0);
+    }
+}
@@ -10,12 +10,7 @@ use core_foundation::{
     string::{CFString, CFStringRef},
 };
 use coreaudio_sys::{
-    kAudioServerPlugInIOOperationConvertInput, kAudioServerPlugInIOOperationConvertMix,
-    kAudioServerPlugInIOOperationCycle, kAudioServerPlugInIOOperationMixOutput,
-    kAudioServerPlugInIOOperationProcessInput, kAudioServerPlugInIOOperationProcessMix,
-    kAudioServerPlugInIOOperationProcessOutput, kAudioServerPlugInIOOperationReadInput,
-    kAudioServerPlugInIOOperationThread, kAudioServerPlugInIOOperationWriteMix, pid_t,
-    AudioObjectID, AudioObjectPropertyAddress, AudioServerPlugInClientInfo,
+    pid_t, AudioObjectID, AudioObjectPropertyAddress, AudioServerPlugInClientInfo,
     AudioServerPlugInDriverInterface, AudioServerPlugInDriverRef, AudioServerPlugInHostInterface,
     AudioServerPlugInHostRef, AudioServerPlugInIOCycleInfo, CFAllocatorRef, CFDictionaryRef,
     OSStatus, HRESULT, LPVOID, REFIID, ULONG,
@@ -23,7 +18,7 @@ use coreaudio_sys::{
 
 use crate::{
     os_err::{result_from_err_code, OSResult, OSStatusError, ResultExt},
-    plugin_driver_interface::AudioServerPluginDriverInterface,
+    plugin_driver_interface::{AudioServerPluginDriverInterface, ChangeRequest},
 };
 #[allow(clippy::missing_safety_doc)]
 pub trait RawAudioServerPlugInDriverInterface {
@@ -70,14 +65,19 @@ pub trait RawAudioServerPlugInDriverInterface {
     /// AudioServerPlugIns are required to support the IUnknown interface and the
     /// AudioServerPlugInDriverInterface. As it happens, all interfaces must also provide the
     /// IUnknown interface, so we can always just return the single interface we made with
-    /// gAudioServerPlugInDriverInterfacePtr regardless of which one is asked for.
+    /// gAudioServerPlugInDriverInterfacePtr regardless of which one is asked for. Like every
+    /// QueryInterface, it adds a reference to the driver for the interface it returns.
     unsafe extern "C" fn query_interface(
         driver: *mut c_void,
         in_uuid: REFIID,
         out_interface: *mut LPVOID,
     ) -> HRESULT;
 
+    /// IUnknown's AddRef. Adds a reference to the driver and returns the new reference count.
     unsafe extern "C" fn retain(driver: *mut c_void) -> ULONG;
+    /// IUnknown's Release. Drops a reference to the driver and returns the new reference count,
+    /// which stays at 0 if the driver is released more often than it was retained. The driver is
+    /// only freed at 0 if it opted in with `AudioServerPluginDriverInterface::DEALLOCATE_ON_ZERO`.
     unsafe extern "C" fn release(driver: *mut c_void) -> ULONG;
 
     /// The job of this method is, as the name implies, to get the driver initialized. One specific
@@ -92,9 +92,9 @@ pub trait RawAudioServerPlugInDriverInterface {
     ) -> OSStatus;
 
     /// This method is used to tell a driver that implements the Transport Manager semantics to
-    /// create an AudioEndpointDevice from a set of AudioEndpoints. Since this driver is not a
-    /// Transport Manager, we just check the arguments and return
-    /// kAudioHardwareUnsupportedOperationError.
+    /// create an AudioEndpointDevice from a set of AudioEndpoints. The blanket implementation
+    /// checks the arguments and forwards to `TransportManagerInterface::create_endpoint_device`,
+    /// or returns kAudioHardwareUnsupportedOperationError if the driver is not a Transport Manager.
     unsafe extern "C" fn create_device(
         driver: AudioServerPlugInDriverRef,
         desc: CFDictionaryRef,
@@ -103,17 +103,18 @@ pub trait RawAudioServerPlugInDriverInterface {
     ) -> OSStatus;
 
     /// This method is used to tell a driver that implements the Transport Manager semantics to
-    /// destroy an AudioEndpointDevice. Since this driver is not a Transport Manager, we just check
-    /// the arguments and return kAudioHardwareUnsupportedOperationError.
+    /// destroy an AudioEndpointDevice. The blanket implementation forwards to
+    /// `TransportManagerInterface::destroy_endpoint_device`, or returns
+    /// kAudioHardwareUnsupportedOperationError if the driver is not a Transport Manager.
     unsafe extern "C" fn destroy_device(
         driver: AudioServerPlugInDriverRef,
         device_id: AudioObjectID,
     ) -> OSStatus;
 
     /// This method is used to inform the driver about a new client that is using the given device.
-    /// This allows the device to act differently depending on who the client is. This driver does
-    /// not need to track the clients using the device, so we just check the arguments and return
-    /// successfully.
+    /// This allows the device to act differently depending on who the client is. The blanket
+    /// implementation keeps track of the clients of each device and forwards to
+    /// `AudioServerPluginDriverInterface::add_client`.
     unsafe extern "C" fn add_device_client(
         driver: AudioServerPlugInDriverRef,
         device_id: AudioObjectID,
@@ -121,8 +122,8 @@ pub trait RawAudioServerPlugInDriverInterface {
     ) -> OSStatus;
 
     /// This method is used to inform the driver about a client that is no longer using the given
-    /// device. This driver does not track clients, so we just check the arguments and return
-    /// successfully.
+    /// device. The blanket implementation forgets the client and forwards to
+    /// `AudioServerPluginDriverInterface::remove_client`.
     unsafe extern "C" fn remove_device_client(
         driver: AudioServerPlugInDriverRef,
         device_id: AudioObjectID,
@@ -147,8 +148,9 @@ pub trait RawAudioServerPlugInDriverInterface {
 
     /// This method is called to tell the driver that a request for a config change has been denied.
     /// This provides the driver an opportunity to clean up any state associated with the request.
-    /// For this driver, an aborted config change requires no action. So we just check the arguments
-    /// and return
+    /// The crate takes back the typed change info handed to the host with the request, drops the mutation of a
+    /// [`ConfigChangeGuard`](crate::plugin_driver_interface::ConfigChangeGuard) without running it, and passes the info
+    /// to [`AudioServerPluginDriverInterface::abort_change`]
     unsafe extern "C" fn abort_device_configuration_change(
         driver: AudioServerPlugInDriverRef,
         device_id: AudioObjectID,
@@ -271,13 +273,26 @@ pub trait RawAudioServerPlugInDriverInterface {
 unsafe impl<T: AudioServerPluginDriverInterface> Sync for PluginHostInterface<T> {}
 unsafe impl<T: AudioServerPluginDriverInterface> Send for PluginHostInterface<T> {}
 //Safe to duplicate this structure since the internal pointer has shared/immutable provenance
+/// The interface of the host the driver was initialized with.
+///
+/// The type parameter is the driver, it ties [`PluginHostInterface::request_change`] to the driver's
+/// `DeviceConfigurationChangeInfo`, the type `perform_change` and `abort_change` receive back. Drivers get it as
+/// `PluginHostInterface<Self>` in `init`
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
-pub struct PluginHostInterface<Implementation: ?Sized + 'static> {
+#[derive(Debug)]
+pub struct PluginHostInterface<Implementation: ?Sized> {
     inner: NonNull<AudioServerPlugInHostInterface>,
-    _boo: PhantomData<&'static Implementation>,
+    _boo: PhantomData<*const Implementation>,
 }
 
+// not derived, which would require the implementation to be `Clone` as well
+impl<Implementation: ?Sized> Clone for PluginHostInterface<Implementation> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<Implementation: ?Sized> Copy for PluginHostInterface<Implementation> {}
+
 impl<Implementation: AudioServerPluginDriverInterface> PluginHostInterface<Implementation> {
     /// # Safety
     /// inner must point to an initialized CA host interface struct
@@ -378,8 +393,12 @@ impl<Implementation: AudioServerPluginDriverInterface> PluginHostInterface<Imple
             )
         })
     }
+    /// Use [`PluginHostInterface::request_change`] or
+    /// [`ConfigChangeGuard`](crate::plugin_driver_interface::ConfigChangeGuard), which manage the change info
     /// # Safety
-    /// May result in a dereference of in_change_info
+    /// The host passes `in_change_info` back to `perform_device_configuration_change` or
+    /// `abort_device_configuration_change`, which take ownership of it. Unless the request fails, it must have been
+    /// created by this crate, which is only possible through the functions above
     pub unsafe fn request_device_configuration_change(
         &self,
         in_device_object_id: AudioObjectID,
@@ -400,77 +419,29 @@ impl<Implementation: AudioServerPluginDriverInterface> PluginHostInterface<Imple
             in_change_info,
         ) })
     }
-    /// request a device configuration change with boxed change info
-    pub fn request_boxed_device_configuration_change(
+    /// Ask the host to run a configuration change of `device` with the driver's `info`, which is handed back by value to
+    /// [`AudioServerPluginDriverInterface::perform_change`] or [`AudioServerPluginDriverInterface::abort_change`]. If the
+    /// host refuses the request, `info` is dropped right away
+    pub fn request_change(
         &self,
-        in_device_object_id: AudioObjectID,
-        in_change_action: u64,
-        in_change_info: Option<Box<Implementation::DeviceConfigurationChangeInfo>>,
+        device: AudioObjectID,
+        info: Implementation::DeviceConfigurationChangeInfo,
     ) -> crate::os_err::OSStatus {
-        let mut ptr = ptr::null_mut();
-        if let Some(change_info) = in_change_info.map(Box::into_raw) {
-            ptr = change_info;
-        };
-        //SAFETY: pointer is either an owning pointer to a correctly initialized `Box<Implementation::DeviceConfigurationChangeInfo>` or null
-        unsafe {
-            self.request_device_configuration_change(
-                in_device_object_id,
-                in_change_action,
-                ptr.cast(),
-            )
+        self.request(device, ChangeRequest::new(info))
+    }
+    /// Pass `request` to the host, which hands it back to perform or abort
+    pub(crate) fn request(
+        &self,
+        device: AudioObjectID,
+        request: ChangeRequest<Implementation>,
+    ) -> crate::os_err::OSStatus {
+        let request = Box::into_raw(Box::new(request));
+        // Safety: the host owns the pointer until it passes it back to perform or abort, which reclaim the box
+        let result = unsafe { self.request_device_configuration_change(device, 0, request.cast()) };
+        if result.is_err() {
+            // Safety: the request was refused, so the pointer is never passed back
+            drop(unsafe { Box::from_raw(request) });
         }
+        result
     }
 }
-// TODO link to safe do_io_operation function in doc
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u32)]
-enum AudioServerPlugInIoOperation {
-    /// This operation marks the beginning and the ending of the IO thread. Note
-    /// that [do_io_operation]() will never be called with this ID.
-    Thread = kAudioServerPlugInIOOperationThread,
-
-    /// This operation marks the beginning and ending of each IO cycle. Note that
-    /// DoIOOperation() will never be called with this ID.
-    Cycle = kAudioServerPlugInIOOperationCycle,
-
-    /// This operation transfers the input data from the device's ring buffer to the
-    /// provided buffer in the stream's native format. Note that this operation
-    /// always happens in-place in the main buffer passed to DoIOOperation(). It is
-    /// required that this operation be implemented if the AudioDevice has input
-    /// streams.
-    ReadInput = kAudioServerPlugInIOOperationReadInput,
-
-    /// This operation converts the input data from its native format to the
-    /// canonical format.
-    ConvertInput = kAudioServerPlugInIOOperationConvertInput,
-
-    /// This operation performs arbitrary signal processing on the input data in the
-    // canonical format.
-    ProcessInput = kAudioServerPlugInIOOperationProcessInput,
-
-    /// This operation performs arbitrary signal processing on the output data in
-    /// the canonical format.
-    ProcessOutput = kAudioServerPlugInIOOperationProcessOutput,
-
-    /// This operation mixes the output data into the device's ring buffer. Note
-    /// that if a plug-in implements this operation, no further output operations
-    /// will occur for that cycle. It is assumed that the device handles everything
-    /// from there down including preparing the data for consumption by the
-    /// hardware. Note also that this operation always happens in-place in the main
-    /// buffer passed to DoIOOperation().
-    MixOutput = kAudioServerPlugInIOOperationMixOutput,
-
-    /// This operation processes the full mix of all clients' data in the canonical
-    /// format.
-    ProcessMix = kAudioServerPlugInIOOperationProcessMix,
-
-    /// This operation converts the fully mixed data from the canonical format to
-    /// the device's native format.
-    ConvertMix = kAudioServerPlugInIOOperationConvertMix,
-
-    /// This operation puts the data into the device's ring buffer for consumption
-    /// of the hardware. Note that this operation always happens in-place in the
-    /// main buffer passed to DoIOOperation(). It is required that this operation be
-    /// implemented if the AudioDevice has output streams.
-    WriteMix = kAudioServerPlugInIOOperationWriteMix,
-}
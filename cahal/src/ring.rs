@@ -0,0 +1,208 @@
+//! A ring buffer to move audio between the IO thread and other threads of the driver, e.g. from the output of one
+//! device to the input of another or to a network thread. It holds interleaved `f32` frames, the layout of the
+//! canonical format (see [`IOBuffer::samples`])
+
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::io::IOBuffer;
+
+/// A fixed-size single-producer single-consumer ring buffer of interleaved `f32` frames.
+///
+/// All operations are wait-free and don't allocate, so both ends can be used from the IO thread. One thread may write
+/// and one thread may read at the same time; more writers or readers at once don't cause undefined behavior, but they
+/// corrupt the data. Writing more than fits drops the frames that don't fit, reading more than is available fills the
+/// rest with silence. Both are counted, see [`RingBuffer::overruns`] and [`RingBuffer::underruns`]
+#[derive(Debug)]
+pub struct RingBuffer {
+    /// `f32` bits, `capacity * channels` of them
+    samples: Box<[AtomicU32]>,
+    channels: usize,
+    capacity: usize,
+    /// The number of frames ever written, wrapping
+    written: AtomicUsize,
+    /// The number of frames ever read, wrapping
+    read: AtomicUsize,
+    overruns: AtomicU64,
+    underruns: AtomicU64,
+}
+
+impl RingBuffer {
+    /// A ring buffer with room for `capacity` frames of `channels` samples each
+    pub fn new(capacity: usize, channels: usize) -> Self {
+        assert!(channels > 0, "a ring buffer needs at least one channel");
+        Self {
+            samples: (0..capacity * channels)
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+            channels,
+            capacity,
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            overruns: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
+        }
+    }
+    /// The number of frames the buffer holds when full
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+    /// The number of frames that can be read
+    pub fn available(&self) -> usize {
+        // `read` never passes `written`, so loading it first can't make the difference wrap around. Both may move in
+        // between, which only makes the result stale
+        let read = self.read.load(Ordering::Acquire);
+        let written = self.written.load(Ordering::Acquire);
+        written.wrapping_sub(read).min(self.capacity)
+    }
+    /// The number of frames that can be written without dropping any
+    pub fn free(&self) -> usize {
+        self.capacity.saturating_sub(self.available())
+    }
+    /// The number of frames dropped because the buffer was full
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+    /// The number of frames of silence read because the buffer was empty
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+    /// Append the whole frames in `samples`, returning the number of frames written. Frames that don't fit are dropped
+    pub fn write(&self, samples: &[f32]) -> usize {
+        let frames = samples.len() / self.channels;
+        let written = self.written.load(Ordering::Relaxed);
+        let free = self
+            .capacity
+            .saturating_sub(written.wrapping_sub(self.read.load(Ordering::Acquire)));
+        let count = frames.min(free);
+        for (frame, samples) in samples.chunks_exact(self.channels).take(count).enumerate() {
+            let start = written.wrapping_add(frame) % self.capacity * self.channels;
+            for (slot, sample) in self.samples[start..start + self.channels]
+                .iter()
+                .zip(samples)
+            {
+                slot.store(sample.to_bits(), Ordering::Relaxed);
+            }
+        }
+        self.written
+            .store(written.wrapping_add(count), Ordering::Release);
+        if count < frames {
+            self.overruns
+                .fetch_add((frames - count) as u64, Ordering::Relaxed);
+        }
+        count
+    }
+    /// Fill the whole frames in `samples` from the buffer, returning the number of frames read. Frames that aren't
+    /// available are filled with silence
+    pub fn read(&self, samples: &mut [f32]) -> usize {
+        let frames = samples.len() / self.channels;
+        let read = self.read.load(Ordering::Relaxed);
+        let available = self.written.load(Ordering::Acquire).wrapping_sub(read);
+        let count = frames.min(available);
+        for (frame, samples) in samples
+            .chunks_exact_mut(self.channels)
+            .take(frames)
+            .enumerate()
+        {
+            if frame >= count {
+                samples.fill(0.0);
+                continue;
+            }
+            let start = read.wrapping_add(frame) % self.capacity * self.channels;
+            for (sample, slot) in samples
+                .iter_mut()
+                .zip(&self.samples[start..start + self.channels])
+            {
+                *sample = f32::from_bits(slot.load(Ordering::Relaxed));
+            }
+        }
+        self.read.store(read.wrapping_add(count), Ordering::Release);
+        if count < frames {
+            self.underruns
+                .fetch_add((frames - count) as u64, Ordering::Relaxed);
+        }
+        count
+    }
+    /// Write the samples of `buffer`, see [`RingBuffer::write`]. `None` if the buffer isn't in `f32` (see
+    /// [`IOBuffer::is_f32`]) or has another number of channels
+    pub fn write_buffer(&self, buffer: &IOBuffer<'_>) -> Option<usize> {
+        (buffer.channels() as usize == self.channels)
+            .then(|| buffer.samples())
+            .flatten()
+            .map(|samples| self.write(samples))
+    }
+    /// Fill the samples of `buffer`, see [`RingBuffer::read`]. `None` if the buffer isn't in `f32` (see
+    /// [`IOBuffer::is_f32`]) or has another number of channels
+    pub fn read_buffer(&self, buffer: &mut IOBuffer<'_>) -> Option<usize> {
+        if buffer.channels() as usize != self.channels {
+            return None;
+        }
+        buffer.samples_mut().map(|samples| self.read(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn frames_come_out_in_order() {
+        let ring = RingBuffer::new(4, 2);
+        assert_eq!(ring.write(&[1.0, 2.0, 3.0, 4.0, 5.0]), 2);
+        assert_eq!(ring.available(), 2);
+        assert_eq!(ring.free(), 2);
+        let mut samples = [f32::NAN; 6];
+        assert_eq!(ring.read(&mut samples), 2);
+        assert_eq!(samples, [1.0, 2.0, 3.0, 4.0, 0.0, 0.0]);
+        assert_eq!(ring.underruns(), 1);
+    }
+
+    #[test]
+    fn full_buffers_drop_frames() {
+        let ring = RingBuffer::new(2, 1);
+        assert_eq!(ring.write(&[1.0, 2.0, 3.0]), 2);
+        assert_eq!(ring.overruns(), 1);
+        assert_eq!(ring.free(), 0);
+        let mut samples = [0.0; 2];
+        ring.read(&mut samples);
+        assert_eq!(samples, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn available_frames_stay_in_range_across_threads() {
+        const FRAMES: usize = 10_000;
+        let ring = RingBuffer::new(64, 2);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut next = 0;
+                while next < FRAMES {
+                    let value = next as f32;
+                    next += ring.write(&[value, -value]);
+                }
+            });
+            scope.spawn(|| {
+                let mut next = 0;
+                let mut samples = [0.0; 2];
+                while next < FRAMES {
+                    if ring.read(&mut samples) == 1 {
+                        assert_eq!(samples, [next as f32, -(next as f32)]);
+                        next += 1;
+                    }
+                }
+            });
+            // a third thread only observes
+            scope.spawn(|| {
+                for _ in 0..FRAMES {
+                    let available = ring.available();
+                    assert!(available <= ring.capacity(), "{available} frames available");
+                    assert!(ring.free() <= ring.capacity());
+                }
+            });
+        });
+        assert_eq!(ring.available(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use coreaudio_sys::AudioObjectID;
+
+use crate::{
+    audio_object::AudioObject,
+    property::{PropertyAddress, PropertySelector},
+};
+
+/// The values of all properties of an object tree at one point in time, see [`RawProperty::value_bytes`].
+///
+/// Diffing two snapshots taken around a configuration change yields exactly the properties the host needs to be told
+/// about, e.g. through a [`ChangeSet`](crate::notification::ChangeSet)
+///
+/// [`RawProperty::value_bytes`]: crate::property::RawProperty::value_bytes
+#[derive(Debug, Clone, Default)]
+pub struct PropertySnapshot {
+    values: HashMap<(AudioObjectID, PropertyAddress), Option<Vec<u8>>>,
+}
+
+impl PropertySnapshot {
+    /// Record the value of every present property on `object` and its subobjects
+    pub fn capture(object: &dyn AudioObject) -> Self {
+        let mut values = HashMap::new();
+        object.visit_matching(
+            PropertyAddress::anywhere(PropertySelector::WILDCARD),
+            &mut |id, address, prop| {
+                values.insert((id, address), prop.value_bytes());
+            },
+        );
+        Self { values }
+    }
+    /// The recorded value of a property, `None` if the property wasn't recorded or its value couldn't be read
+    pub fn get(&self, object: AudioObjectID, address: PropertyAddress) -> Option<&[u8]> {
+        self.values.get(&(object, address))?.as_deref()
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    /// The properties whose values differ between `self` and `other`, including properties that only exist in one of them
+    pub fn diff(&self, other: &PropertySnapshot) -> Vec<(AudioObjectID, PropertyAddress)> {
+        let mut changed: Vec<_> = self
+            .values
+            .iter()
+            .filter(|(key, value)| other.values.get(key) != Some(value))
+            .map(|(key, _)| *key)
+            .collect();
+        changed.extend(
+            other
+                .values
+                .keys()
+                .filter(|key| !self.values.contains_key(key))
+                .copied(),
+        );
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coreaudio_sys::{kAudioDevicePropertyLatency, kAudioDevicePropertySafetyOffset};
+
+    use super::*;
+    use crate::{
+        audio_object::{DeviceBuilder, ObjectRegistry},
+        property::{PropertyScope, RawPropertyExt},
+    };
+
+    fn device() -> (ObjectRegistry, AudioObjectID) {
+        let mut objects = ObjectRegistry::new();
+        let device = DeviceBuilder::new("Device", "com.example.device")
+            .input_stream(1)
+            .output_stream(2)
+            .build(&mut objects)
+            .unwrap();
+        (objects, device.id())
+    }
+
+    /// Set the value of `selector` in `scope` of `device` to `value`
+    fn write(
+        objects: &mut ObjectRegistry,
+        device: AudioObjectID,
+        selector: u32,
+        scope: PropertyScope,
+        value: u32,
+    ) {
+        let address = PropertyAddress::scoped(selector, scope);
+        let prop = objects.property_mut(device, address).unwrap();
+        *prop
+            .instance_at_mut(address)
+            .unwrap()
+            .value_mut::<u32>()
+            .unwrap() = value;
+    }
+
+    #[test]
+    fn identical_trees_have_no_diff() {
+        let (objects, device) = device();
+        let before = PropertySnapshot::capture(objects.get(device).unwrap());
+        let after = PropertySnapshot::capture(objects.get(device).unwrap());
+        assert!(!before.is_empty());
+        assert_eq!(before.len(), after.len());
+        assert!(before.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn only_mutated_properties_differ() {
+        let (mut objects, device) = device();
+        let before = PropertySnapshot::capture(objects.get(device).unwrap());
+        write(
+            &mut objects,
+            device,
+            kAudioDevicePropertyLatency,
+            PropertyScope::OUTPUT,
+            64,
+        );
+        write(
+            &mut objects,
+            device,
+            kAudioDevicePropertySafetyOffset,
+            PropertyScope::INPUT,
+            32,
+        );
+        let after = PropertySnapshot::capture(objects.get(device).unwrap());
+
+        let mut changed: Vec<_> = before
+            .diff(&after)
+            .into_iter()
+            .map(|(id, address)| (id, u32::from(address.selector), address.scope))
+            .collect();
+        changed.sort_by_key(|&(_, selector, _)| selector);
+        let mut expected = vec![
+            (device, kAudioDevicePropertyLatency, PropertyScope::OUTPUT),
+            (
+                device,
+                kAudioDevicePropertySafetyOffset,
+                PropertyScope::INPUT,
+            ),
+        ];
+        expected.sort_by_key(|&(_, selector, _)| selector);
+        assert_eq!(changed, expected);
+        // the diff goes both ways
+        assert_eq!(after.diff(&before).len(), 2);
+
+        let (_, address) = before.diff(&after)[0];
+        assert_ne!(before.get(device, address), after.get(device, address));
+    }
+
+    #[test]
+    fn properties_of_one_snapshot_only_differ() {
+        let (objects, device) = device();
+        let captured = PropertySnapshot::capture(objects.get(device).unwrap());
+        let empty = PropertySnapshot::default();
+        assert_eq!(captured.diff(&empty).len(), captured.len());
+        assert_eq!(empty.diff(&captured).len(), captured.len());
+    }
+}
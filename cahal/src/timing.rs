@@ -0,0 +1,276 @@
+//! The clock of virtual devices: the zero time stamps reported through `GetZeroTimeStamp`, derived from the host clock
+//! (`mach_absolute_time`) and the sample rate. Reading a time stamp neither allocates nor locks, so it can be done from
+//! the IO thread
+
+use std::{
+    hint,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+use crate::{
+    os_err::{OSStatus, OSStatusError},
+    plugin_driver_interface::ZeroTimeStamp,
+};
+
+#[repr(C)]
+struct MachTimebaseInfo {
+    numer: u32,
+    denom: u32,
+}
+
+unsafe extern "C" {
+    fn mach_absolute_time() -> u64;
+    fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+}
+
+/// The current host time, in the ticks of `mach_absolute_time`
+pub fn host_time() -> u64 {
+    // Safety: always safe to call
+    unsafe { mach_absolute_time() }
+}
+
+/// The number of host time ticks per second, from the mach timebase
+pub fn host_ticks_per_second() -> f64 {
+    let mut info = MachTimebaseInfo { numer: 0, denom: 0 };
+    // Safety: `info` is valid for writes
+    if unsafe { mach_timebase_info(&mut info) } != 0 || info.numer == 0 {
+        // the timebase of Intel machines, where ticks are nanoseconds
+        return 1e9;
+    }
+    1e9 * info.denom as f64 / info.numer as f64
+}
+
+/// Generates the zero time stamps of a device whose ring buffer is `period_frames` frames long and runs at the sample
+/// rate against the host clock.
+///
+/// The clock is anchored at a host time, usually when IO starts (see [`TimestampGenerator::reset`]). The zero time
+/// stamp is the last time the ring buffer wrapped around: after `n` whole periods, the sample time is
+/// `n * period_frames` and the host time is the anchor plus `n` periods in host ticks. Changing the sample rate
+/// re-anchors the clock and changes the seed.
+///
+/// Reads don't lock: they retry if a configuration change happens while reading, which the HAL avoids by stopping IO
+/// around configuration changes
+#[derive(Debug)]
+pub struct TimestampGenerator {
+    period_frames: u32,
+    ticks_per_second: f64,
+    /// Odd while the clock is being changed
+    sequence: AtomicU64,
+    anchor: AtomicU64,
+    /// `f64` bits of the length of a period in host ticks
+    ticks_per_period: AtomicU64,
+    seed: AtomicU64,
+}
+
+impl TimestampGenerator {
+    /// A clock for a ring buffer of `period_frames` frames at `sample_rate`, anchored now. Panics unless both are
+    /// positive
+    pub fn new(period_frames: u32, sample_rate: f64) -> Self {
+        Self::with_host_clock(
+            period_frames,
+            sample_rate,
+            host_ticks_per_second(),
+            host_time(),
+        )
+    }
+    /// A clock against a host clock of `ticks_per_second`, anchored at `anchor`, see [`TimestampGenerator::new`]
+    pub fn with_host_clock(
+        period_frames: u32,
+        sample_rate: f64,
+        ticks_per_second: f64,
+        anchor: u64,
+    ) -> Self {
+        assert!(period_frames > 0, "a ring buffer needs at least one frame");
+        assert!(
+            sample_rate > 0.0,
+            "the sample rate must be positive, not {sample_rate}"
+        );
+        let ticks_per_period = period_frames as f64 * ticks_per_second / sample_rate;
+        Self {
+            period_frames,
+            ticks_per_second,
+            sequence: AtomicU64::new(0),
+            anchor: AtomicU64::new(anchor),
+            ticks_per_period: AtomicU64::new(ticks_per_period.to_bits()),
+            seed: AtomicU64::new(1),
+        }
+    }
+    /// The size of the ring buffer in frames
+    pub fn period_frames(&self) -> u32 {
+        self.period_frames
+    }
+    pub fn sample_rate(&self) -> f64 {
+        let ticks_per_period =
+            self.read(|| f64::from_bits(self.ticks_per_period.load(Ordering::Relaxed)));
+        self.period_frames as f64 * self.ticks_per_second / ticks_per_period
+    }
+    /// Anchor the clock now, so that the next period starts at sample time 0 with a new seed
+    pub fn reset(&self) {
+        self.reset_at(host_time());
+    }
+    /// Anchor the clock at `host_time`. The relation between sample and host time changes, and so does the seed
+    pub fn reset_at(&self, host_time: u64) {
+        self.write(|| {
+            self.anchor.store(host_time, Ordering::Relaxed);
+            self.seed.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    /// Run at `sample_rate` from now on. The clock is anchored anew and the seed changes. Fails with
+    /// [`OSStatusError::DEV_UNSUPPORTED_FMT_ERR`] unless the sample rate is positive
+    pub fn set_sample_rate(&self, sample_rate: f64) -> OSStatus {
+        self.set_sample_rate_at(sample_rate, host_time())
+    }
+    /// Run at `sample_rate` from `host_time` on, see [`TimestampGenerator::set_sample_rate`]
+    pub fn set_sample_rate_at(&self, sample_rate: f64, host_time: u64) -> OSStatus {
+        if sample_rate.is_nan() || sample_rate <= 0.0 {
+            log::error!("cannot run a clock at {sample_rate}Hz");
+            return Err(OSStatusError::DEV_UNSUPPORTED_FMT_ERR);
+        }
+        let ticks_per_period = self.period_frames as f64 * self.ticks_per_second / sample_rate;
+        self.write(|| {
+            self.anchor.store(host_time, Ordering::Relaxed);
+            self.ticks_per_period
+                .store(ticks_per_period.to_bits(), Ordering::Relaxed);
+            self.seed.fetch_add(1, Ordering::Relaxed);
+        });
+        Ok(())
+    }
+    /// The most recent zero time stamp
+    pub fn zero_time_stamp(&self) -> ZeroTimeStamp {
+        self.zero_time_stamp_at(host_time())
+    }
+    /// The most recent zero time stamp as of `host_time`. Host times before the anchor give the anchor
+    pub fn zero_time_stamp_at(&self, host_time: u64) -> ZeroTimeStamp {
+        let (anchor, ticks_per_period, seed) = self.read(|| {
+            (
+                self.anchor.load(Ordering::Relaxed),
+                f64::from_bits(self.ticks_per_period.load(Ordering::Relaxed)),
+                self.seed.load(Ordering::Relaxed),
+            )
+        });
+        let periods = (host_time.saturating_sub(anchor) as f64 / ticks_per_period).floor();
+        ZeroTimeStamp {
+            sample_time: periods * self.period_frames as f64,
+            host_time: anchor + (periods * ticks_per_period) as u64,
+            seed,
+        }
+    }
+    /// Run `f` until it read the fields without a change happening at the same time
+    fn read<R>(&self, f: impl Fn() -> R) -> R {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let value = f();
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return value;
+            }
+        }
+    }
+    /// Change the fields in `f`, excluding other writers and making readers retry
+    fn write(&self, f: impl FnOnce()) {
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
+        loop {
+            if sequence % 2 == 1 {
+                hint::spin_loop();
+                sequence = self.sequence.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.sequence.compare_exchange_weak(
+                sequence,
+                sequence + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => sequence = current,
+            }
+        }
+        fence(Ordering::Release);
+        f();
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One tick per nanosecond, as on Intel machines
+    const TICKS_PER_SECOND: f64 = 1e9;
+    const ANCHOR: u64 = 1_000_000;
+
+    /// A clock of 480 frame periods at 48kHz, which last 10ms
+    fn clock() -> TimestampGenerator {
+        TimestampGenerator::with_host_clock(480, 48000.0, TICKS_PER_SECOND, ANCHOR)
+    }
+
+    #[test]
+    fn time_stamps_advance_by_whole_periods() {
+        let clock = clock();
+        let first = clock.zero_time_stamp_at(ANCHOR);
+        assert_eq!(first.sample_time, 0.0);
+        assert_eq!(first.host_time, ANCHOR);
+        // still in the first period
+        assert_eq!(clock.zero_time_stamp_at(ANCHOR + 9_999_999), first);
+
+        let third = clock.zero_time_stamp_at(ANCHOR + 25_000_000);
+        assert_eq!(third.sample_time, 960.0);
+        assert_eq!(third.host_time, ANCHOR + 20_000_000);
+        assert_eq!(third.seed, first.seed);
+        // host times before the anchor give the anchor
+        assert_eq!(clock.zero_time_stamp_at(0), first);
+    }
+
+    #[test]
+    fn resets_re_anchor_with_a_new_seed() {
+        let clock = clock();
+        let before = clock.zero_time_stamp_at(ANCHOR + 25_000_000);
+        clock.reset_at(ANCHOR + 30_000_000);
+        let after = clock.zero_time_stamp_at(ANCHOR + 30_000_000);
+        assert_eq!(after.sample_time, 0.0);
+        assert_eq!(after.host_time, ANCHOR + 30_000_000);
+        assert_ne!(after.seed, before.seed);
+    }
+
+    #[test]
+    fn sample_rate_changes_re_anchor_with_a_new_seed() {
+        let clock = clock();
+        let before = clock.zero_time_stamp_at(ANCHOR);
+        assert!(clock
+            .set_sample_rate_at(96000.0, ANCHOR + 5_000_000)
+            .is_ok());
+        assert_eq!(clock.sample_rate(), 96000.0);
+        // periods last 5ms from the new anchor on
+        let after = clock.zero_time_stamp_at(ANCHOR + 16_000_000);
+        assert_eq!(after.sample_time, 960.0);
+        assert_eq!(after.host_time, ANCHOR + 15_000_000);
+        assert_ne!(after.seed, before.seed);
+    }
+
+    #[test]
+    fn invalid_sample_rates_leave_the_clock_alone() {
+        let clock = clock();
+        let before = clock.zero_time_stamp_at(ANCHOR + 25_000_000);
+        for sample_rate in [0.0, -48000.0, f64::NAN] {
+            assert!(clock.set_sample_rate_at(sample_rate, ANCHOR).is_err());
+        }
+        assert_eq!(clock.sample_rate(), 48000.0);
+        assert_eq!(clock.zero_time_stamp_at(ANCHOR + 25_000_000), before);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_periods_are_rejected() {
+        TimestampGenerator::with_host_clock(0, 48000.0, TICKS_PER_SECOND, ANCHOR);
+    }
+
+    #[test]
+    #[should_panic]
+    fn nan_sample_rates_are_rejected() {
+        TimestampGenerator::with_host_clock(480, f64::NAN, TICKS_PER_SECOND, ANCHOR);
+    }
+}
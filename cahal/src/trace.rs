@@ -0,0 +1,299 @@
+//! Tracing of property accesses, for figuring out which request a client choked on. Only compiled with the `trace` feature.
+//!
+//! Every property call of the HAL produces a [`TraceEvent`] carrying the pid of the client it was made for, and so does
+//! every access through a [`TracedProperty`]. Events are logged at debug level, or handed to the hook installed with
+//! [`set_hook`] instead
+
+use std::{any::Any, ffi::c_void, fmt, sync::RwLock};
+
+use coreaudio_sys::{kAudioObjectUnknown, pid_t, AudioObjectID};
+
+use crate::{
+    os_err::{OSStatus, OSStatusError},
+    property::{PropertyAddress, PropertySelector, Qualifier, RawProperty},
+};
+
+/// What kind of property access happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    HasProperty,
+    IsSettable,
+    GetSize,
+    Get,
+    Set,
+}
+
+/// A single property access
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEvent {
+    pub kind: AccessKind,
+    pub object: AudioObjectID,
+    pub address: PropertyAddress,
+    /// Size of the buffer (for [`AccessKind::Get`]) or the data (for [`AccessKind::Set`]) the client passed
+    pub requested_size: u32,
+    /// Size reported back to the client, if any
+    pub returned_size: Option<u32>,
+    pub status: Result<(), OSStatusError>,
+    /// The process that made the request, if known
+    pub client_pid: Option<pid_t>,
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} on object {} (scope {:?}, element {:?}): requested {} bytes",
+            self.kind,
+            self.address.selector,
+            self.object,
+            self.address.scope,
+            self.address.element,
+            self.requested_size,
+        )?;
+        if let Some(size) = self.returned_size {
+            write!(f, ", returned {size} bytes")?;
+        }
+        match self.status {
+            Ok(()) => write!(f, ", ok")?,
+            Err(err) => write!(f, ", failed with {err:?}")?,
+        }
+        if let Some(pid) = self.client_pid {
+            write!(f, " (client pid {pid})")?;
+        }
+        Ok(())
+    }
+}
+
+type Hook = Box<dyn Fn(&TraceEvent) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Send all trace events to `hook` instead of the log
+pub fn set_hook(hook: impl Fn(&TraceEvent) + Send + Sync + 'static) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(hook));
+}
+
+/// Go back to logging trace events
+pub fn clear_hook() {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Report an access to the installed hook, or the log
+pub fn emit(event: TraceEvent) {
+    match &*HOOK.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(hook) => hook(&event),
+        None => log::debug!("{event}"),
+    }
+}
+
+/// A property wrapper that traces every access made through it. Accesses through the wrapper don't know which client
+/// made them, so the events carry no pid
+pub struct TracedProperty<P> {
+    object: AudioObjectID,
+    inner: P,
+}
+
+impl<P> TracedProperty<P> {
+    /// Trace accesses to `inner`, which lives on the object `object`
+    pub fn new(object: AudioObjectID, inner: P) -> Self {
+        Self { object, inner }
+    }
+    /// Trace accesses to `inner` without attributing them to an object
+    pub fn unattributed(inner: P) -> Self {
+        Self::new(kAudioObjectUnknown, inner)
+    }
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for TracedProperty<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TracedProperty")
+            .field("object", &self.object)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<P: RawProperty> TracedProperty<P> {
+    fn emit(
+        &self,
+        kind: AccessKind,
+        address: PropertyAddress,
+        requested_size: u32,
+        returned_size: Option<u32>,
+        status: OSStatus,
+    ) {
+        emit(TraceEvent {
+            kind,
+            object: self.object,
+            address,
+            requested_size,
+            returned_size,
+            status,
+            client_pid: None,
+        });
+    }
+}
+
+impl<P: RawProperty> RawProperty for TracedProperty<P> {
+    fn selector(&self) -> PropertySelector {
+        self.inner.selector()
+    }
+
+    fn byte_size(&self) -> u32 {
+        self.inner.byte_size()
+    }
+
+    fn is_mut(&self) -> bool {
+        self.inner.is_mut()
+    }
+
+    fn is_present(&self) -> bool {
+        self.inner.is_present()
+    }
+
+    fn dependents(&self) -> &'static [u32] {
+        self.inner.dependents()
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.inner.is_deferred()
+    }
+
+    #[allow(deprecated)]
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    #[allow(deprecated)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self.inner.as_any_mut()
+    }
+
+    fn value_bytes(&self) -> Option<Vec<u8>> {
+        self.inner.value_bytes()
+    }
+
+    unsafe fn set(&mut self, data: *const c_void, data_size: u32) -> OSStatus {
+        let address = PropertyAddress::global(self.selector().into());
+        unsafe { self.set_at(address, Qualifier::NONE, data, data_size) }
+    }
+
+    unsafe fn get(
+        &self,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let address = PropertyAddress::global(self.selector().into());
+        unsafe {
+            self.get_at(
+                address,
+                Qualifier::NONE,
+                out_alloc_size,
+                data_out,
+                data_len_out,
+            )
+        }
+    }
+
+    fn byte_size_qualified(&self, qualifier: Qualifier<'_>) -> u32 {
+        let address = PropertyAddress::global(self.selector().into());
+        self.byte_size_at(address, qualifier)
+    }
+
+    unsafe fn set_qualified(
+        &mut self,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let address = PropertyAddress::global(self.selector().into());
+        unsafe { self.set_at(address, qualifier, data, data_size) }
+    }
+
+    unsafe fn get_qualified(
+        &self,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let address = PropertyAddress::global(self.selector().into());
+        unsafe { self.get_at(address, qualifier, out_alloc_size, data_out, data_len_out) }
+    }
+
+    fn byte_size_at(&self, address: PropertyAddress, qualifier: Qualifier<'_>) -> u32 {
+        let size = self.inner.byte_size_at(address, qualifier);
+        self.emit(AccessKind::GetSize, address, 0, Some(size), Ok(()));
+        size
+    }
+
+    unsafe fn set_at(
+        &mut self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let status = unsafe { self.inner.set_at(address, qualifier, data, data_size) };
+        self.emit(AccessKind::Set, address, data_size, None, status);
+        status
+    }
+
+    unsafe fn set_by_client(
+        &mut self,
+        client_pid: pid_t,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        data: *const c_void,
+        data_size: u32,
+    ) -> OSStatus {
+        let status = unsafe {
+            self.inner
+                .set_by_client(client_pid, address, qualifier, data, data_size)
+        };
+        emit(TraceEvent {
+            kind: AccessKind::Set,
+            object: self.object,
+            address,
+            requested_size: data_size,
+            returned_size: None,
+            status,
+            client_pid: Some(client_pid),
+        });
+        status
+    }
+
+    unsafe fn get_at(
+        &self,
+        address: PropertyAddress,
+        qualifier: Qualifier<'_>,
+        out_alloc_size: u32,
+        data_out: *mut c_void,
+        data_len_out: *mut u32,
+    ) -> OSStatus {
+        let status = unsafe {
+            self.inner
+                .get_at(address, qualifier, out_alloc_size, data_out, data_len_out)
+        };
+        let returned_size =
+            (status.is_ok() && !data_len_out.is_null()).then(|| unsafe { *data_len_out });
+        self.emit(
+            AccessKind::Get,
+            address,
+            out_alloc_size,
+            returned_size,
+            status,
+        );
+        status
+    }
+}
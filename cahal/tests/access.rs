@@ -0,0 +1,172 @@
+//! Processes can be denied access to properties and IO, per pid
+mod common;
+
+use std::{
+    mem,
+    sync::{Mutex, PoisonError},
+};
+
+use cahal::{
+    audio_object::{DeviceBuilder, DeviceHandle, ObjectRegistry, PluginObject},
+    base::{
+        kAudioBooleanControlPropertyValue, kAudioDevicePermissionsError,
+        kAudioDevicePropertyDeviceIsRunning, kAudioObjectPropertyName, pid_t, AudioObjectID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::{OSStatus, OSStatusError},
+    plugin_driver_interface::{AccessOp, AudioServerPluginDriverInterface, LoggingConfig},
+    property::{PropertyAddress, PropertyScope},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::Driver;
+
+/// The only process allowed to mute the device
+const COMPANION: pid_t = 100;
+/// A process that may neither read properties nor start IO
+const BLOCKED: pid_t = 666;
+/// Any other process
+const OTHER: pid_t = 42;
+
+/// A device with an output mute control, which lets only [`COMPANION`] mute it and keeps [`BLOCKED`] out entirely,
+/// recording what it was asked
+struct AccessDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+    asked: Mutex<Vec<(pid_t, AudioObjectID, u32, AccessOp)>>,
+}
+
+impl AccessDriver {
+    fn mute(&self) -> AudioObjectID {
+        self.device.controls().next().unwrap()
+    }
+    /// The checks since the last call, as the pid, the object, the selector and the operation
+    fn take_asked(&self) -> Vec<(pid_t, AudioObjectID, u32, AccessOp)> {
+        mem::take(&mut *self.asked.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl AudioServerPluginDriverInterface for AccessDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "access";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let mut objects = ObjectRegistry::new();
+        let mut plugin = PluginObject::new("cahal", "com.example.test");
+        plugin.attach(&objects);
+        objects.register(Box::new(plugin)).unwrap();
+        let device = DeviceBuilder::new("Test Device", "com.example.test.device")
+            .output_stream(2)
+            .mute_control(PropertyScope::OUTPUT)
+            .build(&mut objects)
+            .unwrap();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+            asked: Mutex::default(),
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn check_access(
+        &self,
+        pid: pid_t,
+        object: AudioObjectID,
+        address: &PropertyAddress,
+        op: AccessOp,
+    ) -> OSStatus {
+        self.asked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((pid, object, address.selector.into(), op));
+        let mutes =
+            op == AccessOp::Set && address.selector == kAudioBooleanControlPropertyValue.into();
+        if pid == BLOCKED || (mutes && pid != COMPANION) {
+            // any error denies, the HAL always sees a permission error
+            return Err(OSStatusError::HW_ILLEGAL_OPERATION_ERR);
+        }
+        Ok(())
+    }
+}
+
+const MUTE: PropertyAddress = PropertyAddress::global(kAudioBooleanControlPropertyValue);
+const NAME: PropertyAddress = PropertyAddress::global(kAudioObjectPropertyName);
+const RUNNING: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyDeviceIsRunning);
+const DENIED: i32 = kAudioDevicePermissionsError as i32;
+
+#[test]
+fn sets_are_allowed_per_pid() {
+    let driver = Driver::<AccessDriver>::initialized();
+    let mute = driver.state().mute();
+    assert_eq!(driver.set(mute, OTHER, MUTE, &1u32), DENIED);
+    assert_eq!(driver.get::<u32>(mute, OTHER, MUTE), Ok(0));
+    let value = kAudioBooleanControlPropertyValue;
+    assert_eq!(
+        driver.state().take_asked(),
+        [
+            (OTHER, mute, value, AccessOp::Set),
+            (OTHER, mute, value, AccessOp::Get),
+        ]
+    );
+    assert!(driver.host.take_changed().is_empty());
+
+    assert_eq!(driver.set(mute, COMPANION, MUTE, &1u32), 0);
+    assert_eq!(driver.get::<u32>(mute, OTHER, MUTE), Ok(1));
+    assert_eq!(driver.host.take_changed(), [(mute, value)]);
+}
+
+#[test]
+fn reads_are_denied_to_blocked_processes() {
+    let driver = Driver::<AccessDriver>::initialized();
+    let device = driver.state().device.id();
+    let mute = driver.state().mute();
+    assert_eq!(driver.get::<u32>(mute, BLOCKED, MUTE), Err(DENIED));
+    assert_eq!(driver.size(device, BLOCKED, NAME), Err(DENIED));
+    assert_eq!(
+        driver.state().take_asked(),
+        [
+            (
+                BLOCKED,
+                mute,
+                kAudioBooleanControlPropertyValue,
+                AccessOp::Get
+            ),
+            (BLOCKED, device, kAudioObjectPropertyName, AccessOp::Get),
+        ]
+    );
+    assert_eq!(driver.get::<u32>(mute, OTHER, MUTE), Ok(0));
+    assert!(driver.size(device, OTHER, NAME).is_ok());
+}
+
+#[test]
+fn io_starts_are_checked_with_the_pid_of_the_client() {
+    let driver = Driver::<AccessDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, BLOCKED), 0);
+    assert_eq!(driver.add_client(device, 2, OTHER), 0);
+    let _ = driver.state().take_asked();
+
+    assert_eq!(driver.start_io(device, 1), DENIED);
+    assert_eq!(driver.get::<u32>(device, OTHER, RUNNING), Ok(0));
+    assert_eq!(driver.start_io(device, 2), 0);
+    // clients the HAL didn't add have no pid
+    assert_eq!(driver.start_io(device, 3), 0);
+    let running = kAudioDevicePropertyDeviceIsRunning;
+    assert_eq!(
+        driver.state().take_asked(),
+        [
+            (BLOCKED, device, running, AccessOp::StartIo),
+            (OTHER, device, running, AccessOp::Get),
+            (OTHER, device, running, AccessOp::StartIo),
+            (-1, device, running, AccessOp::StartIo),
+        ]
+    );
+    assert_eq!(driver.stop_io(device, 2), 0);
+    assert_eq!(driver.stop_io(device, 3), 0);
+}
@@ -0,0 +1,463 @@
+//! Drives drivers through their plug-in interface the way `coreaudiod` does, with a host that records what the driver
+//! tells it
+#![allow(dead_code)] // every test uses a different part of the harness
+
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use cahal::{
+    audio_object::{DeviceBuilder, DeviceHandle, ObjectRegistry, PluginObject},
+    base::{
+        kAudioHardwareIllegalOperationError, kAudioHardwareUnsupportedOperationError,
+        AudioObjectID, AudioObjectPropertyAddress, AudioServerPlugInClientInfo,
+        AudioServerPlugInDriverInterface, AudioServerPlugInDriverRef,
+        AudioServerPlugInHostInterface, AudioServerPlugInHostRef, AudioServerPlugInIOCycleInfo,
+        CFDictionaryRef, CFPropertyListRef, CFStringRef, OSStatus, REFIID,
+    },
+    core_foundation::{
+        base::TCFType,
+        uuid::{CFUUIDBytes, CFUUIDCreateFromUUIDBytes, CFUUID},
+    },
+    plugin_driver_interface::{checked_driver_state, AudioServerPluginDriverInterface},
+    property::PropertyAddress,
+    raw_plugin_driver_interface::RawAudioServerPlugInDriverInterface,
+};
+
+/// `kAudioServerPlugInTypeUUID`, the type `coreaudiod` creates drivers for
+pub const PLUGIN_TYPE: [u8; 16] = [
+    0x44, 0x3A, 0xBA, 0xB8, 0xE7, 0xB3, 0x49, 0x1A, 0xB9, 0x85, 0xBE, 0xB9, 0x18, 0x70, 0x30, 0xDB,
+];
+/// `kAudioServerPlugInDriverInterfaceUUID`
+pub const DRIVER_INTERFACE: [u8; 16] = [
+    0xEE, 0xA5, 0x77, 0x3D, 0xCC, 0x43, 0x49, 0xF1, 0x8E, 0x00, 0x8F, 0x96, 0xE7, 0xD2, 0x3B, 0x17,
+];
+/// `IUnknownUUID`
+pub const IUNKNOWN: [u8; 16] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+
+pub fn uuid(bytes: [u8; 16]) -> CFUUID {
+    // Safety: `CFUUIDBytes` is 16 bytes in order
+    let bytes = unsafe { mem::transmute::<[u8; 16], CFUUIDBytes>(bytes) };
+    unsafe { CFUUID::wrap_under_create_rule(CFUUIDCreateFromUUIDBytes(ptr::null(), bytes)) }
+}
+
+/// A host that records the property changes and configuration change requests of the driver
+#[repr(C)]
+pub struct MockHost {
+    // first, so the host ref the driver gets is a pointer to the whole host
+    interface: AudioServerPlugInHostInterface,
+    changed: Mutex<Vec<(AudioObjectID, PropertyAddress)>>,
+    requests: Mutex<Vec<(AudioObjectID, u64, usize)>>,
+    /// Refuse configuration change requests with `kAudioHardwareIllegalOperationError`
+    pub deny_requests: AtomicBool,
+}
+
+impl MockHost {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {
+            interface: AudioServerPlugInHostInterface {
+                PropertiesChanged: Some(Self::properties_changed),
+                CopyFromStorage: Some(Self::copy_from_storage),
+                WriteToStorage: Some(Self::write_to_storage),
+                DeleteFromStorage: Some(Self::delete_from_storage),
+                RequestDeviceConfigurationChange: Some(Self::request_change),
+            },
+            changed: Mutex::default(),
+            requests: Mutex::default(),
+            deny_requests: AtomicBool::new(false),
+        })
+    }
+    pub fn host_ref(&self) -> AudioServerPlugInHostRef {
+        &self.interface
+    }
+    /// The properties reported changed since the last call, as the object and the selector
+    pub fn take_changed(&self) -> Vec<(AudioObjectID, u32)> {
+        mem::take(&mut *self.changed.lock().unwrap_or_else(PoisonError::into_inner))
+            .into_iter()
+            .map(|(object, address)| (object, address.selector.into()))
+            .collect()
+    }
+    /// The configuration changes requested since the last call, as the device, the action and the change info
+    pub fn take_requests(&self) -> Vec<(AudioObjectID, u64, *mut c_void)> {
+        mem::take(&mut *self.requests.lock().unwrap_or_else(PoisonError::into_inner))
+            .into_iter()
+            .map(|(device, action, info)| (device, action, info as *mut c_void))
+            .collect()
+    }
+
+    unsafe fn of<'a>(host: AudioServerPlugInHostRef) -> &'a Self {
+        unsafe { &*host.cast::<Self>() }
+    }
+    unsafe extern "C" fn properties_changed(
+        host: AudioServerPlugInHostRef,
+        object: AudioObjectID,
+        count: u32,
+        addresses: *const AudioObjectPropertyAddress,
+    ) -> OSStatus {
+        let addresses = unsafe { std::slice::from_raw_parts(addresses, count as usize) };
+        let host = unsafe { Self::of(host) };
+        let mut changed = host.changed.lock().unwrap_or_else(PoisonError::into_inner);
+        changed.extend(addresses.iter().map(|&address| (object, address.into())));
+        0
+    }
+    unsafe extern "C" fn copy_from_storage(
+        _host: AudioServerPlugInHostRef,
+        _key: CFStringRef,
+        _out: *mut CFPropertyListRef,
+    ) -> OSStatus {
+        kAudioHardwareUnsupportedOperationError as OSStatus
+    }
+    unsafe extern "C" fn write_to_storage(
+        _host: AudioServerPlugInHostRef,
+        _key: CFStringRef,
+        _data: CFPropertyListRef,
+    ) -> OSStatus {
+        kAudioHardwareUnsupportedOperationError as OSStatus
+    }
+    unsafe extern "C" fn delete_from_storage(
+        _host: AudioServerPlugInHostRef,
+        _key: CFStringRef,
+    ) -> OSStatus {
+        kAudioHardwareUnsupportedOperationError as OSStatus
+    }
+    unsafe extern "C" fn request_change(
+        host: AudioServerPlugInHostRef,
+        device: AudioObjectID,
+        action: u64,
+        info: *mut c_void,
+    ) -> OSStatus {
+        let host = unsafe { Self::of(host) };
+        if host.deny_requests.load(Ordering::Acquire) {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let mut requests = host.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        requests.push((device, action, info as usize));
+        0
+    }
+}
+
+/// A registry with the plug-in object and an output device with one stereo stream, running at 44.1kHz or 48kHz
+pub fn registry_with_device() -> (ObjectRegistry, DeviceHandle) {
+    let mut objects = ObjectRegistry::new();
+    let mut plugin = PluginObject::new("cahal", "com.example.test");
+    plugin.attach(&objects);
+    objects.register(Box::new(plugin)).unwrap();
+    let device = DeviceBuilder::new("Test Device", "com.example.test.device")
+        .sample_rates(&[44100.0, 48000.0])
+        .output_stream(2)
+        .build(&mut objects)
+        .unwrap();
+    (objects, device)
+}
+
+/// A driver created through its factory function, called through its function table
+pub struct Driver<T> {
+    pub raw: AudioServerPlugInDriverRef,
+    pub host: Box<MockHost>,
+    _driver: PhantomData<T>,
+}
+
+impl<
+        T: AudioServerPluginDriverInterface + RawAudioServerPlugInDriverInterface + Sync + 'static,
+    > Driver<T>
+{
+    /// Create the driver like `coreaudiod` does, without initializing it
+    pub fn create() -> Self {
+        let uuid = uuid(PLUGIN_TYPE);
+        let raw = unsafe {
+            <T as RawAudioServerPlugInDriverInterface>::create(
+                ptr::null(),
+                uuid.as_concrete_TypeRef().cast(),
+            )
+        };
+        assert!(!raw.is_null(), "driver creation failed");
+        Self {
+            raw: raw.cast(),
+            host: MockHost::new(),
+            _driver: PhantomData,
+        }
+    }
+    /// Create and initialize the driver
+    pub fn initialized() -> Self {
+        let driver = Self::create();
+        assert_eq!(driver.initialize(), 0);
+        driver
+    }
+    pub fn vtable(&self) -> &AudioServerPlugInDriverInterface {
+        unsafe { &**self.raw }
+    }
+    pub fn state(&self) -> &T {
+        unsafe { checked_driver_state(self.raw) }.expect("the driver is alive")
+    }
+
+    pub fn initialize(&self) -> OSStatus {
+        unsafe { self.vtable().Initialize.unwrap()(self.raw, self.host.host_ref()) }
+    }
+    /// `QueryInterface` for `interface`, returning the status and the interface written out
+    pub fn query_interface(&self, interface: [u8; 16]) -> (i32, *mut c_void) {
+        let mut out = ptr::null_mut();
+        // Safety: `REFIID` is 16 bytes in order
+        let interface = unsafe { mem::transmute::<[u8; 16], REFIID>(interface) };
+        let status =
+            unsafe { self.vtable().QueryInterface.unwrap()(self.raw.cast(), interface, &mut out) };
+        (status, out)
+    }
+    pub fn add_ref(&self) -> u32 {
+        unsafe { self.vtable().AddRef.unwrap()(self.raw.cast()) }
+    }
+    /// `Release`. The driver may be gone afterwards, so this is the last call for drivers that free themselves at zero
+    pub fn release(&self) -> u32 {
+        unsafe { self.vtable().Release.unwrap()(self.raw.cast()) }
+    }
+
+    pub fn has_property(&self, object: AudioObjectID, pid: i32, address: PropertyAddress) -> bool {
+        let address = address.into();
+        unsafe { self.vtable().HasProperty.unwrap()(self.raw, object, pid, &address) != 0 }
+    }
+    pub fn is_settable(
+        &self,
+        object: AudioObjectID,
+        pid: i32,
+        address: PropertyAddress,
+    ) -> Result<bool, OSStatus> {
+        let address = address.into();
+        let mut out = 0;
+        let status = unsafe {
+            self.vtable().IsPropertySettable.unwrap()(self.raw, object, pid, &address, &mut out)
+        };
+        if status == 0 {
+            Ok(out != 0)
+        } else {
+            Err(status)
+        }
+    }
+    pub fn size(
+        &self,
+        object: AudioObjectID,
+        pid: i32,
+        address: PropertyAddress,
+    ) -> Result<u32, OSStatus> {
+        let address = address.into();
+        let mut out = 0;
+        let status = unsafe {
+            self.vtable().GetPropertyDataSize.unwrap()(
+                self.raw,
+                object,
+                pid,
+                &address,
+                0,
+                ptr::null(),
+                &mut out,
+            )
+        };
+        if status == 0 {
+            Ok(out)
+        } else {
+            Err(status)
+        }
+    }
+    /// Read a property holding a `V`, checking that exactly that many bytes were written
+    pub fn get<V: Copy>(
+        &self,
+        object: AudioObjectID,
+        pid: i32,
+        address: PropertyAddress,
+    ) -> Result<V, OSStatus> {
+        let address = address.into();
+        let mut out = MaybeUninit::<V>::uninit();
+        let mut written = 0;
+        let status = unsafe {
+            self.vtable().GetPropertyData.unwrap()(
+                self.raw,
+                object,
+                pid,
+                &address,
+                0,
+                ptr::null(),
+                mem::size_of::<V>() as u32,
+                &mut written,
+                out.as_mut_ptr().cast(),
+            )
+        };
+        if status != 0 {
+            return Err(status);
+        }
+        assert_eq!(written as usize, mem::size_of::<V>());
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn set<V>(
+        &self,
+        object: AudioObjectID,
+        pid: i32,
+        address: PropertyAddress,
+        value: &V,
+    ) -> OSStatus {
+        let address = address.into();
+        unsafe {
+            self.vtable().SetPropertyData.unwrap()(
+                self.raw,
+                object,
+                pid,
+                &address,
+                0,
+                ptr::null(),
+                mem::size_of::<V>() as u32,
+                (value as *const V).cast(),
+            )
+        }
+    }
+
+    fn client_info(client: u32, pid: i32) -> AudioServerPlugInClientInfo {
+        AudioServerPlugInClientInfo {
+            mClientID: client,
+            mProcessID: pid,
+            mIsNativeEndian: 1,
+            mBundleID: ptr::null(),
+        }
+    }
+    pub fn add_client(&self, device: AudioObjectID, client: u32, pid: i32) -> OSStatus {
+        let info = Self::client_info(client, pid);
+        unsafe { self.vtable().AddDeviceClient.unwrap()(self.raw, device, &info) }
+    }
+    pub fn remove_client(&self, device: AudioObjectID, client: u32, pid: i32) -> OSStatus {
+        let info = Self::client_info(client, pid);
+        unsafe { self.vtable().RemoveDeviceClient.unwrap()(self.raw, device, &info) }
+    }
+    /// `CreateDevice` from the description `desc` on behalf of `client`, returning the status and the new device
+    pub fn create_device(&self, desc: CFDictionaryRef, client: u32) -> (OSStatus, AudioObjectID) {
+        let info = Self::client_info(client, 42);
+        let mut device = 0;
+        let status =
+            unsafe { self.vtable().CreateDevice.unwrap()(self.raw, desc, &info, &mut device) };
+        (status, device)
+    }
+    pub fn destroy_device(&self, device: AudioObjectID) -> OSStatus {
+        unsafe { self.vtable().DestroyDevice.unwrap()(self.raw, device) }
+    }
+    pub fn start_io(&self, device: AudioObjectID, client: u32) -> OSStatus {
+        unsafe { self.vtable().StartIO.unwrap()(self.raw, device, client) }
+    }
+    pub fn stop_io(&self, device: AudioObjectID, client: u32) -> OSStatus {
+        unsafe { self.vtable().StopIO.unwrap()(self.raw, device, client) }
+    }
+    /// The zero time stamp of `device` as the sample time, the host time and the seed
+    pub fn zero_time_stamp(
+        &self,
+        device: AudioObjectID,
+        client: u32,
+    ) -> Result<(f64, u64, u64), OSStatus> {
+        let (mut sample_time, mut host_time, mut seed) = (0.0, 0, 0);
+        let status = unsafe {
+            self.vtable().GetZeroTimeStamp.unwrap()(
+                self.raw,
+                device,
+                client,
+                &mut sample_time,
+                &mut host_time,
+                &mut seed,
+            )
+        };
+        if status == 0 {
+            Ok((sample_time, host_time, seed))
+        } else {
+            Err(status)
+        }
+    }
+    /// Whether the driver does the operation `op` on `device`, and whether in place
+    pub fn will_do_io(
+        &self,
+        device: AudioObjectID,
+        client: u32,
+        op: u32,
+    ) -> Result<(bool, bool), OSStatus> {
+        let (mut will_do, mut in_place) = (0, 0);
+        let status = unsafe {
+            self.vtable().WillDoIOOperation.unwrap()(
+                self.raw,
+                device,
+                client,
+                op,
+                &mut will_do,
+                &mut in_place,
+            )
+        };
+        if status == 0 {
+            Ok((will_do != 0, in_place != 0))
+        } else {
+            Err(status)
+        }
+    }
+    /// `BeginIOOperation` of `op` for a cycle of `frames` frames
+    pub fn begin_io(
+        &self,
+        device: AudioObjectID,
+        client: u32,
+        op: u32,
+        frames: u32,
+        cycle: &AudioServerPlugInIOCycleInfo,
+    ) -> OSStatus {
+        unsafe {
+            self.vtable().BeginIOOperation.unwrap()(self.raw, device, client, op, frames, cycle)
+        }
+    }
+    /// `DoIOOperation` on `stream` with `main` as the main buffer, which holds `frames` frames of the stream
+    #[allow(clippy::too_many_arguments)]
+    pub fn do_io(
+        &self,
+        device: AudioObjectID,
+        stream: AudioObjectID,
+        client: u32,
+        op: u32,
+        frames: u32,
+        cycle: &AudioServerPlugInIOCycleInfo,
+        main: &mut [f32],
+    ) -> OSStatus {
+        unsafe {
+            self.vtable().DoIOOperation.unwrap()(
+                self.raw,
+                device,
+                stream,
+                client,
+                op,
+                frames,
+                cycle,
+                main.as_mut_ptr().cast(),
+                ptr::null_mut(),
+            )
+        }
+    }
+    /// `EndIOOperation` of `op` for a cycle of `frames` frames
+    pub fn end_io(
+        &self,
+        device: AudioObjectID,
+        client: u32,
+        op: u32,
+        frames: u32,
+        cycle: &AudioServerPlugInIOCycleInfo,
+    ) -> OSStatus {
+        unsafe {
+            self.vtable().EndIOOperation.unwrap()(self.raw, device, client, op, frames, cycle)
+        }
+    }
+    /// Perform the configuration change the driver requested with `info`
+    pub fn perform(&self, device: AudioObjectID, action: u64, info: *mut c_void) -> OSStatus {
+        unsafe {
+            self.vtable().PerformDeviceConfigurationChange.unwrap()(self.raw, device, action, info)
+        }
+    }
+    /// Abort the configuration change the driver requested with `info`
+    pub fn abort(&self, device: AudioObjectID, action: u64, info: *mut c_void) -> OSStatus {
+        unsafe {
+            self.vtable().AbortDeviceConfigurationChange.unwrap()(self.raw, device, action, info)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+mod common;
+
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry},
+    base::{
+        kAudioDevicePropertyLatency, kAudioHardwareIllegalOperationError,
+        kAudioHardwareNotReadyError, AudioObjectID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::{result_to_err_code, OSStatus, OSStatusError},
+    plugin_driver_interface::{AudioServerPluginDriverInterface, ConfigChangeGuard, LoggingConfig},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver};
+
+/// A change info that counts how often it was dropped
+struct Payload {
+    value: u32,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drop for Payload {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Records the values of the changes it performed and aborted, failing to perform changes of 0
+struct ChangeDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+    host: OnceLock<PluginHostInterface<Self>>,
+    handled: Mutex<Vec<(&'static str, u32)>>,
+    /// What requesting a guarded change from within `init` returned
+    early: OnceLock<OSStatus>,
+}
+
+impl ChangeDriver {
+    fn request(&self, value: u32, drops: &Arc<AtomicUsize>) -> OSStatus {
+        let payload = Payload {
+            value,
+            drops: drops.clone(),
+        };
+        self.host
+            .get()
+            .unwrap()
+            .request_change(self.device.id(), payload)
+    }
+    /// Request a change through a [`ConfigChangeGuard`] whose mutation counts its runs in `applied` and marks the
+    /// latency of the device as changed
+    fn guard(&self, value: u32, drops: &Arc<AtomicUsize>, applied: &Arc<AtomicUsize>) -> OSStatus {
+        let payload = Payload {
+            value,
+            drops: drops.clone(),
+        };
+        let device = self.device.id();
+        let applied = applied.clone();
+        let guard = ConfigChangeGuard::new(device, payload, move |objects, changes| {
+            assert!(objects.contains(device));
+            applied.fetch_add(1, Ordering::SeqCst);
+            changes.mark_selector(device, kAudioDevicePropertyLatency);
+            Ok(())
+        });
+        guard.request(&self.context().unwrap())
+    }
+    fn take_handled(&self) -> Vec<(&'static str, u32)> {
+        std::mem::take(&mut *self.handled.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    fn handle(&self, how: &'static str, info: &Payload) {
+        let mut handled = self.handled.lock().unwrap_or_else(PoisonError::into_inner);
+        handled.push((how, info.value));
+        // still alive while the driver handles it
+        assert_eq!(info.drops.load(Ordering::SeqCst), 0);
+    }
+}
+
+impl AudioServerPluginDriverInterface for ChangeDriver {
+    type DeviceConfigurationChangeInfo = Payload;
+    const NAME: &'static str = "changes";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+            host: OnceLock::new(),
+            handled: Mutex::default(),
+            early: OnceLock::new(),
+        }
+    }
+    fn init(&self, host: PluginHostInterface<Self>) -> OSStatus {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let applied = Arc::new(AtomicUsize::new(0));
+        let _ = self.early.set(self.guard(1, &drops, &applied));
+        let _ = self.host.set(host);
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn perform_change(&self, device: AudioObjectID, info: Payload) -> OSStatus {
+        assert_eq!(device, self.device.id());
+        self.handle("perform", &info);
+        if info.value == 0 {
+            return Err(OSStatusError::HW_UNSPECIFIED_ERR);
+        }
+        Ok(())
+    }
+    fn abort_change(&self, device: AudioObjectID, info: Payload) -> OSStatus {
+        assert_eq!(device, self.device.id());
+        self.handle("abort", &info);
+        Ok(())
+    }
+}
+
+#[test]
+fn denied_requests_drop_the_info_right_away() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    driver.host.deny_requests.store(true, Ordering::Release);
+    let drops = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().request(1, &drops).is_err());
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(driver.host.take_requests().is_empty());
+    assert!(driver.state().take_handled().is_empty());
+}
+
+#[test]
+fn performed_changes_hand_the_info_over_once() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().request(7, &drops).is_ok());
+    // the host owns the info until it performs the change
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    let requests = driver.host.take_requests();
+    let [(device, action, info)] = requests[..] else {
+        panic!("expected one request, got {requests:?}");
+    };
+    assert_eq!(device, driver.state().device.id());
+
+    assert_eq!(driver.perform(device, action, info), 0);
+    assert_eq!(driver.state().take_handled(), [("perform", 7)]);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn aborted_changes_hand_the_info_over_once() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().request(7, &drops).is_ok());
+    let requests = driver.host.take_requests();
+    let [(device, action, info)] = requests[..] else {
+        panic!("expected one request, got {requests:?}");
+    };
+
+    assert_eq!(driver.abort(device, action, info), 0);
+    assert_eq!(driver.state().take_handled(), [("abort", 7)]);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn failed_changes_still_drop_the_info_once() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().request(0, &drops).is_ok());
+    let requests = driver.host.take_requests();
+    let [(device, action, info)] = requests[..] else {
+        panic!("expected one request, got {requests:?}");
+    };
+
+    assert_ne!(driver.perform(device, action, info), 0);
+    assert_eq!(driver.state().take_handled(), [("perform", 0)]);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn changes_come_back_in_any_order() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops: [_; 3] = std::array::from_fn(|_| Arc::new(AtomicUsize::new(0)));
+    for (value, drops) in (1..).zip(&drops) {
+        assert!(driver.state().request(value, drops).is_ok());
+    }
+    let requests = driver.host.take_requests();
+    assert_eq!(requests.len(), 3);
+    let (device, action) = (requests[0].0, requests[0].1);
+
+    assert_eq!(driver.abort(device, action, requests[1].2), 0);
+    assert_eq!(driver.perform(device, action, requests[2].2), 0);
+    assert_eq!(driver.perform(device, action, requests[0].2), 0);
+    assert_eq!(
+        driver.state().take_handled(),
+        [("abort", 2), ("perform", 3), ("perform", 1)]
+    );
+    assert!(drops.iter().all(|drops| drops.load(Ordering::SeqCst) == 1));
+}
+
+#[test]
+fn missing_infos_are_rejected() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let device = driver.state().device.id();
+    let illegal = kAudioHardwareIllegalOperationError as i32;
+    assert_eq!(driver.perform(device, 0, ptr::null_mut()), illegal);
+    assert_eq!(driver.abort(device, 0, ptr::null_mut()), illegal);
+    assert!(driver.state().take_handled().is_empty());
+}
+
+/// Take the single request the host received
+fn single_request(driver: &Driver<ChangeDriver>) -> (AudioObjectID, u64, *mut std::ffi::c_void) {
+    let requests = driver.host.take_requests();
+    let [request] = requests[..] else {
+        panic!("expected one request, got {requests:?}");
+    };
+    request
+}
+
+#[test]
+fn guarded_changes_apply_on_perform() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    let applied = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().guard(7, &drops, &applied).is_ok());
+    // nothing changes before the HAL performs the change
+    assert_eq!(applied.load(Ordering::SeqCst), 0);
+    let (device, action, info) = single_request(&driver);
+
+    assert_eq!(driver.perform(device, action, info), 0);
+    assert_eq!(applied.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        driver.host.take_changed(),
+        [(device, kAudioDevicePropertyLatency)]
+    );
+    assert_eq!(driver.state().take_handled(), [("perform", 7)]);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn guarded_changes_drop_the_mutation_on_abort() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    let applied = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().guard(7, &drops, &applied).is_ok());
+    let (device, action, info) = single_request(&driver);
+
+    assert_eq!(driver.abort(device, action, info), 0);
+    assert_eq!(applied.load(Ordering::SeqCst), 0);
+    assert!(driver.host.take_changed().is_empty());
+    assert_eq!(driver.state().take_handled(), [("abort", 7)]);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    // the device can change again
+    assert!(driver.state().guard(8, &drops, &applied).is_ok());
+    assert_eq!(driver.host.take_requests().len(), 1);
+}
+
+#[test]
+fn guarded_changes_are_outstanding_one_at_a_time() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let drops = Arc::new(AtomicUsize::new(0));
+    let applied = Arc::new(AtomicUsize::new(0));
+    assert!(driver.state().guard(7, &drops, &applied).is_ok());
+    let rejected = Arc::new(AtomicUsize::new(0));
+    let second = driver.state().guard(8, &rejected, &applied);
+    assert_eq!(
+        result_to_err_code(second),
+        kAudioHardwareIllegalOperationError as i32
+    );
+    // the rejected change went without reaching the host
+    assert_eq!(rejected.load(Ordering::SeqCst), 1);
+    let (device, action, info) = single_request(&driver);
+
+    assert_eq!(driver.perform(device, action, info), 0);
+    assert_eq!(applied.load(Ordering::SeqCst), 1);
+    assert!(driver.state().guard(9, &drops, &applied).is_ok());
+}
+
+#[test]
+fn guarded_changes_need_an_initialized_driver() {
+    let driver = Driver::<ChangeDriver>::initialized();
+    let early = *driver.state().early.get().unwrap();
+    assert_eq!(
+        result_to_err_code(early),
+        kAudioHardwareNotReadyError as i32
+    );
+    assert!(driver.host.take_requests().is_empty());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Mutex,
+};
+
+use cahal::{
+    audio_object::ObjectRegistry,
+    base::{kAudioHardwareIllegalOperationError, kAudioHardwareUnspecifiedError},
+    core_foundation::base::CFAllocatorRef,
+    os_err::{OSStatus, OSStatusError},
+    plugin_driver_interface::{AudioServerPluginDriverInterface, LoggingConfig},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver};
+
+/// Counts how often it was initialized, failing while `fail` is set
+struct InitDriver {
+    objects: Mutex<ObjectRegistry>,
+    inits: AtomicU32,
+    fail: AtomicBool,
+}
+
+impl AudioServerPluginDriverInterface for InitDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "init";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, _) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            inits: AtomicU32::new(0),
+            fail: AtomicBool::new(false),
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        self.inits.fetch_add(1, Ordering::Relaxed);
+        // the host is only stored once this succeeded
+        let context = self.context().unwrap();
+        assert!(!context.is_initialized());
+        assert!(context.host().is_none());
+        if self.fail.load(Ordering::Relaxed) {
+            return Err(OSStatusError::HW_UNSPECIFIED_ERR);
+        }
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+}
+
+/// How often `init` ran, and whether the context reports the driver initialized with a host
+fn init_state(driver: &Driver<InitDriver>) -> (u32, bool, bool) {
+    let state = driver.state();
+    let context = state.context().unwrap();
+    (
+        state.inits.load(Ordering::Relaxed),
+        context.is_initialized(),
+        context.host().is_some(),
+    )
+}
+
+#[test]
+fn drivers_are_initialized_once() {
+    let driver = Driver::<InitDriver>::create();
+    assert_eq!(init_state(&driver), (0, false, false));
+    assert_eq!(driver.initialize(), 0);
+    assert_eq!(init_state(&driver), (1, true, true));
+    assert_eq!(
+        driver.initialize(),
+        kAudioHardwareIllegalOperationError as i32
+    );
+    assert_eq!(init_state(&driver), (1, true, true));
+}
+
+#[test]
+fn failed_initializations_can_be_retried() {
+    let driver = Driver::<InitDriver>::create();
+    driver.state().fail.store(true, Ordering::Relaxed);
+    assert_eq!(driver.initialize(), kAudioHardwareUnspecifiedError as i32);
+    assert_eq!(init_state(&driver), (1, false, false));
+
+    driver.state().fail.store(false, Ordering::Relaxed);
+    assert_eq!(driver.initialize(), 0);
+    assert_eq!(init_state(&driver), (2, true, true));
+    assert_eq!(
+        driver.initialize(),
+        kAudioHardwareIllegalOperationError as i32
+    );
+    assert_eq!(init_state(&driver), (2, true, true));
+}
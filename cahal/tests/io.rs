@@ -0,0 +1,246 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry},
+    base::{
+        kAudioDevicePropertyDeviceIsRunning, kAudioHardwareIllegalOperationError,
+        kAudioHardwareUnspecifiedError, AudioObjectID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::{OSStatus, OSStatusError},
+    plugin_driver_interface::{
+        AudioServerPluginDriverInterface, ClientId, ClientState, ClientStateMap, ClientTable,
+        LoggingConfig,
+    },
+    property::PropertyAddress,
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver};
+
+#[derive(Default)]
+struct NoState;
+
+impl ClientState for NoState {}
+
+/// Counts how often the device really starts and stops, and can refuse to start
+struct IoDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+    clients: ClientStateMap<NoState>,
+    starts: AtomicU32,
+    stops: AtomicU32,
+    refuse: AtomicBool,
+}
+
+impl AudioServerPluginDriverInterface for IoDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "io";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+            clients: ClientStateMap::new(4),
+            starts: AtomicU32::new(0),
+            stops: AtomicU32::new(0),
+            refuse: AtomicBool::new(false),
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn client_states(&self) -> Option<&dyn ClientTable> {
+        Some(&self.clients)
+    }
+    fn start_io(&self, _device: AudioObjectID, _client: ClientId) -> OSStatus {
+        if self.refuse.load(Ordering::Relaxed) {
+            return Err(OSStatusError::HW_UNSPECIFIED_ERR);
+        }
+        self.starts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+    fn stop_io(&self, _device: AudioObjectID, _client: ClientId) -> OSStatus {
+        self.stops.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+const RUNNING: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyDeviceIsRunning);
+
+/// The number of real starts and stops, and whether the device reports itself running
+fn io_state(driver: &Driver<IoDriver>) -> (u32, u32, bool) {
+    let state = driver.state();
+    let running = driver.get::<u32>(state.device.id(), 42, RUNNING).unwrap();
+    (
+        state.starts.load(Ordering::Relaxed),
+        state.stops.load(Ordering::Relaxed),
+        running != 0,
+    )
+}
+
+#[test]
+fn nested_starts_run_the_device_until_the_last_stop() {
+    let driver = Driver::<IoDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    assert_eq!(driver.add_client(device, 2, 43), 0);
+
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.start_io(device, 2), 0);
+    assert_eq!(io_state(&driver), (1, 0, true));
+
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert_eq!(driver.stop_io(device, 2), 0);
+    assert_eq!(io_state(&driver), (1, 0, true));
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert_eq!(io_state(&driver), (1, 1, false));
+
+    // a stopped device starts again
+    assert_eq!(driver.start_io(device, 2), 0);
+    assert_eq!(driver.stop_io(device, 2), 0);
+    assert_eq!(io_state(&driver), (2, 2, false));
+}
+
+#[test]
+fn unmatched_stops_are_rejected() {
+    let driver = Driver::<IoDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    assert_eq!(driver.add_client(device, 2, 43), 0);
+    let illegal = kAudioHardwareIllegalOperationError as i32;
+
+    // stopping a stopped device
+    assert_eq!(driver.stop_io(device, 1), illegal);
+    assert_eq!(io_state(&driver), (0, 0, false));
+
+    // stopping on behalf of a client that didn't start, or more often than it started
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.stop_io(device, 2), illegal);
+    assert_eq!(io_state(&driver), (1, 0, true));
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert_eq!(driver.stop_io(device, 1), illegal);
+    assert_eq!(io_state(&driver), (1, 1, false));
+}
+
+#[test]
+fn failed_starts_leave_the_device_stopped() {
+    let driver = Driver::<IoDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    driver.state().refuse.store(true, Ordering::Relaxed);
+
+    // the added client keeps its state, one the HAL didn't add doesn't
+    let unspecified = kAudioHardwareUnspecifiedError as i32;
+    assert_eq!(driver.start_io(device, 1), unspecified);
+    assert_eq!(driver.start_io(device, 2), unspecified);
+    assert!(driver.state().clients.contains(device, 1));
+    assert!(!driver.state().clients.contains(device, 2));
+    assert_eq!(io_state(&driver), (0, 0, false));
+    assert_eq!(
+        driver.stop_io(device, 1),
+        kAudioHardwareIllegalOperationError as i32
+    );
+
+    driver.state().refuse.store(false, Ordering::Relaxed);
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert_eq!(io_state(&driver), (1, 1, false));
+}
+
+#[test]
+fn clients_the_hal_didnt_add_only_keep_their_state_while_doing_io() {
+    let driver = Driver::<IoDriver>::initialized();
+    let device = driver.state().device.id();
+    let clients = &driver.state().clients;
+    // more rounds than the map has room for
+    for round in 0..2 * clients.capacity() as u32 {
+        let client = 10 + round;
+        assert_eq!(driver.start_io(device, client), 0);
+        assert_eq!(driver.start_io(device, client), 0);
+        assert!(clients.contains(device, client));
+        assert_eq!(driver.stop_io(device, client), 0);
+        assert!(clients.contains(device, client));
+        assert_eq!(driver.stop_io(device, client), 0);
+        assert!(!clients.contains(device, client));
+    }
+
+    // added clients keep their state until the HAL removes them, also when added while doing IO
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert!(clients.contains(device, 1));
+    assert_eq!(driver.start_io(device, 2), 0);
+    assert_eq!(driver.add_client(device, 2, 43), 0);
+    assert_eq!(driver.stop_io(device, 2), 0);
+    assert!(clients.contains(device, 2));
+}
+
+/// Remembers the client it was last reset for
+#[derive(Default)]
+struct Owner(AtomicU32);
+
+impl ClientState for Owner {
+    fn reset(&self, _device: AudioObjectID, client: ClientId) {
+        self.0.store(client, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn client_states_can_be_read_while_clients_come_and_go() {
+    const ROUNDS: u32 = 10_000;
+    let map = ClientStateMap::<Owner>::new(4);
+    // a client of another device that stays, which readers of the first device never see
+    assert!(map.insert(2, 100));
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for round in 0..ROUNDS {
+                let client = round % 3 + 1;
+                assert!(map.insert(1, client));
+                assert!(map.remove(1, client));
+                if round % 2 == 0 {
+                    assert!(map.insert(1, 4));
+                } else {
+                    assert!(map.remove(1, 4));
+                }
+            }
+            done.store(true, Ordering::Release);
+        });
+        for _ in 0..2 {
+            scope.spawn(|| {
+                while !done.load(Ordering::Acquire) {
+                    let mut seen = 0;
+                    for (client, state) in map.iter(1) {
+                        assert!((1..=4).contains(&client), "read client {client}");
+                        // the state of a client of the device, or of one taking over the slot
+                        assert!((1..=4).contains(&state.0.load(Ordering::Relaxed)));
+                        seen += 1;
+                    }
+                    assert!(seen < map.capacity());
+                    let other = map
+                        .get(2, 100)
+                        .expect("the client of the other device stays");
+                    assert_eq!(other.0.load(Ordering::Relaxed), 100);
+                }
+            });
+        }
+    });
+    assert_eq!(map.iter(1).count(), 0);
+    assert_eq!(map.iter(2).count(), 1);
+}
@@ -0,0 +1,466 @@
+//! The calls the HAL makes on the IO thread while a device runs
+mod common;
+
+use std::{
+    mem, ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry, StreamDirection},
+    base::{
+        kAudioHardwareBadStreamError, kAudioHardwareIllegalOperationError,
+        kAudioHardwareUnsupportedOperationError, AudioObjectID, AudioServerPlugInIOCycleInfo,
+    },
+    core_foundation::base::CFAllocatorRef,
+    io::{IOBuffer, IOCapabilities, IOCycleInfo, IOOperation},
+    os_err::{OSResult, OSStatus},
+    plugin_driver_interface::{
+        AudioServerPluginDriverInterface, ClientId, LoggingConfig, ZeroTimeStamp,
+    },
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver};
+
+/// The number of frames in the ring buffer of the device
+const PERIOD: u64 = 512;
+
+/// An IO operation as the driver saw it
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Operation {
+    stream: AudioObjectID,
+    op: IOOperation,
+    frames: u32,
+    /// The number of samples of the main buffer, `None` if it wasn't `f32`
+    samples: Option<usize>,
+    bytes: usize,
+    /// The number of samples of the secondary buffer, if there was one
+    secondary: Option<usize>,
+}
+
+/// Where in the cycle the driver was called
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Begin,
+    Do(AudioObjectID),
+    End,
+}
+
+/// A call of the driver around or for an IO operation, with the cycle info it got as its address and counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Call {
+    step: Step,
+    op: IOOperation,
+    frames: u32,
+    cycle: (usize, u64),
+}
+
+impl Call {
+    fn new(step: Step, op: IOOperation, frames: u32, cycle: &IOCycleInfo) -> Self {
+        Self {
+            step,
+            op,
+            frames,
+            cycle: (ptr::from_ref(cycle) as usize, cycle.mIOCycleCounter),
+        }
+    }
+}
+
+/// A device whose ring buffer wrapped around `wraps` times, each one 10ms of host time after the previous one. It
+/// writes the mix in place and processes the output out of place, adding 1 to every sample of the main buffer
+struct CycleDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+    wraps: AtomicU64,
+    operations: Mutex<Vec<Operation>>,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl CycleDriver {
+    fn take_operations(&self) -> Vec<Operation> {
+        mem::take(
+            &mut *self
+                .operations
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        )
+    }
+    fn take_calls(&self) -> Vec<Call> {
+        mem::take(&mut *self.calls.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    fn record(&self, call: Call) {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(call);
+    }
+}
+
+impl AudioServerPluginDriverInterface for CycleDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "cycle";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+            wraps: AtomicU64::new(0),
+            operations: Mutex::default(),
+            calls: Mutex::default(),
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn zero_time_stamp(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+    ) -> OSResult<ZeroTimeStamp> {
+        let wraps = self.wraps.load(Ordering::Relaxed);
+        Ok(ZeroTimeStamp {
+            sample_time: (wraps * PERIOD) as f64,
+            host_time: 1_000 + wraps * 10_000_000,
+            seed: 1,
+        })
+    }
+    fn io_capabilities(&self, _device: AudioObjectID) -> IOCapabilities {
+        IOCapabilities::from(IOOperation::WriteMix).with_out_of_place(IOOperation::ProcessOutput)
+    }
+    fn begin_io(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        self.record(Call::new(Step::Begin, op, frames, cycle));
+        Ok(())
+    }
+    fn end_io(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        self.record(Call::new(Step::End, op, frames, cycle));
+        Ok(())
+    }
+    fn io_operation(
+        &self,
+        _device: AudioObjectID,
+        stream: AudioObjectID,
+        _client: ClientId,
+        op: IOOperation,
+        frames: u32,
+        cycle: &IOCycleInfo,
+        mut main: IOBuffer<'_>,
+        secondary: Option<IOBuffer<'_>>,
+    ) -> OSStatus {
+        self.record(Call::new(Step::Do(stream), op, frames, cycle));
+        let operation = Operation {
+            stream,
+            op,
+            frames,
+            samples: main.samples().map(<[f32]>::len),
+            bytes: main.bytes().len(),
+            secondary: secondary
+                .as_ref()
+                .and_then(|buffer| Some(buffer.samples()?.len())),
+        };
+        for sample in main.samples_mut().into_iter().flatten() {
+            *sample += 1.0;
+        }
+        self.operations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(operation);
+        Ok(())
+    }
+}
+
+/// A device without a clock, which keeps the default `zero_time_stamp`
+struct ClocklessDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl AudioServerPluginDriverInterface for ClocklessDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "clockless";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+}
+
+#[test]
+fn zero_time_stamps_are_written_out() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(driver.zero_time_stamp(device, 1), Ok((0.0, 1_000, 1)));
+
+    driver.state().wraps.store(3, Ordering::Relaxed);
+    assert_eq!(
+        driver.zero_time_stamp(device, 1),
+        Ok((1536.0, 30_001_000, 1))
+    );
+    assert_eq!(driver.stop_io(device, 1), 0);
+}
+
+#[test]
+fn zero_time_stamps_need_all_out_pointers() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    driver.state().wraps.store(1, Ordering::Relaxed);
+    let get_zero_time_stamp = driver.vtable().GetZeroTimeStamp.unwrap();
+    let get = |sample_time, host_time, seed| unsafe {
+        get_zero_time_stamp(driver.raw, device, 1, sample_time, host_time, seed)
+    };
+    let illegal = kAudioHardwareIllegalOperationError as i32;
+    let (mut sample_time, mut host_time, mut seed) = (-1.0, 0, 0);
+    assert_eq!(
+        get(ptr::null_mut(), &raw mut host_time, &raw mut seed),
+        illegal
+    );
+    assert_eq!(
+        get(&raw mut sample_time, ptr::null_mut(), &raw mut seed),
+        illegal
+    );
+    assert_eq!(
+        get(&raw mut sample_time, &raw mut host_time, ptr::null_mut()),
+        illegal
+    );
+    // nothing is written unless all of them are there
+    assert_eq!((sample_time, host_time, seed), (-1.0, 0, 0));
+
+    assert_eq!(
+        get(&raw mut sample_time, &raw mut host_time, &raw mut seed),
+        0
+    );
+    assert_eq!((sample_time, host_time, seed), (512.0, 10_001_000, 1));
+}
+
+#[test]
+fn devices_without_a_clock_have_no_zero_time_stamp() {
+    let driver = Driver::<ClocklessDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(
+        driver.zero_time_stamp(device, 1),
+        Err(kAudioHardwareUnsupportedOperationError as i32)
+    );
+}
+
+/// The info of a cycle at time zero
+fn cycle() -> AudioServerPlugInIOCycleInfo {
+    // Safety: counters and time stamps, for which all zeroes are valid
+    unsafe { mem::zeroed() }
+}
+
+/// The output stream of the device, which has two channels
+fn output_stream(driver: &Driver<CycleDriver>) -> AudioObjectID {
+    let device = &driver.state().device;
+    device.streams(StreamDirection::Output).next().unwrap()
+}
+
+#[test]
+fn write_mix_sees_the_frames_of_the_cycle() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    let stream = output_stream(&driver);
+    assert_eq!(driver.start_io(device, 1), 0);
+    let op = IOOperation::WriteMix;
+    assert_eq!(driver.will_do_io(device, 1, op.id()), Ok((true, true)));
+
+    // room for more frames than the cycle has, which the driver must not touch
+    let mut buffer = [0.0; 12];
+    let cycle = cycle();
+    assert_eq!(
+        driver.do_io(device, stream, 1, op.id(), 4, &cycle, &mut buffer),
+        0
+    );
+    assert_eq!(
+        driver.state().take_operations(),
+        [Operation {
+            stream,
+            op,
+            frames: 4,
+            samples: Some(8),
+            bytes: 32,
+            secondary: None,
+        }]
+    );
+    assert_eq!(
+        buffer,
+        [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0]
+    );
+
+    // the next cycle may be shorter
+    assert_eq!(
+        driver.do_io(device, stream, 1, op.id(), 1, &cycle, &mut buffer),
+        0
+    );
+    let [operation] = driver.state().take_operations()[..] else {
+        panic!("expected one operation");
+    };
+    assert_eq!((operation.frames, operation.samples), (1, Some(2)));
+    assert_eq!(buffer[..3], [2.0, 2.0, 1.0]);
+}
+
+#[test]
+fn out_of_place_operations_get_both_buffers() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    let stream = output_stream(&driver);
+    assert_eq!(driver.start_io(device, 1), 0);
+    let op = IOOperation::ProcessOutput;
+    assert_eq!(driver.will_do_io(device, 1, op.id()), Ok((true, false)));
+
+    let (mut main, mut secondary) = ([0.0f32; 6], [0.0f32; 6]);
+    let cycle = cycle();
+    let status = unsafe {
+        driver.vtable().DoIOOperation.unwrap()(
+            driver.raw,
+            device,
+            stream,
+            1,
+            op.id(),
+            3,
+            &cycle,
+            main.as_mut_ptr().cast(),
+            secondary.as_mut_ptr().cast(),
+        )
+    };
+    assert_eq!(status, 0);
+    let [operation] = driver.state().take_operations()[..] else {
+        panic!("expected one operation");
+    };
+    assert_eq!(
+        (operation.samples, operation.bytes, operation.secondary),
+        (Some(6), 24, Some(6))
+    );
+}
+
+#[test]
+fn io_needs_a_running_stream_and_a_main_buffer() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    let stream = output_stream(&driver);
+    let op = IOOperation::WriteMix.id();
+    let cycle = cycle();
+    let mut buffer = [0.0; 8];
+    // the formats of the streams are only known while IO runs
+    assert_eq!(
+        driver.do_io(device, stream, 1, op, 4, &cycle, &mut buffer),
+        kAudioHardwareBadStreamError as i32
+    );
+
+    assert_eq!(driver.start_io(device, 1), 0);
+    let status = unsafe {
+        driver.vtable().DoIOOperation.unwrap()(
+            driver.raw,
+            device,
+            stream,
+            1,
+            op,
+            4,
+            &cycle,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    assert_eq!(status, kAudioHardwareIllegalOperationError as i32);
+    assert_eq!(
+        driver.do_io(device, device, 1, op, 4, &cycle, &mut buffer),
+        kAudioHardwareBadStreamError as i32
+    );
+    assert!(driver.state().take_operations().is_empty());
+    assert_eq!(buffer, [0.0; 8]);
+}
+
+#[test]
+fn operations_are_bracketed_by_begin_and_end_with_the_same_cycle() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    let stream = output_stream(&driver);
+    assert_eq!(driver.start_io(device, 1), 0);
+    let mut buffer = [0.0; 8];
+    let mut cycle = cycle();
+    cycle.mIOCycleCounter = 7;
+
+    let (whole, write) = (IOOperation::Cycle, IOOperation::WriteMix);
+    assert_eq!(driver.begin_io(device, 1, whole.id(), 4, &cycle), 0);
+    assert_eq!(driver.begin_io(device, 1, write.id(), 4, &cycle), 0);
+    assert_eq!(
+        driver.do_io(device, stream, 1, write.id(), 4, &cycle, &mut buffer),
+        0
+    );
+    assert_eq!(driver.end_io(device, 1, write.id(), 4, &cycle), 0);
+    assert_eq!(driver.end_io(device, 1, whole.id(), 4, &cycle), 0);
+
+    let calls = driver.state().take_calls();
+    let steps: Vec<_> = calls.iter().map(|call| (call.step, call.op)).collect();
+    assert_eq!(
+        steps,
+        [
+            (Step::Begin, whole),
+            (Step::Begin, write),
+            (Step::Do(stream), write),
+            (Step::End, write),
+            (Step::End, whole),
+        ]
+    );
+    // every call saw the cycle info the HAL passed, not a copy of it
+    let info = (ptr::from_ref(&cycle) as usize, 7);
+    assert!(calls
+        .iter()
+        .all(|call| call.cycle == info && call.frames == 4));
+}
+
+#[test]
+fn begin_and_end_need_cycle_info_and_a_known_operation() {
+    let driver = Driver::<CycleDriver>::initialized();
+    let device = driver.state().device.id();
+    let illegal = kAudioHardwareIllegalOperationError as i32;
+    let op = IOOperation::WriteMix.id();
+    let vtable = driver.vtable();
+    unsafe {
+        let begin = vtable.BeginIOOperation.unwrap();
+        let end = vtable.EndIOOperation.unwrap();
+        assert_eq!(begin(driver.raw, device, 1, op, 4, ptr::null()), illegal);
+        assert_eq!(end(driver.raw, device, 1, op, 4, ptr::null()), illegal);
+    }
+    let cycle = cycle();
+    let unknown = u32::from_be_bytes(*b"what");
+    assert_eq!(driver.begin_io(device, 1, unknown, 4, &cycle), illegal);
+    assert_eq!(driver.end_io(device, 1, unknown, 4, &cycle), illegal);
+    assert!(driver.state().take_calls().is_empty());
+}
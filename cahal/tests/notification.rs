@@ -0,0 +1,120 @@
+mod common;
+
+use std::sync::Mutex;
+
+use cahal::{
+    audio_object::{DeviceBuilder, DeviceHandle, ObjectRegistry, PluginObject},
+    base::{
+        kAudioBooleanControlPropertyValue, kAudioDevicePropertyDeviceIsRunning,
+        kAudioLevelControlPropertyDecibelValue, kAudioLevelControlPropertyScalarValue,
+        kAudioObjectPropertyName,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::OSStatus,
+    plugin_driver_interface::{AudioServerPluginDriverInterface, LoggingConfig},
+    property::{PropertyAddress, PropertyScope},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::Driver;
+
+/// A device with an output volume and mute control
+struct ControlDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl ControlDriver {
+    fn volume(&self) -> u32 {
+        self.device.controls().next().unwrap()
+    }
+    fn mute(&self) -> u32 {
+        self.device.controls().nth(1).unwrap()
+    }
+}
+
+impl AudioServerPluginDriverInterface for ControlDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "controls";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let mut objects = ObjectRegistry::new();
+        let mut plugin = PluginObject::new("cahal", "com.example.test");
+        plugin.attach(&objects);
+        objects.register(Box::new(plugin)).unwrap();
+        let device = DeviceBuilder::new("Test Device", "com.example.test.device")
+            .output_stream(2)
+            .volume_control(PropertyScope::OUTPUT)
+            .mute_control(PropertyScope::OUTPUT)
+            .build(&mut objects)
+            .unwrap();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+}
+
+#[test]
+fn writes_notify_the_property_and_its_dependents() {
+    let driver = Driver::<ControlDriver>::initialized();
+    let volume = driver.state().volume();
+    let scalar = PropertyAddress::global(kAudioLevelControlPropertyScalarValue);
+    assert_eq!(driver.set(volume, 42, scalar, &0.5f32), 0);
+    assert_eq!(
+        driver.host.take_changed(),
+        [
+            (volume, kAudioLevelControlPropertyScalarValue),
+            (volume, kAudioLevelControlPropertyDecibelValue),
+        ]
+    );
+
+    let mute = driver.state().mute();
+    let value = PropertyAddress::global(kAudioBooleanControlPropertyValue);
+    assert_eq!(driver.set(mute, 42, value, &1u32), 0);
+    assert_eq!(
+        driver.host.take_changed(),
+        [(mute, kAudioBooleanControlPropertyValue)]
+    );
+}
+
+#[test]
+fn failed_writes_notify_nothing() {
+    let driver = Driver::<ControlDriver>::initialized();
+    let volume = driver.state().volume();
+    let device = driver.state().device.id();
+    // read-only
+    let name = PropertyAddress::global(kAudioObjectPropertyName);
+    assert_ne!(driver.set(device, 42, name, &0u64), 0);
+    // malformed
+    let scalar = PropertyAddress::global(kAudioLevelControlPropertyScalarValue);
+    assert_ne!(driver.set(volume, 42, scalar, &0u8), 0);
+    // unknown object
+    assert_ne!(driver.set(9999, 42, scalar, &0.5f32), 0);
+    assert!(driver.host.take_changed().is_empty());
+}
+
+#[test]
+fn io_notifies_whether_the_device_is_running() {
+    let driver = Driver::<ControlDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    assert_eq!(driver.start_io(device, 1), 0);
+    assert_eq!(
+        driver.host.take_changed(),
+        [(device, kAudioDevicePropertyDeviceIsRunning)]
+    );
+    assert_eq!(driver.stop_io(device, 1), 0);
+    assert_eq!(
+        driver.host.take_changed(),
+        [(device, kAudioDevicePropertyDeviceIsRunning)]
+    );
+}
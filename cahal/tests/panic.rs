@@ -0,0 +1,482 @@
+//! Every callback of the plug-in interface survives a panic in the driver, answering with an error
+mod common;
+
+use std::{
+    cell::Cell,
+    ptr,
+    sync::{Mutex, Once},
+};
+
+use cahal::{
+    audio_object::{AudioObject, DeviceHandle, HasProperties, ObjectRegistry, StreamDirection},
+    base::{
+        kAudioHardwareUnspecifiedError, kAudioObjectPropertyName, AudioObjectID,
+        AudioServerPlugInIOCycleInfo,
+    },
+    core_foundation::{
+        base::{CFAllocatorRef, TCFType},
+        string::CFString,
+    },
+    io::{IOBuffer, IOCapabilities, IOCycleInfo, IOOperation},
+    os_err::{OSResult, OSStatus},
+    plugin_driver_interface::{
+        AudioServerPluginDriverInterface, ClientId, ClientInfo, LoggingConfig,
+        TransportManagerInterface, ZeroTimeStamp,
+    },
+    property::{PropertyAddress, RawProperty},
+    raw_plugin_driver_interface::{PluginHostInterface, RawAudioServerPlugInDriverInterface},
+};
+use common::{registry_with_device, uuid, Driver, PLUGIN_TYPE};
+
+thread_local! {
+    /// Where the driver panics next, only on the thread of the test that armed it
+    static ARMED: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Panic once the driver reaches `at`, or logs a message starting with it
+fn arm(at: &'static str) {
+    static LOGGER: Once = Once::new();
+    LOGGER.call_once(|| {
+        log::set_logger(&PanickingLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+    });
+    ARMED.set(Some(at));
+}
+
+fn explode(at: &str) {
+    if ARMED.get() == Some(at) {
+        ARMED.set(None);
+        panic!("the driver panicked in {at}");
+    }
+}
+
+/// A logger that panics on the armed message, to reach callbacks that don't call into the driver
+struct PanickingLogger;
+
+impl log::Log for PanickingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        if let Some(at) = ARMED.get()
+            && record.args().to_string().starts_with(at)
+        {
+            explode(at);
+        }
+    }
+    fn flush(&self) {}
+}
+
+/// An object whose property lookups panic when armed with `"property"`
+struct Bomb {
+    id: AudioObjectID,
+}
+
+impl HasProperties for Bomb {
+    fn get_object_property(&self, _address: PropertyAddress) -> Option<&dyn RawProperty> {
+        explode("property");
+        None
+    }
+    fn get_object_property_mut(
+        &mut self,
+        _address: PropertyAddress,
+    ) -> Option<&mut dyn RawProperty> {
+        explode("property");
+        None
+    }
+}
+
+impl AudioObject for Bomb {
+    fn id(&self) -> AudioObjectID {
+        self.id
+    }
+}
+
+/// Panics in whichever method it was armed with
+struct PanicDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+    bomb: AudioObjectID,
+}
+
+impl AudioServerPluginDriverInterface for PanicDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "panics";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        explode("create");
+        let (mut objects, device) = registry_with_device();
+        let bomb = objects.register_new(|id| Box::new(Bomb { id })).unwrap();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+            bomb,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        explode("init");
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn transport_manager(&self) -> Option<&dyn TransportManagerInterface> {
+        explode("transport_manager");
+        None
+    }
+    fn add_client(&self, _device: AudioObjectID, _client: &ClientInfo) -> OSStatus {
+        explode("add_client");
+        Ok(())
+    }
+    fn remove_client(&self, _device: AudioObjectID, _client: &ClientInfo) -> OSStatus {
+        explode("remove_client");
+        Ok(())
+    }
+    fn perform_change(&self, _device: AudioObjectID, _info: ()) -> OSStatus {
+        explode("perform_change");
+        Ok(())
+    }
+    fn abort_change(&self, _device: AudioObjectID, _info: ()) -> OSStatus {
+        explode("abort_change");
+        Ok(())
+    }
+    fn start_io(&self, _device: AudioObjectID, _client: ClientId) -> OSStatus {
+        explode("start_io");
+        Ok(())
+    }
+    fn stop_io(&self, _device: AudioObjectID, _client: ClientId) -> OSStatus {
+        explode("stop_io");
+        Ok(())
+    }
+    fn zero_time_stamp(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+    ) -> OSResult<ZeroTimeStamp> {
+        explode("zero_time_stamp");
+        Ok(ZeroTimeStamp {
+            sample_time: 0.0,
+            host_time: 0,
+            seed: 1,
+        })
+    }
+    fn io_capabilities(&self, _device: AudioObjectID) -> IOCapabilities {
+        explode("io_capabilities");
+        IOOperation::WriteMix.into()
+    }
+    fn begin_io(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+        _op: IOOperation,
+        _frames: u32,
+        _cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        explode("begin_io");
+        Ok(())
+    }
+    fn end_io(
+        &self,
+        _device: AudioObjectID,
+        _client: ClientId,
+        _op: IOOperation,
+        _frames: u32,
+        _cycle: &IOCycleInfo,
+    ) -> OSStatus {
+        explode("end_io");
+        Ok(())
+    }
+    fn io_operation(
+        &self,
+        _device: AudioObjectID,
+        _stream: AudioObjectID,
+        _client: ClientId,
+        _op: IOOperation,
+        _frames: u32,
+        _cycle: &IOCycleInfo,
+        _main: IOBuffer<'_>,
+        _secondary: Option<IOBuffer<'_>>,
+    ) -> OSStatus {
+        explode("io_operation");
+        Ok(())
+    }
+}
+
+const UNSPECIFIED: i32 = kAudioHardwareUnspecifiedError as i32;
+const NAME: PropertyAddress = PropertyAddress::global(kAudioObjectPropertyName);
+
+/// Check that the driver still answers after a panic, even if it held the lock of its objects
+fn assert_survived(driver: &Driver<PanicDriver>) {
+    assert_eq!(
+        ARMED.get(),
+        None,
+        "the driver never reached the armed panic"
+    );
+    assert!(driver.has_property(driver.state().device.id(), 42, NAME));
+}
+
+#[test]
+fn create() {
+    let uuid = uuid(PLUGIN_TYPE);
+    arm("create");
+    let driver = unsafe {
+        <PanicDriver as RawAudioServerPlugInDriverInterface>::create(
+            ptr::null(),
+            uuid.as_concrete_TypeRef().cast(),
+        )
+    };
+    assert!(driver.is_null());
+    assert_survived(&Driver::initialized());
+}
+
+#[test]
+fn query_interface() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("query interface matched");
+    assert_eq!(
+        driver.query_interface(common::DRIVER_INTERFACE).0,
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn retain() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("retain called");
+    assert_eq!(driver.add_ref(), 0);
+    assert_survived(&driver);
+}
+
+#[test]
+fn release() {
+    let driver = Driver::<PanicDriver>::initialized();
+    assert_eq!(driver.add_ref(), 2);
+    arm("release called");
+    assert_eq!(driver.release(), 0);
+    assert_survived(&driver);
+}
+
+#[test]
+fn initialize() {
+    let driver = Driver::<PanicDriver>::create();
+    arm("init");
+    assert_eq!(driver.initialize(), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn create_device() {
+    let driver = Driver::<PanicDriver>::initialized();
+    // never read, the driver panics before
+    let desc = CFString::new("endpoints");
+    arm("transport_manager");
+    let status = driver.create_device(desc.as_concrete_TypeRef().cast(), 1).0;
+    assert_eq!(status, UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn destroy_device() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("transport_manager");
+    assert_eq!(
+        driver.destroy_device(driver.state().device.id()),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn add_device_client() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("add_client");
+    assert_eq!(
+        driver.add_client(driver.state().device.id(), 1, 42),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn remove_device_client() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.add_client(device, 1, 42), 0);
+    arm("remove_client");
+    assert_eq!(driver.remove_client(device, 1, 42), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+/// Request a change through the host, returning the device, the action and the info the host got
+fn request(driver: &Driver<PanicDriver>) -> (AudioObjectID, u64, *mut std::ffi::c_void) {
+    let device = driver.state().device.id();
+    let host = unsafe { PluginHostInterface::<PanicDriver>::new(driver.host.host_ref()) };
+    assert!(host.unwrap().request_change(device, ()).is_ok());
+    driver.host.take_requests()[0]
+}
+
+#[test]
+fn perform_device_configuration_change() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let (device, action, info) = request(&driver);
+    arm("perform_change");
+    assert_eq!(driver.perform(device, action, info), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn abort_device_configuration_change() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let (device, action, info) = request(&driver);
+    arm("abort_change");
+    assert_eq!(driver.abort(device, action, info), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn has_property() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("property");
+    assert!(!driver.has_property(driver.state().bomb, 42, NAME));
+    assert_survived(&driver);
+}
+
+#[test]
+fn is_property_settable() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("property");
+    assert_eq!(
+        driver.is_settable(driver.state().bomb, 42, NAME),
+        Err(UNSPECIFIED)
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn get_property_data_size() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("property");
+    assert_eq!(driver.size(driver.state().bomb, 42, NAME), Err(UNSPECIFIED));
+    assert_survived(&driver);
+}
+
+#[test]
+fn get_property_data() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("property");
+    assert_eq!(
+        driver.get::<u64>(driver.state().bomb, 42, NAME),
+        Err(UNSPECIFIED)
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn set_property_data() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("property");
+    assert_eq!(
+        driver.set(driver.state().bomb, 42, NAME, &0u64),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn start_io() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("start_io");
+    assert_eq!(driver.start_io(driver.state().device.id(), 1), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn stop_io() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.start_io(device, 1), 0);
+    arm("stop_io");
+    assert_eq!(driver.stop_io(device, 1), UNSPECIFIED);
+    assert_survived(&driver);
+}
+
+#[test]
+fn get_zero_time_stamp() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("zero_time_stamp");
+    assert_eq!(
+        driver.zero_time_stamp(driver.state().device.id(), 1),
+        Err(UNSPECIFIED)
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn will_do_io_operation() {
+    let driver = Driver::<PanicDriver>::initialized();
+    arm("io_capabilities");
+    let op = IOOperation::WriteMix.id();
+    assert_eq!(
+        driver.will_do_io(driver.state().device.id(), 1, op),
+        Err(UNSPECIFIED)
+    );
+    assert_survived(&driver);
+}
+
+/// The info of a cycle at time zero
+fn cycle() -> AudioServerPlugInIOCycleInfo {
+    // Safety: counters and time stamps, for which all zeroes are valid
+    unsafe { std::mem::zeroed() }
+}
+
+#[test]
+fn begin_io_operation() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let cycle = cycle();
+    arm("begin_io");
+    let op = IOOperation::WriteMix.id();
+    assert_eq!(
+        driver.begin_io(driver.state().device.id(), 1, op, 4, &cycle),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn do_io_operation() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let device = driver.state().device.id();
+    let stream = driver
+        .state()
+        .device
+        .streams(StreamDirection::Output)
+        .next()
+        .unwrap();
+    assert_eq!(driver.start_io(device, 1), 0);
+    let cycle = cycle();
+    let mut buffer = [0.0; 8];
+    arm("io_operation");
+    let op = IOOperation::WriteMix.id();
+    assert_eq!(
+        driver.do_io(device, stream, 1, op, 4, &cycle, &mut buffer),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
+
+#[test]
+fn end_io_operation() {
+    let driver = Driver::<PanicDriver>::initialized();
+    let cycle = cycle();
+    arm("end_io");
+    let op = IOOperation::WriteMix.id();
+    assert_eq!(
+        driver.end_io(driver.state().device.id(), 1, op, 4, &cycle),
+        UNSPECIFIED
+    );
+    assert_survived(&driver);
+}
@@ -0,0 +1,231 @@
+mod common;
+
+use std::{ffi::c_void, ptr, sync::Mutex};
+
+use cahal::{
+    audio_object::{DeviceBuilder, DeviceHandle, ObjectRegistry, PluginObject, StreamDirection},
+    base::{
+        kAudioDevicePropertyLatency, kAudioDevicePropertyNominalSampleRate,
+        kAudioDevicePropertyStreams, kAudioHardwareBadObjectError,
+        kAudioHardwareBadPropertySizeError, kAudioHardwareIllegalOperationError,
+        kAudioHardwareUnknownPropertyError, kAudioObjectPlugInObject,
+        kAudioPlugInPropertyTranslateUIDToDevice, AudioObjectID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::OSStatus,
+    plugin_driver_interface::{AudioServerPluginDriverInterface, LoggingConfig},
+    property::{PropertyAddress, PropertyScope},
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::Driver;
+
+/// Publishes a device with a mono input and a stereo output stream, which have a latency of 10 and 20 frames
+struct PropDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl AudioServerPluginDriverInterface for PropDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "props";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let mut objects = ObjectRegistry::new();
+        let mut plugin = PluginObject::new("cahal", "com.example.test");
+        plugin.attach(&objects);
+        objects.register(Box::new(plugin)).unwrap();
+        let device = DeviceBuilder::new("Test Device", "com.example.test.device")
+            .sample_rates(&[44100.0, 48000.0])
+            .input_stream(1)
+            .output_stream(2)
+            .latency(PropertyScope::INPUT, 10, 0)
+            .latency(PropertyScope::OUTPUT, 20, 0)
+            .build(&mut objects)
+            .unwrap();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+}
+
+const RATE: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyNominalSampleRate);
+const BAD_SIZE: i32 = kAudioHardwareBadPropertySizeError as i32;
+
+/// `GetPropertyData` into `out_data` claiming `data_size` bytes, returning the status and the size written out
+fn get_raw(
+    driver: &Driver<PropDriver>,
+    object: AudioObjectID,
+    address: PropertyAddress,
+    data_size: u32,
+    out_data: *mut c_void,
+) -> (i32, u32) {
+    let address = address.into();
+    let mut written = u32::MAX;
+    let status = unsafe {
+        driver.vtable().GetPropertyData.unwrap()(
+            driver.raw,
+            object,
+            42,
+            &address,
+            0,
+            ptr::null(),
+            data_size,
+            &mut written,
+            out_data,
+        )
+    };
+    (status, written)
+}
+
+#[test]
+fn values_are_read_into_the_buffer() {
+    let driver = Driver::<PropDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_eq!(driver.size(device, 42, RATE), Ok(8));
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(44100.0));
+
+    // larger buffers are fine, only the value is written
+    let mut buffer = [0.0f64; 2];
+    let (status, written) = get_raw(&driver, device, RATE, 16, buffer.as_mut_ptr().cast());
+    assert_eq!((status, written), (0, 8));
+    assert_eq!(buffer, [44100.0, 0.0]);
+
+    let streams = PropertyAddress::scoped(kAudioDevicePropertyStreams, PropertyScope::OUTPUT);
+    let stream = driver
+        .state()
+        .device
+        .streams(StreamDirection::Output)
+        .next()
+        .unwrap();
+    assert_eq!(driver.size(device, 42, streams), Ok(4));
+    assert_eq!(driver.get::<u32>(device, 42, streams), Ok(stream));
+}
+
+#[test]
+fn small_buffers_are_rejected() {
+    let driver = Driver::<PropDriver>::initialized();
+    let device = driver.state().device.id();
+    let mut buffer = [1.0f64];
+    for size in [0, 4, 7] {
+        let (status, _) = get_raw(&driver, device, RATE, size, buffer.as_mut_ptr().cast());
+        assert_eq!(status, BAD_SIZE, "{size} bytes");
+    }
+    assert_eq!(buffer, [1.0]);
+}
+
+#[test]
+fn unknown_objects_and_properties_are_rejected() {
+    let driver = Driver::<PropDriver>::initialized();
+    let device = driver.state().device.id();
+    let unknown_prop = kAudioHardwareUnknownPropertyError as i32;
+    let bad_object = kAudioHardwareBadObjectError as i32;
+    let unknown = PropertyAddress::global(u32::from_be_bytes(*b"nope"));
+    assert_eq!(driver.size(device, 42, unknown), Err(unknown_prop));
+    assert_eq!(driver.get::<u32>(device, 42, unknown), Err(unknown_prop));
+    assert_eq!(driver.size(9999, 42, RATE), Err(bad_object));
+    assert_eq!(driver.get::<f64>(9999, 42, RATE), Err(bad_object));
+    assert!(!driver.has_property(device, 42, unknown));
+    assert!(!driver.has_property(9999, 42, RATE));
+}
+
+#[test]
+fn missing_out_pointers_are_rejected() {
+    let driver = Driver::<PropDriver>::initialized();
+    let device = driver.state().device.id();
+    let illegal = kAudioHardwareIllegalOperationError as i32;
+    let address = RATE.into();
+    let mut value = 0.0f64;
+    let mut written = 0;
+    let vtable = driver.vtable();
+    unsafe {
+        let get = vtable.GetPropertyData.unwrap();
+        let data = (&raw mut value).cast();
+        let status = get(
+            driver.raw,
+            device,
+            42,
+            &address,
+            0,
+            ptr::null(),
+            8,
+            ptr::null_mut(),
+            data,
+        );
+        assert_eq!(status, illegal);
+        let status = get(
+            driver.raw,
+            device,
+            42,
+            &address,
+            0,
+            ptr::null(),
+            8,
+            &mut written,
+            ptr::null_mut(),
+        );
+        assert_eq!(status, illegal);
+        let status = get(
+            driver.raw,
+            device,
+            42,
+            ptr::null(),
+            0,
+            ptr::null(),
+            8,
+            &mut written,
+            data,
+        );
+        assert_eq!(status, illegal);
+        let size = vtable.GetPropertyDataSize.unwrap();
+        let status = size(
+            driver.raw,
+            device,
+            42,
+            &address,
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        );
+        assert_eq!(status, illegal);
+    }
+    assert_eq!(value, 0.0);
+}
+
+#[test]
+fn sizes_dont_need_the_qualifier_of_the_data() {
+    let driver = Driver::<PropDriver>::initialized();
+    let translate = PropertyAddress::global(kAudioPlugInPropertyTranslateUIDToDevice);
+    assert_eq!(driver.size(kAudioObjectPlugInObject, 42, translate), Ok(4));
+}
+
+#[test]
+fn scopes_resolve_to_their_own_properties() {
+    let driver = Driver::<PropDriver>::initialized();
+    let device = driver.state().device.id();
+    let latency = |scope| PropertyAddress::scoped(kAudioDevicePropertyLatency, scope);
+    assert_eq!(
+        driver.get::<u32>(device, 42, latency(PropertyScope::INPUT)),
+        Ok(10)
+    );
+    assert_eq!(
+        driver.get::<u32>(device, 42, latency(PropertyScope::OUTPUT)),
+        Ok(20)
+    );
+
+    for direction in [StreamDirection::Input, StreamDirection::Output] {
+        let streams = PropertyAddress::scoped(kAudioDevicePropertyStreams, direction.scope());
+        let stream = driver.state().device.streams(direction).next().unwrap();
+        assert_eq!(driver.size(device, 42, streams), Ok(4));
+        assert_eq!(driver.get::<u32>(device, 42, streams), Ok(stream));
+    }
+}
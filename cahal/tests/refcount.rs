@@ -0,0 +1,230 @@
+mod common;
+
+use std::{cell::Cell, ptr, sync::Mutex};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry},
+    base::{
+        kAudioHardwareIllegalOperationError, kAudioObjectPropertyName,
+        AudioServerPlugInDriverInterface, AudioServerPlugInDriverRef, REFIID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::OSStatus,
+    plugin_driver_interface::{
+        checked_driver_state, driver_state, AudioServerPluginDriverInterface, LoggingConfig,
+    },
+    property::PropertyAddress,
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver, DRIVER_INTERFACE, IUNKNOWN};
+
+/// A driver that lives for the whole process, like drivers loaded by `coreaudiod`
+struct KeptDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl AudioServerPluginDriverInterface for KeptDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "kept";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+}
+
+/// Retain once more to read the count, and release that reference again
+fn count(driver: &Driver<KeptDriver>) -> u32 {
+    let count = driver.add_ref() - 1;
+    assert_eq!(driver.release(), count);
+    count
+}
+
+#[test]
+fn the_factory_hands_out_one_reference() {
+    let driver = Driver::<KeptDriver>::create();
+    assert_eq!(count(&driver), 1);
+}
+
+#[test]
+fn references_are_counted() {
+    let driver = Driver::<KeptDriver>::create();
+    assert_eq!(driver.add_ref(), 2);
+    assert_eq!(driver.add_ref(), 3);
+    assert_eq!(driver.release(), 2);
+    assert_eq!(driver.add_ref(), 3);
+    assert_eq!(driver.release(), 2);
+    assert_eq!(driver.release(), 1);
+    assert_eq!(count(&driver), 1);
+}
+
+#[test]
+fn interfaces_are_handed_out_retained() {
+    let driver = Driver::<KeptDriver>::create();
+    let (status, interface) = driver.query_interface(DRIVER_INTERFACE);
+    assert_eq!(status, 0);
+    assert_eq!(interface, driver.raw.cast());
+    assert_eq!(count(&driver), 2);
+    let (status, interface) = driver.query_interface(IUNKNOWN);
+    assert_eq!(status, 0);
+    assert_eq!(interface, driver.raw.cast());
+    assert_eq!(count(&driver), 3);
+    // the host releases every interface it got
+    assert_eq!(driver.release(), 2);
+    assert_eq!(driver.release(), 1);
+}
+
+#[test]
+fn unknown_interfaces_take_no_reference() {
+    let driver = Driver::<KeptDriver>::create();
+    let (status, interface) = driver.query_interface([0xAB; 16]);
+    // E_NOINTERFACE
+    assert_eq!(status, 0x80000004u32 as i32);
+    assert!(interface.is_null());
+    assert_eq!(count(&driver), 1);
+}
+
+#[test]
+fn releasing_too_often_stays_at_zero() {
+    let driver = Driver::<KeptDriver>::initialized();
+    assert_eq!(driver.release(), 0);
+    assert_eq!(driver.release(), 0);
+    assert_eq!(driver.release(), 0);
+    // not freed, so it still answers and can be retained again
+    assert!(driver.has_property(
+        driver.state().device.id(),
+        42,
+        PropertyAddress::global(kAudioObjectPropertyName)
+    ));
+    assert_eq!(driver.add_ref(), 1);
+    assert_eq!(driver.release(), 0);
+}
+
+#[test]
+fn null_drivers_have_no_references() {
+    let driver = Driver::<KeptDriver>::create();
+    let vtable = driver.vtable();
+    unsafe {
+        assert_eq!(vtable.AddRef.unwrap()(ptr::null_mut()), 0);
+        assert_eq!(vtable.Release.unwrap()(ptr::null_mut()), 0);
+    }
+    assert_eq!(count(&driver), 1);
+}
+
+thread_local! {
+    /// How often the drivers freed on this thread were shut down and dropped
+    static SHUTDOWNS: Cell<u32> = const { Cell::new(0) };
+    static DROPS: Cell<u32> = const { Cell::new(0) };
+}
+
+/// A driver that frees itself once released to zero, like drivers of hosts that unload them
+struct FreedDriver {
+    objects: Mutex<ObjectRegistry>,
+}
+
+impl Drop for FreedDriver {
+    fn drop(&mut self) {
+        DROPS.set(DROPS.get() + 1);
+    }
+}
+
+impl AudioServerPluginDriverInterface for FreedDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "freed";
+    const DEALLOCATE_ON_ZERO: bool = true;
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, _) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn shutdown(&self) {
+        // runs before the state is dropped
+        assert_eq!(DROPS.get(), 0);
+        SHUTDOWNS.set(SHUTDOWNS.get() + 1);
+    }
+}
+
+/// Whether the driver is still alive, without touching its memory
+fn alive(driver: &Driver<FreedDriver>) -> bool {
+    unsafe { checked_driver_state::<FreedDriver>(driver.raw) }.is_some()
+}
+
+#[test]
+fn drivers_are_freed_once_with_the_last_reference() {
+    let driver = Driver::<FreedDriver>::initialized();
+    let (status, _) = driver.query_interface(DRIVER_INTERFACE);
+    assert_eq!(status, 0);
+
+    // the reference of the factory goes first, the interface keeps the driver alive
+    assert_eq!(driver.release(), 1);
+    assert!(alive(&driver));
+    assert_eq!((SHUTDOWNS.get(), DROPS.get()), (0, 0));
+
+    assert_eq!(driver.release(), 0);
+    assert!(!alive(&driver));
+    assert_eq!((SHUTDOWNS.get(), DROPS.get()), (1, 1));
+}
+
+#[test]
+fn null_drivers_have_no_interfaces() {
+    let driver = Driver::<KeptDriver>::create();
+    let mut out = ptr::null_mut();
+    let interface = unsafe { std::mem::transmute::<[u8; 16], REFIID>(DRIVER_INTERFACE) };
+    let status =
+        unsafe { driver.vtable().QueryInterface.unwrap()(ptr::null_mut(), interface, &mut out) };
+    assert_eq!(status, kAudioHardwareIllegalOperationError as i32);
+    assert!(out.is_null());
+    assert_eq!(count(&driver), 1);
+}
+
+#[test]
+fn driver_refs_only_give_the_state_of_their_own_type() {
+    let kept = Driver::<KeptDriver>::create();
+    let freed = Driver::<FreedDriver>::create();
+    unsafe {
+        let state = checked_driver_state::<KeptDriver>(kept.raw).unwrap();
+        assert!(ptr::eq(
+            state,
+            driver_state::<KeptDriver>(kept.raw).unwrap()
+        ));
+        assert_eq!(state.device.id(), kept.state().device.id());
+        assert!(checked_driver_state::<FreedDriver>(freed.raw).is_some());
+
+        // the ref of a driver of another type
+        assert!(checked_driver_state::<FreedDriver>(kept.raw).is_none());
+        assert!(checked_driver_state::<KeptDriver>(freed.raw).is_none());
+        assert!(checked_driver_state::<KeptDriver>(ptr::null_mut()).is_none());
+        assert!(driver_state::<KeptDriver>(ptr::null_mut()).is_none());
+
+        // something that starts with the function table of the driver, but isn't one
+        let mut forged: *const AudioServerPlugInDriverInterface = kept.vtable();
+        let forged_ref: AudioServerPlugInDriverRef = (&raw mut forged).cast();
+        assert!(checked_driver_state::<KeptDriver>(forged_ref).is_none());
+    }
+    assert_eq!(freed.release(), 0);
+    assert!(!alive(&freed));
+}
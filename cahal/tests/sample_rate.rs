@@ -0,0 +1,116 @@
+mod common;
+
+use std::{ffi::c_void, sync::Mutex};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry},
+    base::{kAudioDevicePropertyNominalSampleRate, AudioObjectID},
+    core_foundation::base::CFAllocatorRef,
+    os_err::OSStatus,
+    plugin_driver_interface::{AudioServerPluginDriverInterface, LoggingConfig},
+    property::PropertyAddress,
+    raw_plugin_driver_interface::PluginHostInterface,
+};
+use common::{registry_with_device, Driver};
+
+/// Requests rate changes from the host and commits them when the host performs them
+struct RateDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl AudioServerPluginDriverInterface for RateDriver {
+    type DeviceConfigurationChangeInfo = f64;
+    const NAME: &'static str = "rates";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, host: PluginHostInterface<Self>) -> OSStatus {
+        let device = self.device.id();
+        self.device
+            .sample_rate()
+            .on_request(move |rate| host.request_change(device, rate));
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn perform_change(&self, device: AudioObjectID, rate: f64) -> OSStatus {
+        assert_eq!(device, self.device.id());
+        assert_eq!(self.device.sample_rate().perform(), Some(rate));
+        Ok(())
+    }
+    fn abort_change(&self, device: AudioObjectID, rate: f64) -> OSStatus {
+        assert_eq!(device, self.device.id());
+        assert_eq!(self.device.sample_rate().abort(), Some(rate));
+        Ok(())
+    }
+}
+
+const RATE: PropertyAddress = PropertyAddress::global(kAudioDevicePropertyNominalSampleRate);
+
+/// Ask for 48kHz through the HAL, returning the request the host received
+fn request_48k(driver: &Driver<RateDriver>) -> (AudioObjectID, u64, *mut c_void) {
+    let device = driver.state().device.id();
+    assert_eq!(driver.set(device, 42, RATE, &48000.0f64), 0);
+    let requests = driver.host.take_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, device);
+    // nothing changes before the host performs the request
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(44100.0));
+    assert!(driver.host.take_changed().is_empty());
+    requests[0]
+}
+
+#[test]
+fn performed_rate_changes_are_committed() {
+    let driver = Driver::<RateDriver>::initialized();
+    let rates = driver.state().device.sample_rate().clone();
+    let seed = rates.seed_handle().load();
+
+    let (device, action, info) = request_48k(&driver);
+    assert_eq!(rates.pending(), Some(48000.0));
+    assert_eq!(driver.perform(device, action, info), 0);
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(48000.0));
+    assert_eq!(
+        driver.host.take_changed(),
+        [(device, kAudioDevicePropertyNominalSampleRate)]
+    );
+    assert_eq!(rates.pending(), None);
+    assert_ne!(rates.seed_handle().load(), seed);
+}
+
+#[test]
+fn aborted_rate_changes_are_dropped() {
+    let driver = Driver::<RateDriver>::initialized();
+    let rates = driver.state().device.sample_rate().clone();
+    let seed = rates.seed_handle().load();
+
+    let (device, action, info) = request_48k(&driver);
+    assert_eq!(driver.abort(device, action, info), 0);
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(44100.0));
+    assert_eq!(rates.pending(), None);
+    assert_eq!(rates.seed_handle().load(), seed);
+
+    // the device can be asked again afterwards
+    let (device, action, info) = request_48k(&driver);
+    assert_eq!(driver.perform(device, action, info), 0);
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(48000.0));
+}
+
+#[test]
+fn unavailable_rates_are_never_requested() {
+    let driver = Driver::<RateDriver>::initialized();
+    let device = driver.state().device.id();
+    assert_ne!(driver.set(device, 42, RATE, &96000.0f64), 0);
+    assert!(driver.host.take_requests().is_empty());
+    assert_eq!(driver.get::<f64>(device, 42, RATE), Ok(44100.0));
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "trace")]
+
+mod common;
+
+use std::sync::{Mutex, Once, PoisonError};
+
+use cahal::{
+    audio_object::{DeviceHandle, ObjectRegistry, StreamDirection},
+    base::{
+        kAudioDevicePermissionsError, kAudioHardwareUnknownPropertyError,
+        kAudioStreamPropertyIsActive, pid_t, AudioObjectID,
+    },
+    core_foundation::base::CFAllocatorRef,
+    os_err::{result_to_err_code, OSStatus, OSStatusError},
+    plugin_driver_interface::{AccessOp, AudioServerPluginDriverInterface, LoggingConfig},
+    property::PropertyAddress,
+    raw_plugin_driver_interface::PluginHostInterface,
+    trace::{self, AccessKind, TraceEvent},
+};
+use common::{registry_with_device, Driver};
+
+/// Every event emitted in this process, tests pick theirs by the client pid
+static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+
+fn events_of(pid: pid_t) -> Vec<TraceEvent> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        trace::set_hook(|event| {
+            EVENTS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(*event)
+        })
+    });
+    let events = EVENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    events
+        .iter()
+        .filter(|event| event.client_pid == Some(pid))
+        .copied()
+        .collect()
+}
+
+/// Denies every access of pid 666
+struct TracedDriver {
+    objects: Mutex<ObjectRegistry>,
+    device: DeviceHandle,
+}
+
+impl TracedDriver {
+    fn stream(&self) -> AudioObjectID {
+        self.device.streams(StreamDirection::Output).next().unwrap()
+    }
+}
+
+impl AudioServerPluginDriverInterface for TracedDriver {
+    type DeviceConfigurationChangeInfo = ();
+    const NAME: &'static str = "traced";
+
+    fn configure_logging() -> LoggingConfig {
+        LoggingConfig::Disabled
+    }
+    fn create(_cf_allocator: CFAllocatorRef) -> Self {
+        let (objects, device) = registry_with_device();
+        Self {
+            objects: Mutex::new(objects),
+            device,
+        }
+    }
+    fn init(&self, _host: PluginHostInterface<Self>) -> OSStatus {
+        Ok(())
+    }
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
+    fn check_access(
+        &self,
+        pid: pid_t,
+        _object: AudioObjectID,
+        _address: &PropertyAddress,
+        _op: AccessOp,
+    ) -> OSStatus {
+        if pid == 666 {
+            Err(OSStatusError::HW_UNSPECIFIED_ERR)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn property_calls_are_traced_with_their_client() {
+    events_of(42);
+    let driver = Driver::<TracedDriver>::initialized();
+    let stream = driver.state().stream();
+    let active = PropertyAddress::global(kAudioStreamPropertyIsActive);
+
+    assert!(driver.has_property(stream, 42, active));
+    assert_eq!(driver.is_settable(stream, 42, active), Ok(true));
+    assert_eq!(driver.size(stream, 42, active), Ok(4));
+    assert_eq!(driver.get::<u32>(stream, 42, active), Ok(1));
+    assert_eq!(driver.set(stream, 42, active, &0u32), 0);
+
+    let events = events_of(42);
+    let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            AccessKind::HasProperty,
+            AccessKind::IsSettable,
+            AccessKind::GetSize,
+            AccessKind::Get,
+            AccessKind::Set,
+        ]
+    );
+    for event in &events {
+        assert_eq!(event.object, stream);
+        assert_eq!(event.address, active);
+        assert!(event.status.is_ok(), "{event}");
+    }
+    let sizes: Vec<_> = events
+        .iter()
+        .map(|event| (event.requested_size, event.returned_size))
+        .collect();
+    assert_eq!(
+        sizes,
+        [(0, None), (0, None), (0, Some(4)), (4, Some(4)), (4, None)]
+    );
+}
+
+#[test]
+fn failed_property_calls_are_traced_with_their_error() {
+    events_of(666);
+    let driver = Driver::<TracedDriver>::initialized();
+    let stream = driver.state().stream();
+    let unknown = PropertyAddress::global(u32::from_be_bytes(*b"nope"));
+    let active = PropertyAddress::global(kAudioStreamPropertyIsActive);
+
+    assert!(!driver.has_property(stream, 666, unknown));
+    assert!(driver.get::<u32>(stream, 666, active).is_err());
+    assert_ne!(driver.set(stream, 666, active, &0u32), 0);
+
+    let events: Vec<_> = events_of(666)
+        .iter()
+        .map(|event| {
+            (
+                event.kind,
+                event.address,
+                event.returned_size,
+                result_to_err_code(event.status) as u32,
+            )
+        })
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (
+                AccessKind::HasProperty,
+                unknown,
+                None,
+                kAudioHardwareUnknownPropertyError
+            ),
+            (AccessKind::Get, active, None, kAudioDevicePermissionsError),
+            (AccessKind::Set, active, None, kAudioDevicePermissionsError),
+        ]
+    );
+}
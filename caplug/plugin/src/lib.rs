@@ -1,23 +1,41 @@
+use std::sync::Mutex;
+
 use cahal::{
-    core_foundation::base::CFAllocatorRef, entry_point,
+    audio_object::{ObjectRegistry, PluginObject},
+    core_foundation::base::CFAllocatorRef,
+    entry_point,
     plugin_driver_interface::AudioServerPluginDriverInterface,
     raw_plugin_driver_interface::PluginHostInterface,
 };
 
 pub struct TestPlugin {
     _value: u8,
+    objects: Mutex<ObjectRegistry>,
 }
 impl AudioServerPluginDriverInterface for TestPlugin {
     type DeviceConfigurationChangeInfo = ();
     const NAME: &'static str = "test_plugin";
 
     fn create(_cf_allocator: CFAllocatorRef) -> Self {
-        Self { _value: 0 }
+        let mut objects = ObjectRegistry::new();
+        let mut plugin = PluginObject::new("rustaudio", "com.rustaudio.test_plugin");
+        plugin.attach(&objects);
+        objects
+            .register(Box::new(plugin))
+            .expect("the registry is empty");
+        Self {
+            _value: 0,
+            objects: Mutex::new(objects),
+        }
     }
 
     fn init(&self, _host: PluginHostInterface<Self>) -> cahal::os_err::OSStatus {
         Ok(())
     }
+
+    fn objects(&self) -> &Mutex<ObjectRegistry> {
+        &self.objects
+    }
 }
 
 entry_point!(TestPlugin);